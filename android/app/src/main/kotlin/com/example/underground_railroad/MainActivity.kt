@@ -1,5 +1,16 @@
 package com.example.underground_railroad
 
+import android.content.Context
+import android.os.Bundle
 import io.flutter.embedding.android.FlutterActivity
 
-class MainActivity : FlutterActivity()
+class MainActivity : FlutterActivity() {
+    override fun onCreate(savedInstanceState: Bundle?) {
+        // Load through the JVM so JNI_OnLoad runs, then give Veilid the app context
+        System.loadLibrary("underground_railroad")
+        initVeilidAndroid(applicationContext)
+        super.onCreate(savedInstanceState)
+    }
+
+    private external fun initVeilidAndroid(context: Context)
+}
@@ -0,0 +1,82 @@
+// Benchmarks for the crypto and wire-format paths every message goes through
+// Run with: cargo bench --bench hot_paths
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use underground_railroad::contact_qr::{decode_qr, encode_qr};
+use underground_railroad::crypto::{
+    decrypt_data, derive_key, encrypt_data, generate_random_bytes, generate_salt, generate_signing_keypair,
+};
+use underground_railroad::relay::{peel_onion, wrap_onion, RelayHop};
+use underground_railroad::route_blob::RouteBundle;
+use underground_railroad::vouch::{VerificationMethod, Vouch};
+
+fn bench_encryption(c: &mut Criterion) {
+    let key = generate_random_bytes(32);
+    let message = vec![0x42u8; 4096];
+    let ciphertext = encrypt_data(&key, &message).unwrap();
+
+    c.bench_function("encrypt_4k", |b| b.iter(|| encrypt_data(black_box(&key), black_box(&message)).unwrap()));
+    c.bench_function("decrypt_4k", |b| b.iter(|| decrypt_data(black_box(&key), black_box(&ciphertext)).unwrap()));
+}
+
+fn bench_key_derivation(c: &mut Criterion) {
+    let salt = generate_salt();
+    let mut group = c.benchmark_group("argon2id");
+    group.sample_size(10);
+    group.bench_function("derive_key", |b| {
+        b.iter(|| derive_key(black_box("correct horse battery staple"), black_box(&salt)).unwrap())
+    });
+    group.finish();
+}
+
+fn bench_relay_envelopes(c: &mut Criterion) {
+    let hops: Vec<RelayHop> = (0..3)
+        .map(|i| RelayHop {
+            route: format!("VLD1:route:hop{}", i),
+            key: generate_random_bytes(32),
+        })
+        .collect();
+    let recipient = RelayHop {
+        route: "VLD1:route:recipient".to_string(),
+        key: generate_random_bytes(32),
+    };
+    let payload = vec![7u8; 1024];
+
+    c.bench_function("onion_wrap_3_hops", |b| {
+        b.iter(|| wrap_onion(black_box(&hops), black_box(&recipient), black_box(&payload)).unwrap())
+    });
+    c.bench_function("onion_peel_layer", |b| {
+        b.iter_batched(
+            || wrap_onion(&hops, &recipient, &payload).unwrap().1,
+            |blob| peel_onion(black_box(&hops[0].key), black_box(&blob)).unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+fn bench_signed_records(c: &mut Criterion) {
+    let (secret, _) = generate_signing_keypair();
+    let bundle = RouteBundle::create("VLD1:route:abcd", "VLD1:dht:ef01", secret.as_slice()).unwrap();
+    let encoded = bundle.encode();
+    let qr = encode_qr(&bundle).unwrap();
+
+    c.bench_function("route_bundle_decode_verify", |b| b.iter(|| RouteBundle::decode(black_box(&encoded)).unwrap()));
+    c.bench_function("contact_qr_decode", |b| b.iter(|| decode_qr(black_box(&qr)).unwrap()));
+
+    let (voucher_secret, voucher_public) = generate_signing_keypair();
+    let vouch = Vouch::create(voucher_secret.as_slice(), [9u8; 32], VerificationMethod::InPerson, 1_700_000_000)
+        .unwrap()
+        .encode();
+    c.bench_function("vouch_decode_verify", |b| {
+        b.iter(|| Vouch::decode(black_box(&vouch)).unwrap().verify(&voucher_public).unwrap())
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_encryption,
+    bench_key_derivation,
+    bench_relay_envelopes,
+    bench_signed_records
+);
+criterion_main!(benches);
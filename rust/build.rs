@@ -2,4 +2,42 @@ fn main() {
     // flutter_rust_bridge_codegen will handle code generation
     // This is invoked automatically during build
     println!("cargo:rerun-if-changed=src/api.rs");
+
+    #[cfg(feature = "c-header")]
+    c_header();
+}
+
+/// C header for integrations that link the core without Flutter (native targets only)
+/// Written to OUT_DIR, and also to URR_C_HEADER_DIR when set (e.g. to refresh
+/// include/). A failure is reported as a warning and never fails the build
+#[cfg(feature = "c-header")]
+fn c_header() {
+    println!("cargo:rerun-if-changed=src/c_api.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-env-changed=URR_C_HEADER_DIR");
+
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default();
+    let config = match cbindgen::Config::from_file(format!("{}/cbindgen.toml", crate_dir)) {
+        Ok(config) => config,
+        Err(e) => {
+            println!("cargo:warning=C header not generated, cbindgen.toml is invalid: {}", e);
+            return;
+        }
+    };
+    let bindings = cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(format!("{}/src/c_api.rs", crate_dir))
+        .generate();
+    let bindings = match bindings {
+        Ok(bindings) => bindings,
+        Err(e) => {
+            println!("cargo:warning=C header not generated: {}", e);
+            return;
+        }
+    };
+
+    let dirs = std::env::var_os("OUT_DIR").into_iter().chain(std::env::var_os("URR_C_HEADER_DIR"));
+    for dir in dirs {
+        bindings.write_to_file(std::path::Path::new(&dir).join("underground_railroad.h"));
+    }
 }
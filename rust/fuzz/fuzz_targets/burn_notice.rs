@@ -0,0 +1,11 @@
+// Burn notices propagate hop by hop through the trust network
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use underground_railroad::revocation::BurnNotice;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(notice) = BurnNotice::decode(data) {
+        assert_eq!(notice.encode(), data);
+    }
+});
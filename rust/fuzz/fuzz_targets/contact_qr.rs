@@ -0,0 +1,9 @@
+// Scanned QR text is fully attacker-controlled
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use underground_railroad::contact_qr::decode_qr;
+
+fuzz_target!(|text: &str| {
+    let _ = decode_qr(text);
+});
@@ -0,0 +1,11 @@
+// Introductions carry a contact card from a third party
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use underground_railroad::introduction::Introduction;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(intro) = Introduction::decode(data) {
+        assert_eq!(intro.encode(), data);
+    }
+});
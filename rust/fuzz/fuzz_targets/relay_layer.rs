@@ -0,0 +1,11 @@
+// Decrypted onion layers; decryption itself is covered by the AEAD
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use underground_railroad::relay::{decode_layer, RelayLayer};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(RelayLayer::Forward { next_route, payload }) = decode_layer(data) {
+        assert!(!next_route.is_empty() && !payload.is_empty());
+    }
+});
@@ -0,0 +1,11 @@
+// Route bundles arrive from QR codes and mailbox records
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use underground_railroad::route_blob::RouteBundle;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(bundle) = RouteBundle::decode(data) {
+        assert_eq!(bundle.encode(), data);
+    }
+});
@@ -0,0 +1,11 @@
+// Vouches are forwarded between contacts
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use underground_railroad::vouch::Vouch;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(vouch) = Vouch::decode(data) {
+        assert_eq!(vouch.encode(), data);
+    }
+});
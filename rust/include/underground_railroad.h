@@ -0,0 +1,128 @@
+/* Generated by cbindgen from src/c_api.rs; do not edit */
+
+#ifndef UNDERGROUND_RAILROAD_H
+#define UNDERGROUND_RAILROAD_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Returned by every call that succeeded
+ */
+#define URR_OK 0
+
+/**
+ * Opaque handle to an open profile and the runtime driving it
+ */
+typedef struct UrrContext UrrContext;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Open a profile in `config_dir` and start background work
+ * On success `*out` receives a handle to pass to urr_context_close
+ *
+ * # Safety
+ * `config_dir` must be a NUL-terminated string and `out` a valid pointer
+ */
+uint32_t urr_context_open(const char *config_dir, struct UrrContext **out);
+
+/**
+ * Stop background work and free the handle; null is ignored
+ *
+ * # Safety
+ * `handle` must come from urr_context_open and not be used afterwards
+ */
+uint32_t urr_context_close(struct UrrContext *handle);
+
+/**
+ * Attachment and background sync status as JSON
+ *
+ * # Safety
+ * `handle` must be a live handle and `out` a valid pointer
+ */
+uint32_t urr_status_json(const struct UrrContext *handle, char **out);
+
+/**
+ * Personas as a JSON array
+ *
+ * # Safety
+ * `handle` must be a live handle and `out` a valid pointer
+ */
+uint32_t urr_list_personas_json(const struct UrrContext *handle, char **out);
+
+/**
+ * Create a persona; the JSON result holds the secret key, shown only once
+ *
+ * # Safety
+ * `handle` must be a live handle, `name` NUL-terminated and `out` a valid pointer
+ */
+uint32_t urr_create_persona(const struct UrrContext *handle, const char *name, char **out);
+
+/**
+ * Make another persona the active one
+ *
+ * # Safety
+ * `handle` must be a live handle and `persona_id` NUL-terminated
+ */
+uint32_t urr_switch_persona(const struct UrrContext *handle, const char *persona_id);
+
+/**
+ * QR text for the active persona's contact card
+ *
+ * # Safety
+ * `handle` must be a live handle, `secret_key` NUL-terminated and `out` a valid pointer
+ */
+uint32_t urr_my_contact_qr(const struct UrrContext *handle, const char *secret_key, char **out);
+
+/**
+ * Verify a scanned contact code and describe the contact as JSON
+ *
+ * # Safety
+ * `handle` must be a live handle, `text` NUL-terminated and `out` a valid pointer
+ */
+uint32_t urr_parse_contact_qr(const struct UrrContext *handle, const char *text, char **out);
+
+/**
+ * Send `len` bytes from `data` to a remote private route
+ *
+ * # Safety
+ * `handle` must be a live handle, `route` NUL-terminated and `data` valid for `len` bytes
+ */
+uint32_t urr_send_message(const struct UrrContext *handle,
+                          const char *route,
+                          const uint8_t *data,
+                          size_t len);
+
+/**
+ * Verify a vouch against the voucher's pinned key and describe it as JSON
+ *
+ * # Safety
+ * `data` must be valid for `len` bytes, `voucher_key` NUL-terminated and `out` a valid pointer
+ */
+uint32_t urr_verify_vouch(const uint8_t *data, size_t len, const char *voucher_key, char **out);
+
+/**
+ * Message for the last error on this thread, or null if the last call succeeded
+ * Release with urr_string_free
+ */
+char *urr_last_error(void);
+
+/**
+ * Free a string returned by this library; null is ignored
+ *
+ * # Safety
+ * `s` must come from this library and not be freed twice
+ */
+void urr_string_free(char *s);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* UNDERGROUND_RAILROAD_H */
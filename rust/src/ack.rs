@@ -0,0 +1,312 @@
+// End-to-end delivery acknowledgments
+// The recipient's core answers every tracked message with a small ACK frame
+// (not a read receipt; the user never sees it). Unacknowledged sends are
+// retried over the next delivery path after a timeout, and once every path
+// has been tried the contact is reported unreachable
+// An ACK carries a tag keyed with the conversation key, so relays, mailbox
+// readers and nearby devices that saw the message cannot forge one. Only the
+// expected tag is kept while waiting, never the key itself. Pending relay
+// paths hold hop keys, so the saved tracker is sealed with the storage key
+
+use crate::crypto::{hash_blake3, SecureBuffer};
+use crate::error::{Result, UndergroundError};
+use crate::profile_key;
+use crate::relay::RelayHop;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+const MAGIC: &[u8; 3] = b"URA";
+const VERSION: u8 = 2;
+const ID_LEN: usize = 16;
+const TAG_LEN: usize = 32;
+const KDF_CONTEXT: &str = "underground-railroad ack v1";
+
+/// File name of the persisted sends awaiting an ACK inside the config directory
+pub(crate) const ACKS_FILE: &str = "acks.json";
+
+/// How long to wait for an ACK before trying the next path
+pub const DEFAULT_ACK_TIMEOUT_SECS: u64 = 120;
+
+/// Most sends waiting for an ACK at once
+pub const MAX_PENDING_SENDS: usize = 1024;
+
+/// Identifier of a sent message, derived from its bytes so both ends agree
+pub fn message_id(message: &[u8]) -> String {
+    hex::encode(&hash_blake3(message)[..ID_LEN])
+}
+
+/// Tag proving the ACK for `message` came from a holder of the conversation key
+fn ack_tag(conversation_key: &[u8], message: &[u8]) -> Result<[u8; TAG_LEN]> {
+    if conversation_key.len() != 32 {
+        return Err(UndergroundError::InvalidKey);
+    }
+    let key = blake3::derive_key(KDF_CONTEXT, conversation_key);
+    Ok(*blake3::keyed_hash(&key, &hash_blake3(message)[..ID_LEN]).as_bytes())
+}
+
+/// ACK frame for a received message, tagged with the key it was sealed under
+pub fn encode_ack(conversation_key: &[u8], message: &[u8]) -> Result<Vec<u8>> {
+    let tag = ack_tag(conversation_key, message)?;
+    let mut out = Vec::with_capacity(4 + ID_LEN + TAG_LEN);
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    out.extend_from_slice(&hash_blake3(message)[..ID_LEN]);
+    out.extend_from_slice(&tag);
+    Ok(out)
+}
+
+/// Message id and tag carried by `data`, if it is an ACK frame
+/// The tag is only checked by the tracker, against the send it names
+pub fn decode_ack(data: &[u8]) -> Option<(String, [u8; TAG_LEN])> {
+    let rest = data.strip_prefix(MAGIC.as_slice())?.strip_prefix(&[VERSION])?;
+    if rest.len() != ID_LEN + TAG_LEN {
+        return None;
+    }
+    let (id, tag) = rest.split_at(ID_LEN);
+    Some((hex::encode(id), tag.try_into().ok()?))
+}
+
+/// One way of reaching a contact, tried in order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DeliveryPath {
+    Route(String),
+    Relay { hops: Vec<RelayHop>, recipient: RelayHop },
+    /// Write to the contact's mailbox record for them to poll
+    Mailbox(String),
+    /// Hand to nearby devices for the contact's route
+    Mesh(String),
+}
+
+/// Next step for an unacknowledged send
+#[derive(Debug, Clone)]
+pub enum Escalation {
+    Retry {
+        message_id: String,
+        path: DeliveryPath,
+        message: Vec<u8>,
+    },
+    GiveUp {
+        message_id: String,
+        contact: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PendingSend {
+    contact: String,
+    message: Vec<u8>,
+    paths: Vec<DeliveryPath>,
+    /// Index of the path last tried
+    attempt: usize,
+    deadline: u64,
+    /// Tag the contact's ACK must carry
+    expected_tag: [u8; TAG_LEN],
+    #[serde(default)]
+    tracked_at: u64,
+}
+
+/// Sends waiting for an ACK
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AckTracker {
+    pending: HashMap<String, PendingSend>,
+    timeout_secs: u64,
+}
+
+impl AckTracker {
+    pub fn new(timeout_secs: u64) -> Self {
+        Self {
+            pending: HashMap::new(),
+            timeout_secs,
+        }
+    }
+
+    /// Load the tracker from a config directory (empty if none saved yet)
+    pub fn load(config_dir: &Path, storage_key: &SecureBuffer) -> Result<Self> {
+        let path = config_dir.join(ACKS_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_slice(&profile_key::open(storage_key, &fs::read(path)?)?)?)
+    }
+
+    pub fn save(&self, config_dir: &Path, storage_key: &SecureBuffer) -> Result<()> {
+        fs::create_dir_all(config_dir)?;
+        let sealed = profile_key::seal(storage_key, &serde_json::to_vec(self)?)?;
+        fs::write(config_dir.join(ACKS_FILE), sealed)?;
+        Ok(())
+    }
+
+    /// Start waiting for an ACK of a message about to be sent over `paths[0]`
+    /// `conversation_key` is the key the message was sealed under; only the
+    /// contact's ACK tagged with it is accepted
+    pub fn track(
+        &mut self,
+        contact: &str,
+        message: Vec<u8>,
+        conversation_key: &[u8],
+        paths: Vec<DeliveryPath>,
+        now: u64,
+    ) -> Result<String> {
+        let id = message_id(&message);
+        if self.pending.len() >= MAX_PENDING_SENDS && !self.pending.contains_key(&id) {
+            return Err(UndergroundError::Storage(
+                "Too many messages waiting for acknowledgment".to_string(),
+            ));
+        }
+        let expected_tag = ack_tag(conversation_key, &message)?;
+        self.pending.insert(
+            id.clone(),
+            PendingSend {
+                contact: contact.to_string(),
+                message,
+                paths,
+                attempt: 0,
+                deadline: now + self.timeout_secs,
+                expected_tag,
+                tracked_at: now,
+            },
+        );
+        Ok(id)
+    }
+
+    /// Stop tracking a message if `tag` proves its contact acknowledged it
+    /// False if it was not pending or the tag does not match
+    pub fn acknowledge(&mut self, message_id: &str, tag: &[u8; TAG_LEN]) -> bool {
+        let matches = self
+            .pending
+            .get(message_id)
+            // Hash equality is constant time
+            .is_some_and(|send| blake3::Hash::from(send.expected_tag) == blake3::Hash::from(*tag));
+        if matches {
+            self.pending.remove(message_id);
+        }
+        matches
+    }
+
+    /// Stop tracking a message without an ACK (e.g. its first send failed)
+    pub fn forget(&mut self, message_id: &str) -> bool {
+        self.pending.remove(message_id).is_some()
+    }
+
+    /// Escalate every send whose deadline has passed
+    pub fn due(&mut self, now: u64) -> Vec<Escalation> {
+        let mut steps = Vec::new();
+        let mut given_up = Vec::new();
+        for (id, send) in self.pending.iter_mut().filter(|(_, s)| s.deadline <= now) {
+            send.attempt += 1;
+            match send.paths.get(send.attempt) {
+                Some(path) => {
+                    send.deadline = now + self.timeout_secs;
+                    steps.push(Escalation::Retry {
+                        message_id: id.clone(),
+                        path: path.clone(),
+                        message: send.message.clone(),
+                    });
+                }
+                None => given_up.push(id.clone()),
+            }
+        }
+        for id in given_up {
+            if let Some(send) = self.pending.remove(&id) {
+                steps.push(Escalation::GiveUp {
+                    message_id: id,
+                    contact: send.contact,
+                });
+            }
+        }
+        steps
+    }
+
+    /// Drop sends first tracked before `cutoff`, returning how many were dropped
+    pub fn expire_before(&mut self, cutoff: u64) -> usize {
+        let before = self.pending.len();
+        self.pending.retain(|_, s| s.tracked_at >= cutoff);
+        before - self.pending.len()
+    }
+
+    /// Number of sends first tracked before `cutoff`
+    pub fn count_before(&self, cutoff: u64) -> usize {
+        self.pending.values().filter(|s| s.tracked_at < cutoff).count()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+impl Default for AckTracker {
+    fn default() -> Self {
+        Self::new(DEFAULT_ACK_TIMEOUT_SECS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 32] = [5u8; 32];
+
+    #[test]
+    fn test_escalates_then_gives_up() {
+        let mut tracker = AckTracker::new(10);
+        let paths = vec![
+            DeliveryPath::Route("route-a".to_string()),
+            DeliveryPath::Mailbox("VLD1:dht:box".to_string()),
+        ];
+        tracker.track("bob", b"hello".to_vec(), &KEY, paths.clone(), 100).unwrap();
+        assert_eq!(decode_ack(b"hello"), None);
+
+        assert!(tracker.due(105).is_empty());
+        let steps = tracker.due(110);
+        assert!(matches!(&steps[..], [Escalation::Retry { path: DeliveryPath::Mailbox(_), .. }]));
+
+        let steps = tracker.due(120);
+        assert!(matches!(&steps[..], [Escalation::GiveUp { contact, .. }] if contact == "bob"));
+        assert!(tracker.is_empty());
+
+        tracker.track("bob", b"again".to_vec(), &KEY, paths, 200).unwrap();
+        let (id, tag) = decode_ack(&encode_ack(&KEY, b"again").unwrap()).unwrap();
+        assert!(tracker.acknowledge(&id, &tag));
+        assert!(tracker.due(1000).is_empty());
+    }
+
+    #[test]
+    fn test_forged_ack_is_refused_and_pending_sends_persist() {
+        let tmp = crate::util::TempDir::new("acks");
+        let mut tracker = AckTracker::new(10);
+        let paths = vec![DeliveryPath::Route("route-a".to_string())];
+        let id = tracker.track("bob", b"hello".to_vec(), &KEY, paths, 100).unwrap();
+
+        // Anyone who saw the message knows its id, but not the conversation key
+        let (forged_id, forged_tag) = decode_ack(&encode_ack(&[6u8; 32], b"hello").unwrap()).unwrap();
+        assert_eq!(forged_id, id);
+        assert!(!tracker.acknowledge(&forged_id, &forged_tag));
+
+        // Relay paths carry hop keys, so nothing is readable on disk
+        let storage_key = profile_key::load_or_create(tmp.path()).unwrap();
+        tracker.save(tmp.path(), &storage_key).unwrap();
+        let saved = fs::read(tmp.path().join(ACKS_FILE)).unwrap();
+        assert!(!saved.windows(7).any(|w| w == b"route-a"));
+        let mut loaded = AckTracker::load(tmp.path(), &storage_key).unwrap();
+        let (id, tag) = decode_ack(&encode_ack(&KEY, b"hello").unwrap()).unwrap();
+        assert!(loaded.acknowledge(&id, &tag));
+        assert!(loaded.is_empty());
+    }
+
+    #[test]
+    fn test_tracker_is_bounded() {
+        let mut tracker = AckTracker::default();
+        let paths = vec![DeliveryPath::Route("route-a".to_string())];
+        for i in 0..MAX_PENDING_SENDS as u32 {
+            tracker.track("bob", i.to_be_bytes().to_vec(), &KEY, paths.clone(), 0).unwrap();
+        }
+        assert!(tracker.track("bob", b"one more".to_vec(), &KEY, paths, 0).is_err());
+        assert_eq!(tracker.len(), MAX_PENDING_SENDS);
+    }
+}
@@ -0,0 +1,45 @@
+// Android: JNI entry points so Veilid can run in-process
+// MainActivity loads the library through the JVM (running JNI_OnLoad) and then
+// hands over the application context before Dart opens the same library
+
+use android_logger::Config;
+use jni::objects::JObject;
+use jni::sys::{jint, JNI_VERSION_1_6};
+use jni::{JNIEnv, JavaVM};
+use std::ffi::c_void;
+use std::sync::OnceLock;
+
+/// Logcat tag for everything logged from Rust
+const LOG_TAG: &str = "UndergroundRailroad";
+
+static JAVA_VM: OnceLock<JavaVM> = OnceLock::new();
+
+/// The JVM this library was loaded into
+pub fn java_vm() -> Option<&'static JavaVM> {
+    JAVA_VM.get()
+}
+
+#[no_mangle]
+pub extern "system" fn JNI_OnLoad(vm: JavaVM, _reserved: *mut c_void) -> jint {
+    android_logger::init_once(
+        Config::default()
+            .with_max_level(log::LevelFilter::Info)
+            .with_tag(LOG_TAG),
+    );
+    let _ = JAVA_VM.set(vm);
+    JNI_VERSION_1_6
+}
+
+/// Set up veilid-core's Android globals (JVM and application context)
+#[no_mangle]
+pub extern "system" fn Java_com_example_underground_1railroad_MainActivity_initVeilidAndroid(
+    env: JNIEnv,
+    _activity: JObject,
+    context: JObject,
+) {
+    #[cfg(feature = "network")]
+    veilid_core::veilid_core_setup_android(env, context);
+    #[cfg(not(feature = "network"))]
+    let _ = (env, context);
+    tracing::info!("Veilid Android platform initialized");
+}
@@ -1,107 +1,1457 @@
 // Flutter bridge API
 // This file defines the Rust functions callable from Flutter
 
-use crate::crypto::{derive_key, encrypt_data, decrypt_data, generate_random_bytes, generate_salt, hash_blake3};
-use crate::veilid_manager::VeilidManager;
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use crate::crypto::{decode_typed_key, derive_key, derive_key_with_params, encrypt_data, decrypt_data, generate_random_bytes, generate_salt, hash_blake3, signing_public_key};
+use crate::app_context::AppContext;
+use crate::background_sync::SyncStatus;
+use crate::contact_card::ContactCard;
+use crate::connection::{
+    self, ConnectionAcceptance, ConnectionMessage, ConnectionRequest, ConnectionResponse, PendingConnection,
+};
+use crate::contact_qr::{decode_qr, encode_card_qr, encode_qr, fingerprint};
+use crate::config::{NetworkProfile, VeilidConfig};
+use crate::error::UndergroundError;
+use crate::ffi_error::FfiError;
+use crate::diagnostics::{run_diagnostics, NetworkDiagnostics};
+use crate::escrow::{self, EscrowAction, EscrowApproval, EscrowPolicy, EscrowRequest, EscrowShare};
+use crate::events::CoreEvent;
+use crate::inbox::InboxMessage;
+use crate::introduction::Introduction;
+use crate::journal::Intent;
+use crate::key_wrap::{self, KeyProtection};
+use crate::logging::{self, LogRecord};
+use crate::mesh::MeshFrameOutcome;
+use crate::message_codec::{self, MessageKind};
+use crate::metrics::MetricsSnapshot;
+use crate::persona::{Persona, PersonaHandle, MAILBOX_TTL_SECS};
+use crate::pinning::{contact_id, ContactKeys, ImportedPins, PinPolicy, PinStatus, PinnedContact, SharePolicy};
+use crate::profile_archive;
+use crate::progress::{ProgressEvent, ProgressOperation, ProgressReporter};
+use crate::record_keeper::RecordKind;
+use crate::retention::RetentionReport;
+use crate::ack::DeliveryPath;
+use crate::relay::RelayHop;
+use crate::rendezvous::{Rendezvous, RendezvousStatus};
+use crate::revocation::BurnNotice;
+use crate::route_blob::RouteBundle;
+use crate::safety::SafetyProfile;
+use crate::shaper::Urgency;
+use crate::sneakernet::{CarriedAnnouncement, CarryBundle, SneakernetTransport};
+use crate::storage_audit::StorageFinding;
+use crate::vouch::{VerificationMethod, Vouch};
+use crate::bridge_generated::StreamSink;
+use crate::veilid_manager::{AttachmentState, WatchTopic};
+use tracing::Level;
 
-// Global Veilid manager instance
-lazy_static::lazy_static! {
-    static ref VEILID: Arc<RwLock<VeilidManager>> = Arc::new(RwLock::new(VeilidManager::new()));
+/// Initialize the Underground Railroad system
+/// Returns the handle every other stateful call takes
+pub async fn initialize_underground_railroad(config_dir: String) -> Result<AppContext, FfiError> {
+    let progress = ProgressReporter::silent(ProgressOperation::Startup);
+    Ok(AppContext::open_configured(config_dir, &progress).await?)
 }
 
-/// Initialize the Underground Railroad system
-pub async fn initialize_underground_railroad(config_dir: String) -> Result<bool, String> {
-    let manager = VEILID.read().await;
-    manager.initialize(config_dir).await.map_err(|e| e.to_string())?;
+/// Initialize with custom network configuration (e.g. user-supplied bootstrap nodes)
+pub async fn initialize_underground_railroad_with_config(
+    config_dir: String,
+    config: VeilidConfig,
+) -> Result<AppContext, FfiError> {
+    let progress = ProgressReporter::silent(ProgressOperation::Startup);
+    Ok(AppContext::open(config_dir, config, &progress).await?)
+}
+
+/// Initialize while streaming startup progress (network attach can take a while)
+pub async fn initialize_underground_railroad_with_progress(
+    config_dir: String,
+    config: VeilidConfig,
+    sink: StreamSink<ProgressEvent>,
+) -> Result<AppContext, FfiError> {
+    let progress = ProgressReporter::new(ProgressOperation::Startup, move |event| {
+        let _ = sink.add(event);
+    });
+    Ok(AppContext::open(config_dir, config, &progress).await?)
+}
+
+/// Re-read underground-railroad.toml and apply the settings that can change while running
+/// Returns the names of changed settings that only take effect after a restart
+pub async fn reload_config(ctx: &AppContext) -> Result<Vec<String>, FfiError> {
+    Ok(ctx.reload_config().await?)
+}
+
+/// Get the effective bootstrap node list
+pub async fn get_bootstrap_nodes(ctx: &AppContext) -> Result<Vec<String>, FfiError> {
+    let manager = ctx.manager();
+    Ok(manager.bootstrap_nodes().await)
+}
+
+/// Select the bandwidth and battery profile
+pub async fn set_network_profile(ctx: &AppContext, profile: NetworkProfile) -> Result<bool, FfiError> {
+    let manager = ctx.manager();
+    manager.set_network_profile(profile).await;
     Ok(true)
 }
 
-/// Shutdown the system
-pub async fn shutdown_underground_railroad() -> Result<bool, String> {
-    let manager = VEILID.read().await;
-    manager.shutdown().await.map_err(|e| e.to_string())?;
+/// Shutdown the system and stop background tasks for this handle
+pub async fn shutdown_underground_railroad(ctx: &AppContext) -> Result<bool, FfiError> {
+    ctx.close().await?;
     Ok(true)
 }
 
+/// Shut down and wipe all network state this profile keeps on disk, streaming progress
+pub async fn wipe_profile(ctx: &AppContext, sink: StreamSink<ProgressEvent>) -> Result<bool, FfiError> {
+    let progress = ProgressReporter::new(ProgressOperation::Wipe, move |event| {
+        let _ = sink.add(event);
+    });
+    ctx.wipe(&progress).await?;
+    Ok(true)
+}
+
+/// Export this profile as a password-encrypted archive at `dest_path`
+/// Returns how many files were exported
+pub async fn export_profile(
+    ctx: &AppContext,
+    password: String,
+    dest_path: String,
+    sink: StreamSink<ProgressEvent>,
+) -> Result<usize, FfiError> {
+    let progress = ProgressReporter::new(ProgressOperation::Export, move |event| {
+        let _ = sink.add(event);
+    });
+    Ok(profile_archive::export_profile(ctx.config_dir(), &password, std::path::Path::new(&dest_path), &progress)?)
+}
+
+/// Move queued messages and our announcements into an encrypted carry bundle
+/// at `dest_path`, returning how many messages it holds
+/// Messages leave the outbox only once the bundle has been written
+pub async fn export_sneakernet_bundle(ctx: &AppContext, password: String, dest_path: String) -> Result<u32, FfiError> {
+    let carrier = SneakernetTransport::new();
+    let carried = ctx.manager().carry_outbox(&carrier).await;
+    let announcements = ctx
+        .record_keeper
+        .records()
+        .await
+        .into_iter()
+        .filter(|r| r.kind == RecordKind::Announcement)
+        .map(|r| CarriedAnnouncement { key: r.key, value: r.value })
+        .collect();
+    let bundle = CarryBundle {
+        created_at: crate::util::unix_now(),
+        envelopes: carrier.take(),
+        announcements,
+    };
+
+    let sealed = bundle.seal(&password)?;
+    std::fs::write(&dest_path, sealed).map_err(crate::error::UndergroundError::from)?;
+    ctx.manager().remove_carried(&carried).await;
+    Ok(bundle.envelopes.len() as u32)
+}
+
+/// Forward everything in a carry bundle as if it had been sent from this device
+pub async fn import_sneakernet_bundle(
+    ctx: &AppContext,
+    password: String,
+    src_path: String,
+) -> Result<SneakernetImportData, FfiError> {
+    let data = std::fs::read(&src_path).map_err(crate::error::UndergroundError::from)?;
+    let bundle = CarryBundle::open(&data, &password)?;
+    for envelope in &bundle.envelopes {
+        ctx.manager()
+            .send_via_private_route(&envelope.route, envelope.message.clone())
+            .await?;
+    }
+    for announcement in &bundle.announcements {
+        ctx.manager().dht_set(&announcement.key, announcement.value.clone()).await?;
+    }
+    Ok(SneakernetImportData {
+        messages: bundle.envelopes.len() as u32,
+        announcements: bundle.announcements.len() as u32,
+    })
+}
+
+/// Restore a profile archive into `config_dir`; call before initializing that profile
+/// Returns how many files were restored
+pub async fn import_profile(
+    config_dir: String,
+    password: String,
+    src_path: String,
+    sink: StreamSink<ProgressEvent>,
+) -> Result<usize, FfiError> {
+    let progress = ProgressReporter::new(ProgressOperation::Import, move |event| {
+        let _ = sink.add(event);
+    });
+    Ok(profile_archive::import_profile(
+        std::path::Path::new(&config_dir),
+        &password,
+        std::path::Path::new(&src_path),
+        &progress,
+    )?)
+}
+
 /// Check if system is initialized
-pub async fn is_initialized() -> Result<bool, String> {
-    let manager = VEILID.read().await;
+pub async fn is_initialized(ctx: &AppContext) -> Result<bool, FfiError> {
+    let manager = ctx.manager();
     Ok(manager.is_initialized().await)
 }
 
+/// Get current network attachment state
+pub async fn get_attachment_state(ctx: &AppContext) -> Result<AttachmentState, FfiError> {
+    let manager = ctx.manager();
+    Ok(manager.attachment_state().await)
+}
+
+/// Run network diagnostics (results stay on the device)
+pub async fn run_network_diagnostics(ctx: &AppContext) -> Result<NetworkDiagnostics, FfiError> {
+    let manager = ctx.manager();
+    Ok(run_diagnostics(manager).await)
+}
+
+/// Get network performance metrics
+pub async fn get_network_metrics(ctx: &AppContext) -> Result<MetricsSnapshot, FfiError> {
+    let manager = ctx.manager();
+    Ok(manager.metrics().await)
+}
+
+/// Report a message round trip (send to reply) measured by the app
+pub async fn record_message_round_trip(ctx: &AppContext, millis: u64) -> Result<bool, FfiError> {
+    let manager = ctx.manager();
+    manager
+        .record_round_trip(std::time::Duration::from_millis(millis))
+        .await;
+    Ok(true)
+}
+
+/// Change the log level for a module path, or the default level if `module` is empty
+/// `level` is one of error, warn, info, debug, trace, or empty to drop a module override
+pub async fn set_log_level(module: String, level: String) -> Result<bool, FfiError> {
+    let controller = logging::init(Level::INFO);
+    let level = if level.is_empty() {
+        None
+    } else {
+        Some(
+            level
+                .parse::<Level>()
+                .map_err(|_| FfiError::InvalidInput(format!("Unknown log level: {}", level)))?,
+        )
+    };
+    match (module.is_empty(), level) {
+        (true, Some(level)) => controller.set_default_level(level),
+        (true, None) => return Err(FfiError::InvalidInput("A default level is required".to_string())),
+        (false, level) => controller.set_module_level(&module, level),
+    }
+    Ok(true)
+}
+
+/// Newest redacted log records, oldest first, for attaching to diagnostics
+pub async fn get_recent_logs(limit: u32) -> Result<Vec<LogRecord>, FfiError> {
+    Ok(logging::controller()
+        .map(|c| c.recent(limit as usize))
+        .unwrap_or_default())
+}
+
+/// Stream app events (messages, network state, trust changes) to Flutter
+/// Starts with the current network state; ends when the Dart side closes the stream
+pub async fn subscribe_events(ctx: &AppContext, sink: StreamSink<CoreEvent>) -> Result<(), FfiError> {
+    let mut events = ctx.events().subscribe();
+
+    let state = ctx.manager().attachment_state().await;
+    if sink.add(CoreEvent::NetworkStateChanged { state }).is_err() {
+        return Ok(());
+    }
+
+    tokio::spawn(async move {
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(count)) => CoreEvent::EventsMissed { count },
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            };
+            if sink.add(event).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Received messages with an id after `after`, or the whole inbox for None
+/// Messages stay until removed, so nothing is lost if the event stream lags
+pub async fn read_inbox(ctx: &AppContext, after: Option<u64>) -> Result<Vec<InboxMessage>, FfiError> {
+    Ok(ctx.manager().inbox_messages(after).await)
+}
+
+/// Remove processed messages up to and including `through`, returning how many were removed
+pub async fn remove_inbox_messages(ctx: &AppContext, through: u64) -> Result<u32, FfiError> {
+    Ok(ctx.manager().remove_inbox_messages(through).await? as u32)
+}
+
+/// Start the outbox worker, mailbox watcher, DHT refresher and cleanup tasks
+pub async fn start_background_sync(ctx: &AppContext) -> Result<SyncStatus, FfiError> {
+    ctx.sync.start().await;
+    Ok(ctx.sync.status().await)
+}
+
+/// Stop the background sync tasks (e.g. when the OS background window ends)
+pub async fn stop_background_sync(ctx: &AppContext) -> Result<SyncStatus, FfiError> {
+    ctx.sync.stop().await;
+    Ok(ctx.sync.status().await)
+}
+
+/// Run a single sync pass now, for one-shot platform background tasks
+pub async fn run_background_sync_once(ctx: &AppContext) -> Result<SyncStatus, FfiError> {
+    Ok(ctx.sync.run_once().await?)
+}
+
+/// Get the background sync status
+pub async fn get_background_sync_status(ctx: &AppContext) -> Result<SyncStatus, FfiError> {
+    Ok(ctx.sync.status().await)
+}
+
 /// Create a new Veilid identity (keypair)
-pub async fn create_veilid_identity() -> Result<VeilidIdentityData, String> {
-    let manager = VEILID.read().await;
-    manager.create_identity().await.map_err(|e| e.to_string())
+pub async fn create_veilid_identity(ctx: &AppContext) -> Result<VeilidIdentityData, FfiError> {
+    let manager = ctx.manager();
+    manager.create_identity().await.map_err(FfiError::from)
 }
 
 /// Create a private route for receiving messages
-pub async fn create_private_route() -> Result<String, String> {
-    let manager = VEILID.read().await;
-    manager.create_private_route().await.map_err(|e| e.to_string())
+pub async fn create_private_route(ctx: &AppContext) -> Result<String, FfiError> {
+    let manager = ctx.manager();
+    manager.create_private_route().await.map_err(FfiError::from)
 }
 
 /// Store encrypted data in DHT
-pub async fn dht_set(key: String, value: Vec<u8>) -> Result<bool, String> {
-    let manager = VEILID.read().await;
-    manager.dht_set(&key, value).await.map_err(|e| e.to_string())?;
+pub async fn dht_set(ctx: &AppContext, key: String, value: Vec<u8>) -> Result<bool, FfiError> {
+    let manager = ctx.manager();
+    manager.dht_set(&key, value).await?;
     Ok(true)
 }
 
 /// Retrieve encrypted data from DHT
-pub async fn dht_get(key: String) -> Result<Option<Vec<u8>>, String> {
-    let manager = VEILID.read().await;
-    manager.dht_get(&key).await.map_err(|e| e.to_string())
+pub async fn dht_get(ctx: &AppContext, key: String) -> Result<Option<Vec<u8>>, FfiError> {
+    let manager = ctx.manager();
+    manager.dht_get(&key).await.map_err(FfiError::from)
+}
+
+/// Store a payload larger than one DHT value (e.g. a bundle or attachment)
+/// Returns the number of chunks written
+pub async fn dht_set_large(ctx: &AppContext, key: String, value: Vec<u8>) -> Result<u32, FfiError> {
+    let chunks = ctx.manager().dht_set_chunked(&key, &value).await?;
+    Ok(chunks as u32)
+}
+
+/// Retrieve and verify a payload stored with dht_set_large
+pub async fn dht_get_large(ctx: &AppContext, key: String) -> Result<Option<Vec<u8>>, FfiError> {
+    ctx.manager().dht_get_chunked(&key).await.map_err(FfiError::from)
+}
+
+/// Store a record we own in the DHT and keep it refreshed
+pub async fn dht_publish_owned(
+    ctx: &AppContext,
+    key: String,
+    kind: RecordKind,
+    ttl_secs: u64,
+    value: Vec<u8>,
+) -> Result<bool, FfiError> {
+    ctx.record_keeper.publish(&key, kind, ttl_secs, value).await?;
+    Ok(true)
+}
+
+/// Watch a DHT record; each new value is reported as a RecordChanged event
+pub async fn dht_subscribe(ctx: &AppContext, key: String) -> Result<bool, FfiError> {
+    ctx.sync.subscribe(&key, WatchTopic::Record).await;
+    Ok(true)
+}
+
+/// Stop watching a record subscribed with dht_subscribe
+pub async fn dht_unsubscribe(ctx: &AppContext, key: String) -> Result<bool, FfiError> {
+    ctx.sync.unsubscribe(&key, WatchTopic::Record).await;
+    Ok(true)
 }
 
 /// Send encrypted message via private route
+/// Delayed and batched like any non-critical send when shaping is on
 pub async fn send_message_via_route(
+    ctx: &AppContext,
     route: String,
     encrypted_message: Vec<u8>,
-) -> Result<bool, String> {
-    let manager = VEILID.read().await;
-    manager
-        .send_via_private_route(&route, encrypted_message)
+) -> Result<bool, FfiError> {
+    send_message_with_urgency(ctx, route, encrypted_message, Urgency::Normal).await
+}
+
+/// Send encrypted message via private route; Critical sends skip the shaper
+pub async fn send_message_with_urgency(
+    ctx: &AppContext,
+    route: String,
+    encrypted_message: Vec<u8>,
+    urgency: Urgency,
+) -> Result<bool, FfiError> {
+    let manager = ctx.manager();
+    manager.send_shaped(&route, encrypted_message, urgency).await?;
+    Ok(true)
+}
+
+/// Send encrypted message with an explicit safety selection (hop count, sequencing)
+pub async fn send_message_with_safety(
+    ctx: &AppContext,
+    route: String,
+    encrypted_message: Vec<u8>,
+    safety: SafetyProfile,
+) -> Result<bool, FfiError> {
+    let manager = ctx.manager();
+    manager.send_with_safety(&route, encrypted_message, safety).await?;
+    Ok(true)
+}
+
+/// Send encrypted message through a chain of trusted relays
+pub async fn send_message_via_relay(
+    ctx: &AppContext,
+    hops: Vec<RelayHop>,
+    recipient: RelayHop,
+    encrypted_message: Vec<u8>,
+) -> Result<bool, FfiError> {
+    let manager = ctx.manager();
+    manager.send_via_relay(&hops, &recipient, encrypted_message).await?;
+    Ok(true)
+}
+
+/// Send a message that the recipient acknowledges; `paths` are tried in order
+/// until one is acknowledged, then ContactUnreachable is emitted
+/// Returns the message id reported in MessageAcknowledged
+/// `key` is the conversation key the message was sealed under; an ACK counts
+/// only if the recipient made it with the same key
+pub async fn send_message_tracked(
+    ctx: &AppContext,
+    contact_public_key: String,
+    paths: Vec<DeliveryPath>,
+    encrypted_message: Vec<u8>,
+    key: Vec<u8>,
+) -> Result<String, FfiError> {
+    ctx.manager()
+        .send_tracked(&contact_public_key, paths, encrypted_message, &key)
         .await
-        .map_err(|e| e.to_string())?;
+        .map_err(FfiError::from)
+}
+
+/// Deliver messages for a contact's route to their onion-service mailbox
+/// ("<name>.onion[:port]/<mailbox id>") whenever the Tor fallback is in use
+#[cfg(feature = "tor")]
+pub async fn set_onion_mailbox(ctx: &AppContext, route: String, address: String) -> Result<bool, FfiError> {
+    ctx.manager().tor().set_mailbox(&route, &address)?;
+    Ok(true)
+}
+
+/// Set the 32-byte key shared by nearby devices that seals every mesh frame
+pub async fn set_mesh_link_key(ctx: &AppContext, key: Vec<u8>) -> Result<bool, FfiError> {
+    ctx.manager().mesh().set_link_key(&key)?;
     Ok(true)
 }
 
+/// Report how many nearby devices the platform's radios are connected to
+/// Queued messages are offered to them at once while we are offline and stay
+/// queued until one acknowledges them
+pub async fn set_mesh_peer_count(ctx: &AppContext, peers: u32) -> Result<u32, FfiError> {
+    ctx.manager().mesh().set_peer_count(peers as usize);
+    if peers == 0 {
+        return Ok(0);
+    }
+    Ok(ctx.manager().flush_outbox().await? as u32)
+}
+
+/// Frames for the platform to send to nearby devices, oldest first
+pub async fn take_mesh_frames(ctx: &AppContext, limit: u32) -> Result<Vec<Vec<u8>>, FfiError> {
+    Ok(ctx.manager().mesh().take_outgoing(limit as usize))
+}
+
+/// Pass a frame heard from a nearby device to the core
+/// Acknowledgements it produces show up in the next take_mesh_frames
+pub async fn receive_mesh_frame(ctx: &AppContext, frame: Vec<u8>) -> Result<MeshFrameOutcome, FfiError> {
+    Ok(ctx.manager().handle_mesh_frame(&frame).await?)
+}
+
+/// Acknowledge a received message to the sender's route
+/// `key` is the conversation key the message was sealed under
+pub async fn acknowledge_message(
+    ctx: &AppContext,
+    reply_route: String,
+    message: Vec<u8>,
+    key: Vec<u8>,
+) -> Result<bool, FfiError> {
+    ctx.manager().send_ack(&reply_route, &message, &key).await?;
+    Ok(true)
+}
+
+/// Process a relay blob: forwards it onward, or returns the payload if addressed to us
+pub async fn handle_relay_message(ctx: &AppContext, key: Vec<u8>, blob: Vec<u8>) -> Result<Option<Vec<u8>>, FfiError> {
+    let manager = ctx.manager();
+    manager.handle_relay(&key, &blob).await.map_err(FfiError::from)
+}
+
+/// Package our route and mailbox key as a signed, QR-encodable blob
+pub async fn export_route_bundle(
+    secret_key: String,
+    route: String,
+    mailbox_key: String,
+) -> Result<Vec<u8>, FfiError> {
+    let secret = decode_typed_key(&secret_key)?;
+    let bundle = RouteBundle::create(&route, &mailbox_key, &secret)?;
+    Ok(bundle.encode())
+}
+
+/// Verify and unpack a route bundle scanned from another person
+pub async fn import_route_bundle(data: Vec<u8>) -> Result<RouteBundleData, FfiError> {
+    let bundle = RouteBundle::decode(&data)?;
+    Ok(route_bundle_data(bundle))
+}
+
+fn route_bundle_data(bundle: RouteBundle) -> RouteBundleData {
+    RouteBundleData {
+        public_key: format!("VLD1:pub:{}", hex::encode(bundle.public_key)),
+        route: bundle.route,
+        mailbox_key: bundle.mailbox_key,
+        created_at: bundle.created_at,
+    }
+}
+
+/// Start a rendezvous from a shared passphrase, publishing our route bundle
+/// Returns a session id for polling
+pub async fn start_rendezvous(
+    ctx: &AppContext,
+    passphrase: String,
+    secret_key: String,
+    route: String,
+    mailbox_key: String,
+) -> Result<String, FfiError> {
+    let secret = decode_typed_key(&secret_key)?;
+    let bundle = RouteBundle::create(&route, &mailbox_key, &secret)?;
+
+    let mut rendezvous = Rendezvous::from_passphrase(&passphrase)?;
+    let manager = ctx.manager();
+    rendezvous.publish(manager, &bundle).await?;
+
+    let session_id = hex::encode(generate_random_bytes(16));
+    ctx.rendezvous.lock().await.insert(session_id.clone(), rendezvous);
+    Ok(session_id)
+}
+
+/// Poll a rendezvous; returns the peer's verified route bundle once both sides confirmed
+pub async fn poll_rendezvous(ctx: &AppContext, session_id: String, secret_key: String) -> Result<Option<RouteBundleData>, FfiError> {
+    let secret = decode_typed_key(&secret_key)?;
+    let manager = ctx.manager();
+
+    let mut sessions = ctx.rendezvous.lock().await;
+    let rendezvous = sessions
+        .get_mut(&session_id)
+        .ok_or_else(|| FfiError::NotFound("Rendezvous session".to_string()))?;
+
+    match rendezvous.step(manager, &secret).await? {
+        RendezvousStatus::Complete(peer) => {
+            sessions.remove(&session_id);
+            Ok(Some(route_bundle_data(peer)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Abandon a rendezvous and remove its records
+pub async fn cancel_rendezvous(ctx: &AppContext, session_id: String) -> Result<bool, FfiError> {
+    let manager = ctx.manager();
+    if let Some(rendezvous) = ctx.rendezvous.lock().await.remove(&session_id) {
+        rendezvous.close(manager).await?;
+    }
+    Ok(true)
+}
+
+/// List all personas on this device
+pub async fn list_personas(ctx: &AppContext) -> Result<Vec<Persona>, FfiError> {
+    Ok(ctx.personas.read().await.list().to_vec())
+}
+
+/// Get the persona that subsequent calls act as
+pub async fn get_active_persona(ctx: &AppContext) -> Result<Option<Persona>, FfiError> {
+    Ok(ctx.personas.read().await.active().cloned())
+}
+
+/// Create a persona with its own identity, route and mailbox
+/// The secret key is only returned here; store it in platform secure storage
+pub async fn create_persona(ctx: &AppContext, name: String) -> Result<NewPersonaData, FfiError> {
+    let identity = ctx.manager().create_identity().await?;
+    let persona = Persona {
+        id: hex::encode(generate_random_bytes(16)),
+        name: name.trim().to_string(),
+        public_key: identity.public_key,
+        mailbox_key: identity.dht_key,
+        route: identity.route,
+        created_at: crate::util::unix_now(),
+    };
+
+    let mut journal = ctx.journal.lock().await;
+    let intent = journal.begin(
+        Intent::CreatePersona {
+            persona_id: persona.id.clone(),
+            mailbox_key: persona.mailbox_key.clone(),
+            route: persona.route.clone(),
+        },
+        ctx.config_dir(),
+    )?;
+    let first = match add_persona(ctx, &persona).await {
+        Ok(first) => first,
+        Err(e) => {
+            // Undo now; if that fails too, the open intent is retried at the next start
+            let undone = ctx
+                .remove_persona(&persona.id, &persona.mailbox_key, &persona.route)
+                .await
+                .and_then(|()| journal.finish(intent, ctx.config_dir()));
+            if let Err(undo) = undone {
+                tracing::warn!("Could not roll back persona creation: {}", undo);
+            }
+            return Err(e.into());
+        }
+    };
+    journal.finish(intent, ctx.config_dir())?;
+    ctx.sync.watch_mailbox(&persona.mailbox_key).await;
+    if first {
+        ctx.events().publish(CoreEvent::ActivePersonaChanged {
+            persona_id: Some(persona.id.clone()),
+        });
+    }
+
+    Ok(NewPersonaData {
+        persona,
+        secret_key: identity.secret_key,
+    })
+}
+
+/// Save a new persona and publish its mailbox; true if it is the first one
+async fn add_persona(ctx: &AppContext, persona: &Persona) -> Result<bool, UndergroundError> {
+    let mut personas = ctx.personas.write().await;
+    let first = personas.active().is_none();
+    personas.add(persona.clone())?;
+    ctx.record_keeper
+        .publish(&persona.mailbox_key, RecordKind::Mailbox, MAILBOX_TTL_SECS, Vec::new())
+        .await?;
+    personas.save(ctx.config_dir())?;
+    Ok(first)
+}
+
+/// Make another persona the active one
+pub async fn switch_active_persona(ctx: &AppContext, persona_id: String) -> Result<bool, FfiError> {
+    let mut personas = ctx.personas.write().await;
+    personas.switch(&persona_id)?;
+    personas.save(ctx.config_dir())?;
+    ctx.events().publish(CoreEvent::ActivePersonaChanged {
+        persona_id: Some(persona_id),
+    });
+    Ok(true)
+}
+
+/// Delete a persona and tear down its mailbox and route
+pub async fn delete_persona(ctx: &AppContext, persona_id: String) -> Result<bool, FfiError> {
+    let persona = ctx
+        .personas
+        .read()
+        .await
+        .get(&persona_id)
+        .cloned()
+        .ok_or_else(|| FfiError::NotFound("Persona".to_string()))?;
+
+    let mut journal = ctx.journal.lock().await;
+    let intent = journal.begin(
+        Intent::DeletePersona {
+            persona_id: persona.id.clone(),
+            mailbox_key: persona.mailbox_key.clone(),
+            route: persona.route.clone(),
+        },
+        ctx.config_dir(),
+    )?;
+    ctx.remove_persona(&persona.id, &persona.mailbox_key, &persona.route).await?;
+    journal.finish(intent, ctx.config_dir())?;
+    Ok(true)
+}
+
+/// Package the active persona's route and mailbox key as a signed route bundle
+pub async fn export_active_persona_bundle(ctx: &AppContext, secret_key: String) -> Result<Vec<u8>, FfiError> {
+    Ok(active_persona_bundle(ctx, &secret_key).await?.encode())
+}
+
+/// Generate the QR text for the active persona's signed contact card
+pub async fn generate_my_contact_qr(ctx: &AppContext, secret_key: String) -> Result<String, FfiError> {
+    Ok(encode_qr(&active_persona_bundle(ctx, &secret_key).await?)?)
+}
+
+/// Generate QR text for the active persona's card with an introducer chain
+/// `vouches` run from the first introducer down to a vouch for this persona
+pub async fn generate_my_introduced_contact_qr(
+    ctx: &AppContext,
+    secret_key: String,
+    vouches: Vec<Vec<u8>>,
+) -> Result<String, FfiError> {
+    let chain = vouches
+        .iter()
+        .map(|v| Vouch::decode(v))
+        .collect::<crate::error::Result<Vec<_>>>()?;
+    let card = ContactCard::new(active_persona_bundle(ctx, &secret_key).await?, chain)?;
+    Ok(encode_card_qr(&card)?)
+}
+
+/// Verify a scanned contact QR code and return the contact with its fingerprint
+pub async fn parse_contact_qr(ctx: &AppContext, data: Vec<u8>) -> Result<ContactQrData, FfiError> {
+    let text = String::from_utf8(data).map_err(|_| FfiError::InvalidInput("Not a contact QR code".to_string()))?;
+    let card = decode_qr(&text)?;
+    if ctx.manager().is_key_blocked(&card.bundle.public_key).await {
+        return Err(FfiError::Blocked);
+    }
+
+    Ok(ContactQrData {
+        fingerprint: fingerprint(&card.bundle.public_key),
+        introduced_by: card
+            .chain
+            .iter()
+            .map(|v| format!("VLD1:pub:{}", hex::encode(v.voucher_key)))
+            .collect(),
+        contact: route_bundle_data(card.bundle),
+    })
+}
+
+async fn active_persona_bundle(ctx: &AppContext, secret_key: &str) -> Result<RouteBundle, FfiError> {
+    let personas = ctx.personas.read().await;
+    let persona = personas
+        .active()
+        .ok_or_else(|| FfiError::NotFound("Active persona".to_string()))?;
+
+    let secret = decode_typed_key(secret_key)?;
+    if format!("VLD1:pub:{}", hex::encode(signing_public_key(&secret)?)) != persona.public_key {
+        return Err(FfiError::InvalidKey);
+    }
+
+    Ok(RouteBundle::create(&persona.route, &persona.mailbox_key, &secret)?)
+}
+
+/// Sign a vouch that we verified the subject (e.g. in person) at `verified_at`
+pub async fn create_vouch(
+    secret_key: String,
+    subject_public_key: String,
+    method: VerificationMethod,
+    verified_at: u64,
+) -> Result<Vec<u8>, FfiError> {
+    let secret = decode_typed_key(&secret_key)?;
+    let subject = public_key_bytes(&subject_public_key)?;
+    let vouch = Vouch::create(&secret, subject, method, verified_at)?;
+    Ok(vouch.encode())
+}
+
+/// Verify a forwarded vouch against the voucher's pinned public key
+/// Returns the provenance to record as evidence for the subject
+pub async fn verify_vouch(data: Vec<u8>, pinned_voucher_key: String) -> Result<VouchData, FfiError> {
+    let pinned = public_key_bytes(&pinned_voucher_key)?;
+    let vouch = Vouch::decode(&data)?;
+    vouch.verify(&pinned)?;
+
+    Ok(VouchData {
+        voucher_public_key: format!("VLD1:pub:{}", hex::encode(vouch.voucher_key)),
+        subject_public_key: format!("VLD1:pub:{}", hex::encode(vouch.subject_key)),
+        method: vouch.method,
+        verified_at: vouch.verified_at,
+        issued_at: vouch.issued_at,
+    })
+}
+
+fn public_key_bytes(key: &str) -> Result<[u8; 32], FfiError> {
+    decode_typed_key(key)?
+        .try_into()
+        .map_err(|_| FfiError::InvalidKey)
+}
+
+/// Introduce the owner of a route bundle (contact card) to another contact
+/// Call once per direction and send each result to its recipient
+pub async fn create_introduction(
+    secret_key: String,
+    card: Vec<u8>,
+    recipient_public_key: String,
+) -> Result<Vec<u8>, FfiError> {
+    let secret = decode_typed_key(&secret_key)?;
+    let recipient = public_key_bytes(&recipient_public_key)?;
+    let card = RouteBundle::decode(&card)?;
+    let intro = Introduction::create(&secret, &card, recipient)?;
+    Ok(intro.encode())
+}
+
+/// Verify an introduction from a contact whose key we pinned
+/// Returns the new contact to store as introduced by that contact
+pub async fn accept_introduction(
+    ctx: &AppContext,
+    data: Vec<u8>,
+    pinned_introducer_key: String,
+    own_public_key: String,
+) -> Result<IntroducedContactData, FfiError> {
+    let pinned = public_key_bytes(&pinned_introducer_key)?;
+    let own = public_key_bytes(&own_public_key)?;
+    let intro = Introduction::decode(&data)?;
+    let contact = intro.accept(&pinned, &own)?;
+
+    let manager = ctx.manager();
+    if manager.is_key_blocked(&contact.card.public_key).await {
+        return Err(FfiError::Blocked);
+    }
+
+    Ok(IntroducedContactData {
+        contact: route_bundle_data(contact.card),
+        introduced_by: format!("VLD1:pub:{}", hex::encode(contact.introduced_by)),
+    })
+}
+
+/// Issue a signed burn notice against a compromised contact
+pub async fn create_burn_notice(
+    secret_key: String,
+    subject_public_key: String,
+    reason: String,
+    max_hops: u8,
+) -> Result<Vec<u8>, FfiError> {
+    let secret = decode_typed_key(&secret_key)?;
+    let subject = public_key_bytes(&subject_public_key)?;
+    let notice = BurnNotice::create(&secret, subject, &reason, max_hops)?;
+    Ok(notice.encode())
+}
+
+/// Split our identity key among trustees, `threshold` of whom can act on it
+pub async fn create_key_escrow(
+    secret_key: String,
+    trustee_public_keys: Vec<String>,
+    threshold: u8,
+) -> Result<KeyEscrowData, FfiError> {
+    let secret = decode_typed_key(&secret_key)?;
+    let trustees = trustee_public_keys
+        .iter()
+        .map(|key| public_key_bytes(key))
+        .collect::<Result<Vec<_>, _>>()?;
+    let (policy, shares) = escrow::create_escrow(&secret, trustees, threshold)?;
+    Ok(KeyEscrowData {
+        policy: policy.encode(),
+        shares: trustee_public_keys
+            .into_iter()
+            .zip(shares)
+            .map(|(trustee_public_key, share)| EscrowShareData {
+                trustee_public_key,
+                share: share.encode(),
+            })
+            .collect(),
+    })
+}
+
+/// Ask the trustees of an escrow to approve recovering or revoking the key
+/// Returns the request to hand to each trustee; it expires after `ttl_secs`
+pub async fn request_key_escrow_action(
+    policy: Vec<u8>,
+    action: EscrowAction,
+    ttl_secs: u64,
+) -> Result<Vec<u8>, FfiError> {
+    let policy = EscrowPolicy::decode(&policy)?;
+    Ok(EscrowRequest::new(&policy, action, ttl_secs)?.encode())
+}
+
+/// Sign our approval, as a trustee, of one request on an escrowed key
+pub async fn approve_key_escrow(
+    trustee_secret_key: String,
+    policy: Vec<u8>,
+    request: Vec<u8>,
+) -> Result<Vec<u8>, FfiError> {
+    let secret = decode_typed_key(&trustee_secret_key)?;
+    let policy = EscrowPolicy::decode(&policy)?;
+    let request = EscrowRequest::decode(&request)?;
+    Ok(EscrowApproval::create(&secret, &policy, &request)?.encode())
+}
+
+/// Rebuild an escrowed key from trustee approvals of a recovery request and their shares
+pub async fn recover_escrowed_key(
+    policy: Vec<u8>,
+    request: Vec<u8>,
+    approvals: Vec<Vec<u8>>,
+    shares: Vec<Vec<u8>>,
+) -> Result<String, FfiError> {
+    let secret = rebuild_escrowed_key(&policy, &request, &approvals, &shares, EscrowAction::Recover)?;
+    Ok(format!("VLD1:sec:{}", hex::encode(secret.as_slice())))
+}
+
+/// Sign a burn notice against an escrowed key on its owner's behalf
+/// The rebuilt key signs the notice and is discarded
+pub async fn revoke_escrowed_key(
+    policy: Vec<u8>,
+    request: Vec<u8>,
+    approvals: Vec<Vec<u8>>,
+    shares: Vec<Vec<u8>>,
+    reason: String,
+    max_hops: u8,
+) -> Result<Vec<u8>, FfiError> {
+    let secret = rebuild_escrowed_key(&policy, &request, &approvals, &shares, EscrowAction::Revoke)?;
+    let owner = signing_public_key(secret.as_slice())?;
+    Ok(BurnNotice::create(secret.as_slice(), owner, &reason, max_hops)?.encode())
+}
+
+fn rebuild_escrowed_key(
+    policy: &[u8],
+    request: &[u8],
+    approvals: &[Vec<u8>],
+    shares: &[Vec<u8>],
+    action: EscrowAction,
+) -> Result<crate::crypto::SecureBuffer, FfiError> {
+    let policy = EscrowPolicy::decode(policy)?;
+    let request = EscrowRequest::decode(request)?;
+    if request.action != action {
+        return Err(crate::error::UndergroundError::InvalidMessage("Request is for another action".to_string()).into());
+    }
+    let approvals = approvals
+        .iter()
+        .map(|a| EscrowApproval::decode(a))
+        .collect::<crate::error::Result<Vec<_>>>()?;
+    let shares = shares
+        .iter()
+        .map(|s| EscrowShare::decode(s))
+        .collect::<crate::error::Result<Vec<_>>>()?;
+    Ok(escrow::recover_key(&policy, &request, &approvals, &shares)?)
+}
+
+/// Verify and record a burn notice handed to us by a contact, who may have
+/// issued it or be forwarding it from someone further along the trust graph
+/// The signature is checked against the issuer key in the notice
+/// Returns None for duplicates; otherwise the notice and, while its signed
+/// forwarding window is open, the blob to forward to our own contacts
+pub async fn receive_burn_notice(ctx: &AppContext, data: Vec<u8>) -> Result<Option<BurnNoticeData>, FfiError> {
+    let now = crate::util::unix_now();
+    let notice = BurnNotice::decode(&data)?;
+    notice.verify(now)?;
+
+    let mut revocations = ctx.revocations.write().await;
+    if !revocations.accept(&notice) {
+        return Ok(None);
+    }
+    revocations.save(ctx.config_dir())?;
+
+    // Stop talking to the revoked identity
+    let manager = ctx.manager();
+    manager.block_key(notice.subject_key).await?;
+
+    let issuer_public_key = format!("VLD1:pub:{}", hex::encode(notice.issuer_key));
+    let subject_public_key = format!("VLD1:pub:{}", hex::encode(notice.subject_key));
+    ctx.events().publish(CoreEvent::TrustRevoked {
+        subject_public_key: subject_public_key.clone(),
+        issuer_public_key: issuer_public_key.clone(),
+    });
+
+    Ok(Some(BurnNoticeData {
+        issuer_public_key,
+        subject_public_key,
+        reason: notice.reason.clone(),
+        issued_at: notice.issued_at,
+        forward: notice.should_forward(now).then_some(data),
+    }))
+}
+
+/// Check whether a public key has been revoked by someone we trust
+pub async fn is_key_revoked(ctx: &AppContext, public_key: String) -> Result<bool, FfiError> {
+    let key = public_key_bytes(&public_key)?;
+    Ok(ctx.revocations.read().await.is_revoked(&key))
+}
+
+/// Block a contact's identity key
+pub async fn block_contact_key(ctx: &AppContext, public_key: String) -> Result<bool, FfiError> {
+    let key = public_key_bytes(&public_key)?;
+    let manager = ctx.manager();
+    manager.block_key(key).await?;
+    ctx.events().publish(CoreEvent::ContactBlocked { public_key });
+    Ok(true)
+}
+
+/// Unblock a contact's identity key
+pub async fn unblock_contact_key(ctx: &AppContext, public_key: String) -> Result<bool, FfiError> {
+    let key = public_key_bytes(&public_key)?;
+    let manager = ctx.manager();
+    manager.unblock_key(&key).await?;
+    Ok(true)
+}
+
+/// Block a route so nothing is sent or relayed to it
+pub async fn block_route(ctx: &AppContext, route: String) -> Result<bool, FfiError> {
+    let manager = ctx.manager();
+    manager.block_route(&route).await?;
+    Ok(true)
+}
+
+/// Check whether a contact's identity key is blocked
+pub async fn is_contact_blocked(ctx: &AppContext, public_key: String) -> Result<bool, FfiError> {
+    let key = public_key_bytes(&public_key)?;
+    let manager = ctx.manager();
+    Ok(manager.is_key_blocked(&key).await)
+}
+
 /// Derive encryption key from password and salt
-pub async fn derive_encryption_key(password: String, salt: Vec<u8>) -> Result<Vec<u8>, String> {
-    let key = derive_key(&password, &salt).map_err(|e| e.to_string())?;
+pub async fn derive_encryption_key(password: String, salt: Vec<u8>) -> Result<Vec<u8>, FfiError> {
+    let key = derive_key(&password, &salt)?;
+    Ok(key.as_slice().to_vec())
+}
+
+/// Derive the profile storage key with the configured Argon2id parameters
+pub async fn derive_profile_key(ctx: &AppContext, password: String, salt: Vec<u8>) -> Result<Vec<u8>, FfiError> {
+    let kdf = ctx.config().await.kdf;
+    let key = derive_key_with_params(&password, &salt, &kdf)?;
     Ok(key.as_slice().to_vec())
 }
 
+/// Wrap the derived storage key for storage at rest (Secure Enclave on iOS)
+pub async fn wrap_storage_key(key: Vec<u8>) -> Result<WrappedKeyData, FfiError> {
+    let (blob, protection) = key_wrap::wrap_storage_key(&key)?;
+    Ok(WrappedKeyData { blob, protection })
+}
+
+/// Unwrap a stored storage key; re-wrap and replace it when `needs_migration` is set
+pub async fn unwrap_storage_key(blob: Vec<u8>) -> Result<UnwrappedKeyData, FfiError> {
+    let unwrapped = key_wrap::unwrap_storage_key(&blob)?;
+    Ok(UnwrappedKeyData {
+        key: unwrapped.key.as_slice().to_vec(),
+        protection: unwrapped.protection,
+        needs_migration: unwrapped.needs_migration,
+    })
+}
+
 /// Generate random salt for key derivation
-pub async fn generate_key_salt() -> Result<Vec<u8>, String> {
+pub async fn generate_key_salt() -> Result<Vec<u8>, FfiError> {
     Ok(generate_salt().to_vec())
 }
 
 /// Generate random bytes
-pub async fn generate_secure_random(length: usize) -> Result<Vec<u8>, String> {
+pub async fn generate_secure_random(length: usize) -> Result<Vec<u8>, FfiError> {
     Ok(generate_random_bytes(length))
 }
 
 /// Encrypt data with ChaCha20-Poly1305
-pub async fn encrypt_bytes(key: Vec<u8>, plaintext: Vec<u8>) -> Result<Vec<u8>, String> {
-    encrypt_data(&key, &plaintext).map_err(|e| e.to_string())
+pub async fn encrypt_bytes(key: Vec<u8>, plaintext: Vec<u8>) -> Result<Vec<u8>, FfiError> {
+    encrypt_data(&key, &plaintext).map_err(FfiError::from)
 }
 
 /// Decrypt data with ChaCha20-Poly1305
-pub async fn decrypt_bytes(key: Vec<u8>, ciphertext: Vec<u8>) -> Result<Vec<u8>, String> {
-    decrypt_data(&key, &ciphertext).map_err(|e| e.to_string())
+pub async fn decrypt_bytes(key: Vec<u8>, ciphertext: Vec<u8>) -> Result<Vec<u8>, FfiError> {
+    decrypt_data(&key, &ciphertext).map_err(FfiError::from)
+}
+
+async fn active_persona_handle(ctx: &AppContext) -> Result<PersonaHandle, FfiError> {
+    ctx.personas
+        .read()
+        .await
+        .active_handle()
+        .ok_or_else(|| FfiError::NotFound("Active persona".to_string()))
+}
+
+/// Encrypt a message to a contact, compressing it first if the configuration
+/// allows for its kind
+/// Refused while the contact's pinned keys are in question
+pub async fn seal_message(
+    ctx: &AppContext,
+    contact_public_key: String,
+    key: Vec<u8>,
+    kind: MessageKind,
+    plaintext: Vec<u8>,
+) -> Result<Vec<u8>, FfiError> {
+    let contact_public_key = contact_id(&contact_public_key)?;
+    let config = ctx.config().await;
+    let persona = active_persona_handle(ctx).await?;
+    ensure_contact_sendable(ctx, &persona, &contact_public_key, config.pinning.policy).await?;
+
+    let mut replay = ctx.replay.write().await;
+    let counter = replay.get_mut(&persona).next_counter(&contact_public_key);
+    // Persist before sending so a counter is never reused after a crash
+    crate::replay::save(&replay, ctx.config_dir())?;
+    message_codec::seal(&key, kind, counter, &plaintext, &config.compression).map_err(FfiError::from)
+}
+
+async fn ensure_contact_sendable(
+    ctx: &AppContext,
+    persona: &PersonaHandle,
+    contact_public_key: &str,
+    policy: PinPolicy,
+) -> Result<(), FfiError> {
+    let pins = ctx.pins.read().await;
+    let Some(contact_pins) = pins.get(persona) else {
+        return pin_required(policy);
+    };
+    if contact_pins.has_violation(contact_public_key) {
+        ctx.events().publish(CoreEvent::KeyPinViolation {
+            contact: contact_public_key.to_string(),
+        });
+        return Err(UndergroundError::KeyPinViolation(contact_public_key.to_string()).into());
+    }
+    if !contact_pins.is_pinned(contact_public_key) {
+        return pin_required(policy);
+    }
+    Ok(())
+}
+
+fn pin_required(policy: PinPolicy) -> Result<(), FfiError> {
+    if policy == PinPolicy::Manual {
+        return Err(FfiError::InvalidInput("Pin this contact's keys before sending".to_string()));
+    }
+    Ok(())
+}
+
+fn contact_keys(signing_public_key: &str, encryption_public_key: &str) -> Result<ContactKeys, FfiError> {
+    Ok(ContactKeys {
+        signing: public_key_bytes(signing_public_key)?,
+        encryption: public_key_bytes(encryption_public_key)?,
+    })
+}
+
+/// Check the public keys a contact presented (e.g. in a scanned card) against
+/// their pin, pinning them on first use, and remember `route` as theirs
+/// While a key change is unresolved every send to the contact's routes is refused
+pub async fn check_contact_keys(
+    ctx: &AppContext,
+    contact_public_key: String,
+    signing_public_key: String,
+    encryption_public_key: String,
+    route: String,
+) -> Result<(), FfiError> {
+    let contact_public_key = contact_id(&contact_public_key)?;
+    let policy = ctx.config().await.pinning.policy;
+    let persona = active_persona_handle(ctx).await?;
+    let keys = contact_keys(&signing_public_key, &encryption_public_key)?;
+
+    let mut pins = ctx.pins.write().await;
+    let contact_pins = pins.get_mut(&persona);
+    let status = contact_pins.check(&contact_public_key, &keys, policy, crate::util::unix_now());
+    contact_pins.add_route(&contact_public_key, &route);
+    crate::pinning::save(&pins, ctx.config_dir())?;
+    drop(pins);
+    ctx.refresh_pin_holds().await;
+
+    match status {
+        PinStatus::Matches | PinStatus::NewlyPinned => Ok(()),
+        PinStatus::Unpinned => pin_required(policy),
+        PinStatus::Violation => {
+            ctx.events().publish(CoreEvent::KeyPinViolation {
+                contact: contact_public_key.clone(),
+            });
+            Err(UndergroundError::KeyPinViolation(contact_public_key).into())
+        }
+    }
+}
+
+/// Pin a contact's signing and encryption public keys for the active persona,
+/// replacing any pin
+/// Needed before the first send under the manual pinning policy
+pub async fn pin_contact_keys(
+    ctx: &AppContext,
+    contact_public_key: String,
+    signing_public_key: String,
+    encryption_public_key: String,
+) -> Result<(), FfiError> {
+    let contact_public_key = contact_id(&contact_public_key)?;
+    let keys = contact_keys(&signing_public_key, &encryption_public_key)?;
+    let persona = active_persona_handle(ctx).await?;
+    let mut pins = ctx.pins.write().await;
+    if pins.get_mut(&persona).has_violation(&contact_public_key) {
+        return Err(FfiError::InvalidInput("Resolve the key change first".to_string()));
+    }
+    pins.get_mut(&persona).pin(&contact_public_key, &keys, crate::util::unix_now());
+    crate::pinning::save(&pins, ctx.config_dir())?;
+    Ok(())
+}
+
+/// Settle a contact's key change by accepting the new keys or keeping the pinned ones
+pub async fn resolve_contact_key_change(
+    ctx: &AppContext,
+    contact_public_key: String,
+    accept_new_key: bool,
+) -> Result<(), FfiError> {
+    let contact_public_key = contact_id(&contact_public_key)?;
+    let policy = ctx.config().await.pinning.policy;
+    let persona = active_persona_handle(ctx).await?;
+    let mut pins = ctx.pins.write().await;
+    pins.get_mut(&persona)
+        .resolve(&contact_public_key, accept_new_key, policy, crate::util::unix_now())?;
+    crate::pinning::save(&pins, ctx.config_dir())?;
+    drop(pins);
+    ctx.refresh_pin_holds().await;
+    Ok(())
+}
+
+/// Remove a contact's key pin; the next keys seen are pinned afresh
+pub async fn forget_contact_key_pin(ctx: &AppContext, contact_public_key: String) -> Result<bool, FfiError> {
+    let contact_public_key = contact_id(&contact_public_key)?;
+    let persona = active_persona_handle(ctx).await?;
+    let mut pins = ctx.pins.write().await;
+    let removed = pins.get_mut(&persona).forget(&contact_public_key);
+    crate::pinning::save(&pins, ctx.config_dir())?;
+    drop(pins);
+    ctx.refresh_pin_holds().await;
+    Ok(removed)
+}
+
+/// Contacts of the active persona with pinned keys, and the routes learned for them
+pub async fn list_contacts(ctx: &AppContext) -> Result<Vec<PinnedContact>, FfiError> {
+    let persona = active_persona_handle(ctx).await?;
+    Ok(ctx.pins.read().await.get(&persona).map(|pins| pins.contacts()).unwrap_or_default())
+}
+
+/// Choose what of a contact's pin may go into contact bundles; false if the contact has no pin
+pub async fn set_contact_sharing(
+    ctx: &AppContext,
+    contact_public_key: String,
+    share: SharePolicy,
+) -> Result<bool, FfiError> {
+    let contact_public_key = contact_id(&contact_public_key)?;
+    let persona = active_persona_handle(ctx).await?;
+    let mut pins = ctx.pins.write().await;
+    let found = pins.get_mut(&persona).set_share(&contact_public_key, share);
+    crate::pinning::save(&pins, ctx.config_dir())?;
+    Ok(found)
+}
+
+/// Encrypt the selected contacts into a bundle for seeding another device
+/// Every contact must be marked shareable and have no unresolved key change
+pub async fn export_contacts(
+    ctx: &AppContext,
+    contact_public_keys: Vec<String>,
+    password: String,
+) -> Result<Vec<u8>, FfiError> {
+    let persona = active_persona_handle(ctx).await?;
+    let contact_pins = ctx.pins.read().await.get(&persona).cloned().unwrap_or_default();
+    let shared = contact_public_keys
+        .iter()
+        .map(|contact| contact_pins.shared(&contact_id(contact)?))
+        .collect::<crate::error::Result<Vec<_>>>()?;
+    Ok(crate::contact_bundle::seal(&shared, &password)?)
+}
+
+/// Pin the contacts in a bundle under the active persona
+/// Contacts already pinned to different keys are held as key changes
+pub async fn import_contacts(ctx: &AppContext, bundle: Vec<u8>, password: String) -> Result<ImportedPins, FfiError> {
+    let mut shared = crate::contact_bundle::open(&bundle, &password)?;
+    for pin in &mut shared {
+        pin.contact = contact_id(&pin.contact)?;
+    }
+    let persona = active_persona_handle(ctx).await?;
+    let mut pins = ctx.pins.write().await;
+    let imported = pins.get_mut(&persona).import_shared(shared, crate::util::unix_now());
+    crate::pinning::save(&pins, ctx.config_dir())?;
+    drop(pins);
+    ctx.refresh_pin_holds().await;
+    Ok(imported)
+}
+
+/// Ask the owner of `route` to become a contact, sending the active persona's
+/// card, its encryption public key and a short note
+/// The answer arrives as a message for receive_connection_message
+pub async fn send_connection_request(
+    ctx: &AppContext,
+    secret_key: String,
+    encryption_public_key: String,
+    route: String,
+    note: String,
+) -> Result<(), FfiError> {
+    let secret = decode_typed_key(&secret_key)?;
+    let card = active_persona_bundle(ctx, &secret_key).await?;
+    let request = ConnectionRequest::create(&secret, card, public_key_bytes(&encryption_public_key)?, &note)?;
+
+    let persona = active_persona_handle(ctx).await?;
+    let mut connections = ctx.connections.write().await;
+    connections.get_mut(&persona).record_sent(&request.id, crate::util::unix_now());
+    connection::save(&connections, ctx.config_dir())?;
+    drop(connections);
+
+    send_message_via_route(ctx, route, request.encode()).await?;
+    Ok(())
+}
+
+/// Take in a connection request, or an answer to one we sent
+/// Requests wait for approve_connection_request or reject_connection_request;
+/// an approval of ours pins the responder's keys and route
+pub async fn receive_connection_message(ctx: &AppContext, data: Vec<u8>) -> Result<ConnectionUpdate, FfiError> {
+    let persona = active_persona_handle(ctx).await?;
+    match ConnectionMessage::decode(&data)? {
+        ConnectionMessage::Request(request) => {
+            if ctx.manager().is_key_blocked(&request.card.public_key).await {
+                return Err(FfiError::Blocked);
+            }
+            let contact = format!("VLD1:pub:{}", hex::encode(request.card.public_key));
+            if ctx.pins.read().await.get(&persona).is_some_and(|pins| pins.is_pinned(&contact)) {
+                return Err(FfiError::InvalidInput("Already a contact".to_string()));
+            }
+            let mut connections = ctx.connections.write().await;
+            let pending = connections.get_mut(&persona).receive(&request, crate::util::unix_now());
+            connection::save(&connections, ctx.config_dir())?;
+            Ok(ConnectionUpdate::Requested(pending))
+        }
+        ConnectionMessage::Response(response) => {
+            let mut connections = ctx.connections.write().await;
+            if !connections.get_mut(&persona).take_sent(&response.request_id) {
+                return Err(FfiError::InvalidInput("Not an answer to one of our requests".to_string()));
+            }
+            connection::save(&connections, ctx.config_dir())?;
+            drop(connections);
+
+            let contact = format!("VLD1:pub:{}", hex::encode(response.responder_key));
+            match response.acceptance {
+                Some(acceptance) => {
+                    let (card, encryption) = (acceptance.card, acceptance.encryption_key);
+                    pin_connected_contact(ctx, &persona, card.public_key, encryption, &card.route).await?;
+                    Ok(ConnectionUpdate::Accepted { contact })
+                }
+                None => Ok(ConnectionUpdate::Rejected { contact }),
+            }
+        }
+    }
+}
+
+/// Connection requests of the active persona waiting for a decision, oldest first
+pub async fn list_connection_requests(ctx: &AppContext) -> Result<Vec<PendingConnection>, FfiError> {
+    let persona = active_persona_handle(ctx).await?;
+    let connections = ctx.connections.read().await;
+    Ok(connections.get(&persona).map(|c| c.pending()).unwrap_or_default())
+}
+
+/// Approve a pending request: pin the sender's keys and route, then answer with
+/// the active persona's card and encryption public key
+pub async fn approve_connection_request(
+    ctx: &AppContext,
+    contact_public_key: String,
+    secret_key: String,
+    encryption_public_key: String,
+) -> Result<(), FfiError> {
+    let contact_public_key = contact_id(&contact_public_key)?;
+    let secret = decode_typed_key(&secret_key)?;
+    let acceptance = ConnectionAcceptance {
+        card: active_persona_bundle(ctx, &secret_key).await?,
+        encryption_key: public_key_bytes(&encryption_public_key)?,
+    };
+    let persona = active_persona_handle(ctx).await?;
+    let pending = pending_connection(ctx, &persona, &contact_public_key).await?;
+    let response = ConnectionResponse::create(&secret, request_id(&pending)?, Some(acceptance))?;
+
+    let (signing, encryption) = (public_key_bytes(&pending.contact)?, public_key_bytes(&pending.encryption_key)?);
+    pin_connected_contact(ctx, &persona, signing, encryption, &pending.route).await?;
+    send_message_via_route(ctx, pending.route, response.encode()).await?;
+    settle_connection(ctx, &persona, &contact_public_key).await
+}
+
+/// Reject a pending request and tell the sender
+pub async fn reject_connection_request(
+    ctx: &AppContext,
+    contact_public_key: String,
+    secret_key: String,
+) -> Result<(), FfiError> {
+    let contact_public_key = contact_id(&contact_public_key)?;
+    let secret = decode_typed_key(&secret_key)?;
+    let persona = active_persona_handle(ctx).await?;
+    let pending = pending_connection(ctx, &persona, &contact_public_key).await?;
+    let response = ConnectionResponse::create(&secret, request_id(&pending)?, None)?;
+    send_message_via_route(ctx, pending.route, response.encode()).await?;
+    settle_connection(ctx, &persona, &contact_public_key).await
+}
+
+async fn pending_connection(
+    ctx: &AppContext,
+    persona: &PersonaHandle,
+    contact: &str,
+) -> Result<PendingConnection, FfiError> {
+    let connections = ctx.connections.read().await;
+    connections
+        .get(persona)
+        .and_then(|c| c.pending().into_iter().find(|p| p.contact == contact))
+        .ok_or_else(|| FfiError::NotFound("Connection request".to_string()))
+}
+
+async fn settle_connection(ctx: &AppContext, persona: &PersonaHandle, contact: &str) -> Result<(), FfiError> {
+    let mut connections = ctx.connections.write().await;
+    connections.get_mut(persona).take(contact);
+    connection::save(&connections, ctx.config_dir())?;
+    Ok(())
+}
+
+fn request_id(pending: &PendingConnection) -> Result<[u8; 16], FfiError> {
+    hex::decode(&pending.request_id)
+        .ok()
+        .and_then(|id| id.try_into().ok())
+        .ok_or_else(|| FfiError::InvalidInput("Damaged connection request".to_string()))
+}
+
+/// Pin a contact the user chose to connect with and remember its route
+/// Choosing is explicit, so the keys are pinned even under the manual policy
+async fn pin_connected_contact(
+    ctx: &AppContext,
+    persona: &PersonaHandle,
+    signing: [u8; 32],
+    encryption: [u8; 32],
+    route: &str,
+) -> Result<(), FfiError> {
+    if ctx.manager().is_key_blocked(&signing).await {
+        return Err(FfiError::Blocked);
+    }
+    let contact = format!("VLD1:pub:{}", hex::encode(signing));
+    let keys = ContactKeys { signing, encryption };
+    let mut pins = ctx.pins.write().await;
+    let contact_pins = pins.get_mut(persona);
+    let status = contact_pins.check(&contact, &keys, PinPolicy::TrustOnFirstUse, crate::util::unix_now());
+    contact_pins.add_route(&contact, route);
+    crate::pinning::save(&pins, ctx.config_dir())?;
+    drop(pins);
+
+    if status == PinStatus::Violation {
+        ctx.refresh_pin_holds().await;
+        ctx.events().publish(CoreEvent::KeyPinViolation {
+            contact: contact.clone(),
+        });
+        return Err(UndergroundError::KeyPinViolation(contact).into());
+    }
+    Ok(())
+}
+
+/// Decrypt a message from a contact, rejecting replays
+pub async fn open_message(
+    ctx: &AppContext,
+    contact_public_key: String,
+    key: Vec<u8>,
+    sealed: Vec<u8>,
+) -> Result<OpenedMessage, FfiError> {
+    let contact_public_key = contact_id(&contact_public_key)?;
+    let frame = message_codec::open(&key, &sealed)?;
+    let persona = active_persona_handle(ctx).await?;
+    let mut replay = ctx.replay.write().await;
+    if !replay
+        .get_mut(&persona)
+        .accept(&contact_public_key, frame.counter, crate::util::unix_now())
+    {
+        return Err(FfiError::InvalidInput("Message was already received".to_string()));
+    }
+    crate::replay::save(&replay, ctx.config_dir())?;
+    Ok(OpenedMessage {
+        kind: frame.kind,
+        plaintext: frame.plaintext,
+    })
+}
+
+/// Decrypt a message already taken in with open_message, e.g. to show it again
+/// Its counter is not recorded, so this never consumes a message
+pub async fn view_message(key: Vec<u8>, sealed: Vec<u8>) -> Result<OpenedMessage, FfiError> {
+    let frame = message_codec::open(&key, &sealed)?;
+    Ok(OpenedMessage {
+        kind: frame.kind,
+        plaintext: frame.plaintext,
+    })
+}
+
+/// Report stray, oversized or over-shared files in the profile directory
+pub async fn audit_storage(ctx: &AppContext) -> Result<Vec<StorageFinding>, FfiError> {
+    Ok(crate::storage_audit::audit(ctx.config_dir())?)
+}
+
+/// Apply the retention settings now, or with `dry_run` only report what would be removed
+pub async fn run_retention_cleanup(ctx: &AppContext, dry_run: bool) -> Result<RetentionReport, FfiError> {
+    let retention = ctx.config().await.retention;
+    Ok(crate::retention::enforce(ctx.manager(), &retention, dry_run).await)
 }
 
 /// Hash data with Blake3
-pub async fn hash_data(data: Vec<u8>) -> Result<Vec<u8>, String> {
+pub async fn hash_data(data: Vec<u8>) -> Result<Vec<u8>, FfiError> {
     Ok(hash_blake3(&data).to_vec())
 }
 
 /// Simple health check
-pub async fn health_check() -> Result<String, String> {
+pub async fn health_check() -> Result<String, FfiError> {
     Ok("Underground Railroad Rust Core: OK".to_string())
 }
 
@@ -113,3 +1463,107 @@ pub struct VeilidIdentityData {
     pub dht_key: String,
     pub route: String,
 }
+
+/// Newly created persona and its secret key, for bridge
+#[derive(Debug, Clone)]
+pub struct NewPersonaData {
+    pub persona: Persona,
+    pub secret_key: String,
+}
+
+/// Wrapped storage key for bridge
+#[derive(Debug, Clone)]
+pub struct WrappedKeyData {
+    pub blob: Vec<u8>,
+    pub protection: KeyProtection,
+}
+
+/// Unwrapped storage key for bridge
+#[derive(Debug, Clone)]
+pub struct UnwrappedKeyData {
+    pub key: Vec<u8>,
+    pub protection: KeyProtection,
+    pub needs_migration: bool,
+}
+
+/// What taking in a connection message changed, for bridge
+#[derive(Debug, Clone)]
+pub enum ConnectionUpdate {
+    /// A request now waiting for approval
+    Requested(PendingConnection),
+    /// Our request was approved; the responder is now a pinned contact
+    Accepted { contact: String },
+    Rejected { contact: String },
+}
+
+/// Verified route bundle data for bridge
+#[derive(Debug, Clone)]
+pub struct RouteBundleData {
+    pub public_key: String,
+    pub route: String,
+    pub mailbox_key: String,
+    pub created_at: u64,
+}
+
+/// What an imported carry bundle forwarded, for bridge
+#[derive(Debug, Clone)]
+pub struct SneakernetImportData {
+    pub messages: u32,
+    pub announcements: u32,
+}
+
+/// Contact scanned from a QR code, for bridge
+#[derive(Debug, Clone)]
+pub struct ContactQrData {
+    pub contact: RouteBundleData,
+    pub fingerprint: String,
+    /// Verified introducer chain, first introducer first (empty if none)
+    pub introduced_by: Vec<String>,
+}
+
+/// Signed escrow policy and the share to send each trustee
+#[derive(Debug, Clone)]
+pub struct KeyEscrowData {
+    pub policy: Vec<u8>,
+    pub shares: Vec<EscrowShareData>,
+}
+
+#[derive(Debug, Clone)]
+pub struct EscrowShareData {
+    pub trustee_public_key: String,
+    pub share: Vec<u8>,
+}
+
+/// Decrypted message for bridge
+#[derive(Debug, Clone)]
+pub struct OpenedMessage {
+    pub kind: MessageKind,
+    pub plaintext: Vec<u8>,
+}
+
+/// Verified vouch provenance for bridge
+#[derive(Debug, Clone)]
+pub struct VouchData {
+    pub voucher_public_key: String,
+    pub subject_public_key: String,
+    pub method: VerificationMethod,
+    pub verified_at: u64,
+    pub issued_at: u64,
+}
+
+/// Accepted burn notice for bridge
+#[derive(Debug, Clone)]
+pub struct BurnNoticeData {
+    pub issuer_public_key: String,
+    pub subject_public_key: String,
+    pub reason: String,
+    pub issued_at: u64,
+    pub forward: Option<Vec<u8>>,
+}
+
+/// Contact learned through an introduction, for bridge
+#[derive(Debug, Clone)]
+pub struct IntroducedContactData {
+    pub contact: RouteBundleData,
+    pub introduced_by: String,
+}
@@ -0,0 +1,436 @@
+// Per-profile application state
+// Returned to Flutter as an opaque handle instead of living in globals
+
+use crate::background_sync::BackgroundSync;
+use crate::config::VeilidConfig;
+use crate::connection::{self, ConnectionStore};
+use crate::core_config::CoreConfig;
+use crate::events::{forward_network_events, CoreEvent, EventBus};
+use crate::error::Result;
+use crate::journal::{Intent, Journal};
+use crate::persona::PersonaStore;
+use crate::profile_archive::{PROFILE_FILES, SET_ASIDE_FILES};
+use crate::progress::ProgressReporter;
+use crate::record_keeper::RecordKeeper;
+use crate::pinning::{self, PinStore};
+use crate::replay::{self, ReplayStore};
+use crate::rendezvous::Rendezvous;
+use crate::revocation::RevocationList;
+use crate::veilid_manager::VeilidManager;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tracing::Level;
+use tokio::sync::{Mutex, RwLock};
+
+/// Everything one open profile needs; each field carries its own async lock
+pub struct AppContext {
+    /// Directory holding profile files (the configured data dir)
+    pub(crate) config_dir: PathBuf,
+    /// Directory holding the configuration file
+    pub(crate) settings_dir: PathBuf,
+    pub(crate) config: RwLock<CoreConfig>,
+    pub(crate) manager: VeilidManager,
+    pub(crate) record_keeper: RecordKeeper,
+    pub(crate) rendezvous: Mutex<HashMap<String, Rendezvous>>,
+    pub(crate) revocations: RwLock<RevocationList>,
+    pub(crate) personas: RwLock<PersonaStore>,
+    pub(crate) replay: RwLock<ReplayStore>,
+    pub(crate) pins: RwLock<PinStore>,
+    pub(crate) connections: RwLock<ConnectionStore>,
+    pub(crate) journal: Mutex<Journal>,
+    pub(crate) sync: BackgroundSync,
+    pub(crate) events: EventBus,
+    forwarder: tokio::task::JoinHandle<()>,
+}
+
+impl AppContext {
+    /// Initialize a profile using the configuration file in `config_dir`
+    pub async fn open_configured(config_dir: String, progress: &ProgressReporter) -> Result<Self> {
+        let config = CoreConfig::load(Path::new(&config_dir))?;
+        Self::open_with(PathBuf::from(config_dir), config, progress).await
+    }
+
+    /// Initialize a profile with explicit network settings; the configuration
+    /// file still supplies everything else
+    pub async fn open(config_dir: String, network: VeilidConfig, progress: &ProgressReporter) -> Result<Self> {
+        let mut config = CoreConfig::load(Path::new(&config_dir))?;
+        config.network = network;
+        Self::open_with(PathBuf::from(config_dir), config, progress).await
+    }
+
+    async fn open_with(settings_dir: PathBuf, config: CoreConfig, progress: &ProgressReporter) -> Result<Self> {
+        crate::logging::init(Level::INFO);
+        progress.report("Starting network", 0);
+        let dir = config.data_dir(&settings_dir);
+        let manager = VeilidManager::new();
+        manager
+            .initialize_with_config(dir.to_string_lossy().to_string(), config.network.clone())
+            .await?;
+
+        manager.set_relay_cache(config.relay).await;
+        manager.set_shaper(config.shaping).await;
+
+        progress.report("Loading profile", 60);
+        let record_keeper = RecordKeeper::load(manager.clone(), &dir)?;
+        let revocations = RevocationList::load(&dir)?;
+        let personas = PersonaStore::load_or_set_aside(&dir)?;
+        let replay = replay::load(&dir)?;
+        let pins = pinning::load(&dir)?;
+        let connections = connection::load(&dir)?;
+        let journal = Journal::load(&dir)?;
+
+        let events = EventBus::new();
+        let forwarder = forward_network_events(&manager, events.clone());
+
+        let sync = BackgroundSync::new(manager.clone(), record_keeper.clone());
+        sync.set_retention(config.retention).await;
+        for persona in personas.list() {
+            sync.watch_mailbox(&persona.mailbox_key).await;
+        }
+        progress.report("Starting background sync", 90);
+        sync.start().await;
+
+        let ctx = Self {
+            config_dir: dir,
+            settings_dir,
+            config: RwLock::new(config),
+            manager,
+            record_keeper,
+            rendezvous: Mutex::new(HashMap::new()),
+            revocations: RwLock::new(revocations),
+            personas: RwLock::new(personas),
+            replay: RwLock::new(replay),
+            pins: RwLock::new(pins),
+            connections: RwLock::new(connections),
+            journal: Mutex::new(journal),
+            sync,
+            events,
+            forwarder,
+        };
+        ctx.recover_intents().await;
+        ctx.refresh_pin_holds().await;
+
+        progress.report("Ready", 100);
+        Ok(ctx)
+    }
+
+    pub fn config_dir(&self) -> &Path {
+        &self.config_dir
+    }
+
+    pub fn manager(&self) -> &VeilidManager {
+        &self.manager
+    }
+
+    /// Bus every subsystem of this profile publishes to
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    /// Current configuration, including any reloaded settings
+    pub async fn config(&self) -> CoreConfig {
+        self.config.read().await.clone()
+    }
+
+    /// Re-read the configuration file and apply the settings that are safe to
+    /// change while running. Returns the changed settings that need a restart
+    pub async fn reload_config(&self) -> Result<Vec<String>> {
+        let new = CoreConfig::load(&self.settings_dir)?;
+        let mut config = self.config.write().await;
+        let restart = config.apply_reload(&new);
+
+        self.manager.set_network_profile(config.network.network_profile).await;
+        self.sync.set_retention(config.retention).await;
+        self.manager.set_relay_cache(config.relay).await;
+        self.manager.set_shaper(config.shaping).await;
+        if !restart.is_empty() {
+            tracing::info!("Restart needed to apply: {}", restart.join(", "));
+        }
+        Ok(restart.into_iter().map(str::to_string).collect())
+    }
+
+    /// Remove a persona's scoped state, the persona itself, then its network records
+    /// Every step can be repeated, so an interrupted removal is simply run again
+    pub(crate) async fn remove_persona(&self, persona_id: &str, mailbox_key: &str, route: &str) -> Result<()> {
+        let mut personas = self.personas.write().await;
+        if let Some(handle) = personas.handle(persona_id) {
+            let mut replay = self.replay.write().await;
+            replay.remove(&handle);
+            replay::save(&replay, &self.config_dir)?;
+            let mut pins = self.pins.write().await;
+            pins.remove(&handle);
+            pinning::save(&pins, &self.config_dir)?;
+            let mut connections = self.connections.write().await;
+            connections.remove(&handle);
+            connection::save(&connections, &self.config_dir)?;
+
+            let was_active = personas.active().is_some_and(|p| p.id == persona_id);
+            personas.remove(persona_id)?;
+            personas.save(&self.config_dir)?;
+            if was_active {
+                self.events.publish(CoreEvent::ActivePersonaChanged {
+                    persona_id: personas.active().map(|p| p.id.clone()),
+                });
+            }
+        }
+        drop(personas);
+
+        self.sync.unwatch_mailbox(mailbox_key).await;
+        self.record_keeper.forget(mailbox_key).await?;
+        self.manager.dht_delete(mailbox_key).await?;
+        self.manager.release_private_route(route).await
+    }
+
+    /// Hand the routes of every contact with an unresolved key change to the
+    /// manager, which refuses any send to them
+    pub(crate) async fn refresh_pin_holds(&self) {
+        let holds = self
+            .pins
+            .read()
+            .await
+            .across_personas("holding routes of contacts whose keys changed")
+            .flat_map(|(_, pins)| pins.held_routes())
+            .collect();
+        self.manager.set_pin_holds(holds).await;
+    }
+
+    /// Finish or undo operations interrupted by the last shutdown
+    /// Intents that fail again stay open for the next start
+    async fn recover_intents(&self) {
+        let mut journal = self.journal.lock().await;
+        for (id, intent) in journal.pending() {
+            let result = match &intent {
+                Intent::CreatePersona {
+                    persona_id,
+                    mailbox_key,
+                    route,
+                } => {
+                    let saved = self.personas.read().await.get(persona_id).is_some();
+                    if saved {
+                        Ok(())
+                    } else {
+                        self.remove_persona(persona_id, mailbox_key, route).await
+                    }
+                }
+                Intent::DeletePersona {
+                    persona_id,
+                    mailbox_key,
+                    route,
+                } => self.remove_persona(persona_id, mailbox_key, route).await,
+            };
+            match result.and_then(|()| journal.finish(id, &self.config_dir)) {
+                Ok(()) => tracing::info!("Recovered interrupted operation {}", id),
+                Err(e) => tracing::warn!("Could not recover interrupted operation {}: {}", id, e),
+            }
+        }
+    }
+
+    /// Stop background tasks and detach from the network
+    pub async fn close(&self) -> Result<()> {
+        self.sync.stop().await;
+        self.manager.shutdown().await
+    }
+
+    /// Close the profile and overwrite and delete every file it keeps
+    pub async fn wipe(&self, progress: &ProgressReporter) -> Result<()> {
+        progress.report("Stopping network", 0);
+        self.close().await?;
+
+        let total = PROFILE_FILES.len() + SET_ASIDE_FILES.len();
+        for (i, name) in PROFILE_FILES.iter().chain(SET_ASIDE_FILES).enumerate() {
+            let path = self.config_dir.join(name);
+            if path.exists() {
+                // Best effort: flash storage may keep old blocks regardless
+                let len = std::fs::metadata(&path)?.len() as usize;
+                std::fs::write(&path, vec![0u8; len])?;
+                std::fs::remove_file(&path)?;
+            }
+            progress.report("Wiping profile", (10 + 90 * (i + 1) / total) as u8);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for AppContext {
+    fn drop(&mut self) {
+        self.sync.abort();
+        self.forwarder.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::ProgressOperation;
+    use crate::util::TempDir;
+
+    fn silent() -> ProgressReporter {
+        ProgressReporter::silent(ProgressOperation::Startup)
+    }
+
+    #[tokio::test]
+    async fn test_contexts_are_independent() {
+        let (dir_a, dir_b) = (TempDir::new("ctx-a"), TempDir::new("ctx-b"));
+
+        let a = AppContext::open(dir_a.path_string(), VeilidConfig::default(), &silent())
+            .await
+            .unwrap();
+        let b = AppContext::open(dir_b.path_string(), VeilidConfig::default(), &silent())
+            .await
+            .unwrap();
+
+        a.manager().dht_set("k", b"a".to_vec()).await.unwrap();
+        assert_eq!(b.manager().dht_get("k").await.unwrap(), None);
+
+        a.close().await.unwrap();
+        assert!(!a.manager().is_initialized().await);
+        assert!(b.manager().is_initialized().await);
+    }
+
+    #[tokio::test]
+    async fn test_wipe_reports_progress() {
+        let tmp = TempDir::new("wipe");
+        let dir = tmp.path();
+        let ctx = AppContext::open(tmp.path_string(), VeilidConfig::default(), &silent())
+            .await
+            .unwrap();
+        ctx.manager().block_key([9u8; 32]).await.unwrap();
+        assert!(dir.join(crate::blocklist::BLOCKLIST_FILE).exists());
+        std::fs::write(dir.join(crate::rpc::RPC_TOKENS_FILE), b"{}").unwrap();
+        std::fs::write(dir.join(crate::rpc::DAEMON_TOKEN_FILE), b"secret").unwrap();
+        std::fs::write(dir.join(crate::persona::PERSONAS_CORRUPT_FILE), b"{\"personas\"").unwrap();
+
+        let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let progress = ProgressReporter::new(ProgressOperation::Wipe, move |e| sink.lock().unwrap().push(e.percent));
+        ctx.wipe(&progress).await.unwrap();
+
+        assert!(!dir.join(crate::blocklist::BLOCKLIST_FILE).exists());
+        assert!(!dir.join(crate::rpc::RPC_TOKENS_FILE).exists());
+        assert!(!dir.join(crate::rpc::DAEMON_TOKEN_FILE).exists());
+        assert!(!dir.join(crate::persona::PERSONAS_CORRUPT_FILE).exists());
+        assert_eq!(events.lock().unwrap().last(), Some(&100));
+    }
+
+    #[tokio::test]
+    async fn test_key_change_holds_every_send_to_the_contact() {
+        use crate::ack::DeliveryPath;
+        use crate::error::UndergroundError;
+        use crate::relay::RelayHop;
+        use crate::safety::SafetyProfile;
+        use crate::shaper::Urgency;
+
+        let tmp = TempDir::new("pins");
+        let ctx = AppContext::open(tmp.path_string(), VeilidConfig::default(), &silent())
+            .await
+            .unwrap();
+        crate::api::create_persona(&ctx, "Alice".to_string()).await.unwrap();
+        let route = ctx.manager().create_private_route().await.unwrap();
+        let signing = "VLD1:pub:".to_string() + &"11".repeat(32);
+        let (first, second) = ("22".repeat(32), "33".repeat(32));
+        let bob = "VLD1:pub:".to_string() + &"44".repeat(32);
+        let check = |encryption: &str| {
+            let (contact, encryption) = (bob.clone(), encryption.to_string());
+            crate::api::check_contact_keys(&ctx, contact, signing.clone(), encryption, route.clone())
+        };
+
+        check(&first).await.unwrap();
+        crate::api::send_message_via_route(&ctx, route.clone(), vec![1]).await.unwrap();
+        assert!(check(&second).await.is_err());
+
+        let held = |e: UndergroundError| matches!(e, UndergroundError::KeyPinViolation(ref c) if *c == bob);
+        let manager = ctx.manager();
+        assert!(held(manager.send_shaped(&route, vec![2], Urgency::Normal).await.unwrap_err()));
+        assert!(held(manager.send_shaped(&route, vec![2], Urgency::Critical).await.unwrap_err()));
+        assert!(held(manager.send_with_safety(&route, vec![2], SafetyProfile::urgent()).await.unwrap_err()));
+        let recipient = RelayHop { route: route.clone(), key: vec![0u8; 32] };
+        assert!(held(manager.send_via_relay(&[], &recipient, vec![2]).await.unwrap_err()));
+        let paths = vec![DeliveryPath::Route(route.clone())];
+        assert!(held(manager.send_tracked(&bob, paths, vec![2], &[0u8; 32]).await.unwrap_err()));
+        let sealed = crate::api::seal_message(
+            &ctx,
+            bob.clone(),
+            vec![0u8; 32],
+            crate::message_codec::MessageKind::Text,
+            b"hi".to_vec(),
+        )
+        .await;
+        assert!(sealed.is_err());
+
+        crate::api::resolve_contact_key_change(&ctx, bob.clone(), true).await.unwrap();
+        crate::api::send_message_via_route(&ctx, route.clone(), vec![3]).await.unwrap();
+        ctx.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_failed_persona_creation_closes_its_intent() {
+        let tmp = TempDir::new("persona-rollback");
+        let ctx = AppContext::open(tmp.path_string(), VeilidConfig::default(), &silent())
+            .await
+            .unwrap();
+
+        assert!(crate::api::create_persona(&ctx, "   ".to_string()).await.is_err());
+        assert!(ctx.journal.lock().await.pending().is_empty());
+        assert!(ctx.personas.read().await.list().is_empty());
+        ctx.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_carried_messages_leave_the_outbox_only_once_written() {
+        let tmp = TempDir::new("carry");
+        let ctx = AppContext::open(tmp.path_string(), VeilidConfig::default(), &silent())
+            .await
+            .unwrap();
+        let route = ctx.manager().create_private_route().await.unwrap();
+        ctx.manager().detach().await.unwrap();
+        ctx.manager().send_via_private_route(&route, vec![1]).await.unwrap();
+        ctx.manager().send_via_private_route(&route, vec![2]).await.unwrap();
+
+        // Nothing is sent live or lost when the bundle cannot be written
+        let unwritable = tmp.path().join("missing").join("bundle.urc").to_string_lossy().to_string();
+        assert!(crate::api::export_sneakernet_bundle(&ctx, "pw".to_string(), unwritable).await.is_err());
+        assert_eq!(ctx.manager().outbox_len().await, 2);
+
+        let bundle = tmp.path().join("bundle.urc").to_string_lossy().to_string();
+        assert_eq!(crate::api::export_sneakernet_bundle(&ctx, "pw".to_string(), bundle).await.unwrap(), 2);
+        assert_eq!(ctx.manager().outbox_len().await, 0);
+        ctx.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_approved_connection_becomes_a_contact() {
+        use crate::api::ConnectionUpdate;
+        use crate::connection::ConnectionRequest;
+        use crate::route_blob::RouteBundle;
+
+        let tmp = TempDir::new("connections");
+        let ctx = AppContext::open(tmp.path_string(), VeilidConfig::default(), &silent())
+            .await
+            .unwrap();
+        let me = crate::api::create_persona(&ctx, "Bob".to_string()).await.unwrap();
+        let route = ctx.manager().create_private_route().await.unwrap();
+        let (alice_sk, alice_pk) = crate::crypto::generate_signing_keypair();
+        let card = RouteBundle::create(&route, "mailbox-alice", alice_sk.as_slice()).unwrap();
+        let request = ConnectionRequest::create(alice_sk.as_slice(), card, [7; 32], "met at the shelter").unwrap();
+
+        let update = crate::api::receive_connection_message(&ctx, request.encode()).await.unwrap();
+        let ConnectionUpdate::Requested(pending) = update else {
+            panic!("expected a pending request");
+        };
+        assert_eq!(crate::api::list_connection_requests(&ctx).await.unwrap(), vec![pending.clone()]);
+
+        let encryption = hex::encode([8u8; 32]);
+        crate::api::approve_connection_request(&ctx, pending.contact.clone(), me.secret_key, encryption)
+            .await
+            .unwrap();
+        assert!(crate::api::list_connection_requests(&ctx).await.unwrap().is_empty());
+        let contacts = crate::api::list_contacts(&ctx).await.unwrap();
+        assert_eq!(contacts[0].contact, format!("VLD1:pub:{}", hex::encode(alice_pk)));
+        assert_eq!(contacts[0].routes, vec![route]);
+
+        // A contact cannot ask again, and nothing is left to reject
+        assert!(crate::api::receive_connection_message(&ctx, request.encode()).await.is_err());
+        assert!(crate::api::reject_connection_request(&ctx, pending.contact, "00".repeat(32)).await.is_err());
+        ctx.close().await.unwrap();
+    }
+}
@@ -0,0 +1,310 @@
+// Background sync: the periodic work mobile schedulers wake the app for
+// (WorkManager on Android, BGTaskScheduler on iOS)
+
+use crate::core_config::RetentionConfig;
+use crate::error::{Result, UndergroundError};
+use crate::reconnect::ReconnectCoordinator;
+use crate::record_keeper::RecordKeeper;
+use crate::retention;
+use crate::veilid_manager::{VeilidManager, WatchTopic};
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, RwLock};
+use tokio::task::JoinHandle;
+
+/// Mailboxes fetched at once during a sync pass
+const MAX_CONCURRENT_FETCHES: usize = 8;
+
+/// Outcome of reading one watched record
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum MailboxStatus {
+    Changed,
+    Unchanged,
+    /// The record exists but holds no value yet
+    Empty,
+    Failed { error: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MailboxCheck {
+    pub mailbox_key: String,
+    pub status: MailboxStatus,
+}
+
+/// Snapshot of the sync service for the app
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SyncStatus {
+    pub running: bool,
+    pub started_at: Option<u64>,
+    pub last_run_at: Option<u64>,
+    pub runs: u64,
+    pub outbox_len: usize,
+    /// Relay blobs held for offline contacts
+    pub held_relay_blobs: usize,
+    pub owned_records: usize,
+    pub watched_mailboxes: usize,
+    /// Watched records of every topic, mailboxes included
+    pub watched_records: usize,
+    /// Per-record results of the last pass that reached the network
+    pub mailboxes: Vec<MailboxCheck>,
+    pub last_error: Option<String>,
+}
+
+/// Runs the outbox worker, mailbox watcher, DHT refresher and cleanup
+#[derive(Clone)]
+pub struct BackgroundSync {
+    manager: VeilidManager,
+    record_keeper: RecordKeeper,
+    /// Digest of the last value read from each watched record
+    seen: Arc<RwLock<HashMap<String, [u8; 32]>>>,
+    status: Arc<RwLock<SyncStatus>>,
+    tasks: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    retention: Arc<RwLock<RetentionConfig>>,
+}
+
+impl BackgroundSync {
+    pub fn new(manager: VeilidManager, record_keeper: RecordKeeper) -> Self {
+        Self {
+            manager,
+            record_keeper,
+            seen: Arc::new(RwLock::new(HashMap::new())),
+            status: Arc::new(RwLock::new(SyncStatus::default())),
+            tasks: Arc::new(Mutex::new(Vec::new())),
+            retention: Arc::new(RwLock::new(RetentionConfig::default())),
+        }
+    }
+
+    /// Maximum ages applied by the cleanup in each pass
+    pub async fn set_retention(&self, retention: RetentionConfig) {
+        *self.retention.write().await = retention;
+    }
+
+    /// Watch a DHT record for new values, reported on the event bus by topic
+    /// Every watched record is also polled here in case a watch update is missed
+    pub async fn subscribe(&self, key: &str, topic: WatchTopic) {
+        self.manager.watch_record(key, topic).await;
+    }
+
+    pub async fn unsubscribe(&self, key: &str, topic: WatchTopic) {
+        if self.manager.unwatch_record(key, topic).await {
+            self.seen.write().await.remove(key);
+        }
+    }
+
+    /// Watch a mailbox record for new values
+    pub async fn watch_mailbox(&self, key: &str) {
+        self.subscribe(key, WatchTopic::Mailbox).await;
+    }
+
+    pub async fn unwatch_mailbox(&self, key: &str) {
+        self.unsubscribe(key, WatchTopic::Mailbox).await;
+    }
+
+    /// Run one sync pass: flush the outbox, check watched records, refresh records, clean up
+    pub async fn run_once(&self) -> Result<SyncStatus> {
+        let result = self.sync_pass().await;
+
+        let mut status = self.status.write().await;
+        status.last_run_at = Some(crate::util::unix_now());
+        status.runs += 1;
+        status.outbox_len = self.manager.outbox_len().await;
+        status.held_relay_blobs = self.manager.relay_cache_len().await;
+        status.owned_records = self.record_keeper.records().await.len();
+        status.watched_mailboxes = self.manager.watch_count(WatchTopic::Mailbox).await;
+        status.watched_records = self.manager.watched_records().await.len();
+        status.last_error = result.as_ref().err().map(|e| e.to_string());
+        drop(status);
+
+        result?;
+        Ok(self.status().await)
+    }
+
+    async fn sync_pass(&self) -> Result<()> {
+        let retention = *self.retention.read().await;
+        let removed = retention::enforce(&self.manager, &retention, false).await;
+        if removed.total() > 0 {
+            tracing::info!(
+                "Retention dropped {} outbox and {} inbox messages, {} pending ACKs, {} relay blobs, {} log records",
+                removed.outbox_messages,
+                removed.inbox_messages,
+                removed.pending_acks,
+                removed.relay_blobs,
+                removed.log_records
+            );
+        }
+
+        // The manager's own timer releases shaped sends too; this catches
+        // one-shot passes run while the app is suspended
+        let released = self.manager.release_shaped().await;
+        if released > 0 {
+            tracing::debug!("Released {} shaped sends", released);
+        }
+
+        if !self.manager.is_attached().await {
+            return Ok(());
+        }
+
+        self.manager.flush_outbox().await?;
+        let forwarded = self.manager.retry_relay_cache().await;
+        if forwarded > 0 {
+            tracing::info!("Forwarded {} held relay blobs", forwarded);
+        }
+        let retried = self.manager.escalate_unacknowledged().await;
+        if retried > 0 {
+            tracing::info!("Retried {} unacknowledged messages on other paths", retried);
+        }
+        let checks = self.check_watched().await;
+        let failed = checks
+            .iter()
+            .filter(|c| matches!(c.status, MailboxStatus::Failed { .. }))
+            .count();
+        let total = checks.len();
+        self.status.write().await.mailboxes = checks;
+
+        self.record_keeper.refresh_due().await?;
+        if failed > 0 {
+            return Err(UndergroundError::Veilid(format!(
+                "{} of {} watched records could not be read",
+                failed, total
+            )));
+        }
+        Ok(())
+    }
+
+    /// Read every watched record concurrently; one failure does not stop the others
+    async fn check_watched(&self) -> Vec<MailboxCheck> {
+        let keys = self.manager.watched_records().await;
+        let fetched: Vec<(String, Result<Option<Vec<u8>>>)> = stream::iter(keys)
+            .map(|key| async move {
+                let value = self.manager.dht_get(&key).await;
+                (key, value)
+            })
+            .buffer_unordered(MAX_CONCURRENT_FETCHES)
+            .collect()
+            .await;
+
+        let mut checks = Vec::with_capacity(fetched.len());
+        for (key, value) in fetched {
+            let status = match value {
+                Err(e) => MailboxStatus::Failed { error: e.to_string() },
+                Ok(None) => MailboxStatus::Empty,
+                Ok(Some(value)) => {
+                    let digest = crate::crypto::hash_blake3(&value);
+                    let changed = self.seen.write().await.insert(key.clone(), digest) != Some(digest);
+                    if changed {
+                        self.manager.handle_value_change(&key).await;
+                        MailboxStatus::Changed
+                    } else {
+                        MailboxStatus::Unchanged
+                    }
+                }
+            };
+            checks.push(MailboxCheck { mailbox_key: key, status });
+        }
+        checks.sort_by(|a, b| a.mailbox_key.cmp(&b.mailbox_key));
+        checks
+    }
+
+    /// Start the background tasks (no-op if already running)
+    pub async fn start(&self) {
+        let mut tasks = self.tasks.lock().await;
+        if !tasks.is_empty() {
+            return;
+        }
+
+        let tuning = self.manager.network_tuning().await;
+        tasks.push(
+            ReconnectCoordinator::new(self.manager.clone())
+                .with_stale_after(tuning.dht_refresh_interval_secs)
+                .spawn(),
+        );
+        tasks.push(self.record_keeper.clone().spawn());
+
+        let worker = self.clone();
+        let interval_secs = tuning.poll_interval_secs.max(1);
+        tasks.push(tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(Duration::from_secs(interval_secs));
+            loop {
+                ticker.tick().await;
+                if let Err(e) = worker.run_once().await {
+                    tracing::warn!("Background sync failed: {}", e);
+                }
+            }
+        }));
+
+        let mut status = self.status.write().await;
+        status.running = true;
+        status.started_at = Some(crate::util::unix_now());
+    }
+
+    /// Stop the background tasks
+    pub async fn stop(&self) {
+        for task in self.tasks.lock().await.drain(..) {
+            task.abort();
+        }
+
+        let mut status = self.status.write().await;
+        status.running = false;
+        status.started_at = None;
+    }
+
+    /// Abort the background tasks without waiting (for use in Drop)
+    pub fn abort(&self) {
+        if let Ok(mut tasks) = self.tasks.try_lock() {
+            for task in tasks.drain(..) {
+                task.abort();
+            }
+        }
+    }
+
+    pub async fn status(&self) -> SyncStatus {
+        self.status.read().await.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::veilid_manager::VeilidEvent;
+
+    #[tokio::test]
+    async fn test_mailbox_change_emits_event() {
+        let tmp = crate::util::TempDir::new("sync");
+        let manager = VeilidManager::new();
+        manager.initialize(tmp.path_string()).await.unwrap();
+        let keeper = RecordKeeper::load(manager.clone(), tmp.path()).unwrap();
+        let sync = BackgroundSync::new(manager.clone(), keeper);
+        let mut events = manager.subscribe();
+
+        sync.watch_mailbox("mailbox").await;
+        sync.watch_mailbox("quiet").await;
+        manager.dht_set("mailbox", vec![1]).await.unwrap();
+        let status = sync.run_once().await.unwrap();
+        assert_eq!(status.runs, 1);
+        assert_eq!(status.watched_mailboxes, 2);
+        assert_eq!(status.watched_records, 2);
+        assert_eq!(status.mailboxes[0].status, MailboxStatus::Changed);
+        assert_eq!(status.mailboxes[1].status, MailboxStatus::Empty);
+
+        let mut changed = 0;
+        while let Ok(event) = events.try_recv() {
+            if event == VeilidEvent::ValueChanged("mailbox".to_string()) {
+                changed += 1;
+            }
+        }
+        assert_eq!(changed, 1);
+
+        // Same value again is not a change
+        sync.run_once().await.unwrap();
+        assert!(events.try_recv().is_err());
+
+        sync.subscribe("quiet", WatchTopic::Record).await;
+        sync.unwatch_mailbox("quiet").await;
+        let status = sync.run_once().await.unwrap();
+        assert_eq!((status.watched_mailboxes, status.watched_records), (1, 2));
+    }
+}
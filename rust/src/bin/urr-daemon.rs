@@ -0,0 +1,75 @@
+// urr-daemon: run the Underground Railroad core without the UI
+//
+// Usage: urr-daemon --config-dir <dir> [--socket <path>]
+
+#[cfg(unix)]
+#[tokio::main]
+async fn main() {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use underground_railroad::daemon::{token_path, Daemon, SOCKET_FILE};
+
+    underground_railroad::logging::init(tracing::Level::INFO);
+
+    let mut config_dir: Option<PathBuf> = None;
+    let mut socket: Option<PathBuf> = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config-dir" => config_dir = args.next().map(PathBuf::from),
+            "--socket" => socket = args.next().map(PathBuf::from),
+            _ => {
+                eprintln!("Usage: urr-daemon --config-dir <dir> [--socket <path>]");
+                std::process::exit(2);
+            }
+        }
+    }
+
+    let Some(config_dir) = config_dir else {
+        eprintln!("Usage: urr-daemon --config-dir <dir> [--socket <path>]");
+        std::process::exit(2);
+    };
+    let socket = socket.unwrap_or_else(|| config_dir.join(SOCKET_FILE));
+
+    let daemon = match Daemon::start(&config_dir).await {
+        Ok(daemon) => Arc::new(daemon),
+        Err(e) => {
+            eprintln!("Failed to start: {}", e);
+            std::process::exit(1);
+        }
+    };
+    eprintln!("Control token: {}", token_path(daemon.data_dir()).display());
+
+    let signals = daemon.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            signals.request_shutdown();
+        }
+    });
+
+    let reloads = daemon.clone();
+    tokio::spawn(async move {
+        use tokio::signal::unix::{signal, SignalKind};
+        let Ok(mut hangups) = signal(SignalKind::hangup()) else {
+            return;
+        };
+        while hangups.recv().await.is_some() {
+            match reloads.reload_config().await {
+                Ok(restart) if restart.is_empty() => tracing::info!("Configuration reloaded"),
+                Ok(restart) => tracing::warn!("Configuration reloaded; restart to apply {}", restart.join(", ")),
+                Err(e) => tracing::warn!("Configuration not reloaded: {}", e),
+            }
+        }
+    });
+
+    if let Err(e) = daemon.serve(socket).await {
+        eprintln!("Daemon stopped: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("urr-daemon currently supports Unix platforms only");
+    std::process::exit(1);
+}
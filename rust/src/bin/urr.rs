@@ -0,0 +1,332 @@
+// urr: operator command line for power users and drills
+//
+// Usage: urr [--config-dir <dir>] <command> ...
+// The config directory defaults to $URR_CONFIG_DIR, then ~/.local/share/underground-railroad
+
+use std::error::Error;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use underground_railroad::api;
+use underground_railroad::api::ConnectionUpdate;
+use underground_railroad::app_context::AppContext;
+use underground_railroad::connection::ConnectionMessage;
+use underground_railroad::core_config::CoreConfig;
+use underground_railroad::message_codec::MessageKind;
+use underground_railroad::pinning::{self, SharePolicy};
+use underground_railroad::profile_archive;
+use underground_railroad::progress::{ProgressOperation, ProgressReporter};
+
+const USAGE: &str = "Usage: urr [--config-dir <dir>] <command>
+
+Commands:
+  init                                  Create the profile directory and network state
+  status                                Show network and sync status
+  config show                           Effective settings (file plus URR_* overrides)
+  persona list
+  persona create <name>                 Prints the new secret key once; store it safely
+  persona switch <id>
+  persona delete <id>
+  contact qr <secret-key>               QR text for the active persona
+  contact parse <qr-text>               Verify a scanned contact code
+  contact add <qr-text> <encryption-public-key>
+                                        Verify a contact code and pin the contact's keys
+  contact list                          Contacts with pinned keys and their routes
+  contact share <public-key> never|keys|routes
+                                        What of a contact may go into contact bundles
+  contact export <dest> <public-key>... Encrypt shareable contacts for another device
+  contact import <src>                  Pin the contacts in a bundle
+  contact request <route> <secret-key> <encryption-public-key> [note]
+                                        Ask the owner of a route to become a contact
+  contact receive <inbox-id>            Take in a connection request or answer from the inbox
+  contact requests                      Connection requests waiting for a decision
+  contact approve <public-key> <secret-key> <encryption-public-key>
+  contact reject <public-key> <secret-key>
+  contact verify-vouch <hex> <voucher-public-key>
+  contact block <public-key>
+  contact unblock <public-key>
+  message send <contact> <key-hex> <text>
+                                        Seal text under the conversation key and send it
+  message read <key-hex>                Show inbox messages sealed under a conversation key,
+                                        and connection messages
+  message remove <through-id>           Remove inbox messages up to an id
+  profile export <dest>                 Password read from $URR_PASSWORD or stdin (also for
+                                        contact export and import)
+  profile import <src>";
+
+type CliResult = Result<(), Box<dyn Error>>;
+
+#[tokio::main]
+async fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let config_dir = match take_config_dir(&mut args) {
+        Some(dir) => dir,
+        None => {
+            eprintln!("No config directory: pass --config-dir or set URR_CONFIG_DIR");
+            std::process::exit(2);
+        }
+    };
+
+    let words: Vec<&str> = args.iter().map(String::as_str).collect();
+    if let Err(e) = run(&config_dir, &words).await {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn take_config_dir(args: &mut Vec<String>) -> Option<PathBuf> {
+    if let Some(pos) = args.iter().position(|a| a == "--config-dir") {
+        args.remove(pos);
+        return (pos < args.len()).then(|| PathBuf::from(args.remove(pos)));
+    }
+    if let Ok(dir) = std::env::var("URR_CONFIG_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+    std::env::var("HOME")
+        .ok()
+        .map(|home| Path::new(&home).join(".local/share/underground-railroad"))
+}
+
+async fn run(config_dir: &Path, words: &[&str]) -> CliResult {
+    match words {
+        ["init"] => {
+            open(config_dir).await?.close().await?;
+            println!("Profile ready in {}", config_dir.display());
+        }
+        ["status"] => {
+            let ctx = open(config_dir).await?;
+            println!("Attachment: {:?}", api::get_attachment_state(&ctx).await?);
+            println!("Sync: {:?}", api::get_background_sync_status(&ctx).await?);
+            println!("Metrics: {:?}", api::get_network_metrics(&ctx).await?);
+            ctx.close().await?;
+        }
+        ["config", "show"] => {
+            print!("{}", CoreConfig::load(config_dir)?.to_toml()?);
+        }
+        ["persona", rest @ ..] => persona(config_dir, rest).await?,
+        ["contact", rest @ ..] => contact(config_dir, rest).await?,
+        ["message", rest @ ..] => message(config_dir, rest).await?,
+        ["profile", "export", dest] => {
+            let count = profile_archive::export_profile(
+                &data_dir(config_dir)?,
+                &read_password()?,
+                Path::new(dest),
+                &print_progress(ProgressOperation::Export),
+            )?;
+            println!("Exported {} files to {}", count, dest);
+        }
+        ["profile", "import", src] => {
+            let dir = data_dir(config_dir)?;
+            let count = profile_archive::import_profile(
+                &dir,
+                &read_password()?,
+                Path::new(src),
+                &print_progress(ProgressOperation::Import),
+            )?;
+            println!("Restored {} files into {}", count, dir.display());
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    }
+    Ok(())
+}
+
+async fn persona(config_dir: &Path, words: &[&str]) -> CliResult {
+    let ctx = open(config_dir).await?;
+    match words {
+        ["list"] => {
+            let active = api::get_active_persona(&ctx).await?.map(|p| p.id);
+            for p in api::list_personas(&ctx).await? {
+                let marker = if active.as_deref() == Some(p.id.as_str()) { "*" } else { " " };
+                println!("{} {}  {}  {}", marker, p.id, p.name, p.public_key);
+            }
+        }
+        ["create", name] => {
+            let created = api::create_persona(&ctx, name.to_string()).await?;
+            println!("Persona: {}", created.persona.id);
+            println!("Public key: {}", created.persona.public_key);
+            println!("Secret key (shown once): {}", created.secret_key);
+        }
+        ["switch", id] => {
+            api::switch_active_persona(&ctx, id.to_string()).await?;
+        }
+        ["delete", id] => {
+            api::delete_persona(&ctx, id.to_string()).await?;
+        }
+        _ => return Err(USAGE.into()),
+    }
+    ctx.close().await?;
+    Ok(())
+}
+
+async fn contact(config_dir: &Path, words: &[&str]) -> CliResult {
+    let ctx = open(config_dir).await?;
+    match words {
+        ["qr", secret_key] => {
+            println!("{}", api::generate_my_contact_qr(&ctx, secret_key.to_string()).await?);
+        }
+        ["parse", text] => {
+            let scanned = api::parse_contact_qr(&ctx, text.as_bytes().to_vec()).await?;
+            println!("Public key: {}", scanned.contact.public_key);
+            println!("Fingerprint: {}", scanned.fingerprint);
+            println!("Route: {}", scanned.contact.route);
+            println!("Mailbox: {}", scanned.contact.mailbox_key);
+            for introducer in &scanned.introduced_by {
+                println!("Introduced by: {}", introducer);
+            }
+        }
+        ["verify-vouch", data, voucher] => {
+            let vouch = api::verify_vouch(hex::decode(data)?, voucher.to_string()).await?;
+            println!(
+                "{} vouched for {} ({:?}, verified at {})",
+                vouch.voucher_public_key, vouch.subject_public_key, vouch.method, vouch.verified_at
+            );
+        }
+        ["add", text, encryption_key] => {
+            let scanned = api::parse_contact_qr(&ctx, text.as_bytes().to_vec()).await?;
+            let contact = scanned.contact;
+            api::check_contact_keys(
+                &ctx,
+                contact.public_key.clone(),
+                contact.public_key.clone(),
+                encryption_key.to_string(),
+                contact.route,
+            )
+            .await?;
+            println!("Added {} ({})", contact.public_key, scanned.fingerprint);
+        }
+        ["list"] => {
+            for c in api::list_contacts(&ctx).await? {
+                let marker = if c.key_changed { "!" } else { " " };
+                println!(
+                    "{} {}  pinned at {}  {} routes  shared: {:?}",
+                    marker,
+                    c.contact,
+                    c.pinned_at,
+                    c.routes.len(),
+                    c.share
+                );
+            }
+        }
+        ["share", key, share] => {
+            let share = match *share {
+                "never" => SharePolicy::Never,
+                "keys" => SharePolicy::KeysOnly,
+                "routes" => SharePolicy::WithRoutes,
+                _ => return Err(USAGE.into()),
+            };
+            if !api::set_contact_sharing(&ctx, key.to_string(), share).await? {
+                return Err("No pinned contact with this key".into());
+            }
+        }
+        ["export", dest, keys @ ..] if !keys.is_empty() => {
+            let keys: Vec<String> = keys.iter().map(|k| k.to_string()).collect();
+            let count = keys.len();
+            std::fs::write(dest, api::export_contacts(&ctx, keys, read_password()?).await?)?;
+            println!("Exported {} contacts to {}", count, dest);
+        }
+        ["import", src] => {
+            let imported = api::import_contacts(&ctx, std::fs::read(src)?, read_password()?).await?;
+            println!("Pinned {} contacts, {} key changes to resolve", imported.added, imported.key_changes);
+        }
+        ["request", route, secret_key, encryption_key, note @ ..] => {
+            let (secret_key, encryption_key) = (secret_key.to_string(), encryption_key.to_string());
+            api::send_connection_request(&ctx, secret_key, encryption_key, route.to_string(), note.join(" ")).await?;
+        }
+        ["receive", id] => {
+            let id: u64 = id.parse()?;
+            let message = api::read_inbox(&ctx, id.checked_sub(1))
+                .await?
+                .into_iter()
+                .find(|m| m.id == id)
+                .ok_or("No inbox message with this id")?;
+            match api::receive_connection_message(&ctx, message.message).await? {
+                ConnectionUpdate::Requested(p) => println!("Request from {}: {}", p.contact, p.note),
+                ConnectionUpdate::Accepted { contact } => println!("{} accepted; keys pinned", contact),
+                ConnectionUpdate::Rejected { contact } => println!("{} declined", contact),
+            }
+        }
+        ["requests"] => {
+            for p in api::list_connection_requests(&ctx).await? {
+                println!("{}  [{}]  {}", p.contact, p.received_at, p.note);
+            }
+        }
+        ["approve", key, secret_key, encryption_key] => {
+            let (secret_key, encryption_key) = (secret_key.to_string(), encryption_key.to_string());
+            api::approve_connection_request(&ctx, key.to_string(), secret_key, encryption_key).await?;
+        }
+        ["reject", key, secret_key] => {
+            api::reject_connection_request(&ctx, key.to_string(), secret_key.to_string()).await?;
+        }
+        ["block", key] => {
+            api::block_contact_key(&ctx, key.to_string()).await?;
+        }
+        ["unblock", key] => {
+            api::unblock_contact_key(&ctx, key.to_string()).await?;
+        }
+        _ => return Err(USAGE.into()),
+    }
+    ctx.close().await?;
+    Ok(())
+}
+
+async fn message(config_dir: &Path, words: &[&str]) -> CliResult {
+    let ctx = open(config_dir).await?;
+    match words {
+        ["send", contact, key, text] => {
+            let contact = pinning::contact_id(contact)?;
+            let route = api::list_contacts(&ctx)
+                .await?
+                .into_iter()
+                .find(|c| c.contact == contact)
+                .and_then(|c| c.routes.last().cloned())
+                .ok_or("No route known for this contact; add them first")?;
+            let (key, plaintext) = (hex::decode(key)?, text.as_bytes().to_vec());
+            let sealed = api::seal_message(&ctx, contact, key, MessageKind::Text, plaintext).await?;
+            api::send_message_via_route(&ctx, route, sealed).await?;
+        }
+        ["read", key] => {
+            let key = hex::decode(key)?;
+            // Messages sealed under other keys do not open and are skipped
+            for m in api::read_inbox(&ctx, None).await? {
+                if ConnectionMessage::decode(&m.message).is_ok() {
+                    println!("{}  [{}]  (connection message: contact receive {})", m.id, m.received_at, m.id);
+                } else if let Ok(opened) = api::view_message(key.clone(), m.message).await {
+                    println!("{}  [{}]  {}", m.id, m.received_at, String::from_utf8_lossy(&opened.plaintext));
+                }
+            }
+        }
+        ["remove", through] => {
+            let removed = api::remove_inbox_messages(&ctx, through.parse()?).await?;
+            println!("Removed {} messages", removed);
+        }
+        _ => return Err(USAGE.into()),
+    }
+    ctx.close().await?;
+    Ok(())
+}
+
+/// Directory holding the profile files, which `data_dir` may move elsewhere
+fn data_dir(config_dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    Ok(CoreConfig::load(config_dir)?.data_dir(config_dir))
+}
+
+async fn open(config_dir: &Path) -> Result<AppContext, Box<dyn Error>> {
+    let progress = ProgressReporter::silent(ProgressOperation::Startup);
+    Ok(AppContext::open_configured(config_dir.to_string_lossy().to_string(), &progress).await?)
+}
+
+fn read_password() -> Result<String, Box<dyn Error>> {
+    if let Ok(password) = std::env::var("URR_PASSWORD") {
+        return Ok(password);
+    }
+    eprint!("Password: ");
+    let mut line = String::new();
+    std::io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn print_progress(operation: ProgressOperation) -> ProgressReporter {
+    ProgressReporter::new(operation, |e| eprintln!("[{:>3}%] {}", e.percent, e.stage))
+}
@@ -0,0 +1,78 @@
+use crate::error::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+/// File name of the persisted blocklist inside the config directory
+pub(crate) const BLOCKLIST_FILE: &str = "blocklist.json";
+
+/// Blocked identity keys and routes
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Blocklist {
+    keys: HashSet<[u8; 32]>,
+    routes: HashSet<String>,
+}
+
+impl Blocklist {
+    /// Load the blocklist from a config directory (empty if none saved yet)
+    pub fn load(config_dir: &Path) -> Result<Self> {
+        let path = config_dir.join(BLOCKLIST_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    pub fn save(&self, config_dir: &Path) -> Result<()> {
+        fs::create_dir_all(config_dir)?;
+        fs::write(config_dir.join(BLOCKLIST_FILE), serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    pub fn block_key(&mut self, key: [u8; 32]) {
+        self.keys.insert(key);
+    }
+
+    pub fn unblock_key(&mut self, key: &[u8; 32]) {
+        self.keys.remove(key);
+    }
+
+    pub fn block_route(&mut self, route: &str) {
+        self.routes.insert(route.to_string());
+    }
+
+    pub fn unblock_route(&mut self, route: &str) {
+        self.routes.remove(route);
+    }
+
+    pub fn is_key_blocked(&self, key: &[u8; 32]) -> bool {
+        self.keys.contains(key)
+    }
+
+    pub fn is_route_blocked(&self, route: &str) -> bool {
+        self.routes.contains(route)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocks_persist() {
+        let tmp = crate::util::TempDir::new("blocklist");
+        let mut blocklist = Blocklist::default();
+        blocklist.block_key([1u8; 32]);
+        blocklist.block_route("VLD1:route:aa");
+        blocklist.block_route("VLD1:route:bb");
+        blocklist.unblock_route("VLD1:route:bb");
+        blocklist.save(tmp.path()).unwrap();
+
+        let restored = Blocklist::load(tmp.path()).unwrap();
+        assert!(restored.is_key_blocked(&[1u8; 32]));
+        assert!(!restored.is_key_blocked(&[2u8; 32]));
+        assert!(restored.is_route_blocked("VLD1:route:aa"));
+        assert!(!restored.is_route_blocked("VLD1:route:bb"));
+    }
+}
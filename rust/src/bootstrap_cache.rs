@@ -0,0 +1,92 @@
+use crate::error::Result;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// File name of the persisted peer cache inside the config directory
+pub(crate) const CACHE_FILE: &str = "bootstrap_cache.json";
+
+/// Maximum number of peers kept in the cache
+const MAX_CACHED_PEERS: usize = 64;
+
+/// A peer seen on a previous run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedPeer {
+    pub address: String,
+    pub last_seen: u64,
+}
+
+/// Previously-known peers, used to rejoin where default bootstraps are blocked
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BootstrapCache {
+    peers: Vec<CachedPeer>,
+}
+
+impl BootstrapCache {
+    /// Load the cache from a config directory (empty if none saved yet)
+    pub fn load(config_dir: &Path) -> Result<Self> {
+        let path = config_dir.join(CACHE_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let data = fs::read(path)?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    /// Persist the cache to a config directory
+    pub fn save(&self, config_dir: &Path) -> Result<()> {
+        fs::create_dir_all(config_dir)?;
+        let data = serde_json::to_vec(self)?;
+        fs::write(config_dir.join(CACHE_FILE), data)?;
+        Ok(())
+    }
+
+    /// Record a peer as seen, evicting the oldest when full
+    pub fn record_peer(&mut self, address: &str, now: u64) {
+        self.peers.retain(|p| p.address != address);
+        self.peers.push(CachedPeer {
+            address: address.to_string(),
+            last_seen: now,
+        });
+
+        self.peers.sort_by_key(|p| std::cmp::Reverse(p.last_seen));
+        self.peers.truncate(MAX_CACHED_PEERS);
+    }
+
+    /// Cached peer addresses, most recently seen first
+    pub fn addresses(&self) -> Vec<String> {
+        self.peers.iter().map(|p| p.address.clone()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_roundtrip() {
+        let tmp = crate::util::TempDir::new("bootstrap");
+        let dir = tmp.path();
+
+        let mut cache = BootstrapCache::default();
+        cache.record_peer("peer-a.example.org:5150", 100);
+        cache.record_peer("peer-b.example.org:5150", 200);
+        cache.record_peer("peer-a.example.org:5150", 300);
+        cache.save(dir).unwrap();
+
+        let loaded = BootstrapCache::load(dir).unwrap();
+        assert_eq!(
+            loaded.addresses(),
+            vec!["peer-a.example.org:5150", "peer-b.example.org:5150"]
+        );
+    }
+}
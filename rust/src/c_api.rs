@@ -0,0 +1,309 @@
+// Plain C ABI for native integrations that don't use Flutter
+// The header is generated by cbindgen with the c-header feature; the checked-in
+// copy in include/ is refreshed with URR_C_HEADER_DIR=include.
+// Handles are opaque; strings returned through out-parameters are owned by the
+// caller and must be released with urr_string_free. Every call returns URR_OK
+// or an FfiError code, with the message available from urr_last_error
+
+use crate::api;
+use crate::app_context::AppContext;
+use crate::ffi_error::FfiError;
+use crate::progress::{ProgressOperation, ProgressReporter};
+use serde_json::json;
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use tokio::runtime::Runtime;
+
+/// Returned by every call that succeeded
+pub const URR_OK: u32 = 0;
+
+/// Opaque handle to an open profile and the runtime driving it
+pub struct UrrContext {
+    runtime: Runtime,
+    ctx: AppContext,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Open a profile in `config_dir` and start background work
+/// On success `*out` receives a handle to pass to urr_context_close
+///
+/// # Safety
+/// `config_dir` must be a NUL-terminated string and `out` a valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn urr_context_open(config_dir: *const c_char, out: *mut *mut UrrContext) -> u32 {
+    guard(|| {
+        let config_dir = read_str(config_dir)?;
+        let out = out_ptr(out)?;
+        let runtime = Runtime::new().map_err(|_| FfiError::Internal)?;
+        let progress = ProgressReporter::silent(ProgressOperation::Startup);
+        let ctx = runtime.block_on(AppContext::open_configured(config_dir, &progress))?;
+        *out = Box::into_raw(Box::new(UrrContext { runtime, ctx }));
+        Ok(())
+    })
+}
+
+/// Stop background work and free the handle; null is ignored
+///
+/// # Safety
+/// `handle` must come from urr_context_open and not be used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn urr_context_close(handle: *mut UrrContext) -> u32 {
+    if handle.is_null() {
+        return URR_OK;
+    }
+    let handle = Box::from_raw(handle);
+    guard(move || Ok(handle.runtime.block_on(handle.ctx.close())?))
+}
+
+/// Attachment and background sync status as JSON
+///
+/// # Safety
+/// `handle` must be a live handle and `out` a valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn urr_status_json(handle: *const UrrContext, out: *mut *mut c_char) -> u32 {
+    guard(|| {
+        let UrrContext { runtime, ctx } = live(handle)?;
+        let status = runtime.block_on(async {
+            Ok::<_, FfiError>(json!({
+                "attachment": api::get_attachment_state(ctx).await?,
+                "sync": api::get_background_sync_status(ctx).await?,
+            }))
+        })?;
+        write_string(out, status.to_string())
+    })
+}
+
+/// Personas as a JSON array
+///
+/// # Safety
+/// `handle` must be a live handle and `out` a valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn urr_list_personas_json(handle: *const UrrContext, out: *mut *mut c_char) -> u32 {
+    guard(|| {
+        let UrrContext { runtime, ctx } = live(handle)?;
+        let personas = runtime.block_on(api::list_personas(ctx))?;
+        write_string(out, json!(personas).to_string())
+    })
+}
+
+/// Create a persona; the JSON result holds the secret key, shown only once
+///
+/// # Safety
+/// `handle` must be a live handle, `name` NUL-terminated and `out` a valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn urr_create_persona(
+    handle: *const UrrContext,
+    name: *const c_char,
+    out: *mut *mut c_char,
+) -> u32 {
+    guard(|| {
+        let UrrContext { runtime, ctx } = live(handle)?;
+        let created = runtime.block_on(api::create_persona(ctx, read_str(name)?))?;
+        write_string(
+            out,
+            json!({ "persona": created.persona, "secret_key": created.secret_key }).to_string(),
+        )
+    })
+}
+
+/// Make another persona the active one
+///
+/// # Safety
+/// `handle` must be a live handle and `persona_id` NUL-terminated
+#[no_mangle]
+pub unsafe extern "C" fn urr_switch_persona(handle: *const UrrContext, persona_id: *const c_char) -> u32 {
+    guard(|| {
+        let UrrContext { runtime, ctx } = live(handle)?;
+        runtime.block_on(api::switch_active_persona(ctx, read_str(persona_id)?))?;
+        Ok(())
+    })
+}
+
+/// QR text for the active persona's contact card
+///
+/// # Safety
+/// `handle` must be a live handle, `secret_key` NUL-terminated and `out` a valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn urr_my_contact_qr(
+    handle: *const UrrContext,
+    secret_key: *const c_char,
+    out: *mut *mut c_char,
+) -> u32 {
+    guard(|| {
+        let UrrContext { runtime, ctx } = live(handle)?;
+        let qr = runtime.block_on(api::generate_my_contact_qr(ctx, read_str(secret_key)?))?;
+        write_string(out, qr)
+    })
+}
+
+/// Verify a scanned contact code and describe the contact as JSON
+///
+/// # Safety
+/// `handle` must be a live handle, `text` NUL-terminated and `out` a valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn urr_parse_contact_qr(
+    handle: *const UrrContext,
+    text: *const c_char,
+    out: *mut *mut c_char,
+) -> u32 {
+    guard(|| {
+        let UrrContext { runtime, ctx } = live(handle)?;
+        let scanned = runtime.block_on(api::parse_contact_qr(ctx, read_str(text)?.into_bytes()))?;
+        let contact = json!({
+            "public_key": scanned.contact.public_key,
+            "route": scanned.contact.route,
+            "mailbox_key": scanned.contact.mailbox_key,
+            "created_at": scanned.contact.created_at,
+            "fingerprint": scanned.fingerprint,
+            "introduced_by": scanned.introduced_by,
+        });
+        write_string(out, contact.to_string())
+    })
+}
+
+/// Send `len` bytes from `data` to a remote private route
+///
+/// # Safety
+/// `handle` must be a live handle, `route` NUL-terminated and `data` valid for `len` bytes
+#[no_mangle]
+pub unsafe extern "C" fn urr_send_message(
+    handle: *const UrrContext,
+    route: *const c_char,
+    data: *const u8,
+    len: usize,
+) -> u32 {
+    guard(|| {
+        let UrrContext { runtime, ctx } = live(handle)?;
+        runtime.block_on(api::send_message_via_route(ctx, read_str(route)?, read_bytes(data, len)?))?;
+        Ok(())
+    })
+}
+
+/// Verify a vouch against the voucher's pinned key and describe it as JSON
+///
+/// # Safety
+/// `data` must be valid for `len` bytes, `voucher_key` NUL-terminated and `out` a valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn urr_verify_vouch(
+    data: *const u8,
+    len: usize,
+    voucher_key: *const c_char,
+    out: *mut *mut c_char,
+) -> u32 {
+    guard(|| {
+        let data = read_bytes(data, len)?;
+        let voucher_key = read_str(voucher_key)?;
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .map_err(|_| FfiError::Internal)?;
+        let vouch = runtime.block_on(api::verify_vouch(data, voucher_key))?;
+        let described = json!({
+            "voucher_public_key": vouch.voucher_public_key,
+            "subject_public_key": vouch.subject_public_key,
+            "method": format!("{:?}", vouch.method),
+            "verified_at": vouch.verified_at,
+        });
+        write_string(out, described.to_string())
+    })
+}
+
+/// Message for the last error on this thread, or null if the last call succeeded
+/// Release with urr_string_free
+#[no_mangle]
+pub extern "C" fn urr_last_error() -> *mut c_char {
+    LAST_ERROR.with(|last| last.borrow().clone().map_or(ptr::null_mut(), CString::into_raw))
+}
+
+/// Free a string returned by this library; null is ignored
+///
+/// # Safety
+/// `s` must come from this library and not be freed twice
+#[no_mangle]
+pub unsafe extern "C" fn urr_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Run `f`, turning errors and panics into codes and recording the message
+fn guard(f: impl FnOnce() -> Result<(), FfiError>) -> u32 {
+    let result = catch_unwind(AssertUnwindSafe(f)).unwrap_or(Err(FfiError::Internal));
+    let (code, message) = match result {
+        Ok(()) => (URR_OK, None),
+        Err(e) => (e.code(), CString::new(e.user_message()).ok()),
+    };
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+    code
+}
+
+unsafe fn live<'a>(handle: *const UrrContext) -> Result<&'a UrrContext, FfiError> {
+    handle.as_ref().ok_or(FfiError::NotInitialized)
+}
+
+unsafe fn read_str(s: *const c_char) -> Result<String, FfiError> {
+    if s.is_null() {
+        return Err(FfiError::InvalidInput("Missing string argument".to_string()));
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map(str::to_string)
+        .map_err(|_| FfiError::InvalidInput("String argument is not UTF-8".to_string()))
+}
+
+unsafe fn read_bytes(data: *const u8, len: usize) -> Result<Vec<u8>, FfiError> {
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+    if data.is_null() {
+        return Err(FfiError::InvalidInput("Missing data argument".to_string()));
+    }
+    Ok(std::slice::from_raw_parts(data, len).to_vec())
+}
+
+unsafe fn out_ptr<'a, T>(out: *mut *mut T) -> Result<&'a mut *mut T, FfiError> {
+    out.as_mut()
+        .ok_or_else(|| FfiError::InvalidInput("Missing output pointer".to_string()))
+}
+
+/// Hand a string to the caller through an out-parameter
+unsafe fn write_string(out: *mut *mut c_char, s: String) -> Result<(), FfiError> {
+    let out = out_ptr(out)?;
+    *out = CString::new(s).map_err(|_| FfiError::Internal)?.into_raw();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handle_round_trip() {
+        let tmp = crate::util::TempDir::new("c-api");
+        let dir_c = CString::new(tmp.path_string()).unwrap();
+
+        unsafe {
+            let mut handle = ptr::null_mut();
+            assert_eq!(urr_context_open(dir_c.as_ptr(), &mut handle), URR_OK);
+
+            let mut json = ptr::null_mut();
+            assert_eq!(urr_status_json(handle, &mut json), URR_OK);
+            assert!(CStr::from_ptr(json).to_str().unwrap().contains("\"sync\""));
+            urr_string_free(json);
+            assert!(urr_last_error().is_null());
+
+            let missing = CString::new("nope").unwrap();
+            assert_ne!(urr_switch_persona(handle, missing.as_ptr()), URR_OK);
+            let message = urr_last_error();
+            assert!(!message.is_null());
+            urr_string_free(message);
+
+            assert_eq!(urr_status_json(ptr::null(), &mut json), FfiError::NotInitialized.code());
+            assert_eq!(urr_context_close(handle), URR_OK);
+        }
+    }
+}
@@ -0,0 +1,176 @@
+// Chunked payloads for values larger than one DHT subkey
+// Subkey 0 of a record holds a manifest listing the hash of every chunk;
+// chunks follow in subkeys 1..=N. The receiver checks each chunk against the
+// manifest and the reassembled payload against the overall hash
+
+use crate::crypto::hash_blake3;
+use crate::error::{Result, UndergroundError};
+use crate::wire::Reader;
+
+const MAGIC: &[u8; 3] = b"URC";
+const VERSION: u8 = 1;
+
+/// Largest value Veilid stores in one subkey
+pub const MAX_SUBKEY_LEN: usize = 32 * 1024;
+
+/// Payload bytes per chunk
+pub const CHUNK_LEN: usize = MAX_SUBKEY_LEN;
+
+/// Most chunks in one payload; keeps the manifest inside a single subkey
+pub const MAX_CHUNKS: usize = 1000;
+
+/// Index and hashes of a chunked payload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkManifest {
+    pub total_len: u64,
+    pub payload_hash: [u8; 32],
+    pub chunk_hashes: Vec<[u8; 32]>,
+}
+
+impl ChunkManifest {
+    pub fn chunk_count(&self) -> usize {
+        self.chunk_hashes.len()
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(48 + 32 * self.chunk_hashes.len());
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&self.total_len.to_be_bytes());
+        out.extend_from_slice(&self.payload_hash);
+        out.extend_from_slice(&(self.chunk_hashes.len() as u16).to_be_bytes());
+        for hash in &self.chunk_hashes {
+            out.extend_from_slice(hash);
+        }
+        out
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(data, "Chunk manifest");
+        reader.header(MAGIC, VERSION)?;
+        let total_len = reader.u64()?;
+        let payload_hash = reader.array()?;
+        let count = reader.u16()? as usize;
+        if count == 0 || count > MAX_CHUNKS {
+            return Err(reader.invalid("bad chunk count"));
+        }
+        // Every chunk but the last is full
+        if total_len > (count * CHUNK_LEN) as u64 || total_len <= ((count - 1) * CHUNK_LEN) as u64 {
+            return Err(reader.invalid("length does not match chunk count"));
+        }
+        let chunk_hashes = (0..count).map(|_| reader.array()).collect::<Result<Vec<_>>>()?;
+        reader.finish()?;
+
+        Ok(Self {
+            total_len,
+            payload_hash,
+            chunk_hashes,
+        })
+    }
+
+    /// Expected length of chunk `index`
+    fn chunk_len(&self, index: usize) -> usize {
+        let start = index * CHUNK_LEN;
+        (self.total_len as usize - start).min(CHUNK_LEN)
+    }
+}
+
+/// Split a payload into a manifest and its chunks
+pub fn split(payload: &[u8]) -> Result<(ChunkManifest, Vec<Vec<u8>>)> {
+    if payload.is_empty() || payload.len() > MAX_CHUNKS * CHUNK_LEN {
+        return Err(UndergroundError::InvalidMessage(format!(
+            "Chunked payloads must be between 1 byte and {} bytes",
+            MAX_CHUNKS * CHUNK_LEN
+        )));
+    }
+    let chunks: Vec<Vec<u8>> = payload.chunks(CHUNK_LEN).map(<[u8]>::to_vec).collect();
+    let manifest = ChunkManifest {
+        total_len: payload.len() as u64,
+        payload_hash: hash_blake3(payload),
+        chunk_hashes: chunks.iter().map(|c| hash_blake3(c)).collect(),
+    };
+    Ok((manifest, chunks))
+}
+
+/// Address of chunk `index` within the record at `key` (subkey index + 1)
+/// The development DHT store addresses subkeys as "<key>#<subkey>"
+pub fn chunk_key(key: &str, index: usize) -> String {
+    format!("{}#{}", key, index + 1)
+}
+
+/// Collects verified chunks in any order
+#[derive(Debug)]
+pub struct Reassembler {
+    manifest: ChunkManifest,
+    chunks: Vec<Option<Vec<u8>>>,
+}
+
+impl Reassembler {
+    pub fn new(manifest: ChunkManifest) -> Self {
+        let chunks = vec![None; manifest.chunk_count()];
+        Self { manifest, chunks }
+    }
+
+    /// Accept chunk `index` if it matches the manifest
+    pub fn add(&mut self, index: usize, data: Vec<u8>) -> Result<()> {
+        let expected = self
+            .manifest
+            .chunk_hashes
+            .get(index)
+            .ok_or_else(|| UndergroundError::InvalidMessage("Chunk index out of range".to_string()))?;
+        if data.len() != self.manifest.chunk_len(index) || hash_blake3(&data) != *expected {
+            return Err(UndergroundError::InvalidMessage(format!("Chunk {} failed verification", index)));
+        }
+        self.chunks[index] = Some(data);
+        Ok(())
+    }
+
+    /// Indexes of chunks not received yet
+    pub fn missing(&self) -> Vec<usize> {
+        self.chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.is_none())
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Join the chunks and check the whole payload
+    pub fn finish(self) -> Result<Vec<u8>> {
+        let mut payload = Vec::with_capacity(self.manifest.total_len as usize);
+        for (index, chunk) in self.chunks.into_iter().enumerate() {
+            let chunk = chunk.ok_or_else(|| UndergroundError::RecordNotFound {
+                entity: "Chunk".to_string(),
+                id: index.to_string(),
+            })?;
+            payload.extend_from_slice(&chunk);
+        }
+        if hash_blake3(&payload) != self.manifest.payload_hash {
+            return Err(UndergroundError::InvalidMessage("Reassembled payload failed verification".to_string()));
+        }
+        Ok(payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_and_reassemble_out_of_order() {
+        let payload: Vec<u8> = (0..CHUNK_LEN * 2 + 100).map(|i| (i % 251) as u8).collect();
+        let (manifest, chunks) = split(&payload).unwrap();
+        assert_eq!(chunks.len(), 3);
+
+        let manifest = ChunkManifest::decode(&manifest.encode()).unwrap();
+        let mut reassembler = Reassembler::new(manifest);
+        reassembler.add(2, chunks[2].clone()).unwrap();
+        reassembler.add(0, chunks[0].clone()).unwrap();
+        assert!(reassembler.add(1, chunks[2].clone()).is_err());
+        assert_eq!(reassembler.missing(), vec![1]);
+
+        reassembler.add(1, chunks[1].clone()).unwrap();
+        assert_eq!(reassembler.finish().unwrap(), payload);
+        assert_eq!(chunk_key("VLD1:dht:aa", 0), "VLD1:dht:aa#1");
+    }
+}
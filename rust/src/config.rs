@@ -0,0 +1,176 @@
+use crate::error::{Result, UndergroundError};
+use serde::{Deserialize, Serialize};
+
+/// Public Veilid bootstrap nodes
+pub const DEFAULT_BOOTSTRAP_NODES: &[&str] = &["bootstrap.veilid.net"];
+
+/// Network footprint profile
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NetworkProfile {
+    /// Lowest latency, highest bandwidth and battery use
+    Performance,
+    #[default]
+    Balanced,
+    /// Smallest footprint for metered or monitored connections
+    Minimal,
+}
+
+/// Tunables derived from a network profile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetworkTuning {
+    /// How often to poll mailboxes and watched records
+    pub poll_interval_secs: u64,
+    /// Hops in allocated private routes
+    pub route_hop_count: u8,
+    /// How often owned DHT records are refreshed
+    pub dht_refresh_interval_secs: u64,
+    /// Send dummy traffic to mask real activity
+    pub cover_traffic: bool,
+}
+
+impl NetworkProfile {
+    pub fn tuning(&self) -> NetworkTuning {
+        match self {
+            Self::Performance => NetworkTuning {
+                poll_interval_secs: 5,
+                route_hop_count: 1,
+                dht_refresh_interval_secs: 10 * 60,
+                cover_traffic: true,
+            },
+            Self::Balanced => NetworkTuning {
+                poll_interval_secs: 30,
+                route_hop_count: 2,
+                dht_refresh_interval_secs: 30 * 60,
+                cover_traffic: true,
+            },
+            Self::Minimal => NetworkTuning {
+                poll_interval_secs: 5 * 60,
+                route_hop_count: 2,
+                dht_refresh_interval_secs: 2 * 60 * 60,
+                cover_traffic: false,
+            },
+        }
+    }
+}
+
+/// Veilid network configuration
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VeilidConfig {
+    /// User-supplied bootstrap nodes, tried first
+    pub bootstrap_nodes: Vec<String>,
+    /// Fall back to the public bootstrap nodes
+    pub use_default_bootstrap: bool,
+    /// Remember known peers so the node can rejoin without bootstraps
+    pub use_bootstrap_cache: bool,
+    /// Bandwidth and battery profile
+    pub network_profile: NetworkProfile,
+}
+
+impl Default for VeilidConfig {
+    fn default() -> Self {
+        Self {
+            bootstrap_nodes: Vec::new(),
+            use_default_bootstrap: true,
+            use_bootstrap_cache: true,
+            network_profile: NetworkProfile::default(),
+        }
+    }
+}
+
+impl VeilidConfig {
+    /// Check the configuration for unusable values
+    pub fn validate(&self) -> Result<()> {
+        for node in &self.bootstrap_nodes {
+            if node.trim().is_empty() || node.chars().any(char::is_whitespace) {
+                return Err(UndergroundError::Config(format!(
+                    "Invalid bootstrap node: {:?}",
+                    node
+                )));
+            }
+        }
+
+        if self.bootstrap_nodes.is_empty() && !self.use_default_bootstrap && !self.use_bootstrap_cache {
+            return Err(UndergroundError::Config(
+                "No bootstrap source configured".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Tunables for the selected network profile
+    pub fn tuning(&self) -> NetworkTuning {
+        self.network_profile.tuning()
+    }
+
+    /// Bootstrap list in priority order: user nodes, cached peers, defaults
+    pub fn bootstrap_list(&self, cached_peers: &[String]) -> Vec<String> {
+        let mut list: Vec<String> = Vec::new();
+
+        let cached = if self.use_bootstrap_cache { cached_peers } else { &[] };
+        let defaults: &[&str] = if self.use_default_bootstrap {
+            DEFAULT_BOOTSTRAP_NODES
+        } else {
+            &[]
+        };
+
+        let candidates = self
+            .bootstrap_nodes
+            .iter()
+            .map(String::as_str)
+            .chain(cached.iter().map(String::as_str))
+            .chain(defaults.iter().copied());
+
+        for node in candidates {
+            if !list.iter().any(|n| n == node) {
+                list.push(node.to_string());
+            }
+        }
+
+        list
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bootstrap_list_order() {
+        let config = VeilidConfig {
+            bootstrap_nodes: vec!["node.example.org".to_string()],
+            ..Default::default()
+        };
+        let cached = vec!["peer.example.org".to_string(), "node.example.org".to_string()];
+
+        let list = config.bootstrap_list(&cached);
+
+        assert_eq!(list[0], "node.example.org");
+        assert_eq!(list[1], "peer.example.org");
+        assert_eq!(list[2], DEFAULT_BOOTSTRAP_NODES[0]);
+        assert_eq!(list.len(), 3);
+    }
+
+    #[test]
+    fn test_minimal_profile_reduces_footprint() {
+        let minimal = NetworkProfile::Minimal.tuning();
+        let performance = NetworkProfile::Performance.tuning();
+
+        assert!(minimal.poll_interval_secs > performance.poll_interval_secs);
+        assert!(minimal.dht_refresh_interval_secs > performance.dht_refresh_interval_secs);
+        assert!(!minimal.cover_traffic);
+    }
+
+    #[test]
+    fn test_validate_rejects_no_bootstrap_source() {
+        let config = VeilidConfig {
+            bootstrap_nodes: Vec::new(),
+            use_default_bootstrap: false,
+            use_bootstrap_cache: false,
+            ..Default::default()
+        };
+
+        assert!(config.validate().is_err());
+    }
+}
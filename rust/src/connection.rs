@@ -0,0 +1,368 @@
+// Connection requests between people who have not pinned each other yet
+// A request carries the sender's signed route bundle and encryption key with a
+// short note, and waits in the recipient's pending list until the user
+// approves or rejects it. The signed answer names the request it settles, so
+// a sender only pins contacts that answered a request it actually made
+
+use crate::crypto::{generate_random_bytes, sign_data, signing_public_key, verify_signature};
+use crate::error::{Result, UndergroundError};
+use crate::persona::PersonaScoped;
+use crate::route_blob::RouteBundle;
+use crate::wire::{put_bytes, split_signature, Reader};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+const REQUEST_MAGIC: &[u8; 3] = b"URJ";
+const RESPONSE_MAGIC: &[u8; 3] = b"URY";
+const VERSION: u8 = 1;
+
+/// File name of the persisted connection requests inside the config directory
+pub(crate) const CONNECTIONS_FILE: &str = "connections.json";
+
+/// Longest note sent with a request
+pub const MAX_NOTE_LEN: usize = 280;
+
+/// Requests kept waiting for a decision, and our own awaiting an answer,
+/// before the oldest is dropped
+const MAX_PENDING: usize = 256;
+
+/// Largest embedded route bundle
+const MAX_CARD_LEN: usize = 4096;
+
+/// A signed request to become contacts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionRequest {
+    pub id: [u8; 16],
+    pub card: RouteBundle,
+    pub encryption_key: [u8; 32],
+    pub note: String,
+    pub sent_at: u64,
+    signature: [u8; 64],
+}
+
+impl ConnectionRequest {
+    /// Request a connection as the owner of `card`, signed with its secret key
+    pub fn create(secret_key: &[u8], card: RouteBundle, encryption_key: [u8; 32], note: &str) -> Result<Self> {
+        if card.public_key != signing_public_key(secret_key)? {
+            return Err(UndergroundError::InvalidKey);
+        }
+        if note.len() > MAX_NOTE_LEN {
+            return Err(UndergroundError::InvalidMessage("Note too long".to_string()));
+        }
+        let mut id = [0u8; 16];
+        id.copy_from_slice(&generate_random_bytes(16));
+        let mut request = Self {
+            id,
+            card,
+            encryption_key,
+            note: note.to_string(),
+            sent_at: crate::util::unix_now(),
+            signature: [0u8; 64],
+        };
+        request.signature = sign_data(secret_key, &request.signed_bytes())?;
+        Ok(request)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = self.signed_bytes();
+        out.extend_from_slice(&self.signature);
+        out
+    }
+
+    /// Decode a request, verifying it was signed by the owner of its card
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let (body, signature) = split_signature(data, "Connection request")?;
+
+        let mut reader = Reader::new(body, "Connection request");
+        reader.header(REQUEST_MAGIC, VERSION)?;
+        let id = reader.array()?;
+        let encryption_key = reader.array()?;
+        let sent_at = reader.u64()?;
+        let card = RouteBundle::decode(reader.bytes(MAX_CARD_LEN)?)?;
+        let note = reader.string(MAX_NOTE_LEN)?;
+        reader.finish()?;
+
+        verify_signature(&card.public_key, body, &signature)?;
+
+        Ok(Self {
+            id,
+            card,
+            encryption_key,
+            note,
+            sent_at,
+            signature,
+        })
+    }
+
+    fn signed_bytes(&self) -> Vec<u8> {
+        let card = self.card.encode();
+        let mut out = Vec::with_capacity(68 + card.len() + self.note.len());
+        out.extend_from_slice(REQUEST_MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&self.id);
+        out.extend_from_slice(&self.encryption_key);
+        out.extend_from_slice(&self.sent_at.to_be_bytes());
+        put_bytes(&mut out, &card);
+        put_bytes(&mut out, self.note.as_bytes());
+        out
+    }
+}
+
+/// What an approving answer hands back: the responder's card and encryption key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionAcceptance {
+    pub card: RouteBundle,
+    pub encryption_key: [u8; 32],
+}
+
+/// A signed answer to a connection request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionResponse {
+    pub request_id: [u8; 16],
+    pub responder_key: [u8; 32],
+    pub sent_at: u64,
+    /// None for a rejection
+    pub acceptance: Option<ConnectionAcceptance>,
+    signature: [u8; 64],
+}
+
+impl ConnectionResponse {
+    /// Answer request `request_id`, approving it when `acceptance` is given
+    /// An acceptance must carry the responder's own card
+    pub fn create(secret_key: &[u8], request_id: [u8; 16], acceptance: Option<ConnectionAcceptance>) -> Result<Self> {
+        let responder_key = signing_public_key(secret_key)?;
+        if acceptance.as_ref().is_some_and(|a| a.card.public_key != responder_key) {
+            return Err(UndergroundError::InvalidKey);
+        }
+        let mut response = Self {
+            request_id,
+            responder_key,
+            sent_at: crate::util::unix_now(),
+            acceptance,
+            signature: [0u8; 64],
+        };
+        response.signature = sign_data(secret_key, &response.signed_bytes())?;
+        Ok(response)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = self.signed_bytes();
+        out.extend_from_slice(&self.signature);
+        out
+    }
+
+    /// Decode an answer, verifying the responder's signature
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let (body, signature) = split_signature(data, "Connection response")?;
+
+        let mut reader = Reader::new(body, "Connection response");
+        reader.header(RESPONSE_MAGIC, VERSION)?;
+        let request_id = reader.array()?;
+        let responder_key: [u8; 32] = reader.array()?;
+        let sent_at = reader.u64()?;
+        let acceptance = match reader.u8()? {
+            0 => None,
+            1 => Some(ConnectionAcceptance {
+                encryption_key: reader.array()?,
+                card: RouteBundle::decode(reader.bytes(MAX_CARD_LEN)?)?,
+            }),
+            _ => return Err(reader.invalid("unknown answer")),
+        };
+        reader.finish()?;
+
+        verify_signature(&responder_key, body, &signature)?;
+        if acceptance.as_ref().is_some_and(|a| a.card.public_key != responder_key) {
+            return Err(UndergroundError::AuthenticationFailed);
+        }
+
+        Ok(Self {
+            request_id,
+            responder_key,
+            sent_at,
+            acceptance,
+            signature,
+        })
+    }
+
+    fn signed_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(128);
+        out.extend_from_slice(RESPONSE_MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&self.request_id);
+        out.extend_from_slice(&self.responder_key);
+        out.extend_from_slice(&self.sent_at.to_be_bytes());
+        match &self.acceptance {
+            Some(acceptance) => {
+                out.push(1);
+                out.extend_from_slice(&acceptance.encryption_key);
+                put_bytes(&mut out, &acceptance.card.encode());
+            }
+            None => out.push(0),
+        }
+        out
+    }
+}
+
+/// A connection message of either kind, as it arrives in the inbox
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionMessage {
+    Request(ConnectionRequest),
+    Response(ConnectionResponse),
+}
+
+impl ConnectionMessage {
+    /// Decode and verify a request or an answer, told apart by their magic
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        match data.get(..REQUEST_MAGIC.len()) {
+            Some(magic) if magic == REQUEST_MAGIC => Ok(Self::Request(ConnectionRequest::decode(data)?)),
+            Some(magic) if magic == RESPONSE_MAGIC => Ok(Self::Response(ConnectionResponse::decode(data)?)),
+            _ => Err(UndergroundError::InvalidMessage("Not a connection message".to_string())),
+        }
+    }
+}
+
+/// A received request waiting for the user's decision
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingConnection {
+    /// The sender's contact id
+    pub contact: String,
+    pub route: String,
+    pub mailbox_key: String,
+    /// Hex of the sender's encryption public key
+    pub encryption_key: String,
+    pub note: String,
+    pub received_at: u64,
+    /// Hex id the answer must name
+    pub request_id: String,
+}
+
+/// Connection requests of one persona
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Connections {
+    /// Received requests by sender
+    pending: HashMap<String, PendingConnection>,
+    /// Hex ids of requests we sent that are not answered yet, with when they were sent
+    sent: HashMap<String, u64>,
+}
+
+/// Connection requests of every persona
+pub type ConnectionStore = PersonaScoped<Connections>;
+
+/// Load saved requests from a config directory (empty if none saved yet)
+pub fn load(config_dir: &Path) -> Result<ConnectionStore> {
+    let path = config_dir.join(CONNECTIONS_FILE);
+    if !path.exists() {
+        return Ok(ConnectionStore::default());
+    }
+    Ok(serde_json::from_slice(&fs::read(path)?)?)
+}
+
+pub fn save(store: &ConnectionStore, config_dir: &Path) -> Result<()> {
+    fs::create_dir_all(config_dir)?;
+    fs::write(config_dir.join(CONNECTIONS_FILE), serde_json::to_vec(store)?)?;
+    Ok(())
+}
+
+impl Connections {
+    /// Keep a verified request for the user, replacing any earlier one from the
+    /// same sender
+    pub fn receive(&mut self, request: &ConnectionRequest, now: u64) -> PendingConnection {
+        let contact = format!("VLD1:pub:{}", hex::encode(request.card.public_key));
+        let pending = PendingConnection {
+            contact: contact.clone(),
+            route: request.card.route.clone(),
+            mailbox_key: request.card.mailbox_key.clone(),
+            encryption_key: hex::encode(request.encryption_key),
+            note: request.note.clone(),
+            received_at: now,
+            request_id: hex::encode(request.id),
+        };
+        if !self.pending.contains_key(&contact) && self.pending.len() >= MAX_PENDING {
+            if let Some(oldest) = self
+                .pending
+                .values()
+                .min_by_key(|p| p.received_at)
+                .map(|p| p.contact.clone())
+            {
+                self.pending.remove(&oldest);
+            }
+        }
+        self.pending.insert(contact, pending.clone());
+        pending
+    }
+
+    /// Requests waiting for a decision, oldest first
+    pub fn pending(&self) -> Vec<PendingConnection> {
+        let mut pending: Vec<PendingConnection> = self.pending.values().cloned().collect();
+        pending.sort_by(|a, b| a.received_at.cmp(&b.received_at).then(a.contact.cmp(&b.contact)));
+        pending
+    }
+
+    /// Remove the request from `contact` once it is decided
+    pub fn take(&mut self, contact: &str) -> Option<PendingConnection> {
+        self.pending.remove(contact)
+    }
+
+    /// Remember a request we sent so its answer is accepted
+    pub fn record_sent(&mut self, id: &[u8; 16], now: u64) {
+        if self.sent.len() >= MAX_PENDING {
+            if let Some(oldest) = self.sent.iter().min_by_key(|(_, at)| **at).map(|(id, _)| id.clone()) {
+                self.sent.remove(&oldest);
+            }
+        }
+        self.sent.insert(hex::encode(id), now);
+    }
+
+    /// Settle a request we sent; false if we made no such request or it was answered
+    pub fn take_sent(&mut self, id: &[u8; 16]) -> bool {
+        self.sent.remove(&hex::encode(id)).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_signing_keypair;
+
+    #[test]
+    fn test_request_and_answer_roundtrip() {
+        let (alice_sk, alice_pk) = generate_signing_keypair();
+        let (bob_sk, bob_pk) = generate_signing_keypair();
+        let alice_card = RouteBundle::create("route-alice", "mailbox-alice", alice_sk.as_slice()).unwrap();
+        let bob_card = RouteBundle::create("route-bob", "mailbox-bob", bob_sk.as_slice()).unwrap();
+        assert!(ConnectionRequest::create(bob_sk.as_slice(), alice_card.clone(), [1; 32], "hi").is_err());
+
+        // Alice asks; Bob keeps the request until he decides
+        let mut alice = Connections::default();
+        let request = ConnectionRequest::create(alice_sk.as_slice(), alice_card, [1; 32], "from the library").unwrap();
+        alice.record_sent(&request.id, 10);
+        let received = ConnectionRequest::decode(&request.encode()).unwrap();
+        let mut bob = Connections::default();
+        let pending = bob.receive(&received, 11);
+        assert_eq!(pending.contact, format!("VLD1:pub:{}", hex::encode(alice_pk)));
+        assert_eq!((pending.route.as_str(), pending.note.as_str()), ("route-alice", "from the library"));
+        assert_eq!(bob.pending(), vec![pending.clone()]);
+
+        let mut tampered = request.encode();
+        tampered[20] ^= 1;
+        assert!(ConnectionRequest::decode(&tampered).is_err());
+
+        // Bob approves with his own card; Alice takes the answer once
+        assert!(bob.take(&pending.contact).is_some());
+        let acceptance = ConnectionAcceptance {
+            card: bob_card,
+            encryption_key: [2; 32],
+        };
+        let response = ConnectionResponse::create(bob_sk.as_slice(), request.id, Some(acceptance)).unwrap();
+        let answer = ConnectionResponse::decode(&response.encode()).unwrap();
+        assert_eq!(answer.responder_key, bob_pk);
+        assert_eq!(answer.acceptance.unwrap().card.route, "route-bob");
+        assert!(alice.take_sent(&answer.request_id));
+        assert!(!alice.take_sent(&answer.request_id));
+
+        let rejection = ConnectionResponse::create(bob_sk.as_slice(), [9; 16], None).unwrap();
+        assert!(ConnectionResponse::decode(&rejection.encode()).unwrap().acceptance.is_none());
+    }
+}
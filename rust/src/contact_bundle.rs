@@ -0,0 +1,134 @@
+// Password-encrypted contact bundles for seeding a new cell member's device
+// A bundle carries the pins of contacts the user marked shareable: the hash of
+// each contact's keys and, when allowed, the routes learned for it. The keys
+// themselves are never stored, so the new device still receives them from the
+// contact's card, and they must match the pin it was seeded with
+
+use crate::crypto::{decrypt_data, derive_key, encrypt_data, generate_salt};
+use crate::error::{Result, UndergroundError};
+use crate::pinning::{SharedPin, MAX_ROUTES_PER_CONTACT};
+use crate::wire::{put_bytes, Reader};
+
+const MAGIC: &[u8; 3] = b"URP";
+const VERSION: u8 = 1;
+
+/// Most contacts in one bundle
+pub const MAX_BUNDLE_CONTACTS: usize = 4096;
+
+/// Longest contact key or route carried
+const MAX_ADDRESS_LEN: usize = 1024;
+
+/// Encrypt `contacts` under a key derived from `password`
+pub fn seal(contacts: &[SharedPin], password: &str) -> Result<Vec<u8>> {
+    if contacts.len() > MAX_BUNDLE_CONTACTS {
+        return Err(UndergroundError::InvalidMessage("Too many contacts for one bundle".to_string()));
+    }
+    let too_long = contacts.iter().any(|c| {
+        c.contact.len() > MAX_ADDRESS_LEN
+            || c.routes.len() > MAX_ROUTES_PER_CONTACT
+            || c.routes.iter().any(|r| r.len() > MAX_ADDRESS_LEN)
+    });
+    if too_long {
+        return Err(UndergroundError::InvalidMessage("Contact too large for a bundle".to_string()));
+    }
+
+    let mut payload = Vec::new();
+    payload.extend_from_slice(&(contacts.len() as u16).to_be_bytes());
+    for contact in contacts {
+        put_bytes(&mut payload, contact.contact.as_bytes());
+        payload.extend_from_slice(&contact.key_hash);
+        payload.push(contact.routes.len() as u8);
+        for route in &contact.routes {
+            put_bytes(&mut payload, route.as_bytes());
+        }
+    }
+
+    let salt = generate_salt();
+    let key = derive_key(password, &salt)?;
+    let ciphertext = encrypt_data(key.as_slice(), &payload)?;
+
+    let mut out = Vec::with_capacity(4 + salt.len() + ciphertext.len());
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    out.extend_from_slice(&salt);
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+/// Decrypt and parse a bundle
+pub fn open(data: &[u8], password: &str) -> Result<Vec<SharedPin>> {
+    let mut reader = Reader::new(data, "Contact bundle");
+    reader.header(MAGIC, VERSION)?;
+    let salt: [u8; 32] = reader.array()?;
+    let key = derive_key(password, &salt)?;
+    let payload = decrypt_data(key.as_slice(), reader.rest()).map_err(|_| UndergroundError::WrongPassword)?;
+
+    let mut reader = Reader::new(&payload, "Contact bundle");
+    let count = reader.u16()? as usize;
+    if count > MAX_BUNDLE_CONTACTS {
+        return Err(reader.invalid("too many contacts"));
+    }
+    let mut contacts = Vec::with_capacity(count);
+    for _ in 0..count {
+        let contact = reader.string(MAX_ADDRESS_LEN)?;
+        let key_hash = reader.array()?;
+        let route_count = reader.u8()? as usize;
+        if route_count > MAX_ROUTES_PER_CONTACT {
+            return Err(reader.invalid("too many routes"));
+        }
+        let routes = (0..route_count)
+            .map(|_| reader.string(MAX_ADDRESS_LEN))
+            .collect::<Result<_>>()?;
+        contacts.push(SharedPin {
+            contact,
+            key_hash,
+            routes,
+        });
+    }
+    reader.finish()?;
+    Ok(contacts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pinning::{ContactKeys, ContactPins, PinPolicy, PinStatus, SharePolicy};
+
+    fn keys(byte: u8) -> ContactKeys {
+        ContactKeys {
+            signing: [byte; 32],
+            encryption: [byte; 32],
+        }
+    }
+
+    #[test]
+    fn test_only_consented_pins_travel() {
+        let mut pins = ContactPins::default();
+        for (contact, byte) in [("alice", 1), ("bob", 2), ("carol", 3)] {
+            pins.pin(contact, &keys(byte), 10);
+            pins.add_route(contact, &format!("VLD1:route:{}", contact));
+        }
+        assert!(pins.set_share("alice", SharePolicy::WithRoutes));
+        assert!(pins.set_share("bob", SharePolicy::KeysOnly));
+        assert!(pins.shared("carol").is_err(), "never marked shareable");
+
+        let shared = vec![pins.shared("alice").unwrap(), pins.shared("bob").unwrap()];
+        let bundle = seal(&shared, "cell password").unwrap();
+        assert!(matches!(open(&bundle, "wrong"), Err(UndergroundError::WrongPassword)));
+        let opened = open(&bundle, "cell password").unwrap();
+        assert_eq!(opened, shared);
+        assert!(opened[1].routes.is_empty());
+
+        // The new device already knows bob under other keys: that is held as a key change
+        let mut seeded = ContactPins::default();
+        seeded.pin("bob", &keys(9), 5);
+        let imported = seeded.import_shared(opened, 30);
+        assert_eq!((imported.added, imported.key_changes), (1, 1));
+        assert!(seeded.has_violation("bob"));
+
+        // Alice's card must match the seeded pin, and she is not shareable onward
+        assert_eq!(seeded.check("alice", &keys(1), PinPolicy::Strict, 31), PinStatus::Matches);
+        assert_eq!(seeded.contacts()[0].routes, vec!["VLD1:route:alice".to_string()]);
+        assert!(seeded.shared("alice").is_err());
+    }
+}
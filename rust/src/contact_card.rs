@@ -0,0 +1,124 @@
+// Contact cards: a route bundle plus an optional introducer chain
+// The chain is a run of vouches "A vouches for B, B vouches for C" ending at
+// the card's owner, so a scanned contact carries provenance that can be
+// checked rather than taken on trust
+
+use crate::error::{Result, UndergroundError};
+use crate::route_blob::RouteBundle;
+use crate::vouch::Vouch;
+use crate::wire::{put_bytes, Reader};
+
+const MAGIC: &[u8; 3] = b"URQ";
+const VERSION: u8 = 1;
+
+/// Longest introducer chain carried by one card
+pub const MAX_CHAIN_LEN: usize = 4;
+
+/// Largest embedded route bundle or vouch
+const MAX_PART_LEN: usize = 4096;
+
+/// A contact's signed route bundle and the vouches leading to it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContactCard {
+    pub bundle: RouteBundle,
+    /// Vouches from the first introducer down to the card's owner
+    pub chain: Vec<Vouch>,
+}
+
+impl ContactCard {
+    /// Card with an introducer chain, checked before it is handed out
+    pub fn new(bundle: RouteBundle, chain: Vec<Vouch>) -> Result<Self> {
+        let card = Self { bundle, chain };
+        card.check_chain()?;
+        Ok(card)
+    }
+
+    /// Key of the first introducer, if the card has a chain
+    pub fn chain_root(&self) -> Option<[u8; 32]> {
+        self.chain.first().map(|v| v.voucher_key)
+    }
+
+    /// Check the chain starts at a key we have pinned
+    pub fn verify_root(&self, pinned_root_key: &[u8; 32]) -> Result<()> {
+        match self.chain.first() {
+            Some(first) => first.verify(pinned_root_key),
+            None => Err(UndergroundError::InvalidMessage("Contact card has no introducers".to_string())),
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let bundle = self.bundle.encode();
+        let mut out = Vec::with_capacity(8 + bundle.len() + 150 * self.chain.len());
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        put_bytes(&mut out, &bundle);
+        out.push(self.chain.len() as u8);
+        for vouch in &self.chain {
+            put_bytes(&mut out, &vouch.encode());
+        }
+        out
+    }
+
+    /// Decode a card, verifying the bundle, every vouch and the links between them
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(data, "Contact card");
+        reader.header(MAGIC, VERSION)?;
+        let bundle = RouteBundle::decode(reader.bytes(MAX_PART_LEN)?)?;
+        let count = reader.u8()? as usize;
+        if count > MAX_CHAIN_LEN {
+            return Err(reader.invalid("introducer chain too long"));
+        }
+        let chain = (0..count)
+            .map(|_| Vouch::decode(reader.bytes(MAX_PART_LEN)?))
+            .collect::<Result<Vec<_>>>()?;
+        reader.finish()?;
+
+        Self::new(bundle, chain)
+    }
+
+    fn check_chain(&self) -> Result<()> {
+        if self.chain.len() > MAX_CHAIN_LEN {
+            return Err(UndergroundError::InvalidMessage("Introducer chain too long".to_string()));
+        }
+        let links = self.chain.windows(2).all(|pair| pair[0].subject_key == pair[1].voucher_key);
+        let ends_at_owner = self.chain.last().is_none_or(|v| v.subject_key == self.bundle.public_key);
+        if !links || !ends_at_owner {
+            return Err(UndergroundError::InvalidMessage("Broken introducer chain".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_signing_keypair;
+    use crate::vouch::VerificationMethod;
+
+    #[test]
+    fn test_chain_verified_on_decode() {
+        let (a_sk, a_pk) = generate_signing_keypair();
+        let (b_sk, b_pk) = generate_signing_keypair();
+        let (c_sk, c_pk) = generate_signing_keypair();
+        let a_for_b = Vouch::create(a_sk.as_slice(), b_pk, VerificationMethod::InPerson, 1_700_000_000).unwrap();
+        let b_for_c = Vouch::create(b_sk.as_slice(), c_pk, VerificationMethod::VideoCall, 1_700_000_000).unwrap();
+        let bundle = RouteBundle::create("route-c", "mailbox-c", c_sk.as_slice()).unwrap();
+
+        let card = ContactCard::new(bundle.clone(), vec![a_for_b.clone(), b_for_c.clone()]).unwrap();
+        let decoded = ContactCard::decode(&card.encode()).unwrap();
+        assert_eq!(decoded, card);
+        assert_eq!(decoded.chain_root(), Some(a_pk));
+        assert!(decoded.verify_root(&a_pk).is_ok());
+        assert!(decoded.verify_root(&b_pk).is_err());
+
+        assert!(ContactCard::new(bundle.clone(), vec![b_for_c.clone(), a_for_b]).is_err());
+        assert!(ContactCard::new(bundle, vec![]).unwrap().verify_root(&a_pk).is_err());
+        let mut tampered = card.encode();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 0x01;
+        assert!(ContactCard::decode(&tampered).is_err());
+
+        let (wrapped, _) = crate::key_wrap::wrap_storage_key(&[7u8; 32]).unwrap();
+        assert!(ContactCard::decode(&wrapped).is_err());
+    }
+}
@@ -0,0 +1,100 @@
+// Contact QR codes: a signed route bundle as scannable text
+// Cards with an introducer chain use a second prefix; plain cards keep the
+// original one so older scanners still read them
+
+use crate::contact_card::ContactCard;
+use crate::error::{Result, UndergroundError};
+use crate::route_blob::RouteBundle;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+
+/// Prefix identifying our QR payloads
+const QR_PREFIX: &str = "URR1:";
+
+/// Prefix of QR payloads carrying a full contact card
+const CARD_QR_PREFIX: &str = "URR2:";
+
+/// Longest QR text (base64url needs byte mode, where a version 40 QR code
+/// holds 2953 bytes at the lowest error correction)
+const MAX_QR_TEXT_LEN: usize = 2953;
+
+/// Number of hash bytes shown in a fingerprint
+const FINGERPRINT_BYTES: usize = 10;
+
+/// Encode a route bundle as QR text
+pub fn encode_qr(bundle: &RouteBundle) -> Result<String> {
+    fit_qr(format!("{}{}", QR_PREFIX, URL_SAFE_NO_PAD.encode(bundle.encode())))
+}
+
+/// Encode a contact card as QR text
+pub fn encode_card_qr(card: &ContactCard) -> Result<String> {
+    if card.chain.is_empty() {
+        return encode_qr(&card.bundle);
+    }
+    fit_qr(format!("{}{}", CARD_QR_PREFIX, URL_SAFE_NO_PAD.encode(card.encode())))
+}
+
+/// Refuse text no QR code can hold
+fn fit_qr(text: String) -> Result<String> {
+    if text.len() > MAX_QR_TEXT_LEN {
+        return Err(UndergroundError::InvalidMessage("Contact card too large for a QR code".to_string()));
+    }
+    Ok(text)
+}
+
+/// Decode and verify scanned QR text, including any introducer chain
+pub fn decode_qr(text: &str) -> Result<ContactCard> {
+    if text.len() > MAX_QR_TEXT_LEN {
+        return Err(UndergroundError::InvalidMessage("Contact QR code too large".to_string()));
+    }
+    let text = text.trim();
+    if let Some(encoded) = text.strip_prefix(QR_PREFIX) {
+        return ContactCard::new(RouteBundle::decode(&decode_base64(encoded)?)?, Vec::new());
+    }
+    let encoded = text
+        .strip_prefix(CARD_QR_PREFIX)
+        .ok_or_else(|| UndergroundError::InvalidMessage("Not a contact QR code".to_string()))?;
+    ContactCard::decode(&decode_base64(encoded)?)
+}
+
+fn decode_base64(encoded: &str) -> Result<Vec<u8>> {
+    URL_SAFE_NO_PAD
+        .decode(encoded)
+        .map_err(|_| UndergroundError::InvalidMessage("Damaged contact QR code".to_string()))
+}
+
+/// Short fingerprint of a public key for reading aloud, e.g. "3F2A 9C01 ..."
+pub fn fingerprint(public_key: &[u8; 32]) -> String {
+    let hash = crate::crypto::hash_blake3(public_key);
+    hex::encode_upper(&hash[..FINGERPRINT_BYTES])
+        .as_bytes()
+        .chunks(4)
+        .map(|group| String::from_utf8_lossy(group).into_owned())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_signing_keypair;
+
+    #[test]
+    fn test_qr_round_trip() {
+        let (secret, public) = generate_signing_keypair();
+        let bundle = RouteBundle::create("VLD1:route:aa", "VLD1:dht:bb", secret.as_slice()).unwrap();
+
+        let text = encode_qr(&bundle).unwrap();
+        let decoded = decode_qr(&text).unwrap();
+        assert_eq!(decoded.bundle.public_key, public);
+        assert_eq!(decoded.bundle.route, "VLD1:route:aa");
+        assert!(decoded.chain.is_empty());
+        assert_eq!(encode_card_qr(&decoded).unwrap(), text);
+
+        assert!(decode_qr("hello").is_err());
+        assert!(decode_qr(&text[..text.len() - 4]).is_err());
+        assert_eq!(fingerprint(&public).len(), 24);
+
+        assert!(fit_qr("A".repeat(MAX_QR_TEXT_LEN + 1)).is_err());
+    }
+}
@@ -0,0 +1,246 @@
+// Core configuration file (underground-railroad.toml in the config directory)
+// Values come from built-in defaults, then the file, then URR_* environment
+// variables. Only settings in the "safe" group can change while running; the
+// rest are reported as needing a restart
+
+use crate::config::{NetworkProfile, VeilidConfig};
+use crate::crypto::KdfParams;
+use crate::error::{Result, UndergroundError};
+use crate::message_codec::CompressionConfig;
+use crate::pinning::PinningConfig;
+use crate::relay_cache::RelayCacheConfig;
+use crate::shaper::ShaperConfig;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Configuration file name inside the config directory
+pub const CONFIG_FILE: &str = "underground-railroad.toml";
+
+/// Queued messages older than this are dropped rather than sent late
+pub const DEFAULT_OUTBOX_MAX_AGE_SECS: u64 = 7 * 24 * 3600;
+
+/// Buffered log records older than this are dropped
+pub const DEFAULT_LOG_MAX_AGE_SECS: u64 = 24 * 3600;
+
+/// Received messages, and sends still waiting for an ACK, older than this are dropped
+pub const DEFAULT_MESSAGE_MAX_AGE_SECS: u64 = 90 * 24 * 3600;
+
+/// Weakest Argon2id memory cost accepted (OWASP minimum for Argon2id)
+const MIN_KDF_MEMORY_KIB: u32 = 19 * 1024;
+
+/// Longest a relay may hold a blob for an offline contact
+const MAX_RELAY_TTL_SECS: u64 = 14 * 24 * 3600;
+
+/// How long each kind of data is kept before it is dropped
+/// Held relay blobs follow relay.ttl_secs instead
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionConfig {
+    pub outbox_max_age_secs: u64,
+    pub log_max_age_secs: u64,
+    pub message_max_age_secs: u64,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            outbox_max_age_secs: DEFAULT_OUTBOX_MAX_AGE_SECS,
+            log_max_age_secs: DEFAULT_LOG_MAX_AGE_SECS,
+            message_max_age_secs: DEFAULT_MESSAGE_MAX_AGE_SECS,
+        }
+    }
+}
+
+/// Everything read from the configuration file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CoreConfig {
+    /// Where profile files live; defaults to the config directory
+    pub data_dir: Option<PathBuf>,
+    pub kdf: KdfParams,
+    pub network: VeilidConfig,
+    pub retention: RetentionConfig,
+    /// Store-and-forward for contacts we relay to (off by default)
+    pub relay: RelayCacheConfig,
+    /// Message kinds compressed before encryption
+    pub compression: CompressionConfig,
+    /// How contact keys are pinned
+    pub pinning: PinningConfig,
+    /// Timing jitter and batching of non-critical sends (off by default)
+    pub shaping: ShaperConfig,
+}
+
+impl CoreConfig {
+    /// Load the file in `config_dir` (defaults if missing), apply environment
+    /// overrides and validate the result
+    pub fn load(config_dir: &Path) -> Result<Self> {
+        let path = config_dir.join(CONFIG_FILE);
+        let mut config = if path.exists() {
+            Self::parse(&fs::read_to_string(path)?)?
+        } else {
+            Self::default()
+        };
+        config.apply_env(|name| std::env::var(name).ok())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Parse TOML, rejecting unknown keys
+    pub fn parse(text: &str) -> Result<Self> {
+        toml::from_str(text).map_err(|e| UndergroundError::Config(format!("{}: {}", CONFIG_FILE, e.message())))
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string_pretty(self).map_err(|e| UndergroundError::Config(e.to_string()))
+    }
+
+    /// Override settings from URR_* variables looked up through `var`
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<()> {
+        if let Some(dir) = var("URR_DATA_DIR") {
+            self.data_dir = Some(PathBuf::from(dir));
+        }
+        if let Some(profile) = var("URR_NETWORK_PROFILE") {
+            self.network.network_profile = match profile.to_ascii_lowercase().as_str() {
+                "performance" => NetworkProfile::Performance,
+                "balanced" => NetworkProfile::Balanced,
+                "minimal" => NetworkProfile::Minimal,
+                _ => return Err(UndergroundError::Config(format!("URR_NETWORK_PROFILE: unknown profile {:?}", profile))),
+            };
+        }
+        if let Some(nodes) = var("URR_BOOTSTRAP_NODES") {
+            self.network.bootstrap_nodes = nodes
+                .split(',')
+                .map(str::trim)
+                .filter(|n| !n.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        if let Some(value) = var("URR_KDF_MEMORY_KIB") {
+            self.kdf.memory_kib = parse_env("URR_KDF_MEMORY_KIB", &value)?;
+        }
+        if let Some(value) = var("URR_OUTBOX_MAX_AGE_SECS") {
+            self.retention.outbox_max_age_secs = parse_env("URR_OUTBOX_MAX_AGE_SECS", &value)?;
+        }
+        if let Some(value) = var("URR_LOG_MAX_AGE_SECS") {
+            self.retention.log_max_age_secs = parse_env("URR_LOG_MAX_AGE_SECS", &value)?;
+        }
+        if let Some(value) = var("URR_MESSAGE_MAX_AGE_SECS") {
+            self.retention.message_max_age_secs = parse_env("URR_MESSAGE_MAX_AGE_SECS", &value)?;
+        }
+        Ok(())
+    }
+
+    /// Check every section for unusable values
+    pub fn validate(&self) -> Result<()> {
+        self.network.validate()?;
+        self.compression.validate()?;
+        self.shaping.validate()?;
+
+        if self.kdf.memory_kib < MIN_KDF_MEMORY_KIB {
+            return Err(UndergroundError::Config(format!(
+                "kdf.memory_kib must be at least {}",
+                MIN_KDF_MEMORY_KIB
+            )));
+        }
+        if self.kdf.iterations == 0 || !(1..=16).contains(&self.kdf.parallelism) {
+            return Err(UndergroundError::Config(
+                "kdf.iterations must be positive and kdf.parallelism between 1 and 16".to_string(),
+            ));
+        }
+        if self.retention.outbox_max_age_secs < 3600 {
+            return Err(UndergroundError::Config(
+                "retention.outbox_max_age_secs must be at least an hour".to_string(),
+            ));
+        }
+        if self.retention.log_max_age_secs == 0 {
+            return Err(UndergroundError::Config(
+                "retention.log_max_age_secs must be positive".to_string(),
+            ));
+        }
+        if self.relay.enabled
+            && (self.relay.max_bytes == 0 || self.relay.max_per_route == 0 || self.relay.ttl_secs > MAX_RELAY_TTL_SECS)
+        {
+            return Err(UndergroundError::Config(format!(
+                "relay limits must be positive and relay.ttl_secs at most {}",
+                MAX_RELAY_TTL_SECS
+            )));
+        }
+        if self.retention.message_max_age_secs < 3600 {
+            return Err(UndergroundError::Config(
+                "retention.message_max_age_secs must be at least an hour".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Directory holding profile files
+    pub fn data_dir(&self, config_dir: &Path) -> PathBuf {
+        self.data_dir.clone().unwrap_or_else(|| config_dir.to_path_buf())
+    }
+
+    /// Take the safe settings from `new` (network profile, retention, relay,
+    /// compression, pinning, shaping)
+    /// Returns the names of other settings that differ and need a restart
+    pub fn apply_reload(&mut self, new: &CoreConfig) -> Vec<&'static str> {
+        self.network.network_profile = new.network.network_profile;
+        self.retention = new.retention;
+        self.relay = new.relay;
+        self.compression = new.compression.clone();
+        self.pinning = new.pinning;
+        self.shaping = new.shaping;
+
+        let mut restart = Vec::new();
+        if self.data_dir != new.data_dir {
+            restart.push("data_dir");
+        }
+        if self.kdf != new.kdf {
+            restart.push("kdf");
+        }
+        if self.network != new.network {
+            restart.push("network");
+        }
+        restart
+    }
+}
+
+fn parse_env<T: std::str::FromStr>(name: &str, value: &str) -> Result<T> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| UndergroundError::Config(format!("{}: invalid value {:?}", name, value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_env_and_reload() {
+        let mut config = CoreConfig::parse(
+            "[network]\nnetwork_profile = \"Minimal\"\n\n[retention]\noutbox_max_age_secs = 86400\nlog_max_age_secs = 3600\n",
+        )
+        .unwrap();
+        assert_eq!(config.network.network_profile, NetworkProfile::Minimal);
+        assert!(config.network.use_default_bootstrap);
+        assert!(CoreConfig::parse("[retention]\nforever = true\n").is_err());
+
+        config
+            .apply_env(|name| (name == "URR_MESSAGE_MAX_AGE_SECS").then(|| "7200".to_string()))
+            .unwrap();
+        assert_eq!(config.retention.message_max_age_secs, 7200);
+        config.validate().unwrap();
+        assert!(CoreConfig::default()
+            .apply_env(|name| (name == "URR_NETWORK_PROFILE").then(|| "fast".to_string()))
+            .is_err());
+
+        let mut running = CoreConfig::default();
+        let mut edited = config.clone();
+        edited.kdf.iterations = 4;
+        let restart = running.apply_reload(&edited);
+        assert_eq!(running.retention.outbox_max_age_secs, 86400);
+        assert_eq!(running.retention.log_max_age_secs, 3600);
+        assert_eq!(running.network.network_profile, NetworkProfile::Minimal);
+        assert_eq!(restart, vec!["kdf"]);
+    }
+}
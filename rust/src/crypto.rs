@@ -7,7 +7,9 @@ use chacha20poly1305::{
     aead::{Aead, KeyInit, OsRng},
     ChaCha20Poly1305, Nonce,
 };
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
 use rand::RngCore;
+use serde::{Deserialize, Serialize};
 use zeroize::{Zeroize, ZeroizeOnDrop};
 
 /// Secure memory buffer that zeros on drop
@@ -32,9 +34,33 @@ impl SecureBuffer {
     }
 }
 
+/// Argon2id cost parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            memory_kib: 65536,
+            iterations: 3,
+            parallelism: 4,
+        }
+    }
+}
+
 /// Key derivation using Argon2id
 pub fn derive_key(password: &str, salt: &[u8]) -> Result<SecureBuffer> {
-    let params = Params::new(65536, 3, 4, Some(32))
+    derive_key_with_params(password, salt, &KdfParams::default())
+}
+
+/// Key derivation using Argon2id with explicit cost parameters
+pub fn derive_key_with_params(password: &str, salt: &[u8], kdf: &KdfParams) -> Result<SecureBuffer> {
+    let params = Params::new(kdf.memory_kib, kdf.iterations, kdf.parallelism, Some(32))
         .map_err(|e| UndergroundError::Crypto(e.to_string()))?;
 
     let argon2 = Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
@@ -127,6 +153,50 @@ pub fn hash_blake3(data: &[u8]) -> [u8; 32] {
     *hash.as_bytes()
 }
 
+/// Generate an Ed25519 signing keypair as (secret key, public key)
+pub fn generate_signing_keypair() -> (SecureBuffer, [u8; 32]) {
+    let signing_key = SigningKey::generate(&mut OsRng);
+    let public_key = signing_key.verifying_key().to_bytes();
+    (SecureBuffer::new(signing_key.to_bytes().to_vec()), public_key)
+}
+
+/// Derive the Ed25519 public key for a secret key
+pub fn signing_public_key(secret_key: &[u8]) -> Result<[u8; 32]> {
+    Ok(signing_key_from_bytes(secret_key)?.verifying_key().to_bytes())
+}
+
+/// Sign data with an Ed25519 secret key
+pub fn sign_data(secret_key: &[u8], data: &[u8]) -> Result<[u8; 64]> {
+    Ok(signing_key_from_bytes(secret_key)?.sign(data).to_bytes())
+}
+
+/// Verify an Ed25519 signature
+pub fn verify_signature(public_key: &[u8], data: &[u8], signature: &[u8]) -> Result<()> {
+    let public_key: [u8; 32] = public_key
+        .try_into()
+        .map_err(|_| UndergroundError::InvalidKey)?;
+    let verifying_key = VerifyingKey::from_bytes(&public_key).map_err(|_| UndergroundError::InvalidKey)?;
+
+    let signature = Signature::from_slice(signature).map_err(|_| UndergroundError::SignatureInvalid)?;
+
+    verifying_key
+        .verify(data, &signature)
+        .map_err(|_| UndergroundError::SignatureInvalid)
+}
+
+fn signing_key_from_bytes(secret_key: &[u8]) -> Result<SigningKey> {
+    let secret_key: [u8; 32] = secret_key
+        .try_into()
+        .map_err(|_| UndergroundError::InvalidKey)?;
+    Ok(SigningKey::from_bytes(&secret_key))
+}
+
+/// Decode a typed key string such as "VLD1:pub:<hex>" into raw bytes
+pub fn decode_typed_key(key: &str) -> Result<Vec<u8>> {
+    let encoded = key.rsplit(':').next().unwrap_or(key);
+    hex::decode(encoded).map_err(|_| UndergroundError::InvalidKey)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -153,6 +223,18 @@ mod tests {
         assert_eq!(plaintext.as_slice(), decrypted.as_slice());
     }
 
+    #[test]
+    fn test_sign_verify() {
+        let (secret, public) = generate_signing_keypair();
+        let signature = sign_data(secret.as_slice(), b"route").unwrap();
+
+        assert!(verify_signature(&public, b"route", &signature).is_ok());
+        assert!(matches!(
+            verify_signature(&public, b"other", &signature),
+            Err(UndergroundError::SignatureInvalid)
+        ));
+    }
+
     #[test]
     fn test_blake3_hash() {
         let data = b"test data";
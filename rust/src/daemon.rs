@@ -0,0 +1,439 @@
+// Headless daemon: keeps the core running without the UI and serves a local
+// control socket speaking line-delimited JSON-RPC (see rpc.rs). The owner token
+// stored next to the profile (readable only by the owning user) has every
+// capability; clients get scoped tokens through v1.tokens.issue
+// Secret keys of personas created here stay in the daemon's key store
+
+use crate::api;
+use crate::app_context::AppContext;
+use crate::error::{Result, UndergroundError};
+use crate::progress::{ProgressOperation, ProgressReporter};
+use crate::rpc::{self, Capability, RpcError, TokenStore};
+use crate::events::CoreEvent;
+use crate::keystore::KeyStore;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::fs;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{broadcast::error::RecvError, Mutex, Notify};
+
+/// Default socket file name inside the config directory
+pub const SOCKET_FILE: &str = "urr.sock";
+
+/// Events kept for clients that poll while nobody is connected
+const MAX_BUFFERED_EVENTS: usize = 1024;
+
+/// Longest accepted request line
+const MAX_REQUEST_LEN: usize = 64 * 1024;
+
+/// Running core plus control socket state
+pub struct Daemon {
+    ctx: Arc<AppContext>,
+    token: String,
+    tokens: Mutex<TokenStore>,
+    keys: Mutex<KeyStore>,
+    events: Arc<Mutex<VecDeque<CoreEvent>>>,
+    shutdown: Arc<Notify>,
+}
+
+impl Daemon {
+    /// Open the profile, start background work and begin buffering events
+    pub async fn start(config_dir: &Path) -> Result<Self> {
+        let progress = ProgressReporter::new(ProgressOperation::Startup, |e| {
+            tracing::info!("{} ({}%)", e.stage, e.percent)
+        });
+        let ctx = AppContext::open_configured(config_dir.to_string_lossy().to_string(), &progress).await?;
+        // Tokens and keys live with the profile, so wipe and export cover them
+        let token = load_or_create_token(ctx.config_dir())?;
+        let tokens = TokenStore::load(ctx.config_dir())?;
+        let keys = KeyStore::load(ctx.config_dir())?;
+
+        let events = Arc::new(Mutex::new(VecDeque::new()));
+        let buffer = events.clone();
+        let mut receiver = ctx.events().subscribe();
+        tokio::spawn(async move {
+            loop {
+                let event = match receiver.recv().await {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(count)) => CoreEvent::EventsMissed { count },
+                    Err(RecvError::Closed) => break,
+                };
+                let mut buffer = buffer.lock().await;
+                if buffer.len() >= MAX_BUFFERED_EVENTS {
+                    buffer.pop_front();
+                }
+                buffer.push_back(event);
+            }
+        });
+
+        Ok(Self {
+            ctx: Arc::new(ctx),
+            token,
+            tokens: Mutex::new(tokens),
+            keys: Mutex::new(keys),
+            events,
+            shutdown: Arc::new(Notify::new()),
+        })
+    }
+
+    /// Serve the control socket until a shutdown request arrives
+    pub async fn serve(self: Arc<Self>, socket_path: PathBuf) -> Result<()> {
+        let listener = bind_private(&socket_path).await?;
+        tracing::info!("Control socket listening on {}", socket_path.display());
+
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => {
+                    let (stream, _) = accepted?;
+                    let daemon = self.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = daemon.handle_connection(stream).await {
+                            tracing::warn!("Control connection failed: {}", e);
+                        }
+                    });
+                }
+                _ = self.shutdown.notified() => break,
+            }
+        }
+
+        let _ = fs::remove_file(&socket_path);
+        self.ctx.close().await
+    }
+
+    /// Directory holding the profile, including the control token
+    pub fn data_dir(&self) -> &Path {
+        self.ctx.config_dir()
+    }
+
+    /// Re-read the configuration file (e.g. on SIGHUP)
+    pub async fn reload_config(&self) -> Result<Vec<String>> {
+        self.ctx.reload_config().await
+    }
+
+    /// Ask `serve` to stop
+    pub fn request_shutdown(&self) {
+        self.shutdown.notify_one();
+    }
+
+    /// Secret key of the active persona from the key store
+    async fn active_secret_key(&self) -> std::result::Result<String, RpcError> {
+        let persona = api::get_active_persona(&self.ctx)
+            .await?
+            .ok_or_else(|| RpcError::new(rpc::APPLICATION_ERROR, "No active persona"))?;
+        self.keys
+            .lock()
+            .await
+            .get(&persona.id)
+            .map(str::to_string)
+            .ok_or_else(|| RpcError::new(rpc::APPLICATION_ERROR, "The daemon holds no key for the active persona"))
+    }
+
+    async fn handle_connection(&self, stream: UnixStream) -> Result<()> {
+        let (read, mut write) = stream.into_split();
+        let mut reader = BufReader::new(read);
+        let mut line = Vec::new();
+
+        loop {
+            // Never buffer more than one request's worth from an unauthenticated peer
+            line.clear();
+            let limit = MAX_REQUEST_LEN as u64 + 1;
+            if (&mut reader).take(limit).read_until(b'\n', &mut line).await? == 0 {
+                break;
+            }
+            if line.last() == Some(&b'\n') {
+                line.pop();
+            }
+            let too_large = line.len() > MAX_REQUEST_LEN;
+            let response = match std::str::from_utf8(&line) {
+                _ if too_large => rpc::failure(Value::Null, RpcError::new(rpc::INVALID_REQUEST, "Request too large")),
+                Ok(text) => self.handle_request(text).await,
+                Err(_) => rpc::failure(Value::Null, RpcError::new(rpc::PARSE_ERROR, "Request is not UTF-8")),
+            };
+            write.write_all(response.to_string().as_bytes()).await?;
+            write.write_all(b"\n").await?;
+            // The rest of an oversized line cannot be told apart from the next request
+            if too_large {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    async fn handle_request(&self, line: &str) -> Value {
+        let request = match rpc::parse_request(line) {
+            Ok(request) => request,
+            Err(e) => return rpc::failure(Value::Null, e),
+        };
+        let id = request.id.clone();
+
+        let Some(required) = rpc::required_capability(&request.method) else {
+            return rpc::failure(id, RpcError::new(rpc::METHOD_NOT_FOUND, "Method not found"));
+        };
+        let allowed = if token_matches(&request.auth, &self.token) {
+            true
+        } else {
+            match self.tokens.lock().await.capabilities_for(&request.auth) {
+                Some(capabilities) => capabilities.contains(&required),
+                None => return rpc::failure(id, RpcError::new(rpc::UNAUTHORIZED, "Not authorized")),
+            }
+        };
+        if !allowed {
+            return rpc::failure(id, RpcError::new(rpc::FORBIDDEN, "Token lacks the required capability"));
+        }
+
+        match self.dispatch(&request.method, &request.params).await {
+            Ok(result) => rpc::success(id, result),
+            Err(e) => rpc::failure(id, e),
+        }
+    }
+
+    async fn dispatch(&self, method: &str, params: &Value) -> std::result::Result<Value, RpcError> {
+        let ctx = self.ctx.as_ref();
+        let result = match method {
+            "v1.status" => json!({
+                "attachment": api::get_attachment_state(ctx).await?,
+                "sync": api::get_background_sync_status(ctx).await?,
+            }),
+            "v1.events.poll" => {
+                let drained: Vec<CoreEvent> = self.events.lock().await.drain(..).collect();
+                json!(drained)
+            }
+            "v1.identity.list_personas" => json!(api::list_personas(ctx).await?),
+            "v1.identity.create_persona" => {
+                let created = api::create_persona(ctx, rpc::string_param(params, "name")?).await?;
+                let mut keys = self.keys.lock().await;
+                keys.insert(&created.persona.id, created.secret_key);
+                keys.save(ctx.config_dir())?;
+                json!({ "persona": created.persona })
+            }
+            "v1.identity.switch_persona" => {
+                json!(api::switch_active_persona(ctx, rpc::string_param(params, "id")?).await?)
+            }
+            "v1.contacts.my_qr" => json!(api::generate_my_contact_qr(ctx, self.active_secret_key().await?).await?),
+            "v1.contacts.parse_qr" => {
+                let text = rpc::string_param(params, "text")?;
+                let scanned = api::parse_contact_qr(ctx, text.into_bytes()).await?;
+                json!({
+                    "public_key": scanned.contact.public_key,
+                    "route": scanned.contact.route,
+                    "mailbox_key": scanned.contact.mailbox_key,
+                    "created_at": scanned.contact.created_at,
+                    "fingerprint": scanned.fingerprint,
+                    "introduced_by": scanned.introduced_by,
+                })
+            }
+            "v1.contacts.block" => {
+                json!(api::block_contact_key(ctx, rpc::string_param(params, "public_key")?).await?)
+            }
+            "v1.contacts.unblock" => {
+                json!(api::unblock_contact_key(ctx, rpc::string_param(params, "public_key")?).await?)
+            }
+            "v1.messaging.send" => {
+                let message = hex::decode(rpc::string_param(params, "message_hex")?)
+                    .map_err(|_| RpcError::invalid_params("`message_hex` is not hex"))?;
+                json!(api::send_message_via_route(ctx, rpc::string_param(params, "route")?, message).await?)
+            }
+            "v1.messaging.inbox" => {
+                let after = params.get("after").and_then(Value::as_u64);
+                json!(api::read_inbox(ctx, after).await?)
+            }
+            "v1.messaging.remove" => json!(api::remove_inbox_messages(ctx, rpc::u64_param(params, "through")?).await?),
+            "v1.tokens.issue" => {
+                let label = rpc::string_param(params, "label")?;
+                let capabilities: Vec<Capability> = params
+                    .get("capabilities")
+                    .cloned()
+                    .and_then(|v| serde_json::from_value(v).ok())
+                    .ok_or_else(|| RpcError::invalid_params("`capabilities` must list capability names"))?;
+                let mut tokens = self.tokens.lock().await;
+                let token = tokens.issue(&label, capabilities);
+                tokens.save(ctx.config_dir())?;
+                json!({ "label": label, "token": token })
+            }
+            "v1.tokens.revoke" => {
+                let mut tokens = self.tokens.lock().await;
+                let revoked = tokens.revoke(&rpc::string_param(params, "label")?);
+                tokens.save(ctx.config_dir())?;
+                json!(revoked)
+            }
+            "v1.logs.recent" => {
+                let limit = params.get("limit").and_then(Value::as_u64).unwrap_or(100);
+                json!(api::get_recent_logs(limit.min(u32::MAX as u64) as u32).await?)
+            }
+            "v1.logs.set_level" => {
+                let module = params.get("module").and_then(Value::as_str).unwrap_or_default();
+                let level = params.get("level").and_then(Value::as_str).unwrap_or_default();
+                json!(api::set_log_level(module.to_string(), level.to_string()).await?)
+            }
+            "v1.storage.audit" => json!(api::audit_storage(ctx).await?),
+            "v1.config.reload" => json!({ "restart_required": self.reload_config().await? }),
+            "v1.shutdown" => {
+                self.request_shutdown();
+                Value::Null
+            }
+            _ => return Err(RpcError::new(rpc::METHOD_NOT_FOUND, "Method not found")),
+        };
+        Ok(result)
+    }
+}
+
+/// Bind the control socket so no other user can ever connect to it
+/// The socket is created inside a fresh 0700 directory, restricted to 0600 and
+/// only then renamed into place. A live socket at `path` is left alone
+async fn bind_private(path: &Path) -> Result<UnixListener> {
+    if path.exists() {
+        if UnixStream::connect(path).await.is_ok() {
+            return Err(UndergroundError::Storage(format!(
+                "Another daemon is listening on {}",
+                path.display()
+            )));
+        }
+        fs::remove_file(path)?;
+    }
+
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".bind");
+    let staging = path.with_file_name(name);
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    fs::DirBuilder::new().mode(0o700).create(&staging)?;
+    let staged = staging.join(SOCKET_FILE);
+    let bound = UnixListener::bind(&staged).and_then(|listener| {
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o600))?;
+        fs::rename(&staged, path)?;
+        Ok(listener)
+    });
+    fs::remove_dir_all(&staging)?;
+    Ok(bound?)
+}
+
+/// Compare digests so timing does not reveal how much of the token matched
+fn token_matches(given: &str, expected: &str) -> bool {
+    crate::crypto::hash_blake3(given.as_bytes()) == crate::crypto::hash_blake3(expected.as_bytes())
+}
+
+/// Read the control token, creating it (mode 0600) on first start
+pub fn load_or_create_token(config_dir: &Path) -> Result<String> {
+    let path = token_path(config_dir);
+    if path.exists() {
+        return Ok(fs::read_to_string(path)?.trim().to_string());
+    }
+
+    fs::create_dir_all(config_dir)?;
+    let token = hex::encode(crate::crypto::generate_random_bytes(32));
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)
+        .map_err(|e| UndergroundError::Storage(format!("Cannot create daemon token: {}", e)))?;
+    std::io::Write::write_all(&mut file, token.as_bytes())?;
+    Ok(token)
+}
+
+pub fn token_path(config_dir: &Path) -> PathBuf {
+    config_dir.join(rpc::DAEMON_TOKEN_FILE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn request(stream: &mut BufReader<UnixStream>, body: Value) -> Value {
+        stream.get_mut().write_all(format!("{}\n", body).as_bytes()).await.unwrap();
+        let mut line = String::new();
+        stream.read_line(&mut line).await.unwrap();
+        serde_json::from_str(&line).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_control_socket_requires_token() {
+        let tmp = crate::util::TempDir::new("daemon");
+        let socket = tmp.path().join(SOCKET_FILE);
+        let daemon = Arc::new(Daemon::start(tmp.path()).await.unwrap());
+        let token = daemon.token.clone();
+        let server = tokio::spawn(daemon.clone().serve(socket.clone()));
+
+        let mut stream = loop {
+            match UnixStream::connect(&socket).await {
+                Ok(stream) => break BufReader::new(stream),
+                Err(_) => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+            }
+        };
+
+        let call = |auth: &str, method: &str, params: Value| {
+            json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params, "auth": auth })
+        };
+
+        let denied = request(&mut stream, call("guess", "v1.status", Value::Null)).await;
+        assert_eq!(denied["error"]["code"], rpc::UNAUTHORIZED);
+
+        let status = request(&mut stream, call(&token, "v1.status", Value::Null)).await;
+        assert_eq!(status["result"]["sync"]["running"], true);
+
+        let issued = request(
+            &mut stream,
+            call(&token, "v1.tokens.issue", json!({ "label": "viewer", "capabilities": ["status"] })),
+        )
+        .await;
+        let viewer = issued["result"]["token"].as_str().unwrap().to_string();
+        assert_eq!(request(&mut stream, call(&viewer, "v1.status", Value::Null)).await["result"]["sync"]["running"], true);
+        let forbidden = request(&mut stream, call(&viewer, "v1.shutdown", Value::Null)).await;
+        assert_eq!(forbidden["error"]["code"], rpc::FORBIDDEN);
+
+        // Our QR comes from the daemon's own key store
+        let created = request(&mut stream, call(&token, "v1.identity.create_persona", json!({ "name": "desk" }))).await;
+        assert!(created["result"]["secret_key"].is_null());
+        let qr = request(&mut stream, call(&token, "v1.contacts.my_qr", Value::Null)).await;
+        assert!(qr["result"].is_string());
+
+        // A second daemon cannot take over the live socket
+        assert!(bind_private(&socket).await.is_err());
+
+        // An oversized request is refused without being buffered, then the connection ends
+        let mut flood = BufReader::new(UnixStream::connect(&socket).await.unwrap());
+        let oversized = vec![b'x'; MAX_REQUEST_LEN * 4];
+        let _ = flood.get_mut().write_all(&oversized).await;
+        let mut line = String::new();
+        flood.read_line(&mut line).await.unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&line).unwrap()["error"]["code"], rpc::INVALID_REQUEST);
+
+        request(&mut stream, call(&token, "v1.shutdown", Value::Null)).await;
+        server.await.unwrap().unwrap();
+        assert!(!socket.exists());
+    }
+
+    #[tokio::test]
+    async fn test_tokens_live_in_the_data_dir() {
+        let settings = crate::util::TempDir::new("daemon-settings");
+        let data = crate::util::TempDir::new("daemon-data");
+        fs::write(
+            settings.path().join(crate::core_config::CONFIG_FILE),
+            format!("data_dir = {:?}\n", data.path_string()),
+        )
+        .unwrap();
+
+        let call = |auth: &str, method: &str, params: Value| {
+            json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params, "auth": auth }).to_string()
+        };
+        let daemon = Daemon::start(settings.path()).await.unwrap();
+        assert_eq!(daemon.data_dir(), data.path());
+        let issue = call(&daemon.token, "v1.tokens.issue", json!({ "label": "viewer", "capabilities": ["status"] }));
+        let issued = daemon.handle_request(&issue).await;
+        let viewer = issued["result"]["token"].as_str().unwrap().to_string();
+        daemon.ctx.close().await.unwrap();
+
+        assert!(token_path(data.path()).exists());
+        assert!(!token_path(settings.path()).exists());
+        let restarted = Daemon::start(settings.path()).await.unwrap();
+        assert_eq!(restarted.token, daemon.token);
+        let status = restarted.handle_request(&call(&viewer, "v1.status", Value::Null)).await;
+        assert_eq!(status["result"]["sync"]["running"], true);
+        restarted.ctx.close().await.unwrap();
+    }
+}
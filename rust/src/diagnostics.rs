@@ -0,0 +1,120 @@
+// Network diagnostics for field debugging
+// Results are returned to the caller only and never leave the device
+
+use crate::veilid_manager::{AttachmentState, VeilidManager};
+use std::time::Instant;
+
+/// NAT classification reported by the network layer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NatType {
+    Unknown,
+    Open,
+    FullCone,
+    Restricted,
+    Symmetric,
+}
+
+/// Snapshot of network health
+#[derive(Debug, Clone)]
+pub struct NetworkDiagnostics {
+    pub attachment: AttachmentState,
+    pub nat_type: NatType,
+    pub relay_in_use: bool,
+    pub bootstrap_node_count: u32,
+    pub bootstrap_reachable: bool,
+    pub route_allocation_ok: bool,
+    pub dht_write_latency_ms: Option<u64>,
+    pub dht_read_latency_ms: Option<u64>,
+    pub errors: Vec<String>,
+}
+
+/// Run all network checks
+pub async fn run_diagnostics(manager: &VeilidManager) -> NetworkDiagnostics {
+    let mut errors = Vec::new();
+    let attachment = manager.attachment_state().await;
+    let bootstrap_node_count = manager.bootstrap_nodes().await.len() as u32;
+
+    // TODO: Real implementation reads VeilidStateNetwork for NAT type,
+    // relay usage and peer table, and probes bootstrap nodes directly
+    let nat_type = NatType::Unknown;
+    let relay_in_use = false;
+    let bootstrap_reachable = attachment.is_attached();
+    if !bootstrap_reachable {
+        errors.push(format!("Not attached ({:?})", attachment));
+    }
+
+    let route_allocation_ok = match manager.create_private_route().await {
+        Ok(route) => {
+            if let Err(e) = manager.release_private_route(&route).await {
+                errors.push(format!("Route release failed: {}", e));
+            }
+            true
+        }
+        Err(e) => {
+            errors.push(format!("Route allocation failed: {}", e));
+            false
+        }
+    };
+
+    let (dht_write_latency_ms, dht_read_latency_ms) = probe_dht(manager, &mut errors).await;
+
+    NetworkDiagnostics {
+        attachment,
+        nat_type,
+        relay_in_use,
+        bootstrap_node_count,
+        bootstrap_reachable,
+        route_allocation_ok,
+        dht_write_latency_ms,
+        dht_read_latency_ms,
+        errors,
+    }
+}
+
+/// Time a write and read of a throwaway DHT record
+async fn probe_dht(manager: &VeilidManager, errors: &mut Vec<String>) -> (Option<u64>, Option<u64>) {
+    let key = format!("urr-diag:{}", hex::encode(crate::crypto::generate_random_bytes(16)));
+    let probe = crate::crypto::generate_random_bytes(32);
+
+    let started = Instant::now();
+    if let Err(e) = manager.dht_set(&key, probe.clone()).await {
+        errors.push(format!("DHT write failed: {}", e));
+        return (None, None);
+    }
+    let write_ms = started.elapsed().as_millis() as u64;
+
+    let started = Instant::now();
+    let read_ms = match manager.dht_get(&key).await {
+        Ok(Some(value)) if value == probe => Some(started.elapsed().as_millis() as u64),
+        Ok(_) => {
+            errors.push("DHT read returned stale or missing value".to_string());
+            None
+        }
+        Err(e) => {
+            errors.push(format!("DHT read failed: {}", e));
+            None
+        }
+    };
+
+    if let Err(e) = manager.dht_delete(&key).await {
+        errors.push(format!("DHT cleanup failed: {}", e));
+    }
+
+    (Some(write_ms), read_ms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_diagnostics_when_not_initialized() {
+        let manager = VeilidManager::new();
+        let report = run_diagnostics(&manager).await;
+
+        assert!(!report.bootstrap_reachable);
+        assert!(!report.route_allocation_ok);
+        assert!(report.dht_write_latency_ms.is_none());
+        assert!(!report.errors.is_empty());
+    }
+}
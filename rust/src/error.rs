@@ -8,9 +8,18 @@ pub enum UndergroundError {
     #[error("Crypto error: {0}")]
     Crypto(String),
 
+    #[error("Configuration error: {0}")]
+    Config(String),
+
     #[error("Storage error: {0}")]
     Storage(String),
 
+    #[error("Invalid message: {0}")]
+    InvalidMessage(String),
+
+    #[error("Contact is blocked")]
+    Blocked,
+
     #[error("Authentication failed")]
     AuthenticationFailed,
 
@@ -20,6 +29,28 @@ pub enum UndergroundError {
     #[error("Not initialized")]
     NotInitialized,
 
+    /// A password or passphrase did not unlock the data
+    #[error("Wrong password")]
+    WrongPassword,
+
+    /// Local data is held open by another process
+    #[error("Local data is locked by another process")]
+    DatabaseLocked,
+
+    #[error("{entity} not found: {id}")]
+    RecordNotFound { entity: String, id: String },
+
+    /// The operation needs the network and the node is detached
+    #[error("Not attached to the network")]
+    NetworkDetached,
+
+    #[error("Invalid signature")]
+    SignatureInvalid,
+
+    /// A contact's key differs from the one pinned and the change is unresolved
+    #[error("Key changed for contact: {0}")]
+    KeyPinViolation(String),
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
@@ -0,0 +1,518 @@
+// Threshold escrow of the identity signing key
+// The owner splits their key into Shamir shares (GF(256), one per trustee) and
+// signs a policy naming the trustees and the threshold. To recover the key or
+// revoke it for a detained member, someone publishes a request with a fresh
+// nonce and an expiry; at least M trustees sign approvals of that request and
+// release their shares, and the approvals are kept as the record
+
+use crate::crypto::{generate_random_bytes, sign_data, signing_public_key, verify_signature, SecureBuffer};
+use crate::error::{Result, UndergroundError};
+use crate::wire::{split_signature, Reader};
+use std::collections::HashSet;
+use zeroize::Zeroizing;
+
+const POLICY_MAGIC: &[u8; 3] = b"URE";
+const SHARE_MAGIC: &[u8; 3] = b"URS";
+const REQUEST_MAGIC: &[u8; 3] = b"URR";
+const APPROVAL_MAGIC: &[u8; 3] = b"URT";
+const VERSION: u8 = 1;
+
+/// Most trustees in one escrow
+pub const MAX_TRUSTEES: usize = 16;
+
+/// Longest a request may stay open for approvals
+pub const MAX_REQUEST_TTL_SECS: u64 = 7 * 24 * 3600;
+
+/// What the trustees are authorizing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EscrowAction {
+    /// Rebuild the key on a new device
+    Recover,
+    /// Rebuild the key only to sign a burn notice against it
+    Revoke,
+}
+
+impl EscrowAction {
+    fn to_byte(self) -> u8 {
+        match self {
+            Self::Recover => 0,
+            Self::Revoke => 1,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Self::Recover),
+            1 => Some(Self::Revoke),
+            _ => None,
+        }
+    }
+}
+
+/// Owner-signed list of trustees and the number needed to act
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EscrowPolicy {
+    pub escrow_id: [u8; 16],
+    pub owner_key: [u8; 32],
+    pub threshold: u8,
+    /// Trustee i holds the share with index i + 1
+    pub trustees: Vec<[u8; 32]>,
+    pub created_at: u64,
+    signature: [u8; 64],
+}
+
+/// One trustee's share of the key; send it over an encrypted channel
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EscrowShare {
+    pub escrow_id: [u8; 16],
+    pub index: u8,
+    data: Zeroizing<[u8; 32]>,
+}
+
+/// One request to act on an escrow; approvals only count for the request they sign
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EscrowRequest {
+    pub escrow_id: [u8; 16],
+    pub action: EscrowAction,
+    pub nonce: [u8; 16],
+    pub expires_at: u64,
+}
+
+/// A trustee's signed approval of one request on an escrow
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EscrowApproval {
+    pub escrow_id: [u8; 16],
+    pub trustee_key: [u8; 32],
+    pub action: EscrowAction,
+    pub nonce: [u8; 16],
+    pub expires_at: u64,
+    pub issued_at: u64,
+    signature: [u8; 64],
+}
+
+/// Split `secret_key` among `trustees`, any `threshold` of whom can rebuild it
+pub fn create_escrow(
+    secret_key: &[u8],
+    trustees: Vec<[u8; 32]>,
+    threshold: u8,
+) -> Result<(EscrowPolicy, Vec<EscrowShare>)> {
+    let owner_key = signing_public_key(secret_key)?;
+    let unique: HashSet<_> = trustees.iter().collect();
+    if unique.len() != trustees.len() || unique.contains(&owner_key) {
+        return Err(UndergroundError::InvalidMessage("Trustees must be distinct and not yourself".to_string()));
+    }
+    if trustees.len() > MAX_TRUSTEES || threshold < 2 || threshold as usize > trustees.len() {
+        return Err(UndergroundError::InvalidMessage(format!(
+            "Escrow needs 2 to {} trustees and a threshold no larger than the trustee count",
+            MAX_TRUSTEES
+        )));
+    }
+
+    let mut escrow_id = [0u8; 16];
+    escrow_id.copy_from_slice(&generate_random_bytes(16));
+    let mut policy = EscrowPolicy {
+        escrow_id,
+        owner_key,
+        threshold,
+        trustees,
+        created_at: crate::util::unix_now(),
+        signature: [0u8; 64],
+    };
+    policy.signature = sign_data(secret_key, &policy.signed_bytes())?;
+
+    let secret: Zeroizing<[u8; 32]> =
+        Zeroizing::new(secret_key.try_into().map_err(|_| UndergroundError::InvalidKey)?);
+    let shares = shamir_split(&secret, threshold, policy.trustees.len() as u8)
+        .into_iter()
+        .map(|(index, data)| EscrowShare { escrow_id, index, data })
+        .collect();
+    Ok((policy, shares))
+}
+
+/// Rebuild the owner's key once enough trustees approved `request`
+/// Only shares belonging to approving trustees are used
+pub fn recover_key(
+    policy: &EscrowPolicy,
+    request: &EscrowRequest,
+    approvals: &[EscrowApproval],
+    shares: &[EscrowShare],
+) -> Result<SecureBuffer> {
+    let approved = policy.approving_indexes(request, approvals, crate::util::unix_now())?;
+    let mut points: Vec<(u8, Zeroizing<[u8; 32]>)> = Vec::new();
+    for share in shares {
+        if share.escrow_id == policy.escrow_id
+            && approved.contains(&share.index)
+            && !points.iter().any(|(x, _)| *x == share.index)
+        {
+            points.push((share.index, share.data.clone()));
+        }
+    }
+    if points.len() < policy.threshold as usize {
+        return Err(UndergroundError::InvalidMessage("Not enough shares from approving trustees".to_string()));
+    }
+    points.truncate(policy.threshold as usize);
+
+    let secret = SecureBuffer::new(shamir_combine(&points).to_vec());
+    if signing_public_key(secret.as_slice())? != policy.owner_key {
+        return Err(UndergroundError::Crypto("Escrow shares do not rebuild the owner key".to_string()));
+    }
+    Ok(secret)
+}
+
+impl EscrowPolicy {
+    pub fn verify(&self) -> Result<()> {
+        verify_signature(&self.owner_key, &self.signed_bytes(), &self.signature)
+    }
+
+    /// Share indexes of trustees with a valid approval of `request`
+    /// Fails once the request expired or unless at least `threshold` distinct trustees approved
+    pub fn approving_indexes(
+        &self,
+        request: &EscrowRequest,
+        approvals: &[EscrowApproval],
+        now: u64,
+    ) -> Result<HashSet<u8>> {
+        self.verify()?;
+        if request.escrow_id != self.escrow_id {
+            return Err(UndergroundError::InvalidMessage("Request is for another escrow".to_string()));
+        }
+        request.check_open(now)?;
+        let mut indexes = HashSet::new();
+        for approval in approvals {
+            if !approval.approves(request) {
+                continue;
+            }
+            let Some(position) = self.trustees.iter().position(|t| *t == approval.trustee_key) else {
+                continue;
+            };
+            approval.verify()?;
+            indexes.insert(position as u8 + 1);
+        }
+        if indexes.len() < self.threshold as usize {
+            return Err(UndergroundError::AuthenticationFailed);
+        }
+        Ok(indexes)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = self.signed_bytes();
+        out.extend_from_slice(&self.signature);
+        out
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let (body, signature) = split_signature(data, "Escrow policy")?;
+        let mut reader = Reader::new(body, "Escrow policy");
+        reader.header(POLICY_MAGIC, VERSION)?;
+        let escrow_id = reader.array()?;
+        let owner_key = reader.array()?;
+        let threshold = reader.u8()?;
+        let count = reader.u8()? as usize;
+        if count > MAX_TRUSTEES || threshold < 2 || threshold as usize > count {
+            return Err(reader.invalid("bad trustee count"));
+        }
+        let trustees = (0..count).map(|_| reader.array()).collect::<Result<Vec<_>>>()?;
+        let created_at = reader.u64()?;
+        reader.finish()?;
+
+        let policy = Self {
+            escrow_id,
+            owner_key,
+            threshold,
+            trustees,
+            created_at,
+            signature,
+        };
+        policy.verify()?;
+        Ok(policy)
+    }
+
+    fn signed_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(70 + 32 * self.trustees.len());
+        out.extend_from_slice(POLICY_MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&self.escrow_id);
+        out.extend_from_slice(&self.owner_key);
+        out.push(self.threshold);
+        out.push(self.trustees.len() as u8);
+        for trustee in &self.trustees {
+            out.extend_from_slice(trustee);
+        }
+        out.extend_from_slice(&self.created_at.to_be_bytes());
+        out
+    }
+}
+
+impl EscrowShare {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(53);
+        out.extend_from_slice(SHARE_MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&self.escrow_id);
+        out.push(self.index);
+        out.extend_from_slice(self.data.as_slice());
+        out
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(data, "Escrow share");
+        reader.header(SHARE_MAGIC, VERSION)?;
+        let escrow_id = reader.array()?;
+        let index = reader.u8()?;
+        let data = Zeroizing::new(reader.array()?);
+        reader.finish()?;
+        if index == 0 {
+            return Err(reader.invalid("bad share index"));
+        }
+        Ok(Self { escrow_id, index, data })
+    }
+}
+
+impl EscrowRequest {
+    /// Ask the trustees of `policy` to approve `action` within `ttl_secs`
+    pub fn new(policy: &EscrowPolicy, action: EscrowAction, ttl_secs: u64) -> Result<Self> {
+        if ttl_secs == 0 || ttl_secs > MAX_REQUEST_TTL_SECS {
+            return Err(UndergroundError::InvalidMessage(format!(
+                "Escrow requests must expire within {} seconds",
+                MAX_REQUEST_TTL_SECS
+            )));
+        }
+        let mut nonce = [0u8; 16];
+        nonce.copy_from_slice(&generate_random_bytes(16));
+        Ok(Self {
+            escrow_id: policy.escrow_id,
+            action,
+            nonce,
+            expires_at: crate::util::unix_now() + ttl_secs,
+        })
+    }
+
+    /// Fail if the request has expired or claims an expiry further out than allowed
+    pub fn check_open(&self, now: u64) -> Result<()> {
+        if now >= self.expires_at || self.expires_at > now + MAX_REQUEST_TTL_SECS {
+            return Err(UndergroundError::InvalidMessage("Escrow request has expired".to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(45);
+        out.extend_from_slice(REQUEST_MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&self.escrow_id);
+        out.push(self.action.to_byte());
+        out.extend_from_slice(&self.nonce);
+        out.extend_from_slice(&self.expires_at.to_be_bytes());
+        out
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(data, "Escrow request");
+        reader.header(REQUEST_MAGIC, VERSION)?;
+        let escrow_id = reader.array()?;
+        let action = EscrowAction::from_byte(reader.u8()?).ok_or_else(|| reader.invalid("unknown action"))?;
+        let nonce = reader.array()?;
+        let expires_at = reader.u64()?;
+        reader.finish()?;
+        Ok(Self {
+            escrow_id,
+            action,
+            nonce,
+            expires_at,
+        })
+    }
+}
+
+impl EscrowApproval {
+    /// Approve a request on an escrow we are a trustee of
+    pub fn create(secret_key: &[u8], policy: &EscrowPolicy, request: &EscrowRequest) -> Result<Self> {
+        let trustee_key = signing_public_key(secret_key)?;
+        if !policy.trustees.contains(&trustee_key) {
+            return Err(UndergroundError::InvalidMessage("Not a trustee of this escrow".to_string()));
+        }
+        if request.escrow_id != policy.escrow_id {
+            return Err(UndergroundError::InvalidMessage("Request is for another escrow".to_string()));
+        }
+        let now = crate::util::unix_now();
+        request.check_open(now)?;
+        let mut approval = Self {
+            escrow_id: policy.escrow_id,
+            trustee_key,
+            action: request.action,
+            nonce: request.nonce,
+            expires_at: request.expires_at,
+            issued_at: now,
+            signature: [0u8; 64],
+        };
+        approval.signature = sign_data(secret_key, &approval.signed_bytes())?;
+        Ok(approval)
+    }
+
+    pub fn verify(&self) -> Result<()> {
+        verify_signature(&self.trustee_key, &self.signed_bytes(), &self.signature)
+    }
+
+    /// Whether this approval was given for exactly `request`
+    pub fn approves(&self, request: &EscrowRequest) -> bool {
+        self.escrow_id == request.escrow_id
+            && self.action == request.action
+            && self.nonce == request.nonce
+            && self.expires_at == request.expires_at
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = self.signed_bytes();
+        out.extend_from_slice(&self.signature);
+        out
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let (body, signature) = split_signature(data, "Escrow approval")?;
+        let mut reader = Reader::new(body, "Escrow approval");
+        reader.header(APPROVAL_MAGIC, VERSION)?;
+        let escrow_id = reader.array()?;
+        let trustee_key = reader.array()?;
+        let action = EscrowAction::from_byte(reader.u8()?).ok_or_else(|| reader.invalid("unknown action"))?;
+        let nonce = reader.array()?;
+        let expires_at = reader.u64()?;
+        let issued_at = reader.u64()?;
+        reader.finish()?;
+
+        let approval = Self {
+            escrow_id,
+            trustee_key,
+            action,
+            nonce,
+            expires_at,
+            issued_at,
+            signature,
+        };
+        approval.verify()?;
+        Ok(approval)
+    }
+
+    fn signed_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(85);
+        out.extend_from_slice(APPROVAL_MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&self.escrow_id);
+        out.extend_from_slice(&self.trustee_key);
+        out.push(self.action.to_byte());
+        out.extend_from_slice(&self.nonce);
+        out.extend_from_slice(&self.expires_at.to_be_bytes());
+        out.extend_from_slice(&self.issued_at.to_be_bytes());
+        out
+    }
+}
+
+/// Multiply in GF(256) with the AES polynomial, without secret-dependent branches
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    for _ in 0..8 {
+        product ^= a & 0u8.wrapping_sub(b & 1);
+        let carry = a >> 7;
+        a = (a << 1) ^ (0x1b & 0u8.wrapping_sub(carry));
+        b >>= 1;
+    }
+    product
+}
+
+/// Multiplicative inverse (a^254)
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1;
+    let mut base = a;
+    let mut exp = 254u8;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = gf_mul(result, base);
+        }
+        base = gf_mul(base, base);
+        exp >>= 1;
+    }
+    result
+}
+
+/// Shares at x = 1..=count of a random degree threshold-1 polynomial per byte
+fn shamir_split(secret: &[u8; 32], threshold: u8, count: u8) -> Vec<(u8, Zeroizing<[u8; 32]>)> {
+    let random = SecureBuffer::new(generate_random_bytes(32 * (threshold as usize - 1)));
+    let coefficients = random.as_slice();
+    (1..=count)
+        .map(|x| {
+            let mut y = Zeroizing::new([0u8; 32]);
+            for (i, byte) in y.iter_mut().enumerate() {
+                // Horner's rule from the highest coefficient down to the secret
+                let mut acc = 0;
+                for degree in (1..threshold as usize).rev() {
+                    acc = gf_mul(acc, x) ^ coefficients[(degree - 1) * 32 + i];
+                }
+                *byte = gf_mul(acc, x) ^ secret[i];
+            }
+            (x, y)
+        })
+        .collect()
+}
+
+/// Lagrange interpolation at x = 0
+fn shamir_combine(points: &[(u8, Zeroizing<[u8; 32]>)]) -> Zeroizing<[u8; 32]> {
+    let mut secret = Zeroizing::new([0u8; 32]);
+    for (i, (xi, yi)) in points.iter().enumerate() {
+        let mut basis = 1;
+        for (j, (xj, _)) in points.iter().enumerate() {
+            if i != j {
+                basis = gf_mul(basis, gf_mul(*xj, gf_inv(xj ^ xi)));
+            }
+        }
+        for (s, y) in secret.iter_mut().zip(yi.iter()) {
+            *s ^= gf_mul(*y, basis);
+        }
+    }
+    secret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_signing_keypair;
+
+    #[test]
+    fn test_threshold_recovery_needs_approvals() {
+        let (owner_secret, _) = generate_signing_keypair();
+        let trustees: Vec<_> = (0..3).map(|_| generate_signing_keypair()).collect();
+        let (policy, shares) = create_escrow(
+            owner_secret.as_slice(),
+            trustees.iter().map(|(_, public)| *public).collect(),
+            2,
+        )
+        .unwrap();
+        let policy = EscrowPolicy::decode(&policy.encode()).unwrap();
+        let shares: Vec<_> = shares.iter().map(|s| EscrowShare::decode(&s.encode()).unwrap()).collect();
+
+        let recover = EscrowRequest::new(&policy, EscrowAction::Recover, 3600).unwrap();
+        let recover = EscrowRequest::decode(&recover.encode()).unwrap();
+        let revoke = EscrowRequest::new(&policy, EscrowAction::Revoke, 3600).unwrap();
+        let approve = |t: usize, request: &EscrowRequest| {
+            let approval = EscrowApproval::create(trustees[t].0.as_slice(), &policy, request).unwrap();
+            EscrowApproval::decode(&approval.encode()).unwrap()
+        };
+
+        // One approval is not enough, and revoke approvals do not authorize recovery
+        let one = [approve(0, &recover), approve(2, &revoke)];
+        assert!(recover_key(&policy, &recover, &one, &shares).is_err());
+
+        // Approvals of an earlier request with the same action do not carry over
+        let earlier = EscrowRequest::new(&policy, EscrowAction::Recover, 3600).unwrap();
+        let replayed = [approve(0, &recover), approve(2, &earlier)];
+        assert!(recover_key(&policy, &recover, &replayed, &shares).is_err());
+
+        let two = [approve(0, &recover), approve(2, &recover)];
+        let recovered = recover_key(&policy, &recover, &two, &shares[1..]);
+        assert!(recovered.is_err(), "share of a non-approving trustee must not count");
+
+        // Approvals stop counting once the request expires
+        assert!(policy.approving_indexes(&recover, &two, recover.expires_at).is_err());
+        assert!(EscrowRequest::new(&policy, EscrowAction::Recover, MAX_REQUEST_TTL_SECS + 1).is_err());
+
+        let recovered = recover_key(&policy, &recover, &two, &shares).unwrap();
+        assert_eq!(recovered.as_slice(), owner_secret.as_slice());
+    }
+}
@@ -0,0 +1,146 @@
+// App-level notification bus
+// Subsystems publish CoreEvents here and the Flutter stream and daemon
+// subscribe; network-level VeilidEvents are translated by forward_network_events
+
+use crate::veilid_manager::{AttachmentState, VeilidEvent, VeilidManager, WatchTopic};
+use serde::Serialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::JoinHandle;
+
+/// Capacity of the app event channel
+const EVENT_BUS_CAPACITY: usize = 128;
+
+/// Something the app should react to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CoreEvent {
+    /// A message arrived on one of our routes; read it from the inbox by id
+    NewMessage { id: u64 },
+    /// A watched mailbox record has a new value
+    MailboxChanged { mailbox_key: String },
+    /// Another watched DHT record has a new value
+    RecordChanged { key: String },
+    NetworkStateChanged { state: AttachmentState },
+    /// A trusted contact revoked an identity, which is now blocked
+    TrustRevoked {
+        subject_public_key: String,
+        issuer_public_key: String,
+    },
+    ContactBlocked { public_key: String },
+    /// The recipient's core confirmed delivery (not a read receipt)
+    MessageAcknowledged { message_id: String },
+    /// Every delivery path was tried without an acknowledgment
+    ContactUnreachable { message_id: String, contact: String },
+    /// A contact's key differs from the pinned one; sending is blocked until resolved
+    KeyPinViolation { contact: String },
+    /// The active persona changed, or None if the last one was deleted
+    ActivePersonaChanged { persona_id: Option<String> },
+    /// This subscriber fell behind and `count` events were lost; re-read
+    /// current state such as the inbox
+    EventsMissed { count: u64 },
+}
+
+/// Typed broadcast bus; cloning yields another handle to the same channel
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<CoreEvent>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self {
+            sender: broadcast::channel(EVENT_BUS_CAPACITY).0,
+        }
+    }
+
+    pub fn publish(&self, event: CoreEvent) {
+        // Sending only fails when nobody is subscribed
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<CoreEvent> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Republish the manager's network events on the bus until its channel closes
+pub fn forward_network_events(manager: &VeilidManager, bus: EventBus) -> JoinHandle<()> {
+    let mut events = manager.subscribe();
+    let manager = manager.clone();
+    tokio::spawn(async move {
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(count)) => {
+                    bus.publish(CoreEvent::EventsMissed { count });
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            let translated = match event {
+                VeilidEvent::Attachment(state) => CoreEvent::NetworkStateChanged { state },
+                VeilidEvent::MessageReceived(id) => CoreEvent::NewMessage { id },
+                VeilidEvent::ValueChanged(key) => {
+                    // One change reaches every topic the record is watched for
+                    for topic in manager.watch_topics(&key).await {
+                        bus.publish(match topic {
+                            WatchTopic::Mailbox => CoreEvent::MailboxChanged { mailbox_key: key.clone() },
+                            WatchTopic::Record => CoreEvent::RecordChanged { key: key.clone() },
+                        });
+                    }
+                    continue;
+                }
+                VeilidEvent::Acknowledged(message_id) => CoreEvent::MessageAcknowledged { message_id },
+                VeilidEvent::Unreachable { message_id, contact } => {
+                    CoreEvent::ContactUnreachable { message_id, contact }
+                }
+                _ => continue,
+            };
+            bus.publish(translated);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_network_events_are_translated() {
+        let manager = VeilidManager::new();
+        let bus = EventBus::new();
+        let mut events = bus.subscribe();
+        let forwarder = forward_network_events(&manager, bus.clone());
+
+        manager.handle_app_message(vec![7]).await;
+        manager.handle_route_change(vec!["VLD1:route:gone".to_string()]).await;
+        manager.watch_record("VLD1:dht:box", WatchTopic::Mailbox).await;
+        manager.watch_record("VLD1:dht:feed", WatchTopic::Record).await;
+        manager.handle_value_change("VLD1:dht:box").await;
+        manager.handle_value_change("VLD1:dht:unwatched").await;
+        manager.handle_value_change("VLD1:dht:feed").await;
+
+        assert_eq!(events.recv().await.unwrap(), CoreEvent::NewMessage { id: 0 });
+        assert_eq!(
+            events.recv().await.unwrap(),
+            CoreEvent::MailboxChanged { mailbox_key: "VLD1:dht:box".to_string() }
+        );
+        assert_eq!(
+            events.recv().await.unwrap(),
+            CoreEvent::RecordChanged { key: "VLD1:dht:feed".to_string() }
+        );
+
+        bus.publish(CoreEvent::ContactBlocked { public_key: "VLD1:pub:00".to_string() });
+        assert_eq!(
+            events.recv().await.unwrap(),
+            CoreEvent::ContactBlocked { public_key: "VLD1:pub:00".to_string() }
+        );
+        forwarder.abort();
+    }
+}
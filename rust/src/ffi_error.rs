@@ -0,0 +1,125 @@
+// Typed errors for the Flutter bridge
+// Codes are stable so the UI can branch on them; messages are safe to show
+
+use crate::error::UndergroundError;
+
+/// Error returned across the FFI boundary
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FfiError {
+    NotInitialized,
+    NetworkUnavailable,
+    AuthenticationFailed,
+    InvalidKey,
+    InvalidInput(String),
+    Blocked,
+    CryptoFailure,
+    StorageFailure,
+    InvalidConfig(String),
+    NotFound(String),
+    NetworkDetached,
+    SignatureInvalid,
+    DataLocked,
+    KeyPinViolation,
+    Internal,
+}
+
+impl FfiError {
+    /// Stable numeric code for the UI
+    pub fn code(&self) -> u32 {
+        match self {
+            FfiError::NotInitialized => 1,
+            FfiError::NetworkUnavailable => 2,
+            FfiError::AuthenticationFailed => 3,
+            FfiError::InvalidKey => 4,
+            FfiError::InvalidInput(_) => 5,
+            FfiError::Blocked => 6,
+            FfiError::CryptoFailure => 7,
+            FfiError::StorageFailure => 8,
+            FfiError::InvalidConfig(_) => 9,
+            FfiError::NotFound(_) => 10,
+            FfiError::NetworkDetached => 11,
+            FfiError::SignatureInvalid => 12,
+            FfiError::DataLocked => 13,
+            FfiError::KeyPinViolation => 14,
+            FfiError::Internal => 99,
+        }
+    }
+
+    /// Message that can be shown to the user as-is
+    pub fn user_message(&self) -> String {
+        match self {
+            FfiError::NotInitialized => "The app is still starting up".to_string(),
+            FfiError::NetworkUnavailable => "The network is unavailable".to_string(),
+            FfiError::AuthenticationFailed => "Wrong password or passphrase".to_string(),
+            FfiError::InvalidKey => "Invalid key".to_string(),
+            FfiError::InvalidInput(reason) => reason.clone(),
+            FfiError::Blocked => "Contact is blocked".to_string(),
+            FfiError::CryptoFailure => "Could not verify or decrypt data".to_string(),
+            FfiError::StorageFailure => "Could not read or write local data".to_string(),
+            FfiError::InvalidConfig(reason) => reason.clone(),
+            FfiError::NotFound(what) => format!("{} not found", what),
+            FfiError::NetworkDetached => "Not connected; try again once online".to_string(),
+            FfiError::SignatureInvalid => "The signature does not match".to_string(),
+            FfiError::DataLocked => "The profile is open in another app".to_string(),
+            FfiError::KeyPinViolation => "This contact's key changed; verify it before sending".to_string(),
+            FfiError::Internal => "Something went wrong".to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for FfiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.user_message())
+    }
+}
+
+impl std::error::Error for FfiError {}
+
+impl From<UndergroundError> for FfiError {
+    fn from(err: UndergroundError) -> Self {
+        // Full detail stays in local logs; crypto and IO detail never reaches the UI
+        tracing::debug!("FFI error: {}", err);
+        match err {
+            UndergroundError::Veilid(_) => FfiError::NetworkUnavailable,
+            UndergroundError::Crypto(_) => FfiError::CryptoFailure,
+            UndergroundError::Config(reason) => FfiError::InvalidConfig(reason),
+            UndergroundError::Storage(_) | UndergroundError::Io(_) => FfiError::StorageFailure,
+            UndergroundError::InvalidMessage(reason) => FfiError::InvalidInput(reason),
+            UndergroundError::Blocked => FfiError::Blocked,
+            UndergroundError::AuthenticationFailed => FfiError::AuthenticationFailed,
+            UndergroundError::InvalidKey => FfiError::InvalidKey,
+            UndergroundError::NotInitialized => FfiError::NotInitialized,
+            UndergroundError::WrongPassword => FfiError::AuthenticationFailed,
+            UndergroundError::DatabaseLocked => FfiError::DataLocked,
+            // The id may identify a contact or persona, so only the kind is shown
+            UndergroundError::RecordNotFound { entity, .. } => FfiError::NotFound(entity),
+            UndergroundError::NetworkDetached => FfiError::NetworkDetached,
+            UndergroundError::SignatureInvalid => FfiError::SignatureInvalid,
+            UndergroundError::KeyPinViolation(_) => FfiError::KeyPinViolation,
+            UndergroundError::Serialization(_) | UndergroundError::Unknown(_) => FfiError::Internal,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crypto_detail_is_hidden() {
+        let err: FfiError = UndergroundError::Crypto("aead::Error at key 0xdeadbeef".to_string()).into();
+        assert_eq!(err, FfiError::CryptoFailure);
+        assert_eq!(err.code(), 7);
+        assert!(!err.user_message().contains("deadbeef"));
+
+        let err: FfiError = UndergroundError::InvalidMessage("Contact card too large".to_string()).into();
+        assert_eq!(err.user_message(), "Contact card too large");
+
+        let err: FfiError = UndergroundError::RecordNotFound {
+            entity: "Persona".to_string(),
+            id: "0123456789abcdef".to_string(),
+        }
+        .into();
+        assert_eq!(err.user_message(), "Persona not found");
+    }
+}
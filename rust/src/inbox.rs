@@ -0,0 +1,240 @@
+// Durable inbox of received messages
+// Every message that arrives on one of our routes is stored here before the
+// app is told about it, so a subscriber that falls behind on the event bus
+// reads what it missed instead of losing it. The app removes messages once
+// it has processed them
+
+use crate::error::{Result, UndergroundError};
+use crate::store_format::{self, base64_bytes};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+
+/// File name of the persisted inbox inside the config directory
+pub(crate) const INBOX_FILE: &str = "inbox.json";
+
+/// Where an unparseable inbox is set aside
+pub(crate) const INBOX_CORRUPT_FILE: &str = "inbox.json.corrupt";
+
+/// Layout version of inbox.json; a change adds a converter in `Inbox::read`
+const INBOX_VERSION: u32 = 1;
+
+/// Most messages kept before the oldest unread one is dropped
+const MAX_INBOX_MESSAGES: usize = 4096;
+
+/// A received message waiting for the app
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InboxMessage {
+    /// Increases with every message, so the app can remove what it has read
+    pub id: u64,
+    pub message: Vec<u8>,
+    pub received_at: u64,
+}
+
+/// Received messages, oldest first
+#[derive(Debug, Clone, Default)]
+pub struct Inbox {
+    next_id: u64,
+    messages: VecDeque<InboxMessage>,
+}
+
+/// inbox.json as written since version 1
+#[derive(Serialize, Deserialize)]
+struct StoredInbox {
+    version: u32,
+    next_id: u64,
+    messages: Vec<StoredMessage>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredMessage {
+    id: u64,
+    #[serde(with = "base64_bytes")]
+    message: Vec<u8>,
+    received_at: u64,
+}
+
+/// inbox.json before it was versioned, with message bytes as number arrays
+#[derive(Deserialize)]
+struct InboxV0 {
+    next_id: u64,
+    messages: VecDeque<InboxMessage>,
+}
+
+impl Inbox {
+    /// Load the inbox from a config directory (empty if none saved yet)
+    pub fn load(config_dir: &Path) -> Result<Self> {
+        Ok(Self::read(config_dir)?.0)
+    }
+
+    /// Load the inbox and the layout version it was saved in
+    fn read(config_dir: &Path) -> Result<(Self, u32)> {
+        let path = config_dir.join(INBOX_FILE);
+        if !path.exists() {
+            return Ok((Self::default(), INBOX_VERSION));
+        }
+        let bytes = fs::read(path)?;
+        let version = store_format::read_version(&bytes, INBOX_FILE, INBOX_VERSION)?;
+        let inbox = if version == 0 {
+            let v0: InboxV0 = serde_json::from_slice(&bytes)?;
+            Self {
+                next_id: v0.next_id,
+                messages: v0.messages,
+            }
+        } else {
+            let stored: StoredInbox = serde_json::from_slice(&bytes)?;
+            Self {
+                next_id: stored.next_id,
+                messages: stored
+                    .messages
+                    .into_iter()
+                    .map(|m| InboxMessage {
+                        id: m.id,
+                        message: m.message,
+                        received_at: m.received_at,
+                    })
+                    .collect(),
+            }
+        };
+        Ok((inbox, version))
+    }
+
+    /// Load at startup, setting an unparseable file aside instead of failing
+    /// It is kept as inbox.json.corrupt rather than overwritten by the next save.
+    /// A file in an older layout is rewritten in the current one
+    pub fn load_or_set_aside(config_dir: &Path) -> Result<Self> {
+        match Self::read(config_dir) {
+            Ok((inbox, version)) => {
+                if version < INBOX_VERSION {
+                    inbox.save(config_dir)?;
+                    tracing::info!("Migrated the inbox from layout version {}", version);
+                }
+                Ok(inbox)
+            }
+            Err(UndergroundError::Serialization(e)) => {
+                let path = config_dir.join(INBOX_FILE);
+                tracing::error!("Inbox is corrupt, starting with no received messages: {}", e);
+                fs::rename(&path, config_dir.join(INBOX_CORRUPT_FILE))?;
+                Ok(Self::default())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self, config_dir: &Path) -> Result<()> {
+        let stored = StoredInbox {
+            version: INBOX_VERSION,
+            next_id: self.next_id,
+            messages: self
+                .messages
+                .iter()
+                .map(|m| StoredMessage {
+                    id: m.id,
+                    message: m.message.clone(),
+                    received_at: m.received_at,
+                })
+                .collect(),
+        };
+        fs::create_dir_all(config_dir)?;
+        fs::write(config_dir.join(INBOX_FILE), serde_json::to_vec(&stored)?)?;
+        Ok(())
+    }
+
+    /// Store a message, returning its id and false if the oldest had to be dropped
+    pub fn push(&mut self, message: Vec<u8>, now: u64) -> (u64, bool) {
+        let mut kept_all = true;
+        if self.messages.len() >= MAX_INBOX_MESSAGES {
+            self.messages.pop_front();
+            kept_all = false;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.messages.push_back(InboxMessage {
+            id,
+            message,
+            received_at: now,
+        });
+        (id, kept_all)
+    }
+
+    /// Messages with an id after `after`, or all of them for None
+    pub fn since(&self, after: Option<u64>) -> Vec<InboxMessage> {
+        self.messages
+            .iter()
+            .filter(|m| after.is_none_or(|after| m.id > after))
+            .cloned()
+            .collect()
+    }
+
+    /// Remove messages up to and including `id`, returning how many were removed
+    pub fn remove_through(&mut self, id: u64) -> usize {
+        let before = self.messages.len();
+        self.messages.retain(|m| m.id > id);
+        before - self.messages.len()
+    }
+
+    /// Drop messages received before `cutoff`, returning how many were dropped
+    pub fn expire_before(&mut self, cutoff: u64) -> usize {
+        let before = self.messages.len();
+        self.messages.retain(|m| m.received_at >= cutoff);
+        before - self.messages.len()
+    }
+
+    /// Number of messages received before `cutoff`
+    pub fn count_before(&self, cutoff: u64) -> usize {
+        self.messages.iter().filter(|m| m.received_at < cutoff).count()
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages_survive_reload_until_removed() {
+        let tmp = crate::util::TempDir::new("inbox");
+        let mut inbox = Inbox::default();
+        assert_eq!(inbox.push(vec![1], 10), (0, true));
+        assert_eq!(inbox.push(vec![2], 11), (1, true));
+        inbox.save(tmp.path()).unwrap();
+
+        let mut loaded = Inbox::load(tmp.path()).unwrap();
+        assert_eq!(loaded.since(Some(0)).len(), 1);
+        assert_eq!(loaded.remove_through(0), 1);
+        assert_eq!(loaded.since(None)[0].message, vec![2]);
+        // Ids keep increasing after removal and reload
+        assert_eq!(loaded.push(vec![3], 12).0, 2);
+    }
+
+    #[test]
+    fn test_unversioned_inbox_is_migrated() {
+        let tmp = crate::util::TempDir::new("inbox-migrate");
+        let v0 = r#"{"next_id":3,"messages":[{"id":2,"message":[1,2,3],"received_at":10}]}"#;
+        fs::write(tmp.path().join(INBOX_FILE), v0).unwrap();
+
+        let mut inbox = Inbox::load_or_set_aside(tmp.path()).unwrap();
+        assert_eq!(inbox.since(None)[0].message, vec![1, 2, 3]);
+        assert_eq!(inbox.push(vec![4], 11).0, 3);
+
+        // Rewritten in the current layout, with message bytes as base64
+        let saved: serde_json::Value =
+            serde_json::from_slice(&fs::read(tmp.path().join(INBOX_FILE)).unwrap()).unwrap();
+        assert_eq!(saved["version"], INBOX_VERSION);
+        assert_eq!(saved["messages"][0]["message"], "AQID");
+        assert_eq!(Inbox::load(tmp.path()).unwrap().since(None)[0].message, vec![1, 2, 3]);
+
+        // A layout from a newer version is refused, not set aside
+        fs::write(tmp.path().join(INBOX_FILE), r#"{"version":99}"#).unwrap();
+        assert!(matches!(Inbox::load_or_set_aside(tmp.path()), Err(UndergroundError::Storage(_))));
+        assert!(tmp.path().join(INBOX_FILE).exists());
+    }
+}
@@ -0,0 +1,138 @@
+// Three-party introductions
+// An introducer forwards each party's signed route bundle to the other,
+// countersigned so both sides can check who made the introduction
+
+use crate::crypto::{sign_data, signing_public_key, verify_signature};
+use crate::error::{Result, UndergroundError};
+use crate::route_blob::RouteBundle;
+use crate::wire::{put_bytes, split_signature, Reader};
+
+const MAGIC: &[u8; 3] = b"URI";
+const VERSION: u8 = 1;
+
+/// Largest embedded route bundle
+const MAX_CARD_LEN: usize = 4096;
+
+/// An introducer's signed hand-over of one party's card to another
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Introduction {
+    pub introducer_key: [u8; 32],
+    pub recipient_key: [u8; 32],
+    pub issued_at: u64,
+    card: Vec<u8>,
+    signature: [u8; 64],
+}
+
+/// A new contact learned through an introduction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntroducedContact {
+    pub card: RouteBundle,
+    pub introduced_by: [u8; 32],
+}
+
+impl Introduction {
+    /// Introduce the owner of `card` to `recipient_key`
+    pub fn create(secret_key: &[u8], card: &RouteBundle, recipient_key: [u8; 32]) -> Result<Self> {
+        let introducer_key = signing_public_key(secret_key)?;
+        if card.public_key == recipient_key || introducer_key == recipient_key {
+            return Err(UndergroundError::InvalidMessage("Introduction must join two other people".to_string()));
+        }
+
+        let card = card.encode();
+        if card.len() > MAX_CARD_LEN {
+            return Err(UndergroundError::InvalidMessage("Contact card too large".to_string()));
+        }
+
+        let mut intro = Self {
+            introducer_key,
+            recipient_key,
+            issued_at: crate::util::unix_now(),
+            card,
+            signature: [0u8; 64],
+        };
+        intro.signature = sign_data(secret_key, &intro.signed_bytes())?;
+        Ok(intro)
+    }
+
+    /// Check both signatures and that we are the intended recipient
+    pub fn accept(&self, pinned_introducer_key: &[u8; 32], own_key: &[u8; 32]) -> Result<IntroducedContact> {
+        if &self.introducer_key != pinned_introducer_key {
+            return Err(UndergroundError::AuthenticationFailed);
+        }
+        if &self.recipient_key != own_key {
+            return Err(UndergroundError::InvalidMessage("Introduction is for someone else".to_string()));
+        }
+        verify_signature(&self.introducer_key, &self.signed_bytes(), &self.signature)?;
+
+        let card = RouteBundle::decode(&self.card)?;
+        if &card.public_key == own_key || card.public_key == self.introducer_key {
+            return Err(UndergroundError::InvalidMessage("Introduction must join two other people".to_string()));
+        }
+
+        Ok(IntroducedContact {
+            card,
+            introduced_by: self.introducer_key,
+        })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = self.signed_bytes();
+        out.extend_from_slice(&self.signature);
+        out
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let (body, signature) = split_signature(data, "Introduction")?;
+
+        let mut reader = Reader::new(body, "Introduction");
+        reader.header(MAGIC, VERSION)?;
+        let introducer_key = reader.array()?;
+        let recipient_key = reader.array()?;
+        let issued_at = reader.u64()?;
+        let card = reader.bytes(MAX_CARD_LEN)?.to_vec();
+        reader.finish()?;
+
+        Ok(Self {
+            introducer_key,
+            recipient_key,
+            issued_at,
+            card,
+            signature,
+        })
+    }
+
+    fn signed_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(78 + self.card.len());
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&self.introducer_key);
+        out.extend_from_slice(&self.recipient_key);
+        out.extend_from_slice(&self.issued_at.to_be_bytes());
+        put_bytes(&mut out, &self.card);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_signing_keypair;
+
+    #[test]
+    fn test_introduction_roundtrip() {
+        let (introducer_sk, introducer_pk) = generate_signing_keypair();
+        let (alice_sk, alice_pk) = generate_signing_keypair();
+        let (_, bob_pk) = generate_signing_keypair();
+
+        let alice_card = RouteBundle::create("route-alice", "mailbox-alice", alice_sk.as_slice()).unwrap();
+        let intro = Introduction::create(introducer_sk.as_slice(), &alice_card, bob_pk).unwrap();
+        let received = Introduction::decode(&intro.encode()).unwrap();
+
+        let contact = received.accept(&introducer_pk, &bob_pk).unwrap();
+        assert_eq!(contact.card.public_key, alice_pk);
+        assert_eq!(contact.introduced_by, introducer_pk);
+
+        assert!(received.accept(&alice_pk, &bob_pk).is_err());
+        assert!(received.accept(&introducer_pk, &alice_pk).is_err());
+    }
+}
@@ -0,0 +1,113 @@
+// iOS: wrap the storage key with a Secure Enclave key via the Security framework
+// Falls back to a software keychain key on devices and simulators without an enclave
+
+use crate::crypto::SecureBuffer;
+use crate::error::{Result, UndergroundError};
+use crate::key_wrap::KeyProtection;
+use security_framework::access_control::{ProtectionMode, SecAccessControl};
+use security_framework::item::{ItemClass, ItemSearchOptions, Location, Reference, SearchResult};
+use security_framework::key::{Algorithm, GenerateKeyOptions, KeyType, SecKey, Token};
+use security_framework::passwords::AccessControlOptions;
+
+/// Keychain labels of the wrapping keys
+const ENCLAVE_KEY_LABEL: &str = "org.undergroundrailroad.storage-wrap.enclave";
+const KEYCHAIN_KEY_LABEL: &str = "org.undergroundrailroad.storage-wrap.keychain";
+
+/// ECIES with P-256, as required for Secure Enclave keys
+const WRAP_ALGORITHM: Algorithm = Algorithm::ECIESEncryptionCofactorVariableIVX963SHA256AESGCM;
+
+/// Wrap a key, preferring the Secure Enclave
+pub fn wrap(key: &[u8]) -> Result<(KeyProtection, Vec<u8>)> {
+    let (protection, private_key) = match load_or_create(KeyProtection::SecureEnclave) {
+        Ok(k) => (KeyProtection::SecureEnclave, k),
+        Err(e) => {
+            tracing::warn!("Secure Enclave unavailable, using keychain key: {}", e);
+            (KeyProtection::Keychain, load_or_create(KeyProtection::Keychain)?)
+        }
+    };
+
+    let public_key = private_key
+        .public_key()
+        .ok_or_else(|| UndergroundError::Crypto("No public key for wrapping key".to_string()))?;
+    let wrapped = public_key
+        .encrypt_data(WRAP_ALGORITHM, key)
+        .map_err(|e| UndergroundError::Crypto(e.to_string()))?;
+    Ok((protection, wrapped))
+}
+
+/// Unwrap a key with the wrapping key it was created with
+pub fn unwrap(protection: KeyProtection, wrapped: &[u8]) -> Result<SecureBuffer> {
+    let private_key = find(label(protection)?)?
+        .ok_or_else(|| UndergroundError::Crypto("Wrapping key missing from keychain".to_string()))?;
+    let key = private_key
+        .decrypt_data(WRAP_ALGORITHM, wrapped)
+        .map_err(|e| UndergroundError::Crypto(e.to_string()))?;
+    Ok(SecureBuffer::new(key))
+}
+
+/// Secure Enclave if an enclave key exists or can be created
+pub fn best_available_protection() -> KeyProtection {
+    match load_or_create(KeyProtection::SecureEnclave) {
+        Ok(_) => KeyProtection::SecureEnclave,
+        Err(_) => KeyProtection::Keychain,
+    }
+}
+
+fn label(protection: KeyProtection) -> Result<&'static str> {
+    match protection {
+        KeyProtection::SecureEnclave => Ok(ENCLAVE_KEY_LABEL),
+        KeyProtection::Keychain => Ok(KEYCHAIN_KEY_LABEL),
+        KeyProtection::None => Err(UndergroundError::InvalidKey),
+    }
+}
+
+fn find(label: &str) -> Result<Option<SecKey>> {
+    let results = match ItemSearchOptions::new()
+        .class(ItemClass::key())
+        .label(label)
+        .load_refs(true)
+        .search()
+    {
+        Ok(results) => results,
+        // errSecItemNotFound
+        Err(e) if e.code() == -25300 => return Ok(None),
+        Err(e) => return Err(UndergroundError::Crypto(e.to_string())),
+    };
+
+    Ok(results.into_iter().find_map(|r| match r {
+        SearchResult::Ref(Reference::Key(key)) => Some(key),
+        _ => None,
+    }))
+}
+
+fn load_or_create(protection: KeyProtection) -> Result<SecKey> {
+    let label = label(protection)?;
+    if let Some(key) = find(label)? {
+        return Ok(key);
+    }
+
+    // Usable only while unlocked, never leaves this device
+    let mut flags = AccessControlOptions::empty();
+    if protection == KeyProtection::SecureEnclave {
+        flags |= AccessControlOptions::PRIVATE_KEY_USAGE;
+    }
+    let access = SecAccessControl::create_with_protection(
+        Some(ProtectionMode::AccessibleWhenUnlockedThisDeviceOnly),
+        flags.bits(),
+    )
+    .map_err(|e| UndergroundError::Crypto(e.to_string()))?;
+
+    let mut options = GenerateKeyOptions::default();
+    options
+        .set_key_type(KeyType::ec_sec_prime_random())
+        .set_size_in_bits(256)
+        .set_label(label)
+        .set_location(Location::DataProtectionKeychain)
+        .set_access_control(access)
+        .set_token(match protection {
+            KeyProtection::SecureEnclave => Token::SecureEnclave,
+            _ => Token::Software,
+        });
+
+    SecKey::new(&options).map_err(|e| UndergroundError::Crypto(e.to_string()))
+}
@@ -0,0 +1,100 @@
+// Intent journal for multi-step operations
+// An operation that touches several stores or the network is written here
+// before its first step and cleared after its last. Intents still open at
+// startup were interrupted and are completed or rolled back then
+
+use crate::error::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// File name of the persisted journal inside the config directory
+pub(crate) const JOURNAL_FILE: &str = "journal.json";
+
+/// An operation in progress and what is needed to finish or undo it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Intent {
+    /// Rolled back unless the persona was saved
+    CreatePersona {
+        persona_id: String,
+        mailbox_key: String,
+        route: String,
+    },
+    /// Always completed
+    DeletePersona {
+        persona_id: String,
+        mailbox_key: String,
+        route: String,
+    },
+}
+
+/// Open intents, oldest first
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Journal {
+    next_id: u64,
+    open: BTreeMap<u64, Intent>,
+}
+
+impl Journal {
+    /// Load the journal from a config directory (empty if none saved yet)
+    pub fn load(config_dir: &Path) -> Result<Self> {
+        let path = config_dir.join(JOURNAL_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    pub fn save(&self, config_dir: &Path) -> Result<()> {
+        fs::create_dir_all(config_dir)?;
+        fs::write(config_dir.join(JOURNAL_FILE), serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    /// Record an intent and persist it before any step runs
+    pub fn begin(&mut self, intent: Intent, config_dir: &Path) -> Result<u64> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.open.insert(id, intent);
+        self.save(config_dir)?;
+        Ok(id)
+    }
+
+    /// Clear an intent once every step has run
+    pub fn finish(&mut self, id: u64, config_dir: &Path) -> Result<()> {
+        if self.open.remove(&id).is_some() {
+            self.save(config_dir)?;
+        }
+        Ok(())
+    }
+
+    /// Intents left open by an interrupted run
+    pub fn pending(&self) -> Vec<(u64, Intent)> {
+        self.open.iter().map(|(id, intent)| (*id, intent.clone())).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_intents_survive_restart() {
+        let tmp = crate::util::TempDir::new("journal");
+        let dir = tmp.path();
+        let intent = |id: &str| Intent::DeletePersona {
+            persona_id: id.to_string(),
+            mailbox_key: "VLD1:dht:aa".to_string(),
+            route: "VLD1:route:bb".to_string(),
+        };
+
+        let mut journal = Journal::default();
+        let first = journal.begin(intent("a"), dir).unwrap();
+        let second = journal.begin(intent("b"), dir).unwrap();
+        journal.finish(first, dir).unwrap();
+
+        let restored = Journal::load(dir).unwrap();
+        assert_eq!(restored.pending(), vec![(second, intent("b"))]);
+    }
+}
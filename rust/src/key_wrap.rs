@@ -0,0 +1,125 @@
+// Hardware wrapping of the derived storage key at rest
+// Uses the Secure Enclave on iOS; other platforms store the key unwrapped for now
+
+use crate::crypto::SecureBuffer;
+use crate::error::Result;
+use crate::wire::Reader;
+
+const MAGIC: &[u8; 3] = b"URK";
+const VERSION: u8 = 1;
+
+/// Length of the derived storage key
+const STORAGE_KEY_LEN: usize = 32;
+
+/// How a stored key is protected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyProtection {
+    /// Wrapped by a non-extractable Secure Enclave key
+    SecureEnclave,
+    /// Wrapped by a software key in the device keychain (no enclave available)
+    Keychain,
+    /// Not wrapped
+    None,
+}
+
+impl KeyProtection {
+    fn to_byte(self) -> u8 {
+        match self {
+            KeyProtection::SecureEnclave => 1,
+            KeyProtection::Keychain => 2,
+            KeyProtection::None => 3,
+        }
+    }
+
+    fn from_byte(b: u8) -> Option<Self> {
+        match b {
+            1 => Some(KeyProtection::SecureEnclave),
+            2 => Some(KeyProtection::Keychain),
+            3 => Some(KeyProtection::None),
+            _ => None,
+        }
+    }
+}
+
+/// Result of unwrapping a stored key
+pub struct UnwrappedKey {
+    pub key: SecureBuffer,
+    pub protection: KeyProtection,
+    /// Stored blob predates wrapping or used a weaker protection than now available;
+    /// the caller should re-wrap and replace it
+    pub needs_migration: bool,
+}
+
+/// Wrap a storage key with the strongest protection the platform offers
+pub fn wrap_storage_key(key: &[u8]) -> Result<(Vec<u8>, KeyProtection)> {
+    #[cfg(target_os = "ios")]
+    let (protection, payload) = crate::ios::wrap(key)?;
+    #[cfg(not(target_os = "ios"))]
+    let (protection, payload) = (KeyProtection::None, key.to_vec());
+
+    let mut out = Vec::with_capacity(MAGIC.len() + 2 + payload.len());
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    out.push(protection.to_byte());
+    out.extend_from_slice(&payload);
+    Ok((out, protection))
+}
+
+/// Unwrap a stored key; raw keys from installs before wrapping are accepted
+pub fn unwrap_storage_key(blob: &[u8]) -> Result<UnwrappedKey> {
+    if blob.len() == STORAGE_KEY_LEN && !blob.starts_with(MAGIC) {
+        return Ok(UnwrappedKey {
+            key: SecureBuffer::new(blob.to_vec()),
+            protection: KeyProtection::None,
+            needs_migration: true,
+        });
+    }
+
+    let mut reader = Reader::new(blob, "Wrapped key");
+    reader.header(MAGIC, VERSION)?;
+    let protection = KeyProtection::from_byte(reader.u8()?).ok_or_else(|| reader.invalid("unknown protection"))?;
+    let payload = reader.rest();
+
+    let key = match protection {
+        #[cfg(target_os = "ios")]
+        KeyProtection::SecureEnclave | KeyProtection::Keychain => crate::ios::unwrap(protection, payload)?,
+        #[cfg(not(target_os = "ios"))]
+        KeyProtection::SecureEnclave | KeyProtection::Keychain => {
+            return Err(reader.invalid("wrapped on another platform"));
+        }
+        KeyProtection::None => SecureBuffer::new(payload.to_vec()),
+    };
+
+    Ok(UnwrappedKey {
+        needs_migration: protection != best_available_protection(),
+        key,
+        protection,
+    })
+}
+
+/// Strongest protection this device supports
+pub fn best_available_protection() -> KeyProtection {
+    #[cfg(target_os = "ios")]
+    return crate::ios::best_available_protection();
+    #[cfg(not(target_os = "ios"))]
+    KeyProtection::None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_legacy_raw_key_needs_migration() {
+        let raw = [7u8; STORAGE_KEY_LEN];
+        let legacy = unwrap_storage_key(&raw).unwrap();
+        assert!(legacy.needs_migration);
+        assert_eq!(legacy.key.as_slice(), &raw);
+
+        let (blob, protection) = wrap_storage_key(&raw).unwrap();
+        let unwrapped = unwrap_storage_key(&blob).unwrap();
+        assert_eq!(unwrapped.protection, protection);
+        assert_eq!(unwrapped.key.as_slice(), &raw);
+        assert!(!unwrapped.needs_migration);
+    }
+}
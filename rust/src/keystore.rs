@@ -0,0 +1,87 @@
+// Persona secret keys held by the headless daemon
+// The apps keep secret keys in platform secure storage; the daemon has none,
+// so it keeps them here, sealed with the profile's storage key in a file
+// readable only by the owning user, and RPC clients never see or supply them
+
+use crate::crypto::SecureBuffer;
+use crate::error::Result;
+use crate::profile_key;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use zeroize::Zeroize;
+
+/// File name of the daemon's key store inside the config directory
+pub(crate) const KEYSTORE_FILE: &str = "daemon_keys.json";
+
+/// Secret keys by persona id
+pub struct KeyStore {
+    keys: HashMap<String, String>,
+    storage_key: SecureBuffer,
+}
+
+impl KeyStore {
+    /// Load the key store from a config directory (empty if none saved yet)
+    pub fn load(config_dir: &Path) -> Result<Self> {
+        let storage_key = profile_key::load_or_create(config_dir)?;
+        let path = config_dir.join(KEYSTORE_FILE);
+        let keys = if path.exists() {
+            let mut data = profile_key::open(&storage_key, &fs::read(path)?)?;
+            let keys = serde_json::from_slice(&data);
+            data.zeroize();
+            keys?
+        } else {
+            HashMap::new()
+        };
+        Ok(Self { keys, storage_key })
+    }
+
+    /// Save the key store sealed with the storage key (mode 0600 on unix)
+    pub fn save(&self, config_dir: &Path) -> Result<()> {
+        fs::create_dir_all(config_dir)?;
+        let mut data = serde_json::to_vec(&self.keys)?;
+        let sealed = profile_key::seal(&self.storage_key, &data);
+        data.zeroize();
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(config_dir.join(KEYSTORE_FILE))?;
+        Ok(std::io::Write::write_all(&mut file, &sealed?)?)
+    }
+
+    pub fn insert(&mut self, persona_id: &str, secret_key: String) {
+        if let Some(mut old) = self.keys.insert(persona_id.to_string(), secret_key) {
+            old.zeroize();
+        }
+    }
+
+    /// Secret key of a persona, if the daemon created it
+    pub fn get(&self, persona_id: &str) -> Option<&str> {
+        self.keys.get(persona_id).map(String::as_str)
+    }
+}
+
+impl Drop for KeyStore {
+    fn drop(&mut self) {
+        self.keys.values_mut().for_each(Zeroize::zeroize);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_are_sealed_on_disk() {
+        let tmp = crate::util::TempDir::new("keystore");
+        let mut store = KeyStore::load(tmp.path()).unwrap();
+        store.insert("persona-1", "secret-key-material".to_string());
+        store.save(tmp.path()).unwrap();
+
+        let saved = fs::read(tmp.path().join(KEYSTORE_FILE)).unwrap();
+        assert!(!saved.windows(6).any(|w| w == b"secret"));
+        assert_eq!(KeyStore::load(tmp.path()).unwrap().get("persona-1"), Some("secret-key-material"));
+    }
+}
@@ -4,12 +4,90 @@
 #![allow(dead_code)]
 #![allow(unused_imports)]
 
+#[cfg(feature = "native")]
 mod bridge_generated; /* AUTO INJECTED BY flutter_rust_bridge. This line may not be accurate, and you can change it according to your needs. */
 
+#[cfg(feature = "native")]
 pub mod api;
+#[cfg(feature = "native")]
+pub mod app_context;
+#[cfg(feature = "native")]
+pub mod background_sync;
+#[cfg(feature = "native")]
+pub mod retention;
+#[cfg(feature = "native")]
 pub mod veilid_manager;
+#[cfg(feature = "native")]
+pub mod transport;
+#[cfg(feature = "native")]
+pub mod mesh;
+#[cfg(feature = "tor")]
+pub mod tor;
+#[cfg(feature = "native")]
+pub mod events;
 pub mod crypto;
 pub mod error;
+pub mod ffi_error;
+pub mod config;
+pub mod core_config;
+pub mod contact_qr;
+pub mod contact_card;
+pub mod contact_bundle;
+pub mod bootstrap_cache;
+pub mod util;
+mod wire;
+mod store_format;
+pub mod outbox;
+pub mod inbox;
+#[cfg(feature = "native")]
+pub mod reconnect;
+pub mod relay;
+pub mod relay_cache;
+pub mod message_codec;
+pub mod chunking;
+pub mod sneakernet;
+pub mod replay;
+pub mod pinning;
+pub mod shaper;
+pub mod ack;
+#[cfg(feature = "native")]
+pub mod record_keeper;
+pub mod route_blob;
+#[cfg(feature = "native")]
+pub mod diagnostics;
+#[cfg(feature = "native")]
+pub mod rendezvous;
+pub mod safety;
+pub mod metrics;
+pub mod vouch;
+pub mod revocation;
+pub mod escrow;
+pub mod introduction;
+pub mod connection;
+pub mod blocklist;
+pub mod persona;
+pub mod journal;
+pub mod progress;
+pub mod logging;
+#[cfg(feature = "native")]
+pub mod profile_archive;
+#[cfg(feature = "native")]
+pub mod storage_audit;
+#[cfg(all(unix, feature = "native"))]
+pub mod daemon;
+pub mod rpc;
+pub mod keystore;
+pub mod key_wrap;
+pub mod profile_key;
+#[cfg(feature = "native")]
+pub mod c_api;
+#[cfg(target_os = "ios")]
+mod ios;
+#[cfg(all(target_os = "android", feature = "native"))]
+pub mod android;
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
 
 // Re-export for flutter_rust_bridge
+#[cfg(feature = "native")]
 pub use api::*;
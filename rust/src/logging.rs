@@ -0,0 +1,358 @@
+// Structured logging with automatic redaction
+// Every tracing event passes through RedactingLayer before it is written or
+// buffered: typed keys, routes, fingerprints and long hex ids are stripped from
+// messages, and sensitive fields (ids, regions, content) lose their values.
+// Levels can be changed per module at runtime
+
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Write as _};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use tracing::field::{Field, Visit};
+use tracing::subscriber::Interest;
+use tracing::{Event, Level, Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::registry::Registry;
+
+/// Records kept in memory for diagnostics
+const MAX_BUFFERED_LOGS: usize = 1000;
+
+/// Fields whose values are never logged
+const SENSITIVE_FIELDS: &[&str] = &[
+    "person_id",
+    "persona_id",
+    "fingerprint",
+    "region",
+    "location",
+    "content",
+    "body",
+    "plaintext",
+    "secret_key",
+    "public_key",
+    "route",
+    "mailbox_key",
+];
+
+const REDACTED: &str = "[redacted]";
+
+/// Shortest run of hex digits treated as an identifier
+const MIN_HEX_RUN: usize = 16;
+
+/// Groups in a displayed contact fingerprint ("ABCD 1234 ...")
+const FINGERPRINT_GROUPS: usize = 5;
+
+static CONTROLLER: OnceLock<LogController> = OnceLock::new();
+
+/// One redacted log line
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LogRecord {
+    pub timestamp: u64,
+    pub level: String,
+    pub target: String,
+    pub message: String,
+}
+
+/// Runtime control over levels and the in-memory log buffer
+#[derive(Clone)]
+pub struct LogController {
+    default_level: Arc<RwLock<Level>>,
+    module_levels: Arc<RwLock<HashMap<String, Level>>>,
+    buffer: Arc<Mutex<VecDeque<LogRecord>>>,
+}
+
+impl LogController {
+    pub fn new(default_level: Level) -> Self {
+        Self {
+            default_level: Arc::new(RwLock::new(default_level)),
+            module_levels: Arc::new(RwLock::new(HashMap::new())),
+            buffer: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    /// Layer that filters, redacts and buffers events; `echo` also writes them out
+    pub fn layer(&self, echo: bool) -> RedactingLayer {
+        RedactingLayer {
+            controller: self.clone(),
+            echo,
+        }
+    }
+
+    pub fn set_default_level(&self, level: Level) {
+        *self.default_level.write().unwrap() = level;
+    }
+
+    /// Override the level for a module path (e.g. "underground_railroad::relay");
+    /// None removes the override
+    pub fn set_module_level(&self, module: &str, level: Option<Level>) {
+        let mut levels = self.module_levels.write().unwrap();
+        match level {
+            Some(level) => levels.insert(module.to_string(), level),
+            None => levels.remove(module),
+        };
+    }
+
+    /// Most verbose level enabled for a target, using the longest matching module override
+    pub fn level_for(&self, target: &str) -> Level {
+        let levels = self.module_levels.read().unwrap();
+        levels
+            .iter()
+            .filter(|(module, _)| {
+                target == module.as_str()
+                    || (target.starts_with(module.as_str()) && target[module.len()..].starts_with("::"))
+            })
+            .max_by_key(|(module, _)| module.len())
+            .map(|(_, level)| *level)
+            .unwrap_or_else(|| *self.default_level.read().unwrap())
+    }
+
+    /// Up to `limit` of the newest buffered records, oldest first
+    pub fn recent(&self, limit: usize) -> Vec<LogRecord> {
+        let buffer = self.buffer.lock().unwrap();
+        buffer.iter().skip(buffer.len().saturating_sub(limit)).cloned().collect()
+    }
+
+    /// Drop buffered records older than `cutoff`, or only count them on a dry run
+    pub fn expire_before(&self, cutoff: u64, dry_run: bool) -> usize {
+        let mut buffer = self.buffer.lock().unwrap();
+        let expired = buffer.iter().filter(|r| r.timestamp < cutoff).count();
+        if !dry_run {
+            buffer.retain(|r| r.timestamp >= cutoff);
+        }
+        expired
+    }
+
+    pub fn clear(&self) {
+        self.buffer.lock().unwrap().clear();
+    }
+
+    fn push(&self, record: LogRecord) {
+        let mut buffer = self.buffer.lock().unwrap();
+        if buffer.len() >= MAX_BUFFERED_LOGS {
+            buffer.pop_front();
+        }
+        buffer.push_back(record);
+    }
+}
+
+/// Install the redacting subscriber for the process
+/// Later calls return the controller installed by the first one
+pub fn init(default_level: Level) -> LogController {
+    CONTROLLER
+        .get_or_init(|| {
+            let controller = LogController::new(default_level);
+            let subscriber = Registry::default().with(controller.layer(true));
+            if tracing::subscriber::set_global_default(subscriber).is_err() {
+                eprintln!("A tracing subscriber is already installed; logs will not be redacted");
+            }
+            controller
+        })
+        .clone()
+}
+
+/// The process-wide controller, if logging has been initialized
+pub fn controller() -> Option<LogController> {
+    CONTROLLER.get().cloned()
+}
+
+/// tracing layer that applies levels, redaction and buffering
+pub struct RedactingLayer {
+    controller: LogController,
+    echo: bool,
+}
+
+impl<S: Subscriber> Layer<S> for RedactingLayer {
+    // Levels change at runtime, so callsites must not cache a decision
+    fn register_callsite(&self, _metadata: &'static Metadata<'static>) -> Interest {
+        Interest::sometimes()
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>, _ctx: Context<'_, S>) -> bool {
+        *metadata.level() <= self.controller.level_for(metadata.target())
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = RedactingVisitor::default();
+        event.record(&mut visitor);
+
+        let level = *event.metadata().level();
+        let record = LogRecord {
+            timestamp: crate::util::unix_now(),
+            level: level.to_string(),
+            target: event.metadata().target().to_string(),
+            message: visitor.message + &visitor.fields,
+        };
+        if self.echo {
+            emit(level, &record);
+        }
+        self.controller.push(record);
+    }
+}
+
+#[derive(Default)]
+struct RedactingVisitor {
+    message: String,
+    fields: String,
+}
+
+impl RedactingVisitor {
+    fn record_value(&mut self, field: &Field, value: String) {
+        if field.name() == "message" {
+            self.message = redact(&value);
+        } else if SENSITIVE_FIELDS.contains(&field.name()) {
+            let _ = write!(self.fields, " {}={}", field.name(), REDACTED);
+        } else {
+            let _ = write!(self.fields, " {}={}", field.name(), redact(&value));
+        }
+    }
+}
+
+impl Visit for RedactingVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record_value(field, value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.record_value(field, format!("{:?}", value));
+    }
+}
+
+#[cfg(target_os = "android")]
+fn emit(level: Level, record: &LogRecord) {
+    let level = match level {
+        Level::ERROR => log::Level::Error,
+        Level::WARN => log::Level::Warn,
+        Level::INFO => log::Level::Info,
+        Level::DEBUG => log::Level::Debug,
+        Level::TRACE => log::Level::Trace,
+    };
+    log::log!(target: &record.target, level, "{}", record.message);
+}
+
+#[cfg(not(target_os = "android"))]
+fn emit(_level: Level, record: &LogRecord) {
+    eprintln!("{} {:>5} {}: {}", record.timestamp, record.level, record.target, record.message);
+}
+
+/// Strip identifiers from free text: typed keys and routes ("VLD1:pub:..."),
+/// contact fingerprints and long hex ids such as persona ids
+pub fn redact(text: &str) -> String {
+    redact_words(&redact_typed_keys(text))
+}
+
+fn redact_typed_keys(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(pos) = rest.find("VLD1:") {
+        let (before, after) = rest.split_at(pos + "VLD1:".len());
+        out.push_str(before);
+
+        let kind_len = after
+            .find(':')
+            .filter(|&n| after[..n].chars().all(|c| c.is_ascii_alphanumeric()))
+            .map(|n| n + 1)
+            .unwrap_or(0);
+        out.push_str(&after[..kind_len]);
+
+        let value = &after[kind_len..];
+        let end = value
+            .find(|c: char| c.is_whitespace() || "\"',;)]}".contains(c))
+            .unwrap_or(value.len());
+        if end > 0 {
+            out.push_str(REDACTED);
+        }
+        rest = &value[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn redact_words(text: &str) -> String {
+    let words = split_words(text);
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < words.len() {
+        let groups = fingerprint_groups(&words[i..]);
+        if groups >= FINGERPRINT_GROUPS {
+            out.push_str("[fingerprint]");
+            i += groups * 2 - 1;
+            continue;
+        }
+        let word = words[i];
+        if word.len() >= MIN_HEX_RUN && word.chars().all(|c| c.is_ascii_hexdigit()) {
+            out.push_str("[hex]");
+        } else {
+            out.push_str(word);
+        }
+        i += 1;
+    }
+    out
+}
+
+/// Split into alternating runs of alphanumeric and other characters
+fn split_words(text: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = 0;
+    let mut in_word = None;
+    for (i, c) in text.char_indices() {
+        let is_word = c.is_ascii_alphanumeric();
+        if in_word.is_some_and(|w| w != is_word) {
+            words.push(&text[start..i]);
+            start = i;
+        }
+        in_word = Some(is_word);
+    }
+    if start < text.len() {
+        words.push(&text[start..]);
+    }
+    words
+}
+
+/// Number of space-separated four-digit uppercase hex groups at the start of `words`
+fn fingerprint_groups(words: &[&str]) -> usize {
+    let is_group = |w: &str| w.len() == 4 && w.chars().all(|c| c.is_ascii_digit() || ('A'..='F').contains(&c));
+    let mut groups = 0;
+    while words.get(groups * 2).is_some_and(|w| is_group(w)) {
+        groups += 1;
+        if words.get(groups * 2 - 1) != Some(&" ") {
+            break;
+        }
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_are_redacted_and_filtered() {
+        let controller = LogController::new(Level::INFO);
+        controller.set_module_level("noisy", Some(Level::WARN));
+        let subscriber = Registry::default().with(controller.layer(false));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(
+                region = "north",
+                attempt = 2,
+                "Sent to VLD1:pub:{} via VLD1:route:abc, fingerprint ABCD 1234 EF56 7890 AAAA",
+                "ab".repeat(32)
+            );
+            tracing::info!(target: "noisy::inner", "hidden");
+            tracing::warn!(target: "noisy::inner", "shown");
+        });
+
+        let logs = controller.recent(10);
+        assert_eq!(logs.len(), 2);
+        assert_eq!(
+            logs[0].message,
+            "Sent to VLD1:pub:[redacted] via VLD1:route:[redacted], fingerprint [fingerprint] region=[redacted] attempt=2"
+        );
+        assert_eq!(logs[1].message, "shown");
+        assert_eq!(controller.expire_before(logs[0].timestamp, true), 0);
+        assert_eq!(controller.expire_before(logs[1].timestamp + 1, true), 2);
+        assert_eq!(controller.recent(10).len(), 2);
+        controller.expire_before(logs[1].timestamp + 1, false);
+        assert!(controller.recent(10).is_empty());
+        assert_eq!(redact("persona 0123456789abcdef0123 retry 1000 2000"), "persona [hex] retry 1000 2000");
+    }
+}
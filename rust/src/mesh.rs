@@ -0,0 +1,283 @@
+// Local mesh transport over Bluetooth LE or Wi-Fi Direct
+// The platform owns the radios: it drains outgoing frames with take_outgoing
+// and hands frames heard from nearby devices back to the core. Frames carry
+// the same sealed app messages as any other transport, sealed again under a
+// link key shared by the devices in range, so the destination route is only
+// visible to them. A device that delivers or forwards a message answers with
+// an acknowledgement frame, and the message leaves our outbox only then
+
+use crate::chunking::MAX_SUBKEY_LEN;
+use crate::crypto::{decrypt_data, encrypt_data, hash_blake3, SecureBuffer};
+use crate::error::{Result, UndergroundError};
+use crate::safety::SafetyProfile;
+use crate::transport::Transport;
+use crate::wire::{put_bytes, Reader};
+use futures::future::{self, BoxFuture};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+const MAGIC: &[u8; 3] = b"URM";
+const VERSION: u8 = 2;
+
+const KIND_MESSAGE: u8 = 0;
+const KIND_ACK: u8 = 1;
+
+/// Frames held for the platform; sends fail once this many are waiting
+pub const MAX_QUEUED_FRAMES: usize = 256;
+
+/// A message handed to the platform is queued again if not acknowledged by then
+const RESEND_AFTER_SECS: u64 = 60;
+
+/// Longest destination route in a frame
+const MAX_ROUTE_LEN: usize = 1024;
+
+/// Id a nearby device acknowledges a message by
+pub type FrameId = [u8; 16];
+
+/// What became of a frame heard from a nearby device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeshFrameOutcome {
+    /// Addressed to one of our routes
+    DeliveredLocally,
+    /// Passed on over the internet
+    Forwarded,
+    /// For someone else while we are offline too
+    Dropped,
+    /// A nearby device took one of our messages
+    Acknowledged,
+}
+
+/// Contents of a frame once the link key is removed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MeshFrame {
+    /// A sealed message for `route`
+    Message { route: String, message: Vec<u8> },
+    /// The message with this id was delivered or forwarded
+    Ack(FrameId),
+}
+
+/// Id of the message `message` to `route`, the same on every device
+pub fn frame_id(route: &str, message: &[u8]) -> FrameId {
+    let mut data = Vec::with_capacity(4 + route.len() + message.len());
+    put_bytes(&mut data, route.as_bytes());
+    data.extend_from_slice(message);
+    let mut id = [0u8; 16];
+    id.copy_from_slice(&hash_blake3(&data)[..16]);
+    id
+}
+
+/// Seal a frame under the link key for one radio hop
+pub fn seal_frame(link_key: &[u8], frame: &MeshFrame) -> Result<Vec<u8>> {
+    let mut inner = Vec::new();
+    match frame {
+        MeshFrame::Message { route, message } => {
+            inner.push(KIND_MESSAGE);
+            put_bytes(&mut inner, route.as_bytes());
+            put_bytes(&mut inner, message);
+        }
+        MeshFrame::Ack(id) => {
+            inner.push(KIND_ACK);
+            inner.extend_from_slice(id);
+        }
+    }
+    let mut out = Vec::with_capacity(MAGIC.len() + 1 + 28 + inner.len());
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    out.extend_from_slice(&encrypt_data(link_key, &inner)?);
+    Ok(out)
+}
+
+/// Open a frame sealed under the link key
+pub fn open_frame(link_key: &[u8], data: &[u8]) -> Result<MeshFrame> {
+    let mut reader = Reader::new(data, "Mesh frame");
+    reader.header(MAGIC, VERSION)?;
+    let inner = decrypt_data(link_key, reader.rest()).map_err(|_| reader.invalid("not sealed with our link key"))?;
+
+    let mut reader = Reader::new(&inner, "Mesh frame");
+    let frame = match reader.u8()? {
+        KIND_MESSAGE => {
+            let route = reader.string(MAX_ROUTE_LEN)?;
+            let message = reader.bytes(MAX_SUBKEY_LEN)?.to_vec();
+            if route.is_empty() {
+                return Err(reader.invalid("missing route"));
+            }
+            MeshFrame::Message { route, message }
+        }
+        KIND_ACK => MeshFrame::Ack(reader.array()?),
+        _ => return Err(reader.invalid("unknown frame kind")),
+    };
+    reader.finish()?;
+    Ok(frame)
+}
+
+/// Transport that queues frames for the platform's nearby-device radios
+#[derive(Default)]
+pub struct MeshTransport {
+    link_key: Mutex<Option<SecureBuffer>>,
+    outgoing: Mutex<VecDeque<Vec<u8>>>,
+    /// Messages queued or handed to the platform and not yet acknowledged,
+    /// with when they were queued
+    unacked: Mutex<HashMap<FrameId, u64>>,
+    peers: AtomicUsize,
+}
+
+impl MeshTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Key shared by the devices in range that seals every frame
+    pub fn set_link_key(&self, key: &[u8]) -> Result<()> {
+        if key.len() != 32 {
+            return Err(UndergroundError::InvalidKey);
+        }
+        *self.link_key.lock().unwrap() = Some(SecureBuffer::new(key.to_vec()));
+        Ok(())
+    }
+
+    /// Number of nearby devices the platform is connected to
+    pub fn set_peer_count(&self, peers: usize) {
+        self.peers.store(peers, Ordering::Relaxed);
+    }
+
+    pub fn peer_count(&self) -> usize {
+        self.peers.load(Ordering::Relaxed)
+    }
+
+    /// Up to `limit` frames for the platform to broadcast, oldest first
+    pub fn take_outgoing(&self, limit: usize) -> Vec<Vec<u8>> {
+        let mut outgoing = self.outgoing.lock().unwrap();
+        let count = limit.min(outgoing.len());
+        outgoing.drain(..count).collect()
+    }
+
+    /// Open a frame heard from a nearby device
+    pub fn open(&self, frame: &[u8]) -> Result<MeshFrame> {
+        open_frame(self.key()?.as_slice(), frame)
+    }
+
+    /// Stop waiting for an acknowledgement of `id`
+    /// Returns false if we were not waiting for one
+    pub fn acknowledge(&self, id: &FrameId) -> bool {
+        self.unacked.lock().unwrap().remove(id).is_some()
+    }
+
+    /// Tell nearby devices we delivered or forwarded the message with `id`
+    pub fn queue_ack(&self, id: FrameId) -> Result<()> {
+        let frame = seal_frame(self.key()?.as_slice(), &MeshFrame::Ack(id))?;
+        self.enqueue(frame)
+    }
+
+    fn key(&self) -> Result<SecureBuffer> {
+        match self.link_key.lock().unwrap().as_ref() {
+            Some(key) => Ok(SecureBuffer::new(key.as_slice().to_vec())),
+            None => Err(UndergroundError::Veilid("No mesh link key".to_string())),
+        }
+    }
+
+    fn enqueue(&self, frame: Vec<u8>) -> Result<()> {
+        let mut outgoing = self.outgoing.lock().unwrap();
+        if outgoing.len() >= MAX_QUEUED_FRAMES {
+            return Err(UndergroundError::Veilid("Mesh queue is full".to_string()));
+        }
+        outgoing.push_back(frame);
+        Ok(())
+    }
+
+    fn queue_message(&self, route: &str, message: &[u8]) -> Result<()> {
+        if self.peer_count() == 0 {
+            return Err(UndergroundError::Veilid("No nearby devices".to_string()));
+        }
+        let id = frame_id(route, message);
+        let now = crate::util::unix_now();
+        let mut unacked = self.unacked.lock().unwrap();
+        unacked.retain(|_, queued_at| now < *queued_at + RESEND_AFTER_SECS);
+        if unacked.contains_key(&id) {
+            // Still on its way; queueing it again would only add radio traffic
+            return Ok(());
+        }
+
+        let frame = MeshFrame::Message {
+            route: route.to_string(),
+            message: message.to_vec(),
+        };
+        self.enqueue(seal_frame(self.key()?.as_slice(), &frame)?)?;
+        unacked.insert(id, now);
+        Ok(())
+    }
+}
+
+impl Transport for MeshTransport {
+    fn name(&self) -> &'static str {
+        "mesh"
+    }
+
+    fn attach(&self) -> BoxFuture<'_, Result<bool>> {
+        Box::pin(future::ready(Ok(self.peer_count() > 0)))
+    }
+
+    fn detach(&self) -> BoxFuture<'_, Result<()>> {
+        // Messages stay in the outbox until acknowledged, so nothing is lost here
+        self.outgoing.lock().unwrap().clear();
+        self.unacked.lock().unwrap().clear();
+        Box::pin(future::ready(Ok(())))
+    }
+
+    fn send(&self, route: &str, message: Vec<u8>, _safety: SafetyProfile) -> BoxFuture<'_, Result<()>> {
+        Box::pin(future::ready(self.queue_message(route, &message)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_frames_queue_only_with_peers() {
+        let mesh = MeshTransport::new();
+        let safety = SafetyProfile::urgent();
+        assert!(mesh.send("VLD1:route:aa", vec![1], safety).await.is_err());
+
+        mesh.set_peer_count(2);
+        assert!(mesh.attach().await.unwrap());
+        assert!(mesh.send("VLD1:route:aa", vec![1], safety).await.is_err(), "no link key yet");
+        mesh.set_link_key(&[7u8; 32]).unwrap();
+        mesh.send("VLD1:route:aa", vec![1, 2], safety).await.unwrap();
+        mesh.send("VLD1:route:aa", vec![1, 2], safety).await.unwrap();
+        mesh.send("VLD1:route:bb", vec![3], safety).await.unwrap();
+
+        // The route never appears in the clear, and a device without the key cannot open it
+        let frames = mesh.take_outgoing(1);
+        assert!(!frames[0].windows(13).any(|w| w == b"VLD1:route:aa"));
+        assert!(open_frame(&[8u8; 32], &frames[0]).is_err());
+        let expected = MeshFrame::Message {
+            route: "VLD1:route:aa".to_string(),
+            message: vec![1, 2],
+        };
+        assert_eq!(mesh.open(&frames[0]).unwrap(), expected);
+        assert_eq!(mesh.take_outgoing(10).len(), 1, "a message still awaiting its ack is not queued twice");
+
+        assert!(mesh.acknowledge(&frame_id("VLD1:route:aa", &[1, 2])));
+        assert!(!mesh.acknowledge(&frame_id("VLD1:route:aa", &[1, 2])));
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_refuses_instead_of_evicting() {
+        let mesh = MeshTransport::new();
+        mesh.set_peer_count(1);
+        mesh.set_link_key(&[7u8; 32]).unwrap();
+        let safety = SafetyProfile::urgent();
+        for i in 0..MAX_QUEUED_FRAMES as u32 {
+            mesh.send("VLD1:route:aa", i.to_be_bytes().to_vec(), safety).await.unwrap();
+        }
+        assert!(mesh.send("VLD1:route:aa", vec![0xff], safety).await.is_err());
+
+        let first = mesh.take_outgoing(1);
+        let expected = MeshFrame::Message {
+            route: "VLD1:route:aa".to_string(),
+            message: 0u32.to_be_bytes().to_vec(),
+        };
+        assert_eq!(mesh.open(&first[0]).unwrap(), expected);
+    }
+}
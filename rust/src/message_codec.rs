@@ -0,0 +1,234 @@
+// Sealed message payloads: optional compression, padding, then encryption
+// Compression leaks information through ciphertext length when a message mixes
+// secrets with text an attacker can influence (quoted replies, forwarded
+// content), so Mixed messages are never compressed and padding hides the rest.
+// The frame also carries the sender's per-contact counter (see replay.rs)
+
+use crate::crypto::{decrypt_data, encrypt_data};
+use crate::error::{Result, UndergroundError};
+use crate::wire::Reader;
+use serde::{Deserialize, Serialize};
+
+const VERSION: u8 = 1;
+const FLAG_COMPRESSED: u8 = 1;
+
+/// Largest plaintext sealed or opened, after decompression
+pub const MAX_MESSAGE_LEN: usize = 1024 * 1024;
+
+/// Smallest padded frame; frames grow in powers of two from here
+const MIN_PADDED_LEN: usize = 256;
+
+/// zstd level; low levels are fast and most of the gain on short text
+#[cfg(feature = "native")]
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// What a message holds, which decides whether compressing it is safe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageKind {
+    /// Text written by the sender
+    Text,
+    /// Structured data generated by the app (reports, route lists)
+    Bundle,
+    /// Files, usually already compressed
+    Attachment,
+    /// Sender content combined with attacker-influenceable content; never compressed
+    Mixed,
+}
+
+impl MessageKind {
+    fn to_byte(self) -> u8 {
+        match self {
+            Self::Text => 0,
+            Self::Bundle => 1,
+            Self::Attachment => 2,
+            Self::Mixed => 3,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Self::Text),
+            1 => Some(Self::Bundle),
+            2 => Some(Self::Attachment),
+            3 => Some(Self::Mixed),
+            _ => None,
+        }
+    }
+}
+
+/// Which message kinds are compressed before encryption
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompressionConfig {
+    pub enabled: bool,
+    pub kinds: Vec<MessageKind>,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            kinds: vec![MessageKind::Bundle],
+        }
+    }
+}
+
+impl CompressionConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.kinds.contains(&MessageKind::Mixed) {
+            return Err(UndergroundError::Config(
+                "compression.kinds cannot include mixed content".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn compresses(&self, kind: MessageKind) -> bool {
+        self.enabled && kind != MessageKind::Mixed && self.kinds.contains(&kind)
+    }
+}
+
+/// A decrypted message frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenedFrame {
+    pub kind: MessageKind,
+    /// Sender's counter for this contact; check it with ReplayState::accept
+    pub counter: u64,
+    pub plaintext: Vec<u8>,
+}
+
+/// Compress (if allowed and useful), pad and encrypt a message
+pub fn seal(
+    key: &[u8],
+    kind: MessageKind,
+    counter: u64,
+    plaintext: &[u8],
+    config: &CompressionConfig,
+) -> Result<Vec<u8>> {
+    if plaintext.len() > MAX_MESSAGE_LEN {
+        return Err(UndergroundError::InvalidMessage("Message too large".to_string()));
+    }
+
+    let compressed = if config.compresses(kind) {
+        compress(plaintext)?.filter(|c| c.len() < plaintext.len())
+    } else {
+        None
+    };
+    let (flags, body) = match &compressed {
+        Some(c) => (FLAG_COMPRESSED, c.as_slice()),
+        None => (0, plaintext),
+    };
+
+    let unpadded = 15 + body.len();
+    let mut frame = Vec::with_capacity(padded_len(unpadded));
+    frame.push(VERSION);
+    frame.push(flags);
+    frame.push(kind.to_byte());
+    frame.extend_from_slice(&counter.to_be_bytes());
+    frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    frame.extend_from_slice(body);
+    frame.resize(padded_len(unpadded), 0);
+
+    encrypt_data(key, &frame)
+}
+
+/// Decrypt, unpad and decompress a sealed message
+pub fn open(key: &[u8], sealed: &[u8]) -> Result<OpenedFrame> {
+    let frame = decrypt_data(key, sealed)?;
+    let mut reader = Reader::new(&frame, "Sealed message");
+    if reader.u8()? != VERSION {
+        return Err(reader.invalid("unsupported version"));
+    }
+    let flags = reader.u8()?;
+    let kind = MessageKind::from_byte(reader.u8()?).ok_or_else(|| reader.invalid("unknown message kind"))?;
+    let counter = reader.u64()?;
+    let len = reader.u32()? as usize;
+    if len > MAX_MESSAGE_LEN {
+        return Err(reader.invalid("too large"));
+    }
+    let body = reader.take(len)?;
+    if reader.rest().iter().any(|&b| b != 0) {
+        return Err(UndergroundError::InvalidMessage("Sealed message: bad padding".to_string()));
+    }
+
+    let plaintext = if flags & FLAG_COMPRESSED != 0 {
+        decompress(body)?
+    } else {
+        body.to_vec()
+    };
+    Ok(OpenedFrame {
+        kind,
+        counter,
+        plaintext,
+    })
+}
+
+/// Next power of two at or above `len`, at least MIN_PADDED_LEN
+fn padded_len(len: usize) -> usize {
+    len.max(MIN_PADDED_LEN).next_power_of_two()
+}
+
+#[cfg(feature = "native")]
+fn compress(data: &[u8]) -> Result<Option<Vec<u8>>> {
+    zstd::bulk::compress(data, COMPRESSION_LEVEL)
+        .map(Some)
+        .map_err(|e| UndergroundError::Unknown(e.to_string()))
+}
+
+// Without native builds messages are sent uncompressed
+#[cfg(not(feature = "native"))]
+fn compress(_data: &[u8]) -> Result<Option<Vec<u8>>> {
+    Ok(None)
+}
+
+#[cfg(feature = "native")]
+fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    // Bounded so a small frame cannot expand without limit
+    zstd::bulk::decompress(data, MAX_MESSAGE_LEN)
+        .map_err(|_| UndergroundError::InvalidMessage("Sealed message: bad compressed data".to_string()))
+}
+
+#[cfg(not(feature = "native"))]
+fn decompress(_data: &[u8]) -> Result<Vec<u8>> {
+    Err(UndergroundError::InvalidMessage(
+        "Compressed messages are not supported in this build".to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_random_bytes;
+
+    #[test]
+    fn test_seal_open_and_mixed_not_compressed() {
+        let key = generate_random_bytes(32);
+        let config = CompressionConfig {
+            enabled: true,
+            kinds: vec![MessageKind::Bundle, MessageKind::Text],
+        };
+        let report = "checkpoint north road clear; ".repeat(200).into_bytes();
+
+        let sealed = seal(&key, MessageKind::Bundle, 1, &report, &config).unwrap();
+        if cfg!(feature = "native") {
+            assert!(sealed.len() < report.len());
+        }
+        let opened = open(&key, &sealed).unwrap();
+        assert_eq!((opened.kind, opened.counter), (MessageKind::Bundle, 1));
+        assert_eq!(opened.plaintext, report);
+
+        // Same content as mixed: padded to the same bucket as uncompressed text
+        let mixed = seal(&key, MessageKind::Mixed, 2, &report, &config).unwrap();
+        assert!(mixed.len() > report.len());
+        assert_eq!(open(&key, &mixed).unwrap().kind, MessageKind::Mixed);
+
+        let short = seal(&key, MessageKind::Text, 3, b"ok", &config).unwrap();
+        let other = seal(&key, MessageKind::Text, 4, b"meet at six", &config).unwrap();
+        assert_eq!(short.len(), other.len());
+
+        assert!(CompressionConfig { enabled: true, kinds: vec![MessageKind::Mixed] }
+            .validate()
+            .is_err());
+    }
+}
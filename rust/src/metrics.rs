@@ -0,0 +1,174 @@
+// Network performance metrics
+// Lets coordinators see that the network is degraded before relying on it
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Number of routes with latency tracked individually
+const MAX_TRACKED_ROUTES: usize = 128;
+
+/// Minimum samples before a success rate counts toward degradation
+const MIN_SAMPLES: u64 = 5;
+
+/// Success rate below which the network is considered degraded
+const DEGRADED_SUCCESS_RATE: f64 = 0.8;
+
+/// Round trip above which the network is considered degraded
+const DEGRADED_ROUND_TRIP_MS: u64 = 10_000;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct LatencyStats {
+    count: u64,
+    total_ms: u64,
+    max_ms: u64,
+}
+
+impl LatencyStats {
+    fn record(&mut self, elapsed: Duration) {
+        let ms = elapsed.as_millis() as u64;
+        self.count += 1;
+        self.total_ms = self.total_ms.saturating_add(ms);
+        self.max_ms = self.max_ms.max(ms);
+    }
+
+    fn average_ms(&self) -> Option<u64> {
+        (self.count > 0).then(|| self.total_ms / self.count)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct OpCounter {
+    successes: u64,
+    failures: u64,
+}
+
+impl OpCounter {
+    fn record(&mut self, ok: bool) {
+        if ok {
+            self.successes += 1;
+        } else {
+            self.failures += 1;
+        }
+    }
+
+    fn total(&self) -> u64 {
+        self.successes + self.failures
+    }
+
+    fn success_rate(&self) -> Option<f64> {
+        (self.total() > 0).then(|| self.successes as f64 / self.total() as f64)
+    }
+
+    fn is_degraded(&self) -> bool {
+        self.total() >= MIN_SAMPLES
+            && self.success_rate().unwrap_or(1.0) < DEGRADED_SUCCESS_RATE
+    }
+}
+
+/// Collected network metrics
+#[derive(Debug, Default)]
+pub struct NetworkMetrics {
+    route_latency: HashMap<String, LatencyStats>,
+    dht_reads: OpCounter,
+    dht_writes: OpCounter,
+    sends: OpCounter,
+    round_trip: LatencyStats,
+}
+
+/// Point-in-time summary of network metrics
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricsSnapshot {
+    pub dht_read_success_rate: Option<f64>,
+    pub dht_write_success_rate: Option<f64>,
+    pub send_success_rate: Option<f64>,
+    pub avg_route_latency_ms: Option<u64>,
+    pub avg_round_trip_ms: Option<u64>,
+    pub max_round_trip_ms: Option<u64>,
+    pub routes_tracked: u32,
+    pub degraded: bool,
+}
+
+impl NetworkMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a send over a route
+    pub fn record_send(&mut self, route: &str, elapsed: Duration, ok: bool) {
+        self.sends.record(ok);
+        if !ok {
+            return;
+        }
+
+        if !self.route_latency.contains_key(route) && self.route_latency.len() >= MAX_TRACKED_ROUTES {
+            return;
+        }
+        self.route_latency
+            .entry(route.to_string())
+            .or_default()
+            .record(elapsed);
+    }
+
+    pub fn record_dht_read(&mut self, ok: bool) {
+        self.dht_reads.record(ok);
+    }
+
+    pub fn record_dht_write(&mut self, ok: bool) {
+        self.dht_writes.record(ok);
+    }
+
+    /// Record the time between sending a message and getting its reply
+    pub fn record_round_trip(&mut self, elapsed: Duration) {
+        self.round_trip.record(elapsed);
+    }
+
+    /// Stop tracking a route (e.g. after it died)
+    pub fn forget_route(&mut self, route: &str) {
+        self.route_latency.remove(route);
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let (count, total) = self
+            .route_latency
+            .values()
+            .fold((0u64, 0u64), |(c, t), s| (c + s.count, t.saturating_add(s.total_ms)));
+        let avg_round_trip_ms = self.round_trip.average_ms();
+
+        let degraded = self.dht_reads.is_degraded()
+            || self.dht_writes.is_degraded()
+            || self.sends.is_degraded()
+            || avg_round_trip_ms.is_some_and(|ms| ms > DEGRADED_ROUND_TRIP_MS);
+
+        MetricsSnapshot {
+            dht_read_success_rate: self.dht_reads.success_rate(),
+            dht_write_success_rate: self.dht_writes.success_rate(),
+            send_success_rate: self.sends.success_rate(),
+            avg_route_latency_ms: (count > 0).then(|| total / count),
+            avg_round_trip_ms,
+            max_round_trip_ms: (self.round_trip.count > 0).then_some(self.round_trip.max_ms),
+            routes_tracked: self.route_latency.len() as u32,
+            degraded,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_degraded_on_failures() {
+        let mut metrics = NetworkMetrics::new();
+        for _ in 0..3 {
+            metrics.record_dht_read(true);
+        }
+        assert!(!metrics.snapshot().degraded);
+
+        for _ in 0..3 {
+            metrics.record_dht_read(false);
+        }
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.dht_read_success_rate, Some(0.5));
+        assert!(snapshot.degraded);
+    }
+}
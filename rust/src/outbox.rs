@@ -0,0 +1,307 @@
+// Persistent queue of messages waiting for the network
+// Sends made while detached, and shaped sends held until their release time,
+// are kept here and saved with the profile so a restart loses neither
+
+use crate::error::{Result, UndergroundError};
+use crate::safety::SafetyProfile;
+use crate::store_format::{self, base64_bytes};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+
+/// File name of the persisted outbox inside the config directory
+pub(crate) const OUTBOX_FILE: &str = "outbox.json";
+
+/// Where an unparseable outbox is set aside
+pub(crate) const OUTBOX_CORRUPT_FILE: &str = "outbox.json.corrupt";
+
+/// Layout version of outbox.json; a change adds a converter in `Outbox::read`
+const OUTBOX_VERSION: u32 = 1;
+
+/// Maximum number of messages held while detached
+const MAX_OUTBOX_ENTRIES: usize = 1024;
+
+/// A message waiting for the network
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    pub route: String,
+    pub message: Vec<u8>,
+    pub safety: SafetyProfile,
+    pub queued_at: u64,
+    /// Shaped sends stay held until released; None leaves with the next flush
+    #[serde(default)]
+    pub release_at: Option<u64>,
+}
+
+impl OutboxEntry {
+    fn is_held(&self) -> bool {
+        self.release_at.is_some()
+    }
+}
+
+/// Queue of messages waiting for the network, oldest first
+#[derive(Debug, Default)]
+pub struct Outbox {
+    entries: VecDeque<OutboxEntry>,
+}
+
+/// outbox.json as written since version 1
+#[derive(Serialize, Deserialize)]
+struct StoredOutbox {
+    version: u32,
+    entries: Vec<StoredEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredEntry {
+    route: String,
+    #[serde(with = "base64_bytes")]
+    message: Vec<u8>,
+    safety: SafetyProfile,
+    queued_at: u64,
+    release_at: Option<u64>,
+}
+
+/// outbox.json before it was versioned, with message bytes as number arrays
+#[derive(Deserialize)]
+struct OutboxV0 {
+    entries: VecDeque<OutboxEntry>,
+}
+
+impl Outbox {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the outbox from a config directory (empty if none saved yet)
+    pub fn load(config_dir: &Path) -> Result<Self> {
+        Ok(Self::read(config_dir)?.0)
+    }
+
+    /// Load the outbox and the layout version it was saved in
+    fn read(config_dir: &Path) -> Result<(Self, u32)> {
+        let path = config_dir.join(OUTBOX_FILE);
+        if !path.exists() {
+            return Ok((Self::default(), OUTBOX_VERSION));
+        }
+        let bytes = fs::read(path)?;
+        let version = store_format::read_version(&bytes, OUTBOX_FILE, OUTBOX_VERSION)?;
+        let entries = if version == 0 {
+            serde_json::from_slice::<OutboxV0>(&bytes)?.entries
+        } else {
+            let stored: StoredOutbox = serde_json::from_slice(&bytes)?;
+            stored
+                .entries
+                .into_iter()
+                .map(|e| OutboxEntry {
+                    route: e.route,
+                    message: e.message,
+                    safety: e.safety,
+                    queued_at: e.queued_at,
+                    release_at: e.release_at,
+                })
+                .collect()
+        };
+        Ok((Self { entries }, version))
+    }
+
+    /// Load at startup, setting an unparseable file aside instead of failing
+    /// It is kept as outbox.json.corrupt rather than overwritten by the next save.
+    /// A file in an older layout is rewritten in the current one
+    pub fn load_or_set_aside(config_dir: &Path) -> Result<Self> {
+        match Self::read(config_dir) {
+            Ok((outbox, version)) => {
+                if version < OUTBOX_VERSION {
+                    outbox.save(config_dir)?;
+                    tracing::info!("Migrated the outbox from layout version {}", version);
+                }
+                Ok(outbox)
+            }
+            Err(UndergroundError::Serialization(e)) => {
+                let path = config_dir.join(OUTBOX_FILE);
+                tracing::error!("Outbox is corrupt, starting with no queued messages: {}", e);
+                fs::rename(&path, config_dir.join(OUTBOX_CORRUPT_FILE))?;
+                Ok(Self::default())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self, config_dir: &Path) -> Result<()> {
+        let stored = StoredOutbox {
+            version: OUTBOX_VERSION,
+            entries: self
+                .entries
+                .iter()
+                .map(|e| StoredEntry {
+                    route: e.route.clone(),
+                    message: e.message.clone(),
+                    safety: e.safety,
+                    queued_at: e.queued_at,
+                    release_at: e.release_at,
+                })
+                .collect(),
+        };
+        fs::create_dir_all(config_dir)?;
+        fs::write(config_dir.join(OUTBOX_FILE), serde_json::to_vec(&stored)?)?;
+        Ok(())
+    }
+
+    /// Queue a message, dropping the oldest when full
+    /// Returns false if an older message had to be dropped
+    pub fn push(&mut self, entry: OutboxEntry) -> bool {
+        let mut kept_all = true;
+        if self.entries.len() >= MAX_OUTBOX_ENTRIES {
+            self.entries.pop_front();
+            kept_all = false;
+        }
+        self.entries.push_back(entry);
+        kept_all
+    }
+
+    /// Put an entry back at the front (e.g. after a failed flush)
+    pub fn requeue(&mut self, entry: OutboxEntry) {
+        self.entries.push_front(entry);
+    }
+
+    /// Take the oldest message that is not held for shaping
+    pub fn pop(&mut self) -> Option<OutboxEntry> {
+        let index = self.entries.iter().position(|e| !e.is_held())?;
+        self.entries.remove(index)
+    }
+
+    /// Once any held send is due, release a batch of the earliest held ones
+    /// (or all of them for None) so they leave together with the next flush
+    /// Returns how many were released
+    pub fn release_held(&mut self, now: u64, max_batch: Option<usize>) -> usize {
+        let mut held: Vec<&mut OutboxEntry> = self.entries.iter_mut().filter(|e| e.is_held()).collect();
+        let Some(max_batch) = max_batch else {
+            held.iter_mut().for_each(|e| e.release_at = None);
+            return held.len();
+        };
+        if !held.iter().any(|e| e.release_at.is_some_and(|at| at <= now)) {
+            return 0;
+        }
+        held.sort_by_key(|e| e.release_at);
+        let count = max_batch.min(held.len());
+        held.into_iter().take(count).for_each(|e| e.release_at = None);
+        count
+    }
+
+    /// Drop entries matching `done` (e.g. acknowledged by a nearby device),
+    /// returning how many were dropped
+    pub fn remove_where(&mut self, done: impl Fn(&OutboxEntry) -> bool) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|e| !done(e));
+        before - self.entries.len()
+    }
+
+    /// Entries ready to leave (not held for shaping), oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &OutboxEntry> {
+        self.entries.iter().filter(|e| !e.is_held())
+    }
+
+    /// Drop entries queued before `cutoff`, returning how many were dropped
+    pub fn expire_before(&mut self, cutoff: u64) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|e| e.queued_at >= cutoff);
+        before - self.entries.len()
+    }
+
+    /// Number of entries queued before `cutoff`
+    pub fn count_before(&self, cutoff: u64) -> usize {
+        self.entries.iter().filter(|e| e.queued_at < cutoff).count()
+    }
+
+    /// Number of shaped sends still held
+    pub fn held_len(&self) -> usize {
+        self.entries.iter().filter(|e| e.is_held()).count()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(route: &str, release_at: Option<u64>) -> OutboxEntry {
+        OutboxEntry {
+            route: route.to_string(),
+            message: vec![1],
+            safety: SafetyProfile::routine(),
+            queued_at: 10,
+            release_at,
+        }
+    }
+
+    #[test]
+    fn test_requeued_entries_leave_first_and_survive_reload() {
+        let mut outbox = Outbox::new();
+        outbox.push(entry("a", None));
+        outbox.push(entry("b", None));
+        let failed = outbox.pop().unwrap();
+        assert_eq!(failed.route, "a");
+        outbox.requeue(failed);
+
+        let tmp = crate::util::TempDir::new("outbox");
+        outbox.save(tmp.path()).unwrap();
+        let mut restored = Outbox::load(tmp.path()).unwrap();
+        assert_eq!(restored.pop().unwrap().route, "a");
+        assert_eq!(restored.pop().unwrap().route, "b");
+        assert!(restored.pop().is_none());
+    }
+
+    #[test]
+    fn test_held_entries_wait_for_release() {
+        let mut outbox = Outbox::new();
+        outbox.push(entry("late", Some(200)));
+        outbox.push(entry("early", Some(100)));
+        outbox.push(entry("now", None));
+        assert_eq!(outbox.iter().count(), 1);
+        assert_eq!(outbox.pop().unwrap().route, "now");
+        assert!(outbox.pop().is_none());
+
+        assert_eq!(outbox.release_held(50, Some(1)), 0);
+        assert_eq!(outbox.release_held(100, Some(1)), 1);
+        assert_eq!(outbox.pop().unwrap().route, "early");
+        assert_eq!(outbox.held_len(), 1);
+        assert_eq!(outbox.release_held(0, None), 1);
+        assert_eq!(outbox.pop().unwrap().route, "late");
+    }
+
+    #[test]
+    fn test_full_outbox_drops_oldest() {
+        let mut outbox = Outbox::new();
+        for i in 0..MAX_OUTBOX_ENTRIES {
+            assert!(outbox.push(entry(&i.to_string(), None)));
+        }
+        assert!(!outbox.push(entry("newest", None)));
+        assert_eq!(outbox.len(), MAX_OUTBOX_ENTRIES);
+        assert_eq!(outbox.pop().unwrap().route, "1");
+    }
+
+    #[test]
+    fn test_unversioned_outbox_is_migrated() {
+        let tmp = crate::util::TempDir::new("outbox-migrate");
+        let mut v0 = serde_json::json!({ "entries": [entry("a", Some(5))] });
+        v0["entries"][0].as_object_mut().unwrap().remove("release_at");
+        fs::write(tmp.path().join(OUTBOX_FILE), v0.to_string()).unwrap();
+
+        let mut outbox = Outbox::load_or_set_aside(tmp.path()).unwrap();
+        assert_eq!(outbox.pop(), Some(entry("a", None)));
+
+        let saved: serde_json::Value =
+            serde_json::from_slice(&fs::read(tmp.path().join(OUTBOX_FILE)).unwrap()).unwrap();
+        assert_eq!(saved["version"], OUTBOX_VERSION);
+        assert_eq!(saved["entries"][0]["message"], "AQ==");
+        assert_eq!(Outbox::load(tmp.path()).unwrap().pop(), Some(entry("a", None)));
+    }
+}
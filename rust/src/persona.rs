@@ -0,0 +1,240 @@
+// Personas: separate identities, each with its own route and mailbox
+// Secret keys are handed to the app once and never stored here.
+// Per-persona state lives in PersonaScoped containers, reachable only through a
+// PersonaHandle from the store, so one persona's data cannot be read while
+// acting as another by mistake
+
+use crate::error::{Result, UndergroundError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// File name of the persisted persona list inside the config directory
+pub(crate) const PERSONAS_FILE: &str = "personas.json";
+
+/// Where an unparseable persona store is set aside
+pub(crate) const PERSONAS_CORRUPT_FILE: &str = "personas.json.corrupt";
+
+/// Longest accepted persona name
+pub const MAX_PERSONA_NAME_LEN: usize = 64;
+
+/// Lifetime of a persona's mailbox record before it needs refreshing
+pub const MAILBOX_TTL_SECS: u64 = 24 * 3600;
+
+/// Public half of a persona
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Persona {
+    pub id: String,
+    pub name: String,
+    pub public_key: String,
+    pub mailbox_key: String,
+    pub route: String,
+    pub created_at: u64,
+}
+
+/// Capability to act as one persona; only PersonaStore creates these
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PersonaHandle {
+    id: String,
+}
+
+impl PersonaHandle {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+/// State kept separately for each persona
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PersonaScoped<T> {
+    by_persona: HashMap<String, T>,
+}
+
+impl<T> Default for PersonaScoped<T> {
+    fn default() -> Self {
+        Self {
+            by_persona: HashMap::new(),
+        }
+    }
+}
+
+impl<T: Default> PersonaScoped<T> {
+    pub fn get(&self, persona: &PersonaHandle) -> Option<&T> {
+        self.by_persona.get(&persona.id)
+    }
+
+    /// This persona's state, created empty on first use
+    pub fn get_mut(&mut self, persona: &PersonaHandle) -> &mut T {
+        self.by_persona.entry(persona.id.clone()).or_default()
+    }
+
+    pub fn remove(&mut self, persona: &PersonaHandle) -> Option<T> {
+        self.by_persona.remove(&persona.id)
+    }
+
+    /// Every persona's state at once; each call is logged with its reason
+    pub fn across_personas(&self, reason: &str) -> impl Iterator<Item = (&str, &T)> {
+        tracing::info!("Cross-persona access: {}", reason);
+        self.by_persona.iter().map(|(id, state)| (id.as_str(), state))
+    }
+
+    /// Every persona's state at once, for changes that apply to all of them
+    pub fn across_personas_mut(&mut self, reason: &str) -> impl Iterator<Item = (&str, &mut T)> {
+        tracing::info!("Cross-persona access: {}", reason);
+        self.by_persona.iter_mut().map(|(id, state)| (id.as_str(), state))
+    }
+}
+
+/// All personas on this device and which one is active
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PersonaStore {
+    personas: Vec<Persona>,
+    active: Option<String>,
+}
+
+impl PersonaStore {
+    /// Load personas from a config directory (empty if none saved yet)
+    pub fn load(config_dir: &Path) -> Result<Self> {
+        let path = config_dir.join(PERSONAS_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    /// Load personas at startup, setting an unparseable file aside instead of
+    /// failing. It is kept as personas.json.corrupt for manual recovery
+    pub fn load_or_set_aside(config_dir: &Path) -> Result<Self> {
+        match Self::load(config_dir) {
+            Err(UndergroundError::Serialization(e)) => {
+                let path = config_dir.join(PERSONAS_FILE);
+                tracing::error!("Persona store is corrupt, starting without personas: {}", e);
+                fs::rename(&path, config_dir.join(PERSONAS_CORRUPT_FILE))?;
+                Ok(Self::default())
+            }
+            result => result,
+        }
+    }
+
+    pub fn save(&self, config_dir: &Path) -> Result<()> {
+        fs::create_dir_all(config_dir)?;
+        fs::write(config_dir.join(PERSONAS_FILE), serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    pub fn list(&self) -> &[Persona] {
+        &self.personas
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Persona> {
+        self.personas.iter().find(|p| p.id == id)
+    }
+
+    pub fn active(&self) -> Option<&Persona> {
+        self.active.as_deref().and_then(|id| self.get(id))
+    }
+
+    /// Handle for acting as persona `id`
+    pub fn handle(&self, id: &str) -> Option<PersonaHandle> {
+        self.get(id).map(|p| PersonaHandle { id: p.id.clone() })
+    }
+
+    pub fn active_handle(&self) -> Option<PersonaHandle> {
+        self.active().map(|p| PersonaHandle { id: p.id.clone() })
+    }
+
+    /// Add a persona; the first one becomes active
+    pub fn add(&mut self, persona: Persona) -> Result<()> {
+        let name = persona.name.trim();
+        if name.is_empty() || name.len() > MAX_PERSONA_NAME_LEN {
+            return Err(UndergroundError::InvalidMessage("Invalid persona name".to_string()));
+        }
+        if self.active.is_none() {
+            self.active = Some(persona.id.clone());
+        }
+        self.personas.push(persona);
+        Ok(())
+    }
+
+    pub fn switch(&mut self, id: &str) -> Result<()> {
+        if self.get(id).is_none() {
+            return Err(UndergroundError::RecordNotFound {
+                entity: "Persona".to_string(),
+                id: id.to_string(),
+            });
+        }
+        self.active = Some(id.to_string());
+        Ok(())
+    }
+
+    /// Remove a persona; if it was active, the oldest remaining one takes over
+    pub fn remove(&mut self, id: &str) -> Result<Persona> {
+        let index = self
+            .personas
+            .iter()
+            .position(|p| p.id == id)
+            .ok_or_else(|| UndergroundError::RecordNotFound {
+                entity: "Persona".to_string(),
+                id: id.to_string(),
+            })?;
+        let removed = self.personas.remove(index);
+
+        if self.active.as_deref() == Some(id) {
+            self.active = self.personas.first().map(|p| p.id.clone());
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn persona(id: &str) -> Persona {
+        Persona {
+            id: id.to_string(),
+            name: format!("persona {}", id),
+            public_key: format!("VLD1:pub:{}", id),
+            mailbox_key: format!("VLD1:dht:{}", id),
+            route: format!("VLD1:route:{}", id),
+            created_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_switch_and_remove_active() {
+        let mut store = PersonaStore::default();
+        store.add(persona("a")).unwrap();
+        store.add(persona("b")).unwrap();
+        assert_eq!(store.active().unwrap().id, "a");
+
+        store.switch("b").unwrap();
+        assert_eq!(store.active().unwrap().id, "b");
+        assert!(store.switch("missing").is_err());
+
+        store.remove("b").unwrap();
+        assert_eq!(store.active().unwrap().id, "a");
+        let a = store.handle("a").unwrap();
+        let mut counters: PersonaScoped<u32> = PersonaScoped::default();
+        *counters.get_mut(&a) += 1;
+        assert_eq!(counters.get(&a), Some(&1));
+        assert!(store.handle("b").is_none());
+
+        store.remove("a").unwrap();
+        assert!(store.active().is_none());
+        assert_eq!(counters.remove(&a), Some(1));
+    }
+
+    #[test]
+    fn test_corrupt_store_set_aside() {
+        let tmp = crate::util::TempDir::new("personas");
+        fs::write(tmp.path().join(PERSONAS_FILE), b"{\"personas\": [").unwrap();
+
+        assert!(PersonaStore::load(tmp.path()).is_err());
+        assert!(PersonaStore::load_or_set_aside(tmp.path()).unwrap().list().is_empty());
+        assert!(tmp.path().join(PERSONAS_CORRUPT_FILE).exists());
+        assert!(!tmp.path().join(PERSONAS_FILE).exists());
+    }
+}
@@ -0,0 +1,483 @@
+// Per-contact key pinning
+// The first public keys (signing and encryption) seen for a contact are pinned
+// by their hash (the keys themselves are never stored here). Different keys
+// later are a pin violation, and sending to that contact is refused until the
+// user resolves it. Routes learned for the contact are kept with its pin so
+// the manager can refuse sends to them from any path. Pins the user marks
+// shareable can be handed to another device in a contact bundle.
+// Contacts are keyed by one spelling of their public key (see contact_id), so
+// the same key given with or without its prefix, or in either case, is one
+// contact; pins saved under other spellings are merged when loaded
+
+use crate::crypto::{decode_typed_key, hash_blake3};
+use crate::error::{Result, UndergroundError};
+use crate::persona::PersonaScoped;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::Path;
+
+/// File name of the persisted pins inside the config directory
+pub(crate) const PINS_FILE: &str = "pins.json";
+
+/// How keys are pinned and how violations may be resolved
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PinPolicy {
+    /// Pin on first use; a changed key can only be accepted by removing the pin
+    Strict,
+    /// Pin on first use; a changed key is reported and can be accepted
+    #[default]
+    TrustOnFirstUse,
+    /// Nothing is pinned automatically; unpinned contacts cannot be sent to
+    Manual,
+}
+
+/// Key pinning settings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PinningConfig {
+    pub policy: PinPolicy,
+}
+
+/// Routes remembered per contact; the oldest is forgotten first
+pub(crate) const MAX_ROUTES_PER_CONTACT: usize = 16;
+
+/// What of a contact's pin the user agreed to put in contact bundles, least first
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum SharePolicy {
+    #[default]
+    Never,
+    /// The pinned key hash only
+    KeysOnly,
+    /// The pinned key hash and the routes learned for the contact
+    WithRoutes,
+}
+
+/// Public keys a contact is known by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContactKeys {
+    pub signing: [u8; 32],
+    pub encryption: [u8; 32],
+}
+
+impl ContactKeys {
+    fn hash(&self) -> [u8; 32] {
+        let mut both = [0u8; 64];
+        both[..32].copy_from_slice(&self.signing);
+        both[32..].copy_from_slice(&self.encryption);
+        hash_blake3(&both)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Pin {
+    key_hash: [u8; 32],
+    pinned_at: u64,
+    /// Hash of different keys seen since, while unresolved
+    conflicting: Option<[u8; 32]>,
+    /// Routes the contact was reached at, held while a violation is unresolved
+    #[serde(default)]
+    routes: VecDeque<String>,
+    #[serde(default)]
+    share: SharePolicy,
+}
+
+impl Pin {
+    /// Combine two pins of one contact, keeping the older pin's keys
+    /// Different keys in the newer pin are held as a key change
+    fn merge(self, other: Pin) -> Pin {
+        let (mut kept, other) = if other.pinned_at < self.pinned_at {
+            (other, self)
+        } else {
+            (self, other)
+        };
+        if other.key_hash != kept.key_hash {
+            kept.conflicting.get_or_insert(other.key_hash);
+        } else if let Some(conflicting) = other.conflicting {
+            kept.conflicting.get_or_insert(conflicting);
+        }
+        for route in other.routes {
+            if !kept.routes.contains(&route) {
+                kept.routes.push_back(route);
+            }
+        }
+        while kept.routes.len() > MAX_ROUTES_PER_CONTACT {
+            kept.routes.pop_front();
+        }
+        kept.share = kept.share.min(other.share);
+        kept
+    }
+}
+
+/// Outcome of checking a key against a contact's pin
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinStatus {
+    Matches,
+    /// First use; the key is now pinned
+    NewlyPinned,
+    /// Manual policy and no pin yet
+    Unpinned,
+    /// The key differs from the pin, or an earlier violation is unresolved
+    Violation,
+}
+
+/// A contact with pinned keys, as listed to the user
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinnedContact {
+    pub contact: String,
+    pub pinned_at: u64,
+    /// Routes the contact was reached at, newest last
+    pub routes: Vec<String>,
+    /// Different keys were seen and the change is unresolved
+    pub key_changed: bool,
+    pub share: SharePolicy,
+}
+
+/// A pin as carried in a contact bundle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedPin {
+    pub contact: String,
+    pub key_hash: [u8; 32],
+    /// Empty unless the contact's routes may be shared
+    pub routes: Vec<String>,
+}
+
+/// Outcome of adding pins from a contact bundle
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportedPins {
+    /// Contacts pinned for the first time
+    pub added: usize,
+    /// Contacts already pinned to different keys, now held as key changes
+    pub key_changes: usize,
+}
+
+/// Pinned keys of one persona's contacts
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ContactPins {
+    pins: HashMap<String, Pin>,
+}
+
+/// Pins of every persona
+pub type PinStore = PersonaScoped<ContactPins>;
+
+/// The spelling a contact's public key is stored under: "VLD1:pub:<lowercase hex>"
+pub fn contact_id(public_key: &str) -> Result<String> {
+    let key: [u8; 32] = decode_typed_key(public_key)?
+        .try_into()
+        .map_err(|_| UndergroundError::InvalidKey)?;
+    Ok(format!("VLD1:pub:{}", hex::encode(key)))
+}
+
+/// Move entries kept under other spellings of a contact's key to its contact_id,
+/// combining entries that meet with `merge`; returns how many were combined
+/// Keys that are not public keys are left alone
+pub(crate) fn merge_spellings<V>(entries: &mut HashMap<String, V>, merge: impl Fn(V, V) -> V) -> usize {
+    let mut respelled: Vec<(String, String)> = entries
+        .keys()
+        .filter_map(|key| contact_id(key).ok().filter(|id| id != key).map(|id| (key.clone(), id)))
+        .collect();
+    respelled.sort();
+    let mut merged = 0;
+    for (key, id) in respelled {
+        let Some(value) = entries.remove(&key) else {
+            continue;
+        };
+        let value = match entries.remove(&id) {
+            Some(existing) => {
+                merged += 1;
+                merge(existing, value)
+            }
+            None => value,
+        };
+        entries.insert(id, value);
+    }
+    merged
+}
+
+/// Load saved pins from a config directory (empty if none saved yet)
+/// Pins saved under several spellings of one key are merged
+pub fn load(config_dir: &Path) -> Result<PinStore> {
+    let path = config_dir.join(PINS_FILE);
+    if !path.exists() {
+        return Ok(PinStore::default());
+    }
+    let mut store: PinStore = serde_json::from_slice(&fs::read(path)?)?;
+    let merged: usize = store
+        .across_personas_mut("merging contacts pinned under several spellings of one key")
+        .map(|(_, pins)| pins.merge_duplicates())
+        .sum();
+    if merged > 0 {
+        tracing::info!("Merged {} duplicate contact pins", merged);
+    }
+    Ok(store)
+}
+
+pub fn save(store: &PinStore, config_dir: &Path) -> Result<()> {
+    fs::create_dir_all(config_dir)?;
+    fs::write(config_dir.join(PINS_FILE), serde_json::to_vec(store)?)?;
+    Ok(())
+}
+
+impl ContactPins {
+    /// Check `keys` for `contact`, pinning them on first use unless the policy is manual
+    pub fn check(&mut self, contact: &str, keys: &ContactKeys, policy: PinPolicy, now: u64) -> PinStatus {
+        let key_hash = keys.hash();
+        let Some(pin) = self.pins.get_mut(contact) else {
+            if policy == PinPolicy::Manual {
+                return PinStatus::Unpinned;
+            }
+            self.pin(contact, keys, now);
+            return PinStatus::NewlyPinned;
+        };
+        if pin.conflicting.is_some() {
+            return PinStatus::Violation;
+        }
+        if pin.key_hash != key_hash {
+            pin.conflicting = Some(key_hash);
+            return PinStatus::Violation;
+        }
+        PinStatus::Matches
+    }
+
+    /// Pin `keys` for `contact`, replacing any earlier pin but keeping its routes
+    /// and sharing choice
+    pub fn pin(&mut self, contact: &str, keys: &ContactKeys, now: u64) {
+        let (routes, share) = self
+            .pins
+            .remove(contact)
+            .map(|p| (p.routes, p.share))
+            .unwrap_or_default();
+        self.pins.insert(
+            contact.to_string(),
+            Pin {
+                key_hash: keys.hash(),
+                pinned_at: now,
+                conflicting: None,
+                routes,
+                share,
+            },
+        );
+    }
+
+    /// Remember `route` as one of a pinned contact's; false if the contact has no pin
+    pub fn add_route(&mut self, contact: &str, route: &str) -> bool {
+        let Some(pin) = self.pins.get_mut(contact) else {
+            return false;
+        };
+        if !pin.routes.iter().any(|r| r == route) {
+            if pin.routes.len() >= MAX_ROUTES_PER_CONTACT {
+                pin.routes.pop_front();
+            }
+            pin.routes.push_back(route.to_string());
+        }
+        true
+    }
+
+    /// Routes of contacts with an unresolved violation, with the contact each belongs to
+    pub fn held_routes(&self) -> impl Iterator<Item = (String, String)> + '_ {
+        self.pins
+            .iter()
+            .filter(|(_, pin)| pin.conflicting.is_some())
+            .flat_map(|(contact, pin)| pin.routes.iter().map(move |route| (route.clone(), contact.clone())))
+    }
+
+    pub fn is_pinned(&self, contact: &str) -> bool {
+        self.pins.contains_key(contact)
+    }
+
+    /// Settle a violation by accepting the new key or keeping the pinned one
+    pub fn resolve(&mut self, contact: &str, accept_new_key: bool, policy: PinPolicy, now: u64) -> Result<()> {
+        let pin = self.pins.get_mut(contact).ok_or_else(|| UndergroundError::RecordNotFound {
+            entity: "Key pin".to_string(),
+            id: contact.to_string(),
+        })?;
+        let conflicting = pin
+            .conflicting
+            .take()
+            .ok_or_else(|| UndergroundError::InvalidMessage("No key change to resolve".to_string()))?;
+        if accept_new_key {
+            if policy == PinPolicy::Strict {
+                pin.conflicting = Some(conflicting);
+                return Err(UndergroundError::InvalidMessage(
+                    "Strict pinning: remove the pin to accept a new key".to_string(),
+                ));
+            }
+            pin.key_hash = conflicting;
+            pin.pinned_at = now;
+        }
+        Ok(())
+    }
+
+    /// Remove a contact's pin; false if there was none
+    pub fn forget(&mut self, contact: &str) -> bool {
+        self.pins.remove(contact).is_some()
+    }
+
+    pub fn has_violation(&self, contact: &str) -> bool {
+        self.pins.get(contact).is_some_and(|p| p.conflicting.is_some())
+    }
+
+    /// Every pinned contact, ordered by contact key
+    pub fn contacts(&self) -> Vec<PinnedContact> {
+        let mut contacts: Vec<PinnedContact> = self
+            .pins
+            .iter()
+            .map(|(contact, pin)| PinnedContact {
+                contact: contact.clone(),
+                pinned_at: pin.pinned_at,
+                routes: pin.routes.iter().cloned().collect(),
+                key_changed: pin.conflicting.is_some(),
+                share: pin.share,
+            })
+            .collect();
+        contacts.sort_by(|a, b| a.contact.cmp(&b.contact));
+        contacts
+    }
+
+    /// Merge pins kept under other spellings of a contact's key into one pin per
+    /// contact, returning how many were merged
+    /// Routes are combined; pins that disagree on the keys keep the older
+    /// pin's keys and hold the other as a key change for the user to resolve
+    pub fn merge_duplicates(&mut self) -> usize {
+        merge_spellings(&mut self.pins, Pin::merge)
+    }
+
+    /// Set what of a contact's pin may be shared; false if the contact has no pin
+    pub fn set_share(&mut self, contact: &str, share: SharePolicy) -> bool {
+        let Some(pin) = self.pins.get_mut(contact) else {
+            return false;
+        };
+        pin.share = share;
+        true
+    }
+
+    /// The pin of `contact` as it may be shared
+    /// Refused unless the user marked it shareable and its keys are settled
+    pub fn shared(&self, contact: &str) -> Result<SharedPin> {
+        let pin = self.pins.get(contact).ok_or_else(|| UndergroundError::RecordNotFound {
+            entity: "Key pin".to_string(),
+            id: contact.to_string(),
+        })?;
+        if pin.share == SharePolicy::Never {
+            return Err(UndergroundError::InvalidMessage(format!("{} is not marked shareable", contact)));
+        }
+        if pin.conflicting.is_some() {
+            return Err(UndergroundError::KeyPinViolation(contact.to_string()));
+        }
+        let routes = match pin.share {
+            SharePolicy::WithRoutes => pin.routes.iter().cloned().collect(),
+            _ => Vec::new(),
+        };
+        Ok(SharedPin {
+            contact: contact.to_string(),
+            key_hash: pin.key_hash,
+            routes,
+        })
+    }
+
+    /// Add pins from another device's contact bundle
+    /// A contact pinned here to different keys keeps its pin and is held as a key
+    /// change for the user to resolve. Imported pins are not shareable onward
+    pub fn import_shared(&mut self, shared: Vec<SharedPin>, now: u64) -> ImportedPins {
+        let mut imported = ImportedPins::default();
+        for incoming in shared {
+            let skip = incoming.routes.len().saturating_sub(MAX_ROUTES_PER_CONTACT);
+            let routes = incoming.routes.into_iter().skip(skip);
+            match self.pins.get_mut(&incoming.contact) {
+                Some(pin) => {
+                    if pin.key_hash != incoming.key_hash && pin.conflicting.is_none() {
+                        pin.conflicting = Some(incoming.key_hash);
+                        imported.key_changes += 1;
+                    }
+                }
+                None => {
+                    self.pins.insert(
+                        incoming.contact,
+                        Pin {
+                            key_hash: incoming.key_hash,
+                            pinned_at: now,
+                            conflicting: None,
+                            routes: routes.collect(),
+                            share: SharePolicy::Never,
+                        },
+                    );
+                    imported.added += 1;
+                }
+            }
+        }
+        imported
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(signing: u8, encryption: u8) -> ContactKeys {
+        ContactKeys {
+            signing: [signing; 32],
+            encryption: [encryption; 32],
+        }
+    }
+
+    #[test]
+    fn test_violation_blocks_until_resolved() {
+        let mut pins = ContactPins::default();
+        let (first, second) = (keys(1, 1), keys(1, 2));
+        assert_eq!(pins.check("bob", &first, PinPolicy::Manual, 1), PinStatus::Unpinned);
+        assert!(!pins.add_route("bob", "VLD1:route:bob"));
+        assert_eq!(pins.check("bob", &first, PinPolicy::TrustOnFirstUse, 1), PinStatus::NewlyPinned);
+        assert_eq!(pins.check("bob", &first, PinPolicy::TrustOnFirstUse, 2), PinStatus::Matches);
+        assert!(pins.add_route("bob", "VLD1:route:bob"));
+        assert_eq!(pins.held_routes().count(), 0);
+
+        // A changed encryption key alone is a violation, and holds the contact's routes
+        assert_eq!(pins.check("bob", &second, PinPolicy::TrustOnFirstUse, 3), PinStatus::Violation);
+        assert_eq!(pins.check("bob", &first, PinPolicy::TrustOnFirstUse, 4), PinStatus::Violation);
+        assert_eq!(
+            pins.held_routes().collect::<Vec<_>>(),
+            vec![("VLD1:route:bob".to_string(), "bob".to_string())]
+        );
+        assert!(pins.resolve("bob", true, PinPolicy::Strict, 5).is_err());
+        assert!(pins.has_violation("bob"));
+
+        pins.resolve("bob", true, PinPolicy::TrustOnFirstUse, 5).unwrap();
+        assert_eq!(pins.held_routes().count(), 0);
+        assert_eq!(pins.check("bob", &second, PinPolicy::Strict, 6), PinStatus::Matches);
+        assert!(pins.resolve("bob", false, PinPolicy::Strict, 6).is_err());
+        assert!(pins.forget("bob"));
+    }
+
+    #[test]
+    fn test_spellings_of_one_key_merge_into_one_contact() {
+        let hex = "ab".repeat(32);
+        let id = contact_id(&hex).unwrap();
+        assert_eq!(id, format!("VLD1:pub:{}", hex));
+        assert_eq!(contact_id(&format!("VLD1:pub:{}", hex.to_uppercase())).unwrap(), id);
+        assert!(contact_id("bob").is_err());
+
+        let mut pins = ContactPins::default();
+        pins.pin(&hex, &keys(1, 1), 20);
+        pins.add_route(&hex, "VLD1:route:new");
+        pins.pin(&hex.to_uppercase(), &keys(1, 1), 10);
+        pins.add_route(&hex.to_uppercase(), "VLD1:route:old");
+        pins.set_share(&hex.to_uppercase(), SharePolicy::WithRoutes);
+        pins.pin("bob", &keys(2, 2), 10);
+
+        assert_eq!(pins.merge_duplicates(), 1);
+        let contacts = pins.contacts();
+        assert_eq!(contacts.len(), 2);
+        let merged = contacts.iter().find(|c| c.contact == id).unwrap();
+        assert_eq!(merged.pinned_at, 10);
+        assert_eq!(merged.routes.len(), 2);
+        // The more cautious sharing choice wins
+        assert_eq!(merged.share, SharePolicy::Never);
+        assert!(!merged.key_changed);
+
+        // A spelling pinned to other keys becomes a key change on the merged pin
+        pins.pin(&format!("VLD1:pub:{}", hex.to_uppercase()), &keys(3, 3), 30);
+        assert_eq!(pins.merge_duplicates(), 1);
+        assert!(pins.has_violation(&id));
+        assert_eq!(pins.check(&id, &keys(1, 1), PinPolicy::TrustOnFirstUse, 31), PinStatus::Violation);
+    }
+}
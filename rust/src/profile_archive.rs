@@ -0,0 +1,246 @@
+// Encrypted export/import of a whole profile for moving devices
+
+use crate::blocklist::Blocklist;
+use crate::bootstrap_cache::BootstrapCache;
+use crate::crypto::{decrypt_data, derive_key, encrypt_data, generate_salt};
+use crate::error::{Result, UndergroundError};
+use crate::journal::Journal;
+use crate::persona::PersonaStore;
+use crate::progress::ProgressReporter;
+use crate::record_keeper::RecordKeeper;
+use crate::revocation::RevocationList;
+use crate::veilid_manager::VeilidManager;
+use crate::wire::{put_bytes, Reader};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 3] = b"URF";
+const VERSION: u8 = 1;
+
+/// Files this crate keeps in the config directory
+pub(crate) const PROFILE_FILES: &[&str] = &[
+    crate::bootstrap_cache::CACHE_FILE,
+    crate::blocklist::BLOCKLIST_FILE,
+    crate::profile_key::STORAGE_KEY_FILE,
+    crate::record_keeper::RECORDS_FILE,
+    crate::revocation::REVOCATIONS_FILE,
+    crate::persona::PERSONAS_FILE,
+    crate::replay::REPLAY_FILE,
+    crate::pinning::PINS_FILE,
+    crate::connection::CONNECTIONS_FILE,
+    crate::journal::JOURNAL_FILE,
+    crate::inbox::INBOX_FILE,
+    crate::outbox::OUTBOX_FILE,
+    crate::ack::ACKS_FILE,
+    crate::rpc::RPC_TOKENS_FILE,
+    crate::rpc::DAEMON_TOKEN_FILE,
+    crate::keystore::KEYSTORE_FILE,
+];
+
+/// Corrupt stores set aside at startup: wiped with the profile, never exported
+pub(crate) const SET_ASIDE_FILES: &[&str] = &[
+    crate::persona::PERSONAS_CORRUPT_FILE,
+    crate::inbox::INBOX_CORRUPT_FILE,
+    crate::outbox::OUTBOX_CORRUPT_FILE,
+];
+
+/// Largest single file accepted in an archive
+pub(crate) const MAX_FILE_LEN: usize = 16 * 1024 * 1024;
+
+/// Encrypt every profile file in `config_dir` into an archive at `dest`
+/// Returns how many files were exported
+pub fn export_profile(config_dir: &Path, password: &str, dest: &Path, progress: &ProgressReporter) -> Result<usize> {
+    progress.report("Collecting profile", 0);
+    let mut payload = Vec::new();
+    let mut count = 0;
+
+    for (i, name) in PROFILE_FILES.iter().enumerate() {
+        let path = config_dir.join(name);
+        if path.exists() {
+            // The storage key may be wrapped to this device; the archive carries it bare
+            let data = if *name == crate::profile_key::STORAGE_KEY_FILE {
+                crate::profile_key::export(config_dir)?.as_slice().to_vec()
+            } else {
+                fs::read(path)?
+            };
+            if data.len() > MAX_FILE_LEN {
+                return Err(UndergroundError::Storage(format!("{} too large to export", name)));
+            }
+            put_bytes(&mut payload, name.as_bytes());
+            payload.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            payload.extend_from_slice(&data);
+            count += 1;
+        }
+        progress.report("Collecting profile", (50 * (i + 1) / PROFILE_FILES.len()) as u8);
+    }
+
+    progress.report("Encrypting", 60);
+    let salt = generate_salt();
+    let key = derive_key(password, &salt)?;
+    let ciphertext = encrypt_data(key.as_slice(), &payload)?;
+
+    let mut out = Vec::with_capacity(MAGIC.len() + 1 + salt.len() + ciphertext.len());
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    out.extend_from_slice(&salt);
+    out.extend_from_slice(&ciphertext);
+
+    progress.report("Writing archive", 90);
+    fs::write(dest, out)?;
+    progress.report("Done", 100);
+    Ok(count)
+}
+
+/// Verify and restore an archive from `src` into `config_dir`
+/// Nothing is written unless every file decrypts and parses
+pub fn import_profile(config_dir: &Path, password: &str, src: &Path, progress: &ProgressReporter) -> Result<usize> {
+    progress.report("Reading archive", 0);
+    let data = fs::read(src)?;
+    let mut reader = Reader::new(&data, "Profile archive");
+    reader.header(MAGIC, VERSION)?;
+    let salt: [u8; 32] = reader.array()?;
+
+    progress.report("Decrypting", 20);
+    let key = derive_key(password, &salt)?;
+    let payload = decrypt_data(key.as_slice(), reader.rest()).map_err(|_| UndergroundError::WrongPassword)?;
+
+    progress.report("Verifying", 60);
+    let mut files = Vec::new();
+    let mut reader = Reader::new(&payload, "Profile archive");
+    while !reader.is_empty() {
+        let name = reader.string(64)?;
+        let name = PROFILE_FILES
+            .iter()
+            .find(|known| **known == name)
+            .ok_or_else(|| reader.invalid("unknown file"))?;
+        let len = reader.u32()? as usize;
+        if len > MAX_FILE_LEN {
+            return Err(reader.invalid("file too large"));
+        }
+        files.push((*name, reader.take(len)?.to_vec()));
+    }
+
+    // Stage and parse everything before touching the live profile
+    let staging = sibling(config_dir, ".import");
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    fs::create_dir_all(&staging)?;
+    for (name, contents) in &files {
+        fs::write(staging.join(name), contents)?;
+    }
+    let verified = verify_staged(&staging);
+    if let Err(e) = verified {
+        fs::remove_dir_all(&staging)?;
+        return Err(e);
+    }
+
+    progress.report("Restoring", 80);
+    swap_in(config_dir, &staging)?;
+
+    progress.report("Done", 100);
+    Ok(files.len())
+}
+
+fn verify_staged(dir: &Path) -> Result<()> {
+    // Wraps a restored storage key for this device before sealed stores are read
+    let storage_key = crate::profile_key::load_or_create(dir)?;
+    BootstrapCache::load(dir)?;
+    Blocklist::load(dir)?;
+    RecordKeeper::load(VeilidManager::new(), dir)?;
+    RevocationList::load(dir)?;
+    PersonaStore::load(dir)?;
+    crate::replay::load(dir)?;
+    crate::pinning::load(dir)?;
+    crate::connection::load(dir)?;
+    Journal::load(dir)?;
+    crate::inbox::Inbox::load(dir)?;
+    crate::outbox::Outbox::load(dir)?;
+    crate::ack::AckTracker::load(dir, &storage_key)?;
+    crate::rpc::TokenStore::load(dir)?;
+    crate::keystore::KeyStore::load(dir)?;
+    Ok(())
+}
+
+/// Replace the profile in `config_dir` with the staged one in a single rename,
+/// so no file from the old profile survives next to the imported ones
+/// Settings and network storage are not part of the profile and are moved across
+fn swap_in(config_dir: &Path, staging: &Path) -> Result<()> {
+    if !config_dir.exists() {
+        fs::rename(staging, config_dir)?;
+        return Ok(());
+    }
+
+    let old = sibling(config_dir, ".old");
+    if old.exists() {
+        return Err(UndergroundError::Storage(format!(
+            "An earlier import was interrupted; move {} back before importing again",
+            old.display()
+        )));
+    }
+    fs::rename(config_dir, &old)?;
+    if let Err(e) = fs::rename(staging, config_dir) {
+        fs::rename(&old, config_dir)?;
+        return Err(e.into());
+    }
+
+    for entry in fs::read_dir(&old)? {
+        let name = entry?.file_name();
+        if !PROFILE_FILES.iter().chain(SET_ASIDE_FILES).any(|known| name == *known) {
+            fs::rename(old.join(&name), config_dir.join(&name))?;
+        }
+    }
+    fs::remove_dir_all(&old)?;
+    Ok(())
+}
+
+/// Path next to `dir` named after it with `suffix` appended
+fn sibling(dir: &Path, suffix: &str) -> PathBuf {
+    let mut name = dir.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    dir.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::ProgressOperation;
+
+    #[test]
+    fn test_export_import_round_trip() {
+        let tmp = crate::util::TempDir::new("archive");
+        let base = tmp.path();
+        let from = base.join("from");
+        let to = base.join("to");
+        fs::create_dir_all(&to).unwrap();
+
+        let mut blocklist = Blocklist::default();
+        blocklist.block_key([3u8; 32]);
+        blocklist.save(&from).unwrap();
+
+        let progress = ProgressReporter::silent(ProgressOperation::Export);
+        let archive = base.join("profile.urp");
+        assert_eq!(export_profile(&from, "correct horse", &archive, &progress).unwrap(), 1);
+
+        assert!(matches!(
+            import_profile(&to, "wrong horse", &archive, &progress),
+            Err(UndergroundError::WrongPassword)
+        ));
+        assert!(!to.join(crate::blocklist::BLOCKLIST_FILE).exists());
+
+        // Profile files missing from the archive go; settings stay
+        fs::write(to.join(crate::pinning::PINS_FILE), b"{}").unwrap();
+        fs::write(to.join(crate::core_config::CONFIG_FILE), b"").unwrap();
+        assert_eq!(import_profile(&to, "correct horse", &archive, &progress).unwrap(), 1);
+        assert!(Blocklist::load(&to).unwrap().is_key_blocked(&[3u8; 32]));
+        assert!(!to.join(crate::pinning::PINS_FILE).exists());
+        assert!(to.join(crate::core_config::CONFIG_FILE).exists());
+        assert!(!base.join("to.old").exists() && !base.join("to.import").exists());
+
+        // A corrupt journal in the archive is refused before anything changes
+        fs::write(from.join(crate::journal::JOURNAL_FILE), b"not json").unwrap();
+        export_profile(&from, "correct horse", &archive, &progress).unwrap();
+        assert!(import_profile(&to, "correct horse", &archive, &progress).is_err());
+        assert!(!to.join(crate::journal::JOURNAL_FILE).exists());
+    }
+}
@@ -0,0 +1,80 @@
+// Per-profile storage key for encrypting sensitive stores at rest
+// Created on first use and kept wrapped by the platform (see key_wrap.rs).
+// Profile archives carry it unwrapped inside their password encryption, and
+// it is wrapped again for the importing device on first load
+
+use crate::crypto::{decrypt_data, encrypt_data, generate_random_bytes, SecureBuffer};
+use crate::error::Result;
+use crate::key_wrap::{unwrap_storage_key, wrap_storage_key};
+use std::fs;
+use std::path::Path;
+
+/// File name of the wrapped storage key inside the config directory
+pub(crate) const STORAGE_KEY_FILE: &str = "storage.key";
+
+const KEY_LEN: usize = 32;
+
+/// Load the profile's storage key, creating one on first use
+/// A key restored from an archive, or wrapped more weakly than this device
+/// allows, is wrapped again and rewritten
+pub fn load_or_create(config_dir: &Path) -> Result<SecureBuffer> {
+    let path = config_dir.join(STORAGE_KEY_FILE);
+    if !path.exists() {
+        let key = SecureBuffer::new(generate_random_bytes(KEY_LEN));
+        write_wrapped(config_dir, key.as_slice())?;
+        return Ok(key);
+    }
+
+    let unwrapped = unwrap_storage_key(&fs::read(&path)?)?;
+    if unwrapped.needs_migration {
+        write_wrapped(config_dir, unwrapped.key.as_slice())?;
+    }
+    Ok(unwrapped.key)
+}
+
+/// The unwrapped key, for a password-encrypted profile archive
+pub(crate) fn export(config_dir: &Path) -> Result<SecureBuffer> {
+    Ok(unwrap_storage_key(&fs::read(config_dir.join(STORAGE_KEY_FILE))?)?.key)
+}
+
+/// Encrypt a store's contents with the storage key
+pub fn seal(key: &SecureBuffer, plaintext: &[u8]) -> Result<Vec<u8>> {
+    encrypt_data(key.as_slice(), plaintext)
+}
+
+/// Decrypt a store's contents with the storage key
+pub fn open(key: &SecureBuffer, ciphertext: &[u8]) -> Result<Vec<u8>> {
+    decrypt_data(key.as_slice(), ciphertext)
+}
+
+fn write_wrapped(config_dir: &Path, key: &[u8]) -> Result<()> {
+    let (blob, _) = wrap_storage_key(key)?;
+    fs::create_dir_all(config_dir)?;
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    std::io::Write::write_all(&mut options.open(config_dir.join(STORAGE_KEY_FILE))?, &blob)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_is_created_once_and_survives_export() {
+        let tmp = crate::util::TempDir::new("profile-key");
+        let key = load_or_create(tmp.path()).unwrap();
+        assert_eq!(load_or_create(tmp.path()).unwrap().as_slice(), key.as_slice());
+
+        let sealed = seal(&key, b"records").unwrap();
+        assert_eq!(open(&key, &sealed).unwrap(), b"records");
+
+        // An archive restores the raw key, which is wrapped again on load
+        let restored = crate::util::TempDir::new("profile-key-import");
+        fs::write(restored.path().join(STORAGE_KEY_FILE), export(tmp.path()).unwrap().as_slice()).unwrap();
+        let reloaded = load_or_create(restored.path()).unwrap();
+        assert_eq!(open(&reloaded, &sealed).unwrap(), b"records");
+    }
+}
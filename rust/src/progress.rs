@@ -0,0 +1,80 @@
+// Progress reporting for long-running operations
+
+use std::sync::Arc;
+
+/// Long-running operation a progress event belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressOperation {
+    Startup,
+    Wipe,
+    Export,
+    Import,
+}
+
+/// One step of a long-running operation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgressEvent {
+    pub operation: ProgressOperation,
+    pub stage: String,
+    pub percent: u8,
+}
+
+/// Cheap-to-clone callback that receives progress events
+#[derive(Clone)]
+pub struct ProgressReporter {
+    operation: ProgressOperation,
+    callback: Arc<dyn Fn(ProgressEvent) + Send + Sync>,
+}
+
+impl ProgressReporter {
+    pub fn new(operation: ProgressOperation, callback: impl Fn(ProgressEvent) + Send + Sync + 'static) -> Self {
+        Self {
+            operation,
+            callback: Arc::new(callback),
+        }
+    }
+
+    /// Reporter that discards everything
+    pub fn silent(operation: ProgressOperation) -> Self {
+        Self::new(operation, |_| {})
+    }
+
+    pub fn report(&self, stage: &str, percent: u8) {
+        (self.callback)(ProgressEvent {
+            operation: self.operation,
+            stage: stage.to_string(),
+            percent: percent.min(100),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_reporter_tags_and_clamps_events() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let reporter = ProgressReporter::new(ProgressOperation::Export, move |e| sink.lock().unwrap().push(e));
+
+        reporter.clone().report("sealing", 40);
+        reporter.report("done", 250);
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                ProgressEvent {
+                    operation: ProgressOperation::Export,
+                    stage: "sealing".to_string(),
+                    percent: 40,
+                },
+                ProgressEvent {
+                    operation: ProgressOperation::Export,
+                    stage: "done".to_string(),
+                    percent: 100,
+                },
+            ]
+        );
+    }
+}
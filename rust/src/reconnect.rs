@@ -0,0 +1,116 @@
+use crate::error::Result;
+use crate::veilid_manager::{VeilidEvent, VeilidManager};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+
+/// Announcements older than this are re-published after reconnecting
+const DEFAULT_STALE_AFTER_SECS: u64 = 15 * 60;
+
+/// Work done in response to a reconnection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReconnectReport {
+    pub flushed: usize,
+    pub republished: usize,
+}
+
+/// Ties Veilid attachment events to the outbox and DHT announcements
+/// When the node (re)attaches, queued messages are drained and stale
+/// announcements re-published; after a network reset everything is re-published
+pub struct ReconnectCoordinator {
+    manager: VeilidManager,
+    stale_after_secs: u64,
+    was_attached: bool,
+}
+
+impl ReconnectCoordinator {
+    pub fn new(manager: VeilidManager) -> Self {
+        Self {
+            manager,
+            stale_after_secs: DEFAULT_STALE_AFTER_SECS,
+            was_attached: false,
+        }
+    }
+
+    /// Set the age after which announcements count as stale
+    pub fn with_stale_after(mut self, secs: u64) -> Self {
+        self.stale_after_secs = secs;
+        self
+    }
+
+    /// React to a single event
+    pub async fn handle_event(&mut self, event: &VeilidEvent) -> Result<Option<ReconnectReport>> {
+        match event {
+            VeilidEvent::Attachment(state) => {
+                let attached = state.is_attached();
+                let reconnected = attached && !self.was_attached;
+                self.was_attached = attached;
+
+                if !reconnected {
+                    return Ok(None);
+                }
+
+                let flushed = self.manager.flush_outbox().await?;
+                let republished = self.manager.republish_stale(self.stale_after_secs).await?;
+                Ok(Some(ReconnectReport { flushed, republished }))
+            }
+            VeilidEvent::NetworkReset => {
+                let republished = self.manager.republish_stale(0).await?;
+                Ok(Some(ReconnectReport { flushed: 0, republished }))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Run the coordinator in the background until the event channel closes
+    pub fn spawn(mut self) -> JoinHandle<()> {
+        let mut events = self.manager.subscribe();
+
+        tokio::spawn(async move {
+            self.was_attached = self.manager.is_attached().await;
+
+            loop {
+                let event = match events.recv().await {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                };
+
+                if let Err(e) = self.handle_event(&event).await {
+                    tracing::warn!("Reconnect handling failed: {}", e);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_flush_on_reattach() {
+        let manager = VeilidManager::new();
+        let tmp = crate::util::TempDir::new("reconnect");
+        manager.initialize(tmp.path_string()).await.unwrap();
+        let route = manager.create_private_route().await.unwrap();
+        manager.dht_set("announcement", vec![1, 2, 3]).await.unwrap();
+
+        let mut coordinator = ReconnectCoordinator::new(manager.clone()).with_stale_after(0);
+        let mut events = manager.subscribe();
+
+        manager.detach().await.unwrap();
+        manager.send_via_private_route(&route, vec![42]).await.unwrap();
+        assert_eq!(manager.outbox_len().await, 1);
+
+        manager.attach().await.unwrap();
+        let mut report = None;
+        while let Ok(event) = events.try_recv() {
+            if let Some(r) = coordinator.handle_event(&event).await.unwrap() {
+                report = Some(r);
+            }
+        }
+
+        assert_eq!(report, Some(ReconnectReport { flushed: 1, republished: 1 }));
+        assert_eq!(manager.outbox_len().await, 0);
+    }
+}
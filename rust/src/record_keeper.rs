@@ -0,0 +1,205 @@
+use crate::crypto::SecureBuffer;
+use crate::error::Result;
+use crate::profile_key;
+use crate::veilid_manager::{VeilidEvent, VeilidManager};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+
+/// File name of the persisted record descriptors inside the config directory
+/// Encrypted with the profile storage key, since it holds record values
+pub(crate) const RECORDS_FILE: &str = "owned_records.json";
+
+/// Kind of DHT record we own
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RecordKind {
+    Mailbox,
+    Announcement,
+    Channel,
+}
+
+/// A DHT record we own and keep alive
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordDescriptor {
+    pub key: String,
+    pub kind: RecordKind,
+    pub ttl_secs: u64,
+    pub refreshed_at: u64,
+    pub value: Vec<u8>,
+}
+
+impl RecordDescriptor {
+    /// Records are refreshed once three quarters of their TTL has passed
+    pub fn needs_refresh(&self, now: u64) -> bool {
+        now.saturating_sub(self.refreshed_at) >= self.ttl_secs / 4 * 3
+    }
+}
+
+/// Keeps owned DHT records alive: refreshes them before expiry and
+/// re-creates them after network resets
+#[derive(Clone)]
+pub struct RecordKeeper {
+    manager: VeilidManager,
+    config_dir: PathBuf,
+    storage_key: Arc<SecureBuffer>,
+    records: Arc<RwLock<HashMap<String, RecordDescriptor>>>,
+}
+
+impl RecordKeeper {
+    /// Load descriptors persisted in the config directory
+    /// A plaintext file from before encryption is read and encrypted on the next save
+    pub fn load(manager: VeilidManager, config_dir: &Path) -> Result<Self> {
+        let storage_key = profile_key::load_or_create(config_dir)?;
+        let path = config_dir.join(RECORDS_FILE);
+        let records: Vec<RecordDescriptor> = if path.exists() {
+            let data = fs::read(path)?;
+            match profile_key::open(&storage_key, &data) {
+                Ok(plaintext) => serde_json::from_slice(&plaintext)?,
+                Err(e) => serde_json::from_slice(&data).map_err(|_| e)?,
+            }
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            manager,
+            config_dir: config_dir.to_path_buf(),
+            storage_key: Arc::new(storage_key),
+            records: Arc::new(RwLock::new(
+                records.into_iter().map(|r| (r.key.clone(), r)).collect(),
+            )),
+        })
+    }
+
+    /// Write a record to the DHT and keep it alive from now on
+    pub async fn publish(&self, key: &str, kind: RecordKind, ttl_secs: u64, value: Vec<u8>) -> Result<()> {
+        self.manager.dht_set(key, value.clone()).await?;
+
+        self.records.write().await.insert(
+            key.to_string(),
+            RecordDescriptor {
+                key: key.to_string(),
+                kind,
+                ttl_secs,
+                refreshed_at: crate::util::unix_now(),
+                value,
+            },
+        );
+        self.save().await
+    }
+
+    /// Stop keeping a record alive
+    pub async fn forget(&self, key: &str) -> Result<()> {
+        self.records.write().await.remove(key);
+        self.save().await
+    }
+
+    /// Descriptors of all owned records
+    pub async fn records(&self) -> Vec<RecordDescriptor> {
+        self.records.read().await.values().cloned().collect()
+    }
+
+    /// Refresh records close to expiry, returning how many were refreshed
+    pub async fn refresh_due(&self) -> Result<usize> {
+        self.refresh_where(|r, now| r.needs_refresh(now)).await
+    }
+
+    /// Re-create every owned record (after a network reset)
+    pub async fn recreate_all(&self) -> Result<usize> {
+        self.refresh_where(|_, _| true).await
+    }
+
+    async fn refresh_where(&self, due: impl Fn(&RecordDescriptor, u64) -> bool) -> Result<usize> {
+        if !self.manager.is_attached().await {
+            return Ok(0);
+        }
+
+        let now = crate::util::unix_now();
+        let pending: Vec<RecordDescriptor> = self
+            .records
+            .read()
+            .await
+            .values()
+            .filter(|r| due(r, now))
+            .cloned()
+            .collect();
+
+        for record in &pending {
+            self.manager.dht_set(&record.key, record.value.clone()).await?;
+            if let Some(r) = self.records.write().await.get_mut(&record.key) {
+                r.refreshed_at = now;
+            }
+        }
+
+        if !pending.is_empty() {
+            self.save().await?;
+        }
+        Ok(pending.len())
+    }
+
+    async fn save(&self) -> Result<()> {
+        let records: Vec<RecordDescriptor> = self.records.read().await.values().cloned().collect();
+        fs::create_dir_all(&self.config_dir)?;
+        let sealed = profile_key::seal(&self.storage_key, &serde_json::to_vec(&records)?)?;
+        fs::write(self.config_dir.join(RECORDS_FILE), sealed)?;
+        Ok(())
+    }
+
+    /// Run periodic refresh in the background, re-creating records on network reset
+    pub fn spawn(self) -> JoinHandle<()> {
+        let mut events = self.manager.subscribe();
+
+        tokio::spawn(async move {
+            let interval_secs = self.manager.network_tuning().await.poll_interval_secs;
+            let mut ticker = tokio::time::interval(Duration::from_secs(interval_secs.max(1)));
+
+            loop {
+                let result = tokio::select! {
+                    _ = ticker.tick() => self.refresh_due().await,
+                    event = events.recv() => match event {
+                        Ok(VeilidEvent::NetworkReset) => self.recreate_all().await,
+                        Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                        Err(RecvError::Closed) => break,
+                    },
+                };
+
+                if let Err(e) = result {
+                    tracing::warn!("Record refresh failed: {}", e);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_records_persist_and_recreate() {
+        let tmp = crate::util::TempDir::new("records");
+        let manager = VeilidManager::new();
+        manager.initialize(tmp.path_string()).await.unwrap();
+
+        let keeper = RecordKeeper::load(manager.clone(), tmp.path()).unwrap();
+        keeper
+            .publish("mailbox-1", RecordKind::Mailbox, 3600, vec![7, 7])
+            .await
+            .unwrap();
+        assert_eq!(keeper.refresh_due().await.unwrap(), 0);
+        // Record keys and values never reach the disk in the clear
+        let on_disk = fs::read(tmp.path().join(RECORDS_FILE)).unwrap();
+        assert!(!on_disk.windows(9).any(|w| w == b"mailbox-1"));
+
+        let reloaded = RecordKeeper::load(manager.clone(), tmp.path()).unwrap();
+        assert_eq!(reloaded.records().await.len(), 1);
+        assert_eq!(reloaded.recreate_all().await.unwrap(), 1);
+        assert_eq!(manager.dht_get("mailbox-1").await.unwrap(), Some(vec![7, 7]));
+    }
+}
@@ -0,0 +1,163 @@
+// Multi-hop relay through trusted contacts
+// Each hop only learns the next route, so the final recipient never sees
+// the originator's route
+
+use crate::crypto::{decrypt_data, encrypt_data};
+use crate::error::{Result, UndergroundError};
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of relays in a chain
+pub const MAX_RELAY_HOPS: usize = 4;
+
+/// Longest next-hop route accepted in a forward layer
+const MAX_ROUTE_LEN: usize = 1024;
+
+const TAG_DELIVER: u8 = 0;
+const TAG_FORWARD: u8 = 1;
+
+/// A hop in a relay chain: its route and the key shared with that contact
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RelayHop {
+    pub route: String,
+    pub key: Vec<u8>,
+}
+
+/// Shows the route only, so hop keys never reach logs
+impl std::fmt::Debug for RelayHop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RelayHop").field("route", &self.route).finish_non_exhaustive()
+    }
+}
+
+/// Result of peeling one onion layer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RelayLayer {
+    /// Pass the inner blob on to the next route
+    Forward { next_route: String, payload: Vec<u8> },
+    /// This node is the final recipient
+    Deliver { payload: Vec<u8> },
+}
+
+/// Onion-encrypt a payload for a relay chain ending at the recipient
+/// Returns the route of the first hop and the blob to send there
+pub fn wrap_onion(hops: &[RelayHop], recipient: &RelayHop, payload: &[u8]) -> Result<(String, Vec<u8>)> {
+    if hops.len() > MAX_RELAY_HOPS {
+        return Err(UndergroundError::InvalidMessage(format!(
+            "Relay chain longer than {} hops",
+            MAX_RELAY_HOPS
+        )));
+    }
+
+    let mut inner = Vec::with_capacity(payload.len() + 1);
+    inner.push(TAG_DELIVER);
+    inner.extend_from_slice(payload);
+    let mut blob = encrypt_data(&recipient.key, &inner)?;
+    let mut next_route = recipient.route.clone();
+
+    for hop in hops.iter().rev() {
+        let route_bytes = next_route.as_bytes();
+        if route_bytes.is_empty() || route_bytes.len() > MAX_ROUTE_LEN {
+            return Err(UndergroundError::InvalidMessage("Invalid relay route".to_string()));
+        }
+        let route_len = route_bytes.len() as u16;
+
+        let mut layer = Vec::with_capacity(3 + route_bytes.len() + blob.len());
+        layer.push(TAG_FORWARD);
+        layer.extend_from_slice(&route_len.to_be_bytes());
+        layer.extend_from_slice(route_bytes);
+        layer.extend_from_slice(&blob);
+
+        blob = encrypt_data(&hop.key, &layer)?;
+        next_route = hop.route.clone();
+    }
+
+    Ok((next_route, blob))
+}
+
+/// Remove one onion layer with the key shared with the previous hop
+pub fn peel_onion(key: &[u8], blob: &[u8]) -> Result<RelayLayer> {
+    decode_layer(&decrypt_data(key, blob)?)
+}
+
+/// Parse a decrypted onion layer
+pub fn decode_layer(layer: &[u8]) -> Result<RelayLayer> {
+    let (tag, rest) = layer
+        .split_first()
+        .ok_or_else(|| UndergroundError::InvalidMessage("Empty relay layer".to_string()))?;
+
+    match *tag {
+        TAG_DELIVER => Ok(RelayLayer::Deliver { payload: rest.to_vec() }),
+        TAG_FORWARD => {
+            if rest.len() < 2 {
+                return Err(UndergroundError::InvalidMessage("Truncated relay layer".to_string()));
+            }
+            let route_len = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+            let rest = &rest[2..];
+            if route_len == 0 || route_len > MAX_ROUTE_LEN {
+                return Err(UndergroundError::InvalidMessage("Invalid relay route".to_string()));
+            }
+            // A forward layer always wraps an encrypted blob for the next hop
+            if rest.len() <= route_len {
+                return Err(UndergroundError::InvalidMessage("Truncated relay layer".to_string()));
+            }
+
+            let next_route = String::from_utf8(rest[..route_len].to_vec())
+                .map_err(|_| UndergroundError::InvalidMessage("Invalid relay route".to_string()))?;
+
+            Ok(RelayLayer::Forward {
+                next_route,
+                payload: rest[route_len..].to_vec(),
+            })
+        }
+        _ => Err(UndergroundError::InvalidMessage("Unknown relay layer".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_random_bytes;
+
+    fn hop(route: &str) -> RelayHop {
+        RelayHop {
+            route: route.to_string(),
+            key: generate_random_bytes(32),
+        }
+    }
+
+    #[test]
+    fn test_onion_roundtrip() {
+        let hops = vec![hop("route-a"), hop("route-b")];
+        let recipient = hop("route-c");
+
+        let (first, blob) = wrap_onion(&hops, &recipient, b"check-in").unwrap();
+        assert_eq!(first, "route-a");
+
+        let layer = peel_onion(&hops[0].key, &blob).unwrap();
+        let RelayLayer::Forward { next_route, payload } = layer else {
+            panic!("expected forward layer");
+        };
+        assert_eq!(next_route, "route-b");
+
+        let layer = peel_onion(&hops[1].key, &payload).unwrap();
+        let RelayLayer::Forward { next_route, payload } = layer else {
+            panic!("expected forward layer");
+        };
+        assert_eq!(next_route, "route-c");
+
+        let layer = peel_onion(&recipient.key, &payload).unwrap();
+        assert_eq!(layer, RelayLayer::Deliver { payload: b"check-in".to_vec() });
+    }
+
+    #[test]
+    fn test_peel_with_wrong_key_fails() {
+        let hops = vec![hop("route-a")];
+        let recipient = hop("route-b");
+
+        let (_, blob) = wrap_onion(&hops, &recipient, b"data").unwrap();
+        assert!(peel_onion(&recipient.key, &blob).is_err());
+
+        // Forward layer naming a route but carrying nothing to forward
+        assert!(decode_layer(&[TAG_FORWARD, 0, 3, b'a', b'b', b'c']).is_err());
+    }
+}
@@ -0,0 +1,183 @@
+// Store-and-forward cache for relay nodes
+// A well-connected node can opt in to hold onion blobs whose next hop is
+// unreachable and retry them later. Held blobs are still encrypted for the
+// next hop, so the cache only ever learns the next route
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+/// Opt-in settings and storage limits for held blobs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RelayCacheConfig {
+    /// Hold blobs for unreachable next hops instead of failing the relay
+    pub enabled: bool,
+    /// Total payload bytes held across all routes
+    pub max_bytes: usize,
+    /// Blobs held for any one route
+    pub max_per_route: usize,
+    /// Held blobs older than this are dropped
+    pub ttl_secs: u64,
+}
+
+impl Default for RelayCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_bytes: 16 * 1024 * 1024,
+            max_per_route: 64,
+            ttl_secs: 3 * 24 * 3600,
+        }
+    }
+}
+
+/// A blob waiting for its next hop
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeldBlob {
+    pub payload: Vec<u8>,
+    pub held_at: u64,
+}
+
+/// Blobs held per next-hop route
+#[derive(Debug, Default)]
+pub struct RelayCache {
+    config: RelayCacheConfig,
+    routes: HashMap<String, VecDeque<HeldBlob>>,
+    bytes: usize,
+}
+
+impl RelayCache {
+    pub fn new(config: RelayCacheConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// Change limits; turning the cache off drops everything held
+    pub fn set_config(&mut self, config: RelayCacheConfig) {
+        self.config = config;
+        if !config.enabled {
+            self.routes.clear();
+            self.bytes = 0;
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Hold a blob for `route`
+    /// Returns false if it was refused because the cache is off or full
+    pub fn hold(&mut self, route: &str, payload: Vec<u8>, now: u64) -> bool {
+        if !self.config.enabled || self.bytes + payload.len() > self.config.max_bytes {
+            return false;
+        }
+        let queue = self.routes.entry(route.to_string()).or_default();
+        if queue.len() >= self.config.max_per_route {
+            return false;
+        }
+        self.bytes += payload.len();
+        queue.push_back(HeldBlob { payload, held_at: now });
+        true
+    }
+
+    /// Routes with blobs waiting
+    pub fn routes(&self) -> Vec<String> {
+        self.routes.keys().cloned().collect()
+    }
+
+    /// Take every blob held for `route`, oldest first
+    pub fn take(&mut self, route: &str) -> Vec<HeldBlob> {
+        let blobs: Vec<HeldBlob> = self.routes.remove(route).map(Vec::from).unwrap_or_default();
+        self.bytes -= blobs.iter().map(|b| b.payload.len()).sum::<usize>();
+        blobs
+    }
+
+    /// Put blobs back after a failed retry, ahead of anything held since
+    pub fn restore(&mut self, route: &str, blobs: Vec<HeldBlob>) {
+        if blobs.is_empty() {
+            return;
+        }
+        self.bytes += blobs.iter().map(|b| b.payload.len()).sum::<usize>();
+        let queue = self.routes.entry(route.to_string()).or_default();
+        for blob in blobs.into_iter().rev() {
+            queue.push_front(blob);
+        }
+    }
+
+    /// Number of blobs held longer than the configured lifetime
+    pub fn count_expired(&self, now: u64) -> usize {
+        let cutoff = now.saturating_sub(self.config.ttl_secs);
+        self.routes.values().flatten().filter(|b| b.held_at <= cutoff).count()
+    }
+
+    /// Drop blobs held longer than the configured lifetime, returning how many were dropped
+    pub fn expire(&mut self, now: u64) -> usize {
+        let cutoff = now.saturating_sub(self.config.ttl_secs);
+        let mut dropped = 0;
+        for queue in self.routes.values_mut() {
+            let before = queue.len();
+            queue.retain(|b| {
+                let keep = b.held_at > cutoff;
+                if !keep {
+                    self.bytes -= b.payload.len();
+                }
+                keep
+            });
+            dropped += before - queue.len();
+        }
+        self.routes.retain(|_, queue| !queue.is_empty());
+        dropped
+    }
+
+    pub fn len(&self) -> usize {
+        self.routes.values().map(VecDeque::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    /// Payload bytes currently held
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_caps_expiry_and_restore() {
+        let mut cache = RelayCache::default();
+        assert!(!cache.hold("route-a", vec![1], 100));
+
+        cache.set_config(RelayCacheConfig {
+            enabled: true,
+            max_bytes: 10,
+            max_per_route: 2,
+            ttl_secs: 50,
+        });
+        assert!(cache.hold("route-a", vec![1; 4], 100));
+        assert!(cache.hold("route-a", vec![2; 4], 120));
+        assert!(!cache.hold("route-a", vec![3], 120));
+        assert!(!cache.hold("route-b", vec![3; 3], 120));
+        assert!(cache.hold("route-b", vec![3; 2], 130));
+        assert_eq!(cache.bytes(), 10);
+
+        let blobs = cache.take("route-a");
+        assert_eq!(blobs.len(), 2);
+        assert_eq!(cache.bytes(), 2);
+        cache.restore("route-a", blobs);
+        assert_eq!(cache.take("route-a")[0].payload, vec![1; 4]);
+
+        cache.restore("route-a", vec![HeldBlob { payload: vec![4], held_at: 100 }]);
+        assert_eq!(cache.count_expired(160), 1);
+        assert_eq!(cache.expire(160), 1);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.routes(), vec!["route-b".to_string()]);
+        assert_eq!(cache.bytes(), 2);
+    }
+}
@@ -0,0 +1,203 @@
+// Rendezvous for first contact without a shared route
+// Both parties derive the same ephemeral DHT keys from a passphrase or
+// QR-derived secret, write their signed route bundles there, and then
+// confirm each other with signatures over both public keys
+
+use crate::crypto::{decrypt_data, derive_key, encrypt_data, sign_data, signing_public_key, verify_signature, SecureBuffer};
+use crate::error::{Result, UndergroundError};
+use crate::route_blob::RouteBundle;
+use crate::veilid_manager::VeilidManager;
+
+const KDF_CONTEXT: &str = "underground-railroad rendezvous v1";
+const CONFIRM_CONTEXT: &[u8] = b"urr-rendezvous-confirm";
+
+/// Progress of a rendezvous
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RendezvousStatus {
+    /// Our bundle is published, the other party has not appeared yet
+    WaitingForPeer,
+    /// Peer bundle found and confirmed by us, waiting for their confirmation
+    WaitingForConfirmation,
+    /// Both sides verified each other
+    Complete(RouteBundle),
+}
+
+/// One side of a rendezvous
+pub struct Rendezvous {
+    bundle_keys: [String; 2],
+    confirm_keys: [String; 2],
+    enc_key: SecureBuffer,
+    slot: Option<usize>,
+    peer: Option<RouteBundle>,
+}
+
+impl Rendezvous {
+    /// Derive rendezvous keys from a human passphrase (slow KDF)
+    pub fn from_passphrase(passphrase: &str) -> Result<Self> {
+        if passphrase.trim().len() < 8 {
+            return Err(UndergroundError::InvalidMessage(
+                "Rendezvous passphrase too short".to_string(),
+            ));
+        }
+        let salt = blake3::derive_key(KDF_CONTEXT, b"salt");
+        let material = derive_key(passphrase.trim(), &salt)?;
+        Ok(Self::from_material(material.as_slice()))
+    }
+
+    /// Derive rendezvous keys from a high-entropy secret (e.g. scanned from a QR code)
+    pub fn from_secret(secret: &[u8]) -> Result<Self> {
+        if secret.len() < 16 {
+            return Err(UndergroundError::InvalidMessage(
+                "Rendezvous secret too short".to_string(),
+            ));
+        }
+        Ok(Self::from_material(secret))
+    }
+
+    fn from_material(material: &[u8]) -> Self {
+        let sub = |label: &str| {
+            let mut input = material.to_vec();
+            input.extend_from_slice(label.as_bytes());
+            blake3::derive_key(KDF_CONTEXT, &input)
+        };
+        let dht_key = |label: &str| format!("urr-rdv:{}", hex::encode(sub(label)));
+
+        Self {
+            bundle_keys: [dht_key("bundle-0"), dht_key("bundle-1")],
+            confirm_keys: [dht_key("confirm-0"), dht_key("confirm-1")],
+            enc_key: SecureBuffer::new(sub("encryption").to_vec()),
+            slot: None,
+            peer: None,
+        }
+    }
+
+    /// Publish our signed route bundle in a free slot
+    pub async fn publish(&mut self, manager: &VeilidManager, bundle: &RouteBundle) -> Result<()> {
+        let slot = match self.slot {
+            Some(slot) => slot,
+            None => self.claim_slot(manager, bundle).await?,
+        };
+
+        let sealed = encrypt_data(self.enc_key.as_slice(), &bundle.encode())?;
+        manager.dht_set(&self.bundle_keys[slot], sealed).await?;
+        self.slot = Some(slot);
+        Ok(())
+    }
+
+    /// Advance the handshake: find the peer, confirm them, check their confirmation
+    pub async fn step(&mut self, manager: &VeilidManager, secret_key: &[u8]) -> Result<RendezvousStatus> {
+        let slot = self
+            .slot
+            .ok_or_else(|| UndergroundError::InvalidMessage("Rendezvous not published".to_string()))?;
+        let peer_slot = 1 - slot;
+
+        if self.peer.is_none() {
+            self.peer = self.read_bundle(manager, peer_slot).await?;
+        }
+        let peer = match &self.peer {
+            Some(peer) => peer.clone(),
+            None => return Ok(RendezvousStatus::WaitingForPeer),
+        };
+        if manager.is_key_blocked(&peer.public_key).await {
+            return Err(UndergroundError::Blocked);
+        }
+
+        let own_public = signing_public_key(secret_key)?;
+        let confirmation = sign_data(secret_key, &confirm_transcript(&own_public, &peer.public_key))?;
+        manager.dht_set(&self.confirm_keys[slot], confirmation.to_vec()).await?;
+
+        match manager.dht_get(&self.confirm_keys[peer_slot]).await? {
+            Some(theirs) => {
+                verify_signature(&peer.public_key, &confirm_transcript(&peer.public_key, &own_public), &theirs)?;
+
+                // The peer already has our bundle; our confirmation stays until
+                // it expires so the peer can still complete
+                manager.dht_delete(&self.bundle_keys[slot]).await?;
+                Ok(RendezvousStatus::Complete(peer))
+            }
+            None => Ok(RendezvousStatus::WaitingForConfirmation),
+        }
+    }
+
+    /// Abandon the rendezvous and remove our records
+    pub async fn close(&self, manager: &VeilidManager) -> Result<()> {
+        if let Some(slot) = self.slot {
+            manager.dht_delete(&self.bundle_keys[slot]).await?;
+            manager.dht_delete(&self.confirm_keys[slot]).await?;
+        }
+        Ok(())
+    }
+
+    async fn claim_slot(&self, manager: &VeilidManager, bundle: &RouteBundle) -> Result<usize> {
+        for slot in 0..2 {
+            match self.read_bundle(manager, slot).await? {
+                None => return Ok(slot),
+                Some(existing) if existing.public_key == bundle.public_key => return Ok(slot),
+                Some(_) => continue,
+            }
+        }
+        Err(UndergroundError::InvalidMessage("Rendezvous already in use".to_string()))
+    }
+
+    async fn read_bundle(&self, manager: &VeilidManager, slot: usize) -> Result<Option<RouteBundle>> {
+        match manager.dht_get(&self.bundle_keys[slot]).await? {
+            Some(sealed) => {
+                let encoded = decrypt_data(self.enc_key.as_slice(), &sealed)?;
+                Ok(Some(RouteBundle::decode(&encoded)?))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// What each side signs: its own key followed by the peer's
+fn confirm_transcript(signer: &[u8; 32], peer: &[u8; 32]) -> Vec<u8> {
+    let mut transcript = CONFIRM_CONTEXT.to_vec();
+    transcript.extend_from_slice(signer);
+    transcript.extend_from_slice(peer);
+    transcript
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_signing_keypair;
+
+    #[tokio::test]
+    async fn test_mutual_rendezvous() {
+        let manager = VeilidManager::new();
+        let tmp = crate::util::TempDir::new("rendezvous");
+        manager.initialize(tmp.path_string()).await.unwrap();
+        let secret = crate::crypto::generate_random_bytes(32);
+
+        let (alice_sk, alice_pk) = generate_signing_keypair();
+        let (bob_sk, bob_pk) = generate_signing_keypair();
+        let alice_bundle = RouteBundle::create("route-alice", "mailbox-alice", alice_sk.as_slice()).unwrap();
+        let bob_bundle = RouteBundle::create("route-bob", "mailbox-bob", bob_sk.as_slice()).unwrap();
+
+        let mut alice = Rendezvous::from_secret(&secret).unwrap();
+        let mut bob = Rendezvous::from_secret(&secret).unwrap();
+
+        alice.publish(&manager, &alice_bundle).await.unwrap();
+        assert_eq!(
+            alice.step(&manager, alice_sk.as_slice()).await.unwrap(),
+            RendezvousStatus::WaitingForPeer
+        );
+
+        bob.publish(&manager, &bob_bundle).await.unwrap();
+        assert_eq!(
+            bob.step(&manager, bob_sk.as_slice()).await.unwrap(),
+            RendezvousStatus::WaitingForConfirmation
+        );
+
+        let RendezvousStatus::Complete(peer) = alice.step(&manager, alice_sk.as_slice()).await.unwrap() else {
+            panic!("alice should complete");
+        };
+        assert_eq!(peer.public_key, bob_pk);
+
+        let RendezvousStatus::Complete(peer) = bob.step(&manager, bob_sk.as_slice()).await.unwrap() else {
+            panic!("bob should complete");
+        };
+        assert_eq!(peer.public_key, alice_pk);
+    }
+}
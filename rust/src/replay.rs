@@ -0,0 +1,301 @@
+// Anti-replay state for sealed messages
+// Every sealed message carries a per-contact counter inside its authenticated
+// frame. Receivers keep the highest counter seen and a bitmap of the 64 before
+// it, so reordered messages are accepted once and re-delivered ones never.
+// Contacts evicted to bound the windows keep their highest counter, so
+// nothing at or below it is accepted when they are heard from again. Those
+// marks are bounded too: past the limit the lowest is folded into a single
+// floor that holds for every contact without a window or mark of its own.
+// Counters are kept per persona so contacts cannot link personas by them.
+// State kept under other spellings of a contact's key is merged when loaded,
+// so nothing either spelling has seen is accepted again
+
+use crate::error::Result;
+use crate::persona::PersonaScoped;
+use crate::pinning::merge_spellings;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// File name of the persisted counters inside the config directory
+pub(crate) const REPLAY_FILE: &str = "replay.json";
+
+/// Counters below the highest seen that are still accepted out of order
+const WINDOW: u64 = 64;
+
+/// Contacts tracked before the least recently heard from is forgotten
+const MAX_CONTACTS: usize = 4096;
+
+/// Evicted contacts whose mark is kept before it is folded into the floor
+const MAX_EVICTED: usize = 4096;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct Window {
+    highest: u64,
+    /// Bit i set means counter `highest - i` was seen
+    seen: u64,
+    last_seen_at: u64,
+}
+
+impl Window {
+    fn accept(&mut self, counter: u64) -> bool {
+        if counter > self.highest {
+            let shift = counter - self.highest;
+            self.seen = if shift >= WINDOW { 0 } else { self.seen << shift };
+            self.seen |= 1;
+            self.highest = counter;
+            return true;
+        }
+        let offset = self.highest - counter;
+        if offset >= WINDOW || self.seen & (1 << offset) != 0 {
+            return false;
+        }
+        self.seen |= 1 << offset;
+        true
+    }
+
+    /// Window that has seen everything either window has
+    fn merge(self, other: Window) -> Window {
+        let (mut high, low) = if self.highest >= other.highest {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        let shift = high.highest - low.highest;
+        if shift < WINDOW {
+            high.seen |= low.seen << shift;
+        }
+        high.last_seen_at = high.last_seen_at.max(low.last_seen_at);
+        high
+    }
+}
+
+/// Outgoing counters and received windows per contact
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReplayState {
+    sent: HashMap<String, u64>,
+    received: HashMap<String, Window>,
+    /// Highest counter of contacts whose window was evicted
+    #[serde(default)]
+    evicted: HashMap<String, u64>,
+    /// Highest mark folded away once `evicted` was full
+    #[serde(default)]
+    evicted_floor: u64,
+}
+
+/// Replay state of every persona
+pub type ReplayStore = PersonaScoped<ReplayState>;
+
+/// Load saved counters from a config directory (empty if none saved yet)
+pub fn load(config_dir: &Path) -> Result<ReplayStore> {
+    let path = config_dir.join(REPLAY_FILE);
+    if !path.exists() {
+        return Ok(ReplayStore::default());
+    }
+    let mut store: ReplayStore = serde_json::from_slice(&fs::read(path)?)?;
+    for (_, state) in store.across_personas_mut("merging counters kept under several spellings of one key") {
+        state.merge_duplicates();
+    }
+    Ok(store)
+}
+
+pub fn save(store: &ReplayStore, config_dir: &Path) -> Result<()> {
+    fs::create_dir_all(config_dir)?;
+    fs::write(config_dir.join(REPLAY_FILE), serde_json::to_vec(store)?)?;
+    Ok(())
+}
+
+impl ReplayState {
+    /// Counter for the next message sent to `contact`, starting at 1
+    pub fn next_counter(&mut self, contact: &str) -> u64 {
+        let counter = self.sent.entry(contact.to_string()).or_insert(0);
+        *counter += 1;
+        *counter
+    }
+
+    /// Record a counter received from `contact`; false if it is a replay or too old
+    pub fn accept(&mut self, contact: &str, counter: u64, now: u64) -> bool {
+        if counter == 0 {
+            return false;
+        }
+        if !self.received.contains_key(contact) && self.received.len() >= MAX_CONTACTS {
+            self.forget_stalest();
+        }
+        let (evicted, floor) = (&mut self.evicted, self.evicted_floor);
+        let window = self.received.entry(contact.to_string()).or_insert_with(|| Window {
+            // Everything up to the evicted high-water mark counts as seen
+            highest: evicted.remove(contact).unwrap_or(floor),
+            seen: u64::MAX,
+            last_seen_at: now,
+        });
+        let accepted = window.accept(counter);
+        if accepted {
+            window.last_seen_at = now;
+        }
+        accepted
+    }
+
+    /// Merge state kept under other spellings of a contact's key, keeping the
+    /// highest outgoing counter and everything either received window has seen
+    pub fn merge_duplicates(&mut self) {
+        merge_spellings(&mut self.sent, u64::max);
+        merge_spellings(&mut self.received, Window::merge);
+        merge_spellings(&mut self.evicted, u64::max);
+        // A mark left beside a window of the same contact holds for that window
+        let received = &mut self.received;
+        self.evicted.retain(|contact, highest| match received.get_mut(contact) {
+            Some(window) => {
+                *window = window.merge(Window {
+                    highest: *highest,
+                    seen: u64::MAX,
+                    last_seen_at: 0,
+                });
+                false
+            }
+            None => true,
+        });
+    }
+
+    /// Drop all state for a contact (e.g. when it is deleted)
+    pub fn forget(&mut self, contact: &str) {
+        self.sent.remove(contact);
+        self.received.remove(contact);
+        self.evicted.remove(contact);
+    }
+
+    fn forget_stalest(&mut self) {
+        if let Some(stalest) = self
+            .received
+            .iter()
+            .min_by_key(|(_, w)| w.last_seen_at)
+            .map(|(contact, _)| contact.clone())
+        {
+            if let Some(window) = self.received.remove(&stalest) {
+                self.evicted.insert(stalest, window.highest);
+            }
+        }
+        if self.evicted.len() > MAX_EVICTED {
+            self.fold_lowest_mark();
+        }
+    }
+
+    /// Drop the lowest evicted mark, raising the floor to it
+    fn fold_lowest_mark(&mut self) {
+        if let Some((contact, mark)) = self
+            .evicted
+            .iter()
+            .min_by_key(|(_, mark)| **mark)
+            .map(|(contact, mark)| (contact.clone(), *mark))
+        {
+            self.evicted.remove(&contact);
+            self.evicted_floor = self.evicted_floor.max(mark);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replays_rejected_and_reorder_accepted() {
+        let mut state = ReplayState::default();
+        assert_eq!(state.next_counter("alice"), 1);
+        assert_eq!(state.next_counter("alice"), 2);
+
+        assert!(state.accept("bob", 1, 10));
+        assert!(state.accept("bob", 3, 10));
+        assert!(!state.accept("bob", 3, 11));
+        assert!(state.accept("bob", 2, 11));
+        assert!(!state.accept("bob", 1, 12));
+        assert!(!state.accept("bob", 0, 12));
+
+        assert!(state.accept("bob", 100, 12));
+        assert!(!state.accept("bob", 36, 12));
+        assert!(state.accept("bob", 37, 12));
+        assert!(state.accept("carol", 3, 12));
+
+        let mut personas = crate::persona::PersonaStore::default();
+        for id in ["p1", "p2"] {
+            personas
+                .add(crate::persona::Persona {
+                    id: id.to_string(),
+                    name: id.to_string(),
+                    public_key: String::new(),
+                    mailbox_key: String::new(),
+                    route: String::new(),
+                    created_at: 0,
+                })
+                .unwrap();
+        }
+        let (p1, p2) = (personas.handle("p1").unwrap(), personas.handle("p2").unwrap());
+        let mut store = ReplayStore::default();
+        *store.get_mut(&p1) = state;
+
+        let tmp = crate::util::TempDir::new("replay");
+        save(&store, tmp.path()).unwrap();
+        let mut restored = load(tmp.path()).unwrap();
+        assert!(!restored.get_mut(&p1).accept("bob", 100, 13));
+        assert_eq!(restored.get_mut(&p1).next_counter("alice"), 3);
+        assert_eq!(restored.get_mut(&p2).next_counter("alice"), 1);
+    }
+
+    #[test]
+    fn test_evicted_contacts_keep_their_high_water_mark() {
+        let mut state = ReplayState::default();
+        assert!(state.accept("bob", 5, 1));
+        for i in 0..MAX_CONTACTS {
+            assert!(state.accept(&format!("contact-{}", i), 1, 2));
+        }
+        assert!(!state.received.contains_key("bob"));
+
+        // The mark survives a save and reload
+        let mut state: ReplayState = serde_json::from_slice(&serde_json::to_vec(&state).unwrap()).unwrap();
+
+        assert!(!state.accept("bob", 5, 3));
+        assert!(!state.accept("bob", 1, 3));
+        assert!(state.accept("bob", 6, 3));
+        assert!(!state.accept("bob", 6, 3));
+    }
+
+    #[test]
+    fn test_evicted_marks_are_capped_by_a_floor() {
+        let mut state = ReplayState::default();
+        let contacts = MAX_CONTACTS + MAX_EVICTED + 2;
+        for i in 0..contacts {
+            assert!(state.accept(&format!("contact-{}", i), 10 + i as u64, i as u64));
+        }
+        assert_eq!(state.received.len(), MAX_CONTACTS);
+        assert_eq!(state.evicted.len(), MAX_EVICTED);
+        // The two lowest marks were folded away; the floor covers them both
+        assert_eq!(state.evicted_floor, 11);
+
+        // Each new window evicts, and folds, once more
+        let now = contacts as u64;
+        assert!(!state.accept("contact-0", 12, now));
+        assert_eq!(state.evicted_floor, 12);
+        assert!(state.accept("contact-0", 13, now));
+        assert!(!state.accept("contact-100", 110, now));
+        assert!(state.accept("contact-100", 111, now));
+        assert!(state.evicted.len() <= MAX_EVICTED);
+    }
+
+    #[test]
+    fn test_spellings_of_one_key_share_a_window() {
+        let hex = "cd".repeat(32);
+        let mut state = ReplayState::default();
+        assert_eq!(state.next_counter(&hex), 1);
+        assert_eq!(state.next_counter(&hex.to_uppercase()), 1);
+        assert!(state.accept(&hex, 5, 1));
+        assert!(state.accept(&hex.to_uppercase(), 3, 1));
+
+        state.merge_duplicates();
+        let id = crate::pinning::contact_id(&hex).unwrap();
+        assert_eq!(state.next_counter(&id), 2);
+        // Counters seen under either spelling are replays
+        assert!(!state.accept(&id, 5, 2));
+        assert!(!state.accept(&id, 3, 2));
+        assert!(state.accept(&id, 4, 2));
+    }
+}
@@ -0,0 +1,110 @@
+// Retention cleanup for data held by the core
+// Each category has its own maximum age (relay blobs use the relay TTL;
+// received messages and sends waiting for an ACK share the message age). The
+// background sync runs this every pass; a dry run reports what would be
+// removed without touching anything
+
+use crate::core_config::RetentionConfig;
+use crate::veilid_manager::VeilidManager;
+
+/// Items removed by a cleanup, or that would be on a dry run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetentionReport {
+    pub dry_run: bool,
+    pub outbox_messages: usize,
+    pub inbox_messages: usize,
+    pub pending_acks: usize,
+    pub relay_blobs: usize,
+    pub log_records: usize,
+}
+
+impl RetentionReport {
+    pub fn total(&self) -> usize {
+        self.outbox_messages + self.inbox_messages + self.pending_acks + self.relay_blobs + self.log_records
+    }
+}
+
+/// Apply the retention settings to everything the core holds
+pub async fn enforce(manager: &VeilidManager, retention: &RetentionConfig, dry_run: bool) -> RetentionReport {
+    let log_cutoff = crate::util::unix_now().saturating_sub(retention.log_max_age_secs);
+    RetentionReport {
+        dry_run,
+        outbox_messages: manager.expire_outbox(retention.outbox_max_age_secs, dry_run).await,
+        inbox_messages: manager.expire_inbox(retention.message_max_age_secs, dry_run).await,
+        pending_acks: manager.expire_acks(retention.message_max_age_secs, dry_run).await,
+        relay_blobs: manager.expire_relay_cache(dry_run).await,
+        log_records: crate::logging::controller().map_or(0, |c| c.expire_before(log_cutoff, dry_run)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::outbox::{Outbox, OutboxEntry};
+    use crate::safety::SafetyProfile;
+
+    #[tokio::test]
+    async fn test_expired_outbox_messages_removed_unless_dry_run() {
+        let tmp = crate::util::TempDir::new("retention");
+        let mut outbox = Outbox::new();
+        for queued_at in [1, crate::util::unix_now()] {
+            outbox.push(OutboxEntry {
+                route: "VLD1:route:remote".to_string(),
+                message: vec![1],
+                safety: SafetyProfile::routine(),
+                queued_at,
+                release_at: None,
+            });
+        }
+        outbox.save(tmp.path()).unwrap();
+
+        // Never attaches, so nothing leaves the outbox before expiry
+        let manager = VeilidManager::with_transport(std::sync::Arc::new(crate::transport::NoopTransport));
+        manager.initialize(tmp.path_string()).await.unwrap();
+        let retention = RetentionConfig {
+            outbox_max_age_secs: 3600,
+            ..RetentionConfig::default()
+        };
+
+        let report = enforce(&manager, &retention, true).await;
+        assert_eq!(report.outbox_messages, 1);
+        assert_eq!(manager.outbox_len().await, 2);
+
+        let report = enforce(&manager, &retention, false).await;
+        assert_eq!(report.outbox_messages, 1);
+        assert_eq!(Outbox::load(tmp.path()).unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_old_received_messages_and_pending_acks_removed() {
+        use crate::ack::{AckTracker, DeliveryPath};
+        use crate::inbox::Inbox;
+
+        let tmp = crate::util::TempDir::new("retention-messages");
+        let now = crate::util::unix_now();
+        let mut inbox = Inbox::default();
+        inbox.push(vec![1], 1);
+        inbox.push(vec![2], now);
+        inbox.save(tmp.path()).unwrap();
+        let storage_key = crate::profile_key::load_or_create(tmp.path()).unwrap();
+        let mut acks = AckTracker::default();
+        for (message, at) in [(b"old", 1), (b"new", now)] {
+            let paths = vec![DeliveryPath::Route("VLD1:route:remote".to_string())];
+            acks.track("bob", message.to_vec(), &[7u8; 32], paths, at).unwrap();
+        }
+        acks.save(tmp.path(), &storage_key).unwrap();
+
+        let manager = VeilidManager::with_transport(std::sync::Arc::new(crate::transport::NoopTransport));
+        manager.initialize(tmp.path_string()).await.unwrap();
+        let retention = RetentionConfig::default();
+
+        let report = enforce(&manager, &retention, true).await;
+        assert_eq!((report.inbox_messages, report.pending_acks), (1, 1));
+        assert_eq!(manager.inbox_messages(None).await.len(), 2);
+
+        let report = enforce(&manager, &retention, false).await;
+        assert_eq!((report.inbox_messages, report.pending_acks), (1, 1));
+        assert_eq!(Inbox::load(tmp.path()).unwrap().len(), 1);
+        assert_eq!(AckTracker::load(tmp.path(), &storage_key).unwrap().len(), 1);
+    }
+}
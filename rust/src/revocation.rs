@@ -0,0 +1,241 @@
+// Trust revocation ("burn notices")
+// A signed warning that a contact is compromised, propagated through the
+// trust graph. Every field is signed by the issuer, so forwarders cannot
+// extend its reach: a notice is passed on only until the signed
+// forward_until time, which the issuer derives from the hops it allows
+
+use crate::crypto::{hash_blake3, sign_data, signing_public_key, verify_signature};
+use crate::error::{Result, UndergroundError};
+use crate::wire::{put_bytes, split_signature, Reader};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+const MAGIC: &[u8; 3] = b"URX";
+const VERSION: u8 = 2;
+
+/// File name of the persisted revocation list inside the config directory
+pub(crate) const REVOCATIONS_FILE: &str = "revocations.json";
+
+/// Longest reason accepted in a notice
+const MAX_REASON_LEN: usize = 280;
+
+/// Furthest a notice may be propagated
+pub const MAX_PROPAGATION_HOPS: u8 = 6;
+
+/// How long a notice keeps being forwarded for each hop it may travel
+const FORWARD_WINDOW_PER_HOP_SECS: u64 = 24 * 3600;
+
+/// Furthest in the future an issue time may lie, for clocks that disagree
+const MAX_CLOCK_SKEW_SECS: u64 = 600;
+
+/// A signed burn notice
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BurnNotice {
+    pub issuer_key: [u8; 32],
+    pub subject_key: [u8; 32],
+    pub reason: String,
+    pub issued_at: u64,
+    /// Propagation limit the issuer asked for
+    pub max_hops: u8,
+    /// Forwarders stop passing the notice on after this time
+    pub forward_until: u64,
+    signature: [u8; 64],
+}
+
+impl BurnNotice {
+    /// Issue a notice against a subject
+    pub fn create(secret_key: &[u8], subject_key: [u8; 32], reason: &str, max_hops: u8) -> Result<Self> {
+        if reason.len() > MAX_REASON_LEN {
+            return Err(UndergroundError::InvalidMessage("Revocation reason too long".to_string()));
+        }
+        if max_hops > MAX_PROPAGATION_HOPS {
+            return Err(UndergroundError::InvalidMessage(format!(
+                "Revocation cannot travel more than {} hops",
+                MAX_PROPAGATION_HOPS
+            )));
+        }
+
+        let issued_at = crate::util::unix_now();
+        let mut notice = Self {
+            issuer_key: signing_public_key(secret_key)?,
+            subject_key,
+            reason: reason.to_string(),
+            issued_at,
+            max_hops,
+            forward_until: issued_at + max_hops as u64 * FORWARD_WINDOW_PER_HOP_SECS,
+            signature: [0u8; 64],
+        };
+        notice.signature = sign_data(secret_key, &notice.signed_bytes())?;
+        Ok(notice)
+    }
+
+    /// Check the notice was signed by the issuer key it carries and that its
+    /// times are ones an honest issuer could have signed at `now`
+    pub fn verify(&self, now: u64) -> Result<()> {
+        verify_signature(&self.issuer_key, &self.signed_bytes(), &self.signature)?;
+        if self.issued_at > now + MAX_CLOCK_SKEW_SECS {
+            return Err(UndergroundError::InvalidMessage("Burn notice issued in the future".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Stable identifier used to drop duplicates
+    pub fn id(&self) -> [u8; 32] {
+        hash_blake3(&self.signed_bytes())
+    }
+
+    /// Whether to pass the notice on to our contacts at `now`
+    pub fn should_forward(&self, now: u64) -> bool {
+        self.max_hops > 0 && now < self.forward_until
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = self.signed_bytes();
+        out.extend_from_slice(&self.signature);
+        out
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let (signed, signature) = split_signature(data, "Burn notice")?;
+
+        let mut reader = Reader::new(signed, "Burn notice");
+        reader.header(MAGIC, VERSION)?;
+        let issuer_key = reader.array()?;
+        let subject_key = reader.array()?;
+        let reason = reader.string(MAX_REASON_LEN)?;
+        let issued_at = reader.u64()?;
+        let max_hops = reader.u8()?;
+        let forward_until = reader.u64()?;
+        reader.finish()?;
+
+        let window = max_hops as u64 * FORWARD_WINDOW_PER_HOP_SECS;
+        if max_hops > MAX_PROPAGATION_HOPS || forward_until.saturating_sub(issued_at) > window {
+            return Err(reader.invalid("hop limit exceeded"));
+        }
+
+        verify_signature(&issuer_key, signed, &signature)?;
+
+        Ok(Self {
+            issuer_key,
+            subject_key,
+            reason,
+            issued_at,
+            max_hops,
+            forward_until,
+            signature,
+        })
+    }
+
+    fn signed_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(98 + self.reason.len());
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&self.issuer_key);
+        out.extend_from_slice(&self.subject_key);
+        put_bytes(&mut out, self.reason.as_bytes());
+        out.extend_from_slice(&self.issued_at.to_be_bytes());
+        out.push(self.max_hops);
+        out.extend_from_slice(&self.forward_until.to_be_bytes());
+        out
+    }
+}
+
+/// A revocation we accepted, with who issued it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RevocationRecord {
+    pub subject_key: [u8; 32],
+    pub issuer_key: [u8; 32],
+    pub reason: String,
+    pub issued_at: u64,
+    pub received_at: u64,
+}
+
+/// Locally accepted revocations
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RevocationList {
+    records: Vec<RevocationRecord>,
+    seen: HashSet<[u8; 32]>,
+}
+
+impl RevocationList {
+    /// Load the list from a config directory (empty if none saved yet)
+    pub fn load(config_dir: &Path) -> Result<Self> {
+        let path = config_dir.join(REVOCATIONS_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    pub fn save(&self, config_dir: &Path) -> Result<()> {
+        fs::create_dir_all(config_dir)?;
+        fs::write(config_dir.join(REVOCATIONS_FILE), serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    /// Record a verified notice; returns false if it was already seen
+    pub fn accept(&mut self, notice: &BurnNotice) -> bool {
+        if !self.seen.insert(notice.id()) {
+            return false;
+        }
+
+        self.records.push(RevocationRecord {
+            subject_key: notice.subject_key,
+            issuer_key: notice.issuer_key,
+            reason: notice.reason.clone(),
+            issued_at: notice.issued_at,
+            received_at: crate::util::unix_now(),
+        });
+        true
+    }
+
+    pub fn is_revoked(&self, key: &[u8; 32]) -> bool {
+        self.records.iter().any(|r| &r.subject_key == key)
+    }
+
+    /// All revocations issued against a key
+    pub fn records_for(&self, key: &[u8; 32]) -> Vec<RevocationRecord> {
+        self.records
+            .iter()
+            .filter(|r| &r.subject_key == key)
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_signing_keypair;
+
+    #[test]
+    fn test_notice_propagation_is_bounded() {
+        let (issuer_sk, _) = generate_signing_keypair();
+        let (_, subject_pk) = generate_signing_keypair();
+
+        let notice = BurnNotice::create(issuer_sk.as_slice(), subject_pk, "infiltrator", 1).unwrap();
+        let relayed = BurnNotice::decode(&notice.encode()).unwrap();
+        assert!(relayed.verify(notice.issued_at).is_ok());
+        assert!(relayed.should_forward(notice.issued_at));
+        assert!(!relayed.should_forward(notice.forward_until));
+        assert!(relayed.verify(notice.issued_at - MAX_CLOCK_SKEW_SECS - 1).is_err());
+
+        // Forwarders cannot stretch the reach: every field is signed
+        let mut encoded = notice.encode();
+        let forward_until_at = encoded.len() - 64 - 8;
+        encoded[forward_until_at] ^= 1;
+        assert!(BurnNotice::decode(&encoded).is_err());
+        let stretched = BurnNotice {
+            forward_until: notice.issued_at + 10,
+            ..notice.clone()
+        };
+        assert!(stretched.verify(notice.issued_at).is_err());
+
+        let mut list = RevocationList::default();
+        assert!(list.accept(&notice));
+        assert!(!list.accept(&relayed));
+        assert!(list.is_revoked(&subject_pk));
+    }
+}
@@ -0,0 +1,111 @@
+// Signed route bundles for out-of-band exchange (e.g. QR codes)
+// Lets two people who meet in person exchange reachable routes
+// without any prior network contact
+
+use crate::crypto::{sign_data, signing_public_key, verify_signature};
+use crate::error::{Result, UndergroundError};
+use crate::wire::{put_bytes, split_signature, Reader};
+
+const MAGIC: &[u8; 3] = b"URB";
+const VERSION: u8 = 1;
+
+/// Longest route or mailbox key accepted in a bundle
+const MAX_FIELD_LEN: usize = 1024;
+
+/// A signed package of a private route and mailbox key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteBundle {
+    pub route: String,
+    pub mailbox_key: String,
+    pub public_key: [u8; 32],
+    pub created_at: u64,
+    signature: [u8; 64],
+}
+
+impl RouteBundle {
+    /// Create and sign a bundle with our identity secret key
+    pub fn create(route: &str, mailbox_key: &str, secret_key: &[u8]) -> Result<Self> {
+        if route.is_empty() || route.len() > MAX_FIELD_LEN || mailbox_key.len() > MAX_FIELD_LEN {
+            return Err(UndergroundError::InvalidMessage("Invalid route bundle field".to_string()));
+        }
+
+        let mut bundle = Self {
+            route: route.to_string(),
+            mailbox_key: mailbox_key.to_string(),
+            public_key: signing_public_key(secret_key)?,
+            created_at: crate::util::unix_now(),
+            signature: [0u8; 64],
+        };
+        bundle.signature = sign_data(secret_key, &bundle.signed_bytes())?;
+
+        Ok(bundle)
+    }
+
+    /// Compact binary encoding suitable for a QR code
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = self.signed_bytes();
+        out.extend_from_slice(&self.signature);
+        out
+    }
+
+    /// Decode a bundle and verify its signature
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let (body, signature) = split_signature(data, "Route bundle")?;
+
+        let mut reader = Reader::new(body, "Route bundle");
+        reader.header(MAGIC, VERSION)?;
+        let public_key = reader.array()?;
+        let created_at = reader.u64()?;
+        let route = reader.string(MAX_FIELD_LEN)?;
+        let mailbox_key = reader.string(MAX_FIELD_LEN)?;
+        reader.finish()?;
+
+        verify_signature(&public_key, body, &signature)?;
+
+        Ok(Self {
+            route,
+            mailbox_key,
+            public_key,
+            created_at,
+            signature,
+        })
+    }
+
+    fn signed_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(48 + self.route.len() + self.mailbox_key.len());
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&self.public_key);
+        out.extend_from_slice(&self.created_at.to_be_bytes());
+        put_bytes(&mut out, self.route.as_bytes());
+        put_bytes(&mut out, self.mailbox_key.as_bytes());
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_signing_keypair;
+
+    #[test]
+    fn test_bundle_roundtrip() {
+        let (secret, public) = generate_signing_keypair();
+        let bundle = RouteBundle::create("VLD1:route:abcd", "VLD1:dht:ef01", secret.as_slice()).unwrap();
+
+        let decoded = RouteBundle::decode(&bundle.encode()).unwrap();
+        assert_eq!(decoded, bundle);
+        assert_eq!(decoded.public_key, public);
+    }
+
+    #[test]
+    fn test_tampered_bundle_rejected() {
+        let (secret, _) = generate_signing_keypair();
+        let bundle = RouteBundle::create("VLD1:route:abcd", "VLD1:dht:ef01", secret.as_slice()).unwrap();
+
+        let mut encoded = bundle.encode();
+        encoded[50] ^= 0x01;
+        assert!(RouteBundle::decode(&encoded).is_err());
+        assert!(RouteBundle::decode(&encoded[..10]).is_err());
+    }
+}
@@ -0,0 +1,238 @@
+// Versioned JSON-RPC 2.0 protocol for third-party desktop clients
+// Methods are namespaced by version ("v1.identity.list_personas") and each
+// requires a capability granted to the caller's token
+
+use crate::error::{Result, UndergroundError};
+use crate::ffi_error::FfiError;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+
+/// File holding issued client tokens inside the config directory
+pub(crate) const RPC_TOKENS_FILE: &str = "rpc_tokens.json";
+
+/// File holding the local daemon's control token inside the config directory
+pub(crate) const DAEMON_TOKEN_FILE: &str = "daemon.token";
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const UNAUTHORIZED: i64 = -32001;
+pub const FORBIDDEN: i64 = -32002;
+pub const APPLICATION_ERROR: i64 = -32000;
+
+/// What a client token is allowed to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    Status,
+    Identity,
+    Contacts,
+    Messaging,
+    Admin,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 5] = [
+        Capability::Status,
+        Capability::Identity,
+        Capability::Contacts,
+        Capability::Messaging,
+        Capability::Admin,
+    ];
+}
+
+/// Capability a method requires, or None for unknown methods
+pub fn required_capability(method: &str) -> Option<Capability> {
+    let capability = match method {
+        "v1.status" | "v1.events.poll" => Capability::Status,
+        "v1.identity.list_personas" | "v1.identity.create_persona" | "v1.identity.switch_persona" => {
+            Capability::Identity
+        }
+        "v1.contacts.my_qr" | "v1.contacts.parse_qr" | "v1.contacts.block" | "v1.contacts.unblock" => {
+            Capability::Contacts
+        }
+        "v1.messaging.send" | "v1.messaging.inbox" | "v1.messaging.remove" => Capability::Messaging,
+        "v1.tokens.issue" | "v1.tokens.revoke" | "v1.logs.recent" | "v1.logs.set_level" | "v1.config.reload"
+        | "v1.storage.audit" | "v1.shutdown" => {
+            Capability::Admin
+        }
+        _ => return None,
+    };
+    Some(capability)
+}
+
+/// Incoming call; `auth` carries the client token
+#[derive(Debug, Deserialize)]
+pub struct RpcRequest {
+    pub jsonrpc: String,
+    #[serde(default)]
+    pub id: Value,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+    #[serde(default)]
+    pub auth: String,
+}
+
+/// Error carried in a JSON-RPC error response
+#[derive(Debug, Clone, PartialEq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: &str) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+            data: None,
+        }
+    }
+
+    pub fn invalid_params(message: &str) -> Self {
+        Self::new(INVALID_PARAMS, message)
+    }
+}
+
+impl From<FfiError> for RpcError {
+    fn from(err: FfiError) -> Self {
+        Self {
+            code: APPLICATION_ERROR,
+            message: err.user_message(),
+            data: Some(json!({ "code": err.code() })),
+        }
+    }
+}
+
+impl From<UndergroundError> for RpcError {
+    fn from(err: UndergroundError) -> Self {
+        FfiError::from(err).into()
+    }
+}
+
+pub fn success(id: Value, result: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+pub fn failure(id: Value, error: RpcError) -> Value {
+    let mut body = json!({ "code": error.code, "message": error.message });
+    if let Some(data) = error.data {
+        body["data"] = data;
+    }
+    json!({ "jsonrpc": "2.0", "id": id, "error": body })
+}
+
+/// Parse a request line, validating the protocol version
+pub fn parse_request(line: &str) -> std::result::Result<RpcRequest, RpcError> {
+    let request: RpcRequest =
+        serde_json::from_str(line).map_err(|_| RpcError::new(PARSE_ERROR, "Parse error"))?;
+    if request.jsonrpc != "2.0" {
+        return Err(RpcError::new(INVALID_REQUEST, "Only JSON-RPC 2.0 is supported"));
+    }
+    Ok(request)
+}
+
+/// Fetch a string parameter by name
+pub fn string_param(params: &Value, name: &str) -> std::result::Result<String, RpcError> {
+    params
+        .get(name)
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| RpcError::invalid_params(&format!("Missing string parameter `{}`", name)))
+}
+
+/// Required unsigned integer parameter `name`
+pub fn u64_param(params: &Value, name: &str) -> std::result::Result<u64, RpcError> {
+    params
+        .get(name)
+        .and_then(Value::as_u64)
+        .ok_or_else(|| RpcError::invalid_params(&format!("Missing integer parameter `{}`", name)))
+}
+
+/// A client token; only its hash is stored
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenGrant {
+    pub label: String,
+    pub token_hash: String,
+    pub capabilities: Vec<Capability>,
+    pub created_at: u64,
+}
+
+/// Tokens issued to third-party clients
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TokenStore {
+    grants: Vec<TokenGrant>,
+}
+
+impl TokenStore {
+    /// Load issued tokens from a config directory (empty if none saved yet)
+    pub fn load(config_dir: &Path) -> Result<Self> {
+        let path = config_dir.join(RPC_TOKENS_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    pub fn save(&self, config_dir: &Path) -> Result<()> {
+        fs::create_dir_all(config_dir)?;
+        fs::write(config_dir.join(RPC_TOKENS_FILE), serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    /// Issue a token, replacing any previous token with the same label
+    pub fn issue(&mut self, label: &str, capabilities: Vec<Capability>) -> String {
+        let token = hex::encode(crate::crypto::generate_random_bytes(32));
+        self.grants.retain(|g| g.label != label);
+        self.grants.push(TokenGrant {
+            label: label.to_string(),
+            token_hash: hash_token(&token),
+            capabilities,
+            created_at: crate::util::unix_now(),
+        });
+        token
+    }
+
+    /// Returns false if no token had that label
+    pub fn revoke(&mut self, label: &str) -> bool {
+        let before = self.grants.len();
+        self.grants.retain(|g| g.label != label);
+        self.grants.len() != before
+    }
+
+    pub fn capabilities_for(&self, token: &str) -> Option<&[Capability]> {
+        let hash = hash_token(token);
+        self.grants
+            .iter()
+            .find(|g| g.token_hash == hash)
+            .map(|g| g.capabilities.as_slice())
+    }
+}
+
+fn hash_token(token: &str) -> String {
+    hex::encode(crate::crypto::hash_blake3(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scoped_tokens() {
+        let mut store = TokenStore::default();
+        let token = store.issue("dashboard", vec![Capability::Status]);
+
+        assert_eq!(store.capabilities_for(&token), Some(&[Capability::Status][..]));
+        assert_eq!(store.capabilities_for("guess"), None);
+        assert_eq!(required_capability("v1.shutdown"), Some(Capability::Admin));
+        assert_eq!(required_capability("v2.status"), None);
+
+        assert!(store.revoke("dashboard"));
+        assert_eq!(store.capabilities_for(&token), None);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::config::NetworkTuning;
+use crate::error::{Result, UndergroundError};
+use serde::{Deserialize, Serialize};
+
+/// Most hops Veilid allows in a safety route
+pub const MAX_HOP_COUNT: u8 = 4;
+
+/// Ordering preference for the route (mirrors Veilid's Sequencing)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Sequencing {
+    NoPreference,
+    PreferOrdered,
+    EnsureOrdered,
+}
+
+/// Route stability preference (mirrors Veilid's Stability)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Stability {
+    LowLatency,
+    Reliable,
+}
+
+/// Per-message safety selection
+/// Urgent traffic trades anonymity for latency, routine traffic takes the longest route
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SafetyProfile {
+    pub hop_count: u8,
+    pub sequencing: Sequencing,
+    pub stability: Stability,
+}
+
+impl SafetyProfile {
+    /// Lowest latency, for critical emergencies
+    pub fn urgent() -> Self {
+        Self {
+            hop_count: 1,
+            sequencing: Sequencing::NoPreference,
+            stability: Stability::LowLatency,
+        }
+    }
+
+    /// Maximum anonymity, for routine check-ins
+    pub fn routine() -> Self {
+        Self {
+            hop_count: MAX_HOP_COUNT,
+            sequencing: Sequencing::PreferOrdered,
+            stability: Stability::Reliable,
+        }
+    }
+
+    /// Default for the active network profile
+    pub fn from_tuning(tuning: &NetworkTuning) -> Self {
+        Self {
+            hop_count: tuning.route_hop_count,
+            sequencing: Sequencing::PreferOrdered,
+            stability: Stability::Reliable,
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.hop_count == 0 || self.hop_count > MAX_HOP_COUNT {
+            return Err(UndergroundError::Config(format!(
+                "Hop count must be between 1 and {}",
+                MAX_HOP_COUNT
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hop_count_bounds() {
+        assert!(SafetyProfile::urgent().validate().is_ok());
+        assert!(SafetyProfile::routine().validate().is_ok());
+
+        for hop_count in [0, MAX_HOP_COUNT + 1] {
+            let profile = SafetyProfile {
+                hop_count,
+                ..SafetyProfile::routine()
+            };
+            assert!(matches!(profile.validate(), Err(UndergroundError::Config(_))));
+        }
+    }
+}
@@ -0,0 +1,125 @@
+// Traffic shaping for outbound messages
+// Non-critical sends are held for a random delay and released in batches, so
+// the times messages leave the device say less about when the user acted.
+// Critical sends bypass the shaper entirely
+
+use crate::error::{Result, UndergroundError};
+use rand::rngs::OsRng;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Longest delay the shaper may add
+const MAX_DELAY_SECS: u64 = 3600;
+
+/// How soon a send must leave
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Urgency {
+    /// Sent at once, never shaped
+    Critical,
+    Normal,
+}
+
+/// Delay and batch settings (off by default)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShaperConfig {
+    pub enabled: bool,
+    pub min_delay_secs: u64,
+    pub max_delay_secs: u64,
+    /// Most held sends released together
+    pub max_batch: usize,
+}
+
+impl Default for ShaperConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_delay_secs: 5,
+            max_delay_secs: 120,
+            max_batch: 16,
+        }
+    }
+}
+
+impl ShaperConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.min_delay_secs > self.max_delay_secs || self.max_delay_secs > MAX_DELAY_SECS || self.max_batch == 0 {
+            return Err(UndergroundError::Config(format!(
+                "shaping delays must satisfy min <= max <= {} and shaping.max_batch must be positive",
+                MAX_DELAY_SECS
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Picks release times for non-critical sends
+/// The sends themselves wait in the outbox, so they survive a restart
+#[derive(Debug, Default)]
+pub struct TrafficShaper {
+    config: ShaperConfig,
+}
+
+impl TrafficShaper {
+    pub fn set_config(&mut self, config: ShaperConfig) {
+        self.config = config;
+    }
+
+    /// Whether a send of this urgency should be held
+    pub fn shapes(&self, urgency: Urgency) -> bool {
+        self.config.enabled && urgency != Urgency::Critical
+    }
+
+    /// When a send held now should be released, after a random delay
+    pub fn release_at(&self, now: u64) -> u64 {
+        now + OsRng.gen_range(self.config.min_delay_secs..=self.config.max_delay_secs)
+    }
+
+    /// Most held sends released together
+    pub fn max_batch(&self) -> usize {
+        self.config.max_batch
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::outbox::{Outbox, OutboxEntry};
+    use crate::safety::SafetyProfile;
+
+    #[test]
+    fn test_holds_then_releases_in_batches() {
+        let mut shaper = TrafficShaper::default();
+        assert!(!shaper.shapes(Urgency::Normal));
+        shaper.set_config(ShaperConfig {
+            enabled: true,
+            min_delay_secs: 10,
+            max_delay_secs: 20,
+            max_batch: 2,
+        });
+        assert!(shaper.shapes(Urgency::Normal));
+        assert!(!shaper.shapes(Urgency::Critical));
+
+        let mut outbox = Outbox::new();
+        for i in 0..3u8 {
+            let release_at = shaper.release_at(100);
+            assert!((110..=120).contains(&release_at));
+            outbox.push(OutboxEntry {
+                route: "VLD1:route:aa".to_string(),
+                message: vec![i],
+                safety: SafetyProfile::routine(),
+                queued_at: 100,
+                release_at: Some(release_at),
+            });
+        }
+        assert_eq!(outbox.pop(), None);
+        assert_eq!(outbox.release_held(109, Some(shaper.max_batch())), 0);
+        assert_eq!(outbox.release_held(120, Some(shaper.max_batch())), 2);
+        assert_eq!(outbox.held_len(), 1);
+        assert_eq!(outbox.release_held(100, None), 1);
+        assert_eq!(outbox.iter().count(), 3);
+
+        assert!(ShaperConfig { min_delay_secs: 30, ..ShaperConfig::default() }.validate().is_ok());
+        assert!(ShaperConfig { max_batch: 0, ..ShaperConfig::default() }.validate().is_err());
+    }
+}
@@ -0,0 +1,201 @@
+// Store-and-carry bundles for full network shutdowns
+// Queued messages and our DHT announcements are sealed into a password
+// encrypted file that travels on removable media. A connected device imports
+// it and forwards everything as if it had been sent locally
+
+use crate::chunking::MAX_SUBKEY_LEN;
+use crate::crypto::{decrypt_data, derive_key, encrypt_data, generate_salt};
+use crate::error::{Result, UndergroundError};
+use crate::wire::{put_bytes, Reader};
+
+#[cfg(feature = "native")]
+use crate::safety::SafetyProfile;
+#[cfg(feature = "native")]
+use crate::transport::Transport;
+#[cfg(feature = "native")]
+use futures::future::{self, BoxFuture};
+
+const MAGIC: &[u8; 3] = b"URN";
+const VERSION: u8 = 1;
+
+/// Most envelopes or announcements in one bundle
+pub const MAX_BUNDLE_ITEMS: usize = 1024;
+
+/// Longest route or DHT key carried
+const MAX_ADDRESS_LEN: usize = 1024;
+
+/// A message for a private route, carried instead of sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CarriedEnvelope {
+    pub route: String,
+    pub message: Vec<u8>,
+}
+
+/// A DHT value to publish once the bundle reaches the network
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CarriedAnnouncement {
+    pub key: String,
+    pub value: Vec<u8>,
+}
+
+/// Everything one trip carries
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CarryBundle {
+    pub created_at: u64,
+    pub envelopes: Vec<CarriedEnvelope>,
+    pub announcements: Vec<CarriedAnnouncement>,
+}
+
+impl CarryBundle {
+    /// Encrypt the bundle under a key derived from `password`
+    pub fn seal(&self, password: &str) -> Result<Vec<u8>> {
+        if self.envelopes.len() > MAX_BUNDLE_ITEMS || self.announcements.len() > MAX_BUNDLE_ITEMS {
+            return Err(UndergroundError::InvalidMessage("Too many items for one bundle".to_string()));
+        }
+        let too_long = self.envelopes.iter().any(|e| e.message.len() > MAX_SUBKEY_LEN)
+            || self.announcements.iter().any(|a| a.value.len() > MAX_SUBKEY_LEN);
+        if too_long {
+            return Err(UndergroundError::InvalidMessage("Item too large for a bundle".to_string()));
+        }
+
+        let mut payload = Vec::new();
+        payload.extend_from_slice(&self.created_at.to_be_bytes());
+        payload.extend_from_slice(&(self.envelopes.len() as u16).to_be_bytes());
+        for envelope in &self.envelopes {
+            put_bytes(&mut payload, envelope.route.as_bytes());
+            put_bytes(&mut payload, &envelope.message);
+        }
+        payload.extend_from_slice(&(self.announcements.len() as u16).to_be_bytes());
+        for announcement in &self.announcements {
+            put_bytes(&mut payload, announcement.key.as_bytes());
+            put_bytes(&mut payload, &announcement.value);
+        }
+
+        let salt = generate_salt();
+        let key = derive_key(password, &salt)?;
+        let ciphertext = encrypt_data(key.as_slice(), &payload)?;
+
+        let mut out = Vec::with_capacity(4 + salt.len() + ciphertext.len());
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&salt);
+        out.extend_from_slice(&ciphertext);
+        Ok(out)
+    }
+
+    /// Decrypt and parse a bundle
+    pub fn open(data: &[u8], password: &str) -> Result<Self> {
+        let mut reader = Reader::new(data, "Carry bundle");
+        reader.header(MAGIC, VERSION)?;
+        let salt: [u8; 32] = reader.array()?;
+        let key = derive_key(password, &salt)?;
+        let payload = decrypt_data(key.as_slice(), reader.rest()).map_err(|_| UndergroundError::WrongPassword)?;
+
+        let mut reader = Reader::new(&payload, "Carry bundle");
+        let created_at = reader.u64()?;
+        let count = reader.u16()? as usize;
+        if count > MAX_BUNDLE_ITEMS {
+            return Err(reader.invalid("too many envelopes"));
+        }
+        let mut envelopes = Vec::with_capacity(count);
+        for _ in 0..count {
+            envelopes.push(CarriedEnvelope {
+                route: reader.string(MAX_ADDRESS_LEN)?,
+                message: reader.bytes(MAX_SUBKEY_LEN)?.to_vec(),
+            });
+        }
+        let count = reader.u16()? as usize;
+        if count > MAX_BUNDLE_ITEMS {
+            return Err(reader.invalid("too many announcements"));
+        }
+        let mut announcements = Vec::with_capacity(count);
+        for _ in 0..count {
+            announcements.push(CarriedAnnouncement {
+                key: reader.string(MAX_ADDRESS_LEN)?,
+                value: reader.bytes(MAX_SUBKEY_LEN)?.to_vec(),
+            });
+        }
+        reader.finish()?;
+
+        Ok(Self {
+            created_at,
+            envelopes,
+            announcements,
+        })
+    }
+}
+
+/// Transport that collects sends for a carry bundle instead of delivering them
+#[cfg(feature = "native")]
+#[derive(Default)]
+pub struct SneakernetTransport {
+    carried: std::sync::Mutex<Vec<CarriedEnvelope>>,
+}
+
+#[cfg(feature = "native")]
+impl SneakernetTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Envelopes collected so far, emptying the carrier
+    pub fn take(&self) -> Vec<CarriedEnvelope> {
+        std::mem::take(&mut *self.carried.lock().unwrap())
+    }
+}
+
+#[cfg(feature = "native")]
+impl Transport for SneakernetTransport {
+    fn name(&self) -> &'static str {
+        "sneakernet"
+    }
+
+    fn attach(&self) -> BoxFuture<'_, Result<bool>> {
+        Box::pin(future::ready(Ok(true)))
+    }
+
+    fn detach(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(future::ready(Ok(())))
+    }
+
+    fn send(&self, route: &str, message: Vec<u8>, _safety: SafetyProfile) -> BoxFuture<'_, Result<()>> {
+        let mut carried = self.carried.lock().unwrap();
+        let result = if carried.len() >= MAX_BUNDLE_ITEMS {
+            Err(UndergroundError::InvalidMessage("Carry bundle is full".to_string()))
+        } else {
+            carried.push(CarriedEnvelope {
+                route: route.to_string(),
+                message,
+            });
+            Ok(())
+        };
+        Box::pin(future::ready(result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundle_roundtrip_needs_password() {
+        let bundle = CarryBundle {
+            created_at: 1_700_000_000,
+            envelopes: vec![CarriedEnvelope {
+                route: "VLD1:route:aa".to_string(),
+                message: b"hello".to_vec(),
+            }],
+            announcements: vec![CarriedAnnouncement {
+                key: "VLD1:dht:bb".to_string(),
+                value: b"notice".to_vec(),
+            }],
+        };
+
+        let sealed = bundle.seal("carry me").unwrap();
+        assert_eq!(CarryBundle::open(&sealed, "carry me").unwrap(), bundle);
+        assert!(matches!(
+            CarryBundle::open(&sealed, "wrong"),
+            Err(UndergroundError::WrongPassword)
+        ));
+    }
+}
@@ -0,0 +1,131 @@
+// Storage hygiene audit
+// Lists files in the profile directory that leak more than the profile itself:
+// leftover temporary files, stray backups, files this crate does not know,
+// profile files grown past the export limit, and files other users can read
+
+use crate::core_config::CONFIG_FILE;
+use crate::error::Result;
+use crate::profile_archive::{MAX_FILE_LEN, PROFILE_FILES};
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+/// Suffixes editors and interrupted writes leave behind
+const TEMP_SUFFIXES: &[&str] = &[".tmp", ".temp", ".swp", "~"];
+
+/// Suffixes of manual or tool-made copies
+const BACKUP_SUFFIXES: &[&str] = &[".bak", ".old", ".orig", ".backup"];
+
+/// Why a file was reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum FindingKind {
+    TempFile,
+    BackupFile,
+    UnknownFile,
+    /// A profile file larger than a profile export accepts
+    Oversized,
+    /// Group or other users have some access (Unix only)
+    ReadableByOthers,
+}
+
+/// One file the app should remove or fix
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StorageFinding {
+    pub file: String,
+    pub kind: FindingKind,
+    pub bytes: u64,
+}
+
+/// Inspect every file directly inside `data_dir`
+pub fn audit(data_dir: &Path) -> Result<Vec<StorageFinding>> {
+    let mut findings = Vec::new();
+    if !data_dir.exists() {
+        return Ok(findings);
+    }
+
+    for entry in fs::read_dir(data_dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        let file = entry.file_name().to_string_lossy().into_owned();
+        let bytes = metadata.len();
+        let mut report = |kind| {
+            findings.push(StorageFinding {
+                file: file.clone(),
+                kind,
+                bytes,
+            })
+        };
+
+        if let Some(kind) = classify(&file) {
+            report(kind);
+        } else if bytes > MAX_FILE_LEN as u64 {
+            report(FindingKind::Oversized);
+        }
+        if readable_by_others(&metadata) {
+            report(FindingKind::ReadableByOthers);
+        }
+    }
+    findings.sort_by(|a, b| a.file.cmp(&b.file));
+    Ok(findings)
+}
+
+/// Kind of a file that should not be there, or None for files this crate keeps
+fn classify(file: &str) -> Option<FindingKind> {
+    if PROFILE_FILES.contains(&file) || file == CONFIG_FILE {
+        return None;
+    }
+    if TEMP_SUFFIXES.iter().any(|s| file.ends_with(s)) || file.starts_with(".#") {
+        return Some(FindingKind::TempFile);
+    }
+    // Numbered copies such as "personas.json.1" count as backups too
+    let copy_of_profile = PROFILE_FILES
+        .iter()
+        .any(|name| file.strip_prefix(name).is_some_and(|rest| rest.starts_with('.')));
+    if copy_of_profile || BACKUP_SUFFIXES.iter().any(|s| file.ends_with(s)) {
+        return Some(FindingKind::BackupFile);
+    }
+    Some(FindingKind::UnknownFile)
+}
+
+#[cfg(unix)]
+fn readable_by_others(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o077 != 0
+}
+
+#[cfg(not(unix))]
+fn readable_by_others(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stray_files_reported() {
+        let tmp = crate::util::TempDir::new("audit");
+        let dir = tmp.path();
+        for name in ["personas.json", "personas.json.1", "blocklist.json.tmp", "notes.txt"] {
+            fs::write(dir.join(name), b"{}").unwrap();
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                fs::set_permissions(dir.join(name), fs::Permissions::from_mode(0o600)).unwrap();
+            }
+        }
+
+        let kinds: Vec<(String, FindingKind)> = audit(dir).unwrap().into_iter().map(|f| (f.file, f.kind)).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ("blocklist.json.tmp".to_string(), FindingKind::TempFile),
+                ("notes.txt".to_string(), FindingKind::UnknownFile),
+                ("personas.json.1".to_string(), FindingKind::BackupFile),
+            ]
+        );
+    }
+}
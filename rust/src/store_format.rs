@@ -0,0 +1,43 @@
+// Layout versions of the JSON message stores
+// inbox.json and outbox.json carry a version field so their layout can change
+// without losing queued messages: each store reads the version first, converts
+// older layouts on load and rewrites the file in the current one at startup.
+// Files written before stores were versioned have no field and read as 0
+
+use crate::error::{Result, UndergroundError};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Deserializer, Serializer};
+
+#[derive(Deserialize)]
+struct Header {
+    #[serde(default)]
+    version: u32,
+}
+
+/// Layout version of a store file, refusing layouts newer than `current`
+/// A newer file is left in place for the app version that wrote it
+pub(crate) fn read_version(bytes: &[u8], file: &str, current: u32) -> Result<u32> {
+    let version = serde_json::from_slice::<Header>(bytes)?.version;
+    if version > current {
+        return Err(UndergroundError::Storage(format!(
+            "{} has layout version {}, newer than the supported {}",
+            file, version, current
+        )));
+    }
+    Ok(version)
+}
+
+/// Message bytes as base64 rather than a JSON array of numbers
+pub(crate) mod base64_bytes {
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
@@ -0,0 +1,279 @@
+// Onion-service mailbox transport through a local Tor client
+// When Veilid bootstraps are blocked, envelopes are posted to the contact's
+// onion-service mailbox through the SOCKS5 port of a Tor client running next
+// to the app (tor, or `arti proxy`). Onion names go to the proxy unresolved,
+// so nothing about the destination leaks to local DNS. Routes are mapped to
+// mailbox addresses with set_mailbox; a route without one cannot be reached
+
+use crate::error::{Result, UndergroundError};
+use crate::safety::SafetyProfile;
+use crate::transport::Transport;
+use futures::future::BoxFuture;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+/// SOCKS port of a Tor client with its default configuration
+pub const DEFAULT_SOCKS_ADDR: &str = "127.0.0.1:9050";
+
+/// Circuits to onion services are slow to build; give up on a send after this
+const SEND_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Longest status line read from a mailbox
+const MAX_STATUS_LINE: u64 = 1024;
+
+/// Longest mailbox id in an address
+const MAX_MAILBOX_ID_LEN: usize = 128;
+
+/// Length of a v3 onion service name without ".onion"
+const ONION_V3_LEN: usize = 56;
+
+const SOCKS_VERSION: u8 = 5;
+const SOCKS_NO_AUTH: u8 = 0;
+const SOCKS_CONNECT: u8 = 1;
+const SOCKS_ATYP_IPV4: u8 = 1;
+const SOCKS_ATYP_DOMAIN: u8 = 3;
+const SOCKS_ATYP_IPV6: u8 = 4;
+
+/// A contact's mailbox on an onion service
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OnionMailbox {
+    pub host: String,
+    pub port: u16,
+    pub id: String,
+}
+
+impl OnionMailbox {
+    /// Parse "<v3 name>.onion[:port]/<mailbox id>"
+    pub fn parse(address: &str) -> Result<Self> {
+        let invalid = || UndergroundError::InvalidMessage("Invalid onion mailbox address".to_string());
+        let (authority, id) = address.split_once('/').ok_or_else(invalid)?;
+        let (host, port) = match authority.split_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| invalid())?),
+            None => (authority, 80),
+        };
+
+        let name = host.strip_suffix(".onion").ok_or_else(invalid)?;
+        let valid_name = name.len() == ONION_V3_LEN && name.bytes().all(|b| matches!(b, b'a'..=b'z' | b'2'..=b'7'));
+        let valid_id = !id.is_empty()
+            && id.len() <= MAX_MAILBOX_ID_LEN
+            && id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+        if !valid_name || !valid_id || port == 0 {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            id: id.to_string(),
+        })
+    }
+}
+
+/// Posts envelopes to onion-service mailboxes through a Tor SOCKS proxy
+pub struct TorTransport {
+    proxy: SocketAddr,
+    mailboxes: Mutex<HashMap<String, OnionMailbox>>,
+}
+
+impl TorTransport {
+    pub fn new(proxy: SocketAddr) -> Self {
+        Self {
+            proxy,
+            mailboxes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Deliver messages for `route` to the onion mailbox at `address`
+    pub fn set_mailbox(&self, route: &str, address: &str) -> Result<()> {
+        let mailbox = OnionMailbox::parse(address)?;
+        self.mailboxes.lock().unwrap().insert(route.to_string(), mailbox);
+        Ok(())
+    }
+
+    pub fn remove_mailbox(&self, route: &str) -> bool {
+        self.mailboxes.lock().unwrap().remove(route).is_some()
+    }
+
+    /// Open a stream to `host:port` through the proxy
+    async fn connect(&self, host: &str, port: u16) -> Result<TcpStream> {
+        let mut stream = self.greet().await?;
+
+        let mut request = vec![SOCKS_VERSION, SOCKS_CONNECT, 0, SOCKS_ATYP_DOMAIN, host.len() as u8];
+        request.extend_from_slice(host.as_bytes());
+        request.extend_from_slice(&port.to_be_bytes());
+        stream.write_all(&request).await?;
+
+        let mut reply = [0u8; 4];
+        stream.read_exact(&mut reply).await?;
+        if reply[0] != SOCKS_VERSION || reply[1] != 0 {
+            return Err(UndergroundError::Veilid(format!("Tor proxy could not connect (code {})", reply[1])));
+        }
+        // Skip the bound address the proxy reports
+        let address_len = match reply[3] {
+            SOCKS_ATYP_IPV4 => 4,
+            SOCKS_ATYP_IPV6 => 16,
+            SOCKS_ATYP_DOMAIN => stream.read_u8().await? as usize,
+            _ => return Err(UndergroundError::Veilid("Tor proxy sent an invalid reply".to_string())),
+        };
+        let mut bound = vec![0u8; address_len + 2];
+        stream.read_exact(&mut bound).await?;
+        Ok(stream)
+    }
+
+    /// Connect to the proxy and agree on no authentication
+    async fn greet(&self) -> Result<TcpStream> {
+        let mut stream = TcpStream::connect(self.proxy).await?;
+        stream.write_all(&[SOCKS_VERSION, 1, SOCKS_NO_AUTH]).await?;
+        let mut reply = [0u8; 2];
+        stream.read_exact(&mut reply).await?;
+        if reply != [SOCKS_VERSION, SOCKS_NO_AUTH] {
+            return Err(UndergroundError::Veilid("Tor proxy requires unsupported authentication".to_string()));
+        }
+        Ok(stream)
+    }
+
+    /// POST one envelope to a mailbox, succeeding on any 2xx answer
+    async fn post(&self, mailbox: &OnionMailbox, message: &[u8]) -> Result<()> {
+        let mut stream = self.connect(&mailbox.host, mailbox.port).await?;
+        let head = format!(
+            "POST /mailbox/{} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/octet-stream\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n",
+            mailbox.id,
+            mailbox.host,
+            message.len()
+        );
+        stream.write_all(head.as_bytes()).await?;
+        stream.write_all(message).await?;
+
+        let mut status = String::new();
+        BufReader::new(stream).take(MAX_STATUS_LINE).read_line(&mut status).await?;
+        let code = status.split_whitespace().nth(1).unwrap_or_default();
+        if !status.starts_with("HTTP/1.") || !code.starts_with('2') {
+            return Err(UndergroundError::Veilid(format!("Onion mailbox refused the message: {}", status.trim())));
+        }
+        Ok(())
+    }
+}
+
+impl Default for TorTransport {
+    fn default() -> Self {
+        Self::new(DEFAULT_SOCKS_ADDR.parse().expect("valid default proxy address"))
+    }
+}
+
+impl Transport for TorTransport {
+    fn name(&self) -> &'static str {
+        "tor"
+    }
+
+    /// Attaches when a Tor client answers on the proxy port
+    fn attach(&self) -> BoxFuture<'_, Result<bool>> {
+        Box::pin(async move {
+            match self.greet().await {
+                Ok(_) => Ok(true),
+                Err(e) => {
+                    tracing::debug!("No Tor proxy at {}: {}", self.proxy, e);
+                    Ok(false)
+                }
+            }
+        })
+    }
+
+    fn detach(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(futures::future::ready(Ok(())))
+    }
+
+    fn send(&self, route: &str, message: Vec<u8>, _safety: SafetyProfile) -> BoxFuture<'_, Result<()>> {
+        let mailbox = self.mailboxes.lock().unwrap().get(route).cloned();
+        let missing = || UndergroundError::RecordNotFound {
+            entity: "Onion mailbox".to_string(),
+            id: route.to_string(),
+        };
+        let mailbox = mailbox.ok_or_else(missing);
+        Box::pin(async move {
+            let mailbox = mailbox?;
+            tokio::time::timeout(SEND_TIMEOUT, self.post(&mailbox, &message))
+                .await
+                .map_err(|_| UndergroundError::Veilid("Onion mailbox timed out".to_string()))?
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    const ONION: &str = "abcdefghijklmnopqrstuvwxyz234567abcdefghijklmnopqrstuvwx.onion";
+
+    /// Minimal SOCKS5 proxy that is also the mailbox; returns what was posted
+    async fn serve_once(listener: TcpListener) -> (String, Vec<u8>) {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut greeting = [0u8; 3];
+        stream.read_exact(&mut greeting).await.unwrap();
+        stream.write_all(&[SOCKS_VERSION, SOCKS_NO_AUTH]).await.unwrap();
+
+        let mut head = [0u8; 5];
+        stream.read_exact(&mut head).await.unwrap();
+        let mut host = vec![0u8; head[4] as usize + 2];
+        stream.read_exact(&mut host).await.unwrap();
+        host.truncate(host.len() - 2);
+        stream.write_all(&[SOCKS_VERSION, 0, 0, SOCKS_ATYP_IPV4, 0, 0, 0, 0, 0, 0]).await.unwrap();
+
+        let mut reader = BufReader::new(stream);
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).await.unwrap();
+            if let Some(value) = line.strip_prefix("Content-Length: ") {
+                length = value.trim().parse().unwrap();
+            }
+            if line == "\r\n" {
+                break;
+            }
+        }
+        let mut body = vec![0u8; length];
+        reader.read_exact(&mut body).await.unwrap();
+        reader.get_mut().write_all(b"HTTP/1.1 204 No Content\r\n\r\n").await.unwrap();
+        (String::from_utf8(host).unwrap(), body)
+    }
+
+    #[test]
+    fn test_mailbox_addresses_are_validated() {
+        let mailbox = OnionMailbox::parse(&format!("{}:8080/box-1", ONION)).unwrap();
+        assert_eq!((mailbox.host.as_str(), mailbox.port, mailbox.id.as_str()), (ONION, 8080, "box-1"));
+        assert_eq!(OnionMailbox::parse(&format!("{}/box", ONION)).unwrap().port, 80);
+
+        assert!(OnionMailbox::parse("example.com/box").is_err());
+        assert!(OnionMailbox::parse("short.onion/box").is_err());
+        assert!(OnionMailbox::parse(&format!("{}/", ONION)).is_err());
+        assert!(OnionMailbox::parse(&format!("{}/../etc", ONION)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_envelopes_posted_through_the_proxy() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tor = TorTransport::new(listener.local_addr().unwrap());
+        let server = tokio::spawn(serve_once(listener));
+
+        let safety = SafetyProfile::routine();
+        assert!(tor.send("VLD1:route:aa", vec![1], safety).await.is_err(), "no mailbox yet");
+        tor.set_mailbox("VLD1:route:aa", &format!("{}/box-1", ONION)).unwrap();
+        tor.send("VLD1:route:aa", vec![1, 2, 3], safety).await.unwrap();
+
+        // The onion name reaches the proxy unresolved
+        assert_eq!(server.await.unwrap(), (ONION.to_string(), vec![1, 2, 3]));
+    }
+
+    #[tokio::test]
+    async fn test_attach_needs_a_proxy() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        assert!(!TorTransport::new(addr).attach().await.unwrap());
+    }
+}
@@ -0,0 +1,184 @@
+// Network transport behind VeilidManager
+// Builds with the `network` feature talk to Veilid; builds without it use
+// NoopTransport, which never attaches so messages stay in the outbox.
+// FallbackTransport picks the first of several transports that can attach;
+// with the `tor` feature the manager falls back to onion mailboxes (tor.rs)
+
+use crate::error::{Result, UndergroundError};
+use crate::safety::SafetyProfile;
+use futures::future::{self, BoxFuture};
+use std::sync::{Arc, Mutex};
+
+/// Moves app messages between private routes
+pub trait Transport: Send + Sync {
+    /// Short name for logs and diagnostics
+    fn name(&self) -> &'static str;
+
+    /// Join the network; Ok(false) means this transport cannot attach
+    fn attach(&self) -> BoxFuture<'_, Result<bool>>;
+
+    fn detach(&self) -> BoxFuture<'_, Result<()>>;
+
+    /// Send one app message to a remote private route
+    fn send(&self, route: &str, message: Vec<u8>, safety: SafetyProfile) -> BoxFuture<'_, Result<()>>;
+}
+
+/// Transport for this build: Veilid with the `network` feature, otherwise none
+pub fn default_transport() -> Arc<dyn Transport> {
+    #[cfg(feature = "network")]
+    return Arc::new(VeilidTransport);
+    #[cfg(not(feature = "network"))]
+    return Arc::new(NoopTransport);
+}
+
+/// Veilid routing context
+#[cfg(feature = "network")]
+pub struct VeilidTransport;
+
+#[cfg(feature = "network")]
+impl Transport for VeilidTransport {
+    fn name(&self) -> &'static str {
+        "veilid"
+    }
+
+    fn attach(&self) -> BoxFuture<'_, Result<bool>> {
+        // TODO: Real implementation calls VeilidAPI::attach()
+        // and reports progress through VeilidUpdate::Attachment
+        Box::pin(future::ready(Ok(true)))
+    }
+
+    fn detach(&self) -> BoxFuture<'_, Result<()>> {
+        // TODO: Real implementation calls VeilidAPI::detach()
+        Box::pin(future::ready(Ok(())))
+    }
+
+    fn send(&self, _route: &str, _message: Vec<u8>, _safety: SafetyProfile) -> BoxFuture<'_, Result<()>> {
+        // TODO: Real implementation:
+        // 1. Import the remote private route
+        // 2. Send an app message on a routing context with_safety(safety)
+        Box::pin(future::ready(Ok(())))
+    }
+}
+
+/// Transport for builds without networking
+pub struct NoopTransport;
+
+impl Transport for NoopTransport {
+    fn name(&self) -> &'static str {
+        "none"
+    }
+
+    fn attach(&self) -> BoxFuture<'_, Result<bool>> {
+        Box::pin(future::ready(Ok(false)))
+    }
+
+    fn detach(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(future::ready(Ok(())))
+    }
+
+    fn send(&self, _route: &str, _message: Vec<u8>, _safety: SafetyProfile) -> BoxFuture<'_, Result<()>> {
+        Box::pin(future::ready(Err(UndergroundError::Veilid(
+            "Networking is not included in this build".to_string(),
+        ))))
+    }
+}
+
+/// Tries transports in order of preference and sends through the first that
+/// attaches (e.g. Veilid, then a censorship-resistant fallback)
+pub struct FallbackTransport {
+    transports: Vec<Arc<dyn Transport>>,
+    active: Mutex<Option<usize>>,
+}
+
+impl FallbackTransport {
+    pub fn new(transports: Vec<Arc<dyn Transport>>) -> Self {
+        Self {
+            transports,
+            active: Mutex::new(None),
+        }
+    }
+
+    fn active(&self) -> Option<Arc<dyn Transport>> {
+        let active = *self.active.lock().unwrap();
+        active.map(|i| self.transports[i].clone())
+    }
+}
+
+impl Transport for FallbackTransport {
+    /// Name of the transport in use, or "fallback" while none is attached
+    fn name(&self) -> &'static str {
+        self.active().map_or("fallback", |t| t.name())
+    }
+
+    fn attach(&self) -> BoxFuture<'_, Result<bool>> {
+        Box::pin(async move {
+            for (i, transport) in self.transports.iter().enumerate() {
+                match transport.attach().await {
+                    Ok(true) => {
+                        *self.active.lock().unwrap() = Some(i);
+                        return Ok(true);
+                    }
+                    Ok(false) => {}
+                    Err(e) => tracing::warn!("Transport {} failed to attach: {}", transport.name(), e),
+                }
+            }
+            Ok(false)
+        })
+    }
+
+    fn detach(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            let active = self.active.lock().unwrap().take();
+            match active {
+                Some(i) => self.transports[i].detach().await,
+                None => Ok(()),
+            }
+        })
+    }
+
+    fn send(&self, route: &str, message: Vec<u8>, safety: SafetyProfile) -> BoxFuture<'_, Result<()>> {
+        let route = route.to_string();
+        Box::pin(async move {
+            match self.active() {
+                Some(transport) => transport.send(&route, message, safety).await,
+                None => Err(UndergroundError::NetworkDetached),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::veilid_manager::{AttachmentState, VeilidManager};
+
+    #[tokio::test]
+    async fn test_noop_transport_queues_messages() {
+        let manager = VeilidManager::with_transport(Arc::new(NoopTransport));
+        let tmp = crate::util::TempDir::new("noop");
+        manager.initialize(tmp.path_string()).await.unwrap();
+
+        assert_eq!(manager.attachment_state().await, AttachmentState::Detached);
+        manager.send_via_private_route("VLD1:route:remote", vec![1]).await.unwrap();
+        assert_eq!(manager.outbox_len().await, 1);
+        assert_eq!(manager.flush_outbox().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_fallback_uses_first_transport_that_attaches() {
+        let mesh = Arc::new(crate::mesh::MeshTransport::new());
+        let fallback = FallbackTransport::new(vec![Arc::new(NoopTransport), mesh.clone()]);
+        assert!(!fallback.attach().await.unwrap());
+        assert!(fallback.send("VLD1:route:aa", vec![1], SafetyProfile::urgent()).await.is_err());
+
+        mesh.set_peer_count(1);
+        mesh.set_link_key(&[1u8; 32]).unwrap();
+        assert!(fallback.attach().await.unwrap());
+        assert_eq!(fallback.name(), "mesh");
+        fallback.send("VLD1:route:aa", vec![1], SafetyProfile::urgent()).await.unwrap();
+        assert_eq!(mesh.take_outgoing(10).len(), 1);
+
+        fallback.detach().await.unwrap();
+        assert_eq!(fallback.name(), "fallback");
+    }
+}
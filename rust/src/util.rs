@@ -0,0 +1,50 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Current Unix time in seconds
+#[cfg(not(target_arch = "wasm32"))]
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Current Unix time in seconds (SystemTime is unavailable in the browser)
+#[cfg(target_arch = "wasm32")]
+pub fn unix_now() -> u64 {
+    (js_sys::Date::now() / 1000.0) as u64
+}
+
+/// Scratch directory unique to one test, removed when dropped
+#[cfg(test)]
+pub(crate) struct TempDir(std::path::PathBuf);
+
+#[cfg(test)]
+impl TempDir {
+    pub(crate) fn new(prefix: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "urr-{}-{}",
+            prefix,
+            hex::encode(crate::crypto::generate_random_bytes(8))
+        ));
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    pub(crate) fn path(&self) -> &std::path::Path {
+        &self.0
+    }
+
+    /// The path as the String most initializers take
+    pub(crate) fn path_string(&self) -> String {
+        self.0.to_string_lossy().to_string()
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
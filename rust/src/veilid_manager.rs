@@ -1,52 +1,243 @@
 use crate::error::{Result, UndergroundError};
+use crate::ack::{decode_ack, encode_ack, AckTracker, DeliveryPath, Escalation};
 use crate::api::VeilidIdentityData;
+use crate::blocklist::Blocklist;
+use crate::bootstrap_cache::BootstrapCache;
+use crate::chunking::{self, ChunkManifest, Reassembler};
+use crate::config::{NetworkProfile, NetworkTuning, VeilidConfig};
+use crate::crypto::SecureBuffer;
+use crate::metrics::{MetricsSnapshot, NetworkMetrics};
+use crate::inbox::{Inbox, InboxMessage};
+use crate::outbox::{Outbox, OutboxEntry};
+use crate::relay::{peel_onion, wrap_onion, RelayHop, RelayLayer};
+use crate::relay_cache::{RelayCache, RelayCacheConfig};
+use crate::safety::SafetyProfile;
+use crate::shaper::{ShaperConfig, TrafficShaper, Urgency};
+use crate::mesh::{frame_id, MeshFrame, MeshFrameOutcome, MeshTransport};
+use crate::transport::{default_transport, Transport};
+#[cfg(feature = "tor")]
+use crate::tor::TorTransport;
+use serde::Serialize;
+use futures::stream::{self, Stream, StreamExt};
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use std::collections::HashMap;
+use tokio::sync::{broadcast, RwLock};
+use tokio::task::JoinHandle;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Capacity of the event broadcast channel
+const EVENT_CHANNEL_CAPACITY: usize = 64;
+
+/// Chunk subkeys fetched at once when reassembling a payload
+const MAX_CONCURRENT_CHUNK_FETCHES: usize = 8;
+
+/// How often the manager releases shaped sends that are due
+const SHAPER_TICK_SECS: u64 = 5;
+
+/// Network attachment state (mirrors Veilid's AttachmentState)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum AttachmentState {
+    Detached,
+    Attaching,
+    AttachedWeak,
+    AttachedGood,
+    AttachedStrong,
+    FullyAttached,
+    OverAttached,
+    Detaching,
+}
+
+impl AttachmentState {
+    /// Whether the node can currently reach the network
+    pub fn is_attached(&self) -> bool {
+        matches!(
+            self,
+            Self::AttachedWeak
+                | Self::AttachedGood
+                | Self::AttachedStrong
+                | Self::FullyAttached
+                | Self::OverAttached
+        )
+    }
+}
+
+/// Events derived from Veilid updates
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum VeilidEvent {
+    /// Attachment state changed
+    Attachment(AttachmentState),
+    /// A private route is no longer usable
+    RouteDied(String),
+    /// Network was reset, routes and records must be re-established
+    NetworkReset,
+    /// A message arrived on one of our private routes and is in the inbox with this id
+    MessageReceived(u64),
+    /// A message was queued because the node is detached
+    MessageQueued(String),
+    /// A message was handed to the network
+    MessageSent(String),
+    /// A watched DHT record (e.g. a mailbox) has a new value
+    ValueChanged(String),
+    /// The recipient acknowledged a tracked message
+    Acknowledged(String),
+    /// No delivery path produced an ACK
+    Unreachable { message_id: String, contact: String },
+}
+
+/// Why a DHT record is watched; one record can be watched for several
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WatchTopic {
+    /// A persona's mailbox
+    Mailbox,
+    /// Any other record a feature follows
+    Record,
+}
 
 /// Veilid manager for handling lifecycle and operations
 /// Note: This is a simplified implementation for development
 /// Full Veilid integration requires proper VeilidAPI setup
+/// Cloning yields another handle to the same shared state
+#[derive(Clone)]
 pub struct VeilidManager {
     initialized: Arc<RwLock<bool>>,
     config_dir: Arc<RwLock<Option<String>>>,
+    /// Per-profile key sealing stores that hold secrets (see profile_key.rs)
+    storage_key: Arc<RwLock<Option<Arc<SecureBuffer>>>>,
+    config: Arc<RwLock<VeilidConfig>>,
+    bootstrap_cache: Arc<RwLock<BootstrapCache>>,
     identities: Arc<RwLock<HashMap<String, VeilidIdentityData>>>,
     dht_store: Arc<RwLock<HashMap<String, Vec<u8>>>>,
+    dht_published: Arc<RwLock<HashMap<String, u64>>>,
     private_routes: Arc<RwLock<HashMap<String, Vec<u8>>>>,
+    outbox: Arc<RwLock<Outbox>>,
+    inbox: Arc<RwLock<Inbox>>,
+    /// Routes of contacts with an unresolved key change, and whose they are
+    pin_holds: Arc<RwLock<HashMap<String, String>>>,
+    relay_cache: Arc<RwLock<RelayCache>>,
+    acks: Arc<RwLock<AckTracker>>,
+    metrics: Arc<RwLock<NetworkMetrics>>,
+    blocklist: Arc<RwLock<Blocklist>>,
+    attachment: Arc<RwLock<AttachmentState>>,
+    events: broadcast::Sender<VeilidEvent>,
+    transport: Arc<dyn Transport>,
+    mesh: Arc<MeshTransport>,
+    /// Fallback behind the primary transport when bootstraps are blocked
+    #[cfg(feature = "tor")]
+    tor: Arc<TorTransport>,
+    shaper: Arc<RwLock<TrafficShaper>>,
+    /// Releases held shaped sends while the manager is initialized
+    shaper_timer: Arc<std::sync::Mutex<Option<JoinHandle<()>>>>,
+    watches: Arc<RwLock<HashMap<String, HashSet<WatchTopic>>>>,
 }
 
 impl VeilidManager {
     pub fn new() -> Self {
+        #[cfg(feature = "tor")]
+        {
+            let tor = Arc::new(TorTransport::default());
+            let strategy = crate::transport::FallbackTransport::new(vec![default_transport(), tor.clone()]);
+            Self {
+                tor,
+                ..Self::with_transport(Arc::new(strategy))
+            }
+        }
+        #[cfg(not(feature = "tor"))]
+        Self::with_transport(default_transport())
+    }
+
+    /// Manager that sends through a specific transport
+    pub fn with_transport(transport: Arc<dyn Transport>) -> Self {
         Self {
             initialized: Arc::new(RwLock::new(false)),
             config_dir: Arc::new(RwLock::new(None)),
+            storage_key: Arc::new(RwLock::new(None)),
+            config: Arc::new(RwLock::new(VeilidConfig::default())),
+            bootstrap_cache: Arc::new(RwLock::new(BootstrapCache::default())),
             identities: Arc::new(RwLock::new(HashMap::new())),
             dht_store: Arc::new(RwLock::new(HashMap::new())),
+            dht_published: Arc::new(RwLock::new(HashMap::new())),
             private_routes: Arc::new(RwLock::new(HashMap::new())),
+            outbox: Arc::new(RwLock::new(Outbox::new())),
+            inbox: Arc::new(RwLock::new(Inbox::default())),
+            pin_holds: Arc::new(RwLock::new(HashMap::new())),
+            relay_cache: Arc::new(RwLock::new(RelayCache::default())),
+            acks: Arc::new(RwLock::new(AckTracker::default())),
+            metrics: Arc::new(RwLock::new(NetworkMetrics::new())),
+            blocklist: Arc::new(RwLock::new(Blocklist::default())),
+            attachment: Arc::new(RwLock::new(AttachmentState::Detached)),
+            events: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+            transport,
+            mesh: Arc::new(MeshTransport::new()),
+            #[cfg(feature = "tor")]
+            tor: Arc::new(TorTransport::default()),
+            shaper: Arc::new(RwLock::new(TrafficShaper::default())),
+            shaper_timer: Arc::new(std::sync::Mutex::new(None)),
+            watches: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
-    /// Initialize Veilid with config
+    /// Initialize Veilid with default config
     pub async fn initialize(&self, config_dir: String) -> Result<()> {
+        self.initialize_with_config(config_dir, VeilidConfig::default()).await
+    }
+
+    /// Initialize Veilid with config
+    pub async fn initialize_with_config(&self, config_dir: String, config: VeilidConfig) -> Result<()> {
         let mut is_init = self.initialized.write().await;
         if *is_init {
             return Ok(());
         }
 
+        config.validate()?;
+
+        // Load peers from previous runs; the cache is only a hint, so a
+        // corrupt one is dropped rather than blocking startup
+        if config.use_bootstrap_cache {
+            *self.bootstrap_cache.write().await = BootstrapCache::load(Path::new(&config_dir)).unwrap_or_else(|e| {
+                tracing::warn!("Ignoring unreadable bootstrap cache: {}", e);
+                BootstrapCache::default()
+            });
+        }
+
+        // A corrupt blocklist is reported but must not keep the node offline
+        *self.blocklist.write().await = Blocklist::load(Path::new(&config_dir)).unwrap_or_else(|e| {
+            tracing::warn!("Ignoring unreadable blocklist: {}", e);
+            Blocklist::default()
+        });
+
+        // Received messages and queued or shaped sends survive a restart. A
+        // corrupt file is set aside; one that cannot be read stops startup
+        // rather than being overwritten by the next save
+        *self.inbox.write().await = Inbox::load_or_set_aside(Path::new(&config_dir))?;
+        *self.outbox.write().await = Outbox::load_or_set_aside(Path::new(&config_dir))?;
+
+        // Sends still waiting for an ACK keep escalating after a restart
+        let storage_key = Arc::new(crate::profile_key::load_or_create(Path::new(&config_dir))?);
+        *self.acks.write().await = AckTracker::load(Path::new(&config_dir), &storage_key).unwrap_or_else(|e| {
+            tracing::warn!("Ignoring unreadable ACK tracker: {}", e);
+            AckTracker::default()
+        });
+
         // Store config directory
-        let mut config = self.config_dir.write().await;
-        *config = Some(config_dir.clone());
+        *self.config_dir.write().await = Some(config_dir.clone());
+        *self.storage_key.write().await = Some(storage_key);
+        *self.config.write().await = config;
 
         // TODO: Real Veilid initialization would happen here:
         // 1. Create VeilidConfig with paths (protected, block, table stores)
         // 2. Set network configuration (ports, protocols)
-        // 3. Configure bootstrap nodes
+        // 3. Configure bootstrap nodes from bootstrap_nodes()
         // 4. Initialize VeilidAPI
         // 5. Attach network
 
         // For now, mark as initialized for development
         *is_init = true;
-        Ok(())
+        drop(is_init);
+
+        self.start_shaper_timer();
+
+        self.attach().await
     }
 
     /// Shutdown Veilid
@@ -56,17 +247,275 @@ impl VeilidManager {
         // 2. Shutdown VeilidAPI
         // 3. Clean up resources
 
+        // Teardown always runs to the end; a failed detach is reported after
+        // Held shaped sends stay in the saved outbox for the next start
+        if let Some(timer) = self.shaper_timer.lock().unwrap_or_else(|e| e.into_inner()).take() {
+            timer.abort();
+        }
+        let detached = self.detach().await;
+        if let Err(e) = self.save_bootstrap_cache().await {
+            tracing::warn!("Could not save bootstrap cache: {}", e);
+        }
+
         let mut is_init = self.initialized.write().await;
         *is_init = false;
 
+        detached
+    }
+
+    /// Attach to the network
+    pub async fn attach(&self) -> Result<()> {
+        if !self.is_initialized().await {
+            return Err(UndergroundError::NotInitialized);
+        }
+
+        self.set_attachment_state(AttachmentState::Attaching).await;
+        let state = match self.transport.attach().await {
+            Ok(true) => AttachmentState::AttachedGood,
+            Ok(false) => {
+                tracing::info!("Transport {} cannot attach; staying offline", self.transport.name());
+                AttachmentState::Detached
+            }
+            Err(e) => {
+                self.set_attachment_state(AttachmentState::Detached).await;
+                return Err(e);
+            }
+        };
+        self.set_attachment_state(state).await;
         Ok(())
     }
 
+    /// Detach from the network (messages are queued until re-attached)
+    pub async fn detach(&self) -> Result<()> {
+        if !self.attachment_state().await.is_attached() {
+            return Ok(());
+        }
+
+        self.set_attachment_state(AttachmentState::Detaching).await;
+        let result = self.transport.detach().await;
+        self.set_attachment_state(AttachmentState::Detached).await;
+        result
+    }
+
     /// Check if initialized
     pub async fn is_initialized(&self) -> bool {
         *self.initialized.read().await
     }
 
+    /// Current network configuration
+    pub async fn config(&self) -> VeilidConfig {
+        self.config.read().await.clone()
+    }
+
+    /// Tunables for the active network profile
+    pub async fn network_tuning(&self) -> NetworkTuning {
+        self.config.read().await.tuning()
+    }
+
+    /// Switch network profile (applies to new routes and refresh cycles)
+    pub async fn set_network_profile(&self, profile: NetworkProfile) {
+        self.config.write().await.network_profile = profile;
+    }
+
+    /// Effective bootstrap list: user nodes, cached peers, then defaults
+    pub async fn bootstrap_nodes(&self) -> Vec<String> {
+        let cached = self.bootstrap_cache.read().await.addresses();
+        self.config.read().await.bootstrap_list(&cached)
+    }
+
+    /// Remember a peer for faster bootstrap on the next run
+    pub async fn record_peer(&self, address: &str) -> Result<()> {
+        if !self.config.read().await.use_bootstrap_cache {
+            return Ok(());
+        }
+
+        self.bootstrap_cache
+            .write()
+            .await
+            .record_peer(address, crate::util::unix_now());
+        self.save_bootstrap_cache().await
+    }
+
+    async fn save_bootstrap_cache(&self) -> Result<()> {
+        if !self.config.read().await.use_bootstrap_cache {
+            return Ok(());
+        }
+
+        if let Some(dir) = self.config_dir.read().await.as_ref() {
+            self.bootstrap_cache.read().await.save(Path::new(dir))?;
+        }
+        Ok(())
+    }
+
+    /// Current network attachment state
+    pub async fn attachment_state(&self) -> AttachmentState {
+        *self.attachment.read().await
+    }
+
+    /// Check if attached to the network
+    pub async fn is_attached(&self) -> bool {
+        self.attachment_state().await.is_attached()
+    }
+
+    /// Subscribe to Veilid events
+    pub fn subscribe(&self) -> broadcast::Receiver<VeilidEvent> {
+        self.events.subscribe()
+    }
+
+    /// Veilid events as an async stream
+    /// Events missed by a slow consumer are skipped rather than ending the stream
+    pub fn event_stream(&self) -> impl Stream<Item = VeilidEvent> {
+        stream::unfold(self.subscribe(), |mut rx| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(event) => return Some((event, rx)),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+
+    /// Handle a route change update from Veilid
+    pub async fn handle_route_change(&self, dead_routes: Vec<String>) {
+        let mut routes = self.private_routes.write().await;
+        let mut metrics = self.metrics.write().await;
+        for route in dead_routes {
+            routes.remove(&route);
+            metrics.forget_route(&route);
+            self.emit(VeilidEvent::RouteDied(route));
+        }
+    }
+
+    /// Handle a network reset (e.g. public address change)
+    pub async fn handle_network_reset(&self) {
+        // Private routes are bound to the old network state
+        self.private_routes.write().await.clear();
+        self.emit(VeilidEvent::NetworkReset);
+    }
+
+    /// Handle an app message update from Veilid
+    pub async fn handle_app_message(&self, message: Vec<u8>) {
+        // ACK frames are consumed here and never reach the app as messages
+        if let Some((id, tag)) = decode_ack(&message) {
+            let mut acks = self.acks.write().await;
+            if !acks.acknowledge(&id, &tag) {
+                tracing::debug!("Ignoring ACK for {} that no pending send accepts", id);
+                return;
+            }
+            if let Err(e) = self.save_acks(&acks).await {
+                tracing::warn!("Could not save ACK tracker: {}", e);
+            }
+            drop(acks);
+            self.emit(VeilidEvent::Acknowledged(id));
+            return;
+        }
+        let id = match self.store_received(message).await {
+            Ok(id) => id,
+            Err(e) => {
+                tracing::error!("Could not store received message: {}", e);
+                return;
+            }
+        };
+        self.emit(VeilidEvent::MessageReceived(id));
+    }
+
+    /// Keep a received message in the inbox before anyone is told about it
+    async fn store_received(&self, message: Vec<u8>) -> Result<u64> {
+        let mut inbox = self.inbox.write().await;
+        let (id, kept_all) = inbox.push(message, crate::util::unix_now());
+        if !kept_all {
+            tracing::warn!("Inbox full, dropped the oldest unread message");
+        }
+        if let Some(dir) = self.config_dir.read().await.as_ref() {
+            inbox.save(Path::new(dir))?;
+        }
+        Ok(id)
+    }
+
+    /// Received messages with an id after `after`, or all of them for None
+    pub async fn inbox_messages(&self, after: Option<u64>) -> Vec<InboxMessage> {
+        self.inbox.read().await.since(after)
+    }
+
+    /// Remove processed messages up to and including `id`
+    pub async fn remove_inbox_messages(&self, id: u64) -> Result<usize> {
+        let mut inbox = self.inbox.write().await;
+        let removed = inbox.remove_through(id);
+        if let Some(dir) = self.config_dir.read().await.as_ref() {
+            inbox.save(Path::new(dir))?;
+        }
+        Ok(removed)
+    }
+
+    /// Watch a DHT record for value changes on behalf of `topic`
+    /// Returns true if this started a new watch on the record
+    pub async fn watch_record(&self, key: &str, topic: WatchTopic) -> bool {
+        // TODO: Real implementation calls RoutingContext::watch_dht_values() for a new watch
+        let mut watches = self.watches.write().await;
+        let topics = watches.entry(key.to_string()).or_default();
+        let started = topics.is_empty();
+        topics.insert(topic);
+        started
+    }
+
+    /// Stop watching a record for `topic`; the watch ends with its last topic
+    /// Returns true if the record is no longer watched
+    pub async fn unwatch_record(&self, key: &str, topic: WatchTopic) -> bool {
+        let mut watches = self.watches.write().await;
+        let Some(topics) = watches.get_mut(key) else {
+            return false;
+        };
+        topics.remove(&topic);
+        if !topics.is_empty() {
+            return false;
+        }
+        // TODO: Real implementation calls RoutingContext::cancel_dht_watch()
+        watches.remove(key);
+        true
+    }
+
+    /// Topics a record is watched for (empty if it is not watched)
+    pub async fn watch_topics(&self, key: &str) -> Vec<WatchTopic> {
+        self.watches
+            .read()
+            .await
+            .get(key)
+            .map(|topics| topics.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Every watched record, sorted
+    pub async fn watched_records(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.watches.read().await.keys().cloned().collect();
+        keys.sort();
+        keys
+    }
+
+    /// Number of records watched for `topic`
+    pub async fn watch_count(&self, topic: WatchTopic) -> usize {
+        self.watches.read().await.values().filter(|t| t.contains(&topic)).count()
+    }
+
+    /// Handle a value change on a watched DHT record
+    pub async fn handle_value_change(&self, key: &str) {
+        self.emit(VeilidEvent::ValueChanged(key.to_string()));
+    }
+
+    /// Update attachment state, emitting an event on change
+    async fn set_attachment_state(&self, state: AttachmentState) {
+        let mut current = self.attachment.write().await;
+        if *current != state {
+            *current = state;
+            self.emit(VeilidEvent::Attachment(state));
+        }
+    }
+
+    fn emit(&self, event: VeilidEvent) {
+        // Sending only fails when nobody is subscribed
+        let _ = self.events.send(event);
+    }
+
     /// Create a new Veilid identity (keypair + DHT key + route)
     pub async fn create_identity(&self) -> Result<VeilidIdentityData> {
         if !self.is_initialized().await {
@@ -79,9 +528,10 @@ impl VeilidManager {
         // 3. Create private route for receiving messages
         // 4. Return VeilidIdentityData
 
-        // For now, generate placeholder data
-        let public_key = format!("VLD1:pub:{}", hex::encode(crate::crypto::generate_random_bytes(32)));
-        let secret_key = format!("VLD1:sec:{}", hex::encode(crate::crypto::generate_random_bytes(32)));
+        // For now, generate an Ed25519 keypair and placeholder DHT key and route
+        let (secret, public) = crate::crypto::generate_signing_keypair();
+        let public_key = format!("VLD1:pub:{}", hex::encode(public));
+        let secret_key = format!("VLD1:sec:{}", hex::encode(secret.as_slice()));
         let dht_key = format!("VLD1:dht:{}", hex::encode(crate::crypto::generate_random_bytes(32)));
         let route = format!("VLD1:route:{}", hex::encode(crate::crypto::generate_random_bytes(32)));
 
@@ -107,7 +557,7 @@ impl VeilidManager {
 
         // TODO: Real implementation:
         // 1. Create Veilid private route
-        // 2. Set route parameters (stability, hop count)
+        // 2. Set route parameters (stability, hop count from network_tuning())
         // 3. Return route string
 
         let route = format!("VLD1:route:{}", hex::encode(crate::crypto::generate_random_bytes(32)));
@@ -119,31 +569,89 @@ impl VeilidManager {
         Ok(route)
     }
 
+    /// Release a private route we allocated
+    pub async fn release_private_route(&self, route: &str) -> Result<()> {
+        if !self.is_initialized().await {
+            return Err(UndergroundError::NotInitialized);
+        }
+
+        // TODO: Real implementation calls VeilidAPI::release_private_route()
+        self.private_routes.write().await.remove(route);
+        Ok(())
+    }
+
     /// Store data in DHT
     pub async fn dht_set(&self, key: &str, value: Vec<u8>) -> Result<()> {
         if !self.is_initialized().await {
             return Err(UndergroundError::NotInitialized);
         }
 
+        let result = self.write_record(key, value).await;
+        self.metrics.write().await.record_dht_write(result.is_ok());
+        result
+    }
+
+    async fn write_record(&self, key: &str, value: Vec<u8>) -> Result<()> {
         // TODO: Real implementation:
         // 1. Open DHT record by key
         // 2. Write encrypted value
         // 3. Close record
         // 4. Handle replication and verification
 
+        // Veilid rejects subkey values over its size limit
+        if value.len() > chunking::MAX_SUBKEY_LEN {
+            return Err(UndergroundError::InvalidMessage("Value too large for one subkey".to_string()));
+        }
+
         // For development, use in-memory store
         let mut store = self.dht_store.write().await;
         store.insert(key.to_string(), value);
 
+        let mut published = self.dht_published.write().await;
+        published.insert(key.to_string(), crate::util::unix_now());
+
         Ok(())
     }
 
+    /// Re-publish DHT values not written for `max_age_secs`
+    /// Returns the number of records re-published
+    pub async fn republish_stale(&self, max_age_secs: u64) -> Result<usize> {
+        if !self.is_attached().await {
+            return Err(UndergroundError::NetworkDetached);
+        }
+
+        let now = crate::util::unix_now();
+        let stale: Vec<String> = self
+            .dht_published
+            .read()
+            .await
+            .iter()
+            .filter(|(_, &at)| now.saturating_sub(at) >= max_age_secs)
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for key in &stale {
+            let value = self.dht_store.read().await.get(key).cloned();
+            if let Some(value) = value {
+                self.dht_set(key, value).await?;
+            }
+        }
+
+        Ok(stale.len())
+    }
+
     /// Retrieve data from DHT
     pub async fn dht_get(&self, key: &str) -> Result<Option<Vec<u8>>> {
         if !self.is_initialized().await {
             return Err(UndergroundError::NotInitialized);
         }
 
+        let result = self.read_record(key).await;
+        self.metrics.write().await.record_dht_read(result.is_ok());
+        result
+    }
+
+    async fn read_record(&self, key: &str) -> Result<Option<Vec<u8>>> {
         // TODO: Real implementation:
         // 1. Open DHT record by key
         // 2. Read value
@@ -151,29 +659,663 @@ impl VeilidManager {
         // 4. Return decrypted value
 
         // For development, use in-memory store
-        let store = self.dht_store.read().await;
-        Ok(store.get(key).cloned())
+        Ok(self.dht_store.read().await.get(key).cloned())
+    }
+
+    /// Delete a DHT record we own
+    pub async fn dht_delete(&self, key: &str) -> Result<()> {
+        if !self.is_initialized().await {
+            return Err(UndergroundError::NotInitialized);
+        }
+
+        // TODO: Real implementation calls RoutingContext::delete_dht_record()
+        self.dht_store.write().await.remove(key);
+        self.dht_published.write().await.remove(key);
+        Ok(())
+    }
+
+    /// Store a payload too large for one subkey as a manifest plus chunks
+    pub async fn dht_set_chunked(&self, key: &str, payload: &[u8]) -> Result<usize> {
+        let (manifest, chunks) = chunking::split(payload)?;
+        // Chunks first, so a reader never sees a manifest without its data
+        for (index, chunk) in chunks.into_iter().enumerate() {
+            self.dht_set(&chunking::chunk_key(key, index), chunk).await?;
+        }
+        self.dht_set(key, manifest.encode()).await?;
+        Ok(manifest.chunk_count())
+    }
+
+    /// Fetch and verify a payload stored with dht_set_chunked
+    pub async fn dht_get_chunked(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let manifest = match self.dht_get(key).await? {
+            Some(value) => ChunkManifest::decode(&value)?,
+            None => return Ok(None),
+        };
+
+        let fetched: Vec<(usize, Result<Option<Vec<u8>>>)> = stream::iter(0..manifest.chunk_count())
+            .map(|index| async move { (index, self.dht_get(&chunking::chunk_key(key, index)).await) })
+            .buffer_unordered(MAX_CONCURRENT_CHUNK_FETCHES)
+            .collect()
+            .await;
+
+        let mut reassembler = Reassembler::new(manifest);
+        for (index, chunk) in fetched {
+            if let Some(chunk) = chunk? {
+                reassembler.add(index, chunk)?;
+            }
+        }
+        reassembler.finish().map(Some)
     }
 
-    /// Send message via private route
+    /// Send message via private route with the profile's default safety selection
+    /// While detached the message is queued and sent on reconnection
     pub async fn send_via_private_route(&self, route: &str, message: Vec<u8>) -> Result<()> {
+        let safety = SafetyProfile::from_tuning(&self.network_tuning().await);
+        self.send_with_safety(route, message, safety).await
+    }
+
+    /// Send message via private route with an explicit safety selection
+    pub async fn send_with_safety(&self, route: &str, message: Vec<u8>, safety: SafetyProfile) -> Result<()> {
         if !self.is_initialized().await {
             return Err(UndergroundError::NotInitialized);
         }
 
-        // TODO: Real implementation:
-        // 1. Parse route string
-        // 2. Create app message
-        // 3. Send via Veilid routing system
-        // 4. Handle onion routing layers
-        // 5. Wait for confirmation
+        safety.validate()?;
+        self.check_route(route).await?;
 
-        // For development, store in route's message queue
-        let mut routes = self.private_routes.write().await;
-        if let Some(messages) = routes.get_mut(route) {
-            messages.extend_from_slice(&message);
+        if !self.is_attached().await {
+            self.queue(OutboxEntry {
+                route: route.to_string(),
+                message,
+                safety,
+                queued_at: crate::util::unix_now(),
+                release_at: None,
+            })
+            .await?;
+            self.emit(VeilidEvent::MessageQueued(route.to_string()));
+            return Ok(());
+        }
+
+        self.deliver(route, message, safety).await
+    }
+
+    /// Send a message with timing jitter unless it is critical or shaping is off
+    /// Held messages wait in the saved outbox until release_shaped lets them go,
+    /// which the manager does on its own timer
+    pub async fn send_shaped(&self, route: &str, message: Vec<u8>, urgency: Urgency) -> Result<()> {
+        if !self.shaper.read().await.shapes(urgency) {
+            return self.send_via_private_route(route, message).await;
+        }
+        if !self.is_initialized().await {
+            return Err(UndergroundError::NotInitialized);
+        }
+
+        self.check_route(route).await?;
+        let safety = SafetyProfile::from_tuning(&self.network_tuning().await);
+        let now = crate::util::unix_now();
+        let release_at = self.shaper.read().await.release_at(now);
+        self.queue(OutboxEntry {
+            route: route.to_string(),
+            message,
+            safety,
+            queued_at: now,
+            release_at: Some(release_at),
+        })
+        .await
+    }
+
+    /// Change traffic shaping settings
+    pub async fn set_shaper(&self, config: ShaperConfig) {
+        self.shaper.write().await.set_config(config);
+    }
+
+    /// Release held sends that are due, returning how many were released
+    /// Everything held is released once shaping is turned off. Released sends
+    /// leave with the outbox at once while attached, or on reconnection
+    pub async fn release_shaped(&self) -> usize {
+        let max_batch = {
+            let shaper = self.shaper.read().await;
+            shaper.shapes(Urgency::Normal).then(|| shaper.max_batch())
+        };
+        let released = {
+            let mut outbox = self.outbox.write().await;
+            let released = outbox.release_held(crate::util::unix_now(), max_batch);
+            if released > 0 {
+                self.save_outbox(&outbox).await;
+            }
+            released
+        };
+
+        if released > 0 && self.is_attached().await {
+            if let Err(e) = self.flush_outbox().await {
+                tracing::warn!("Released shaped sends stay queued: {}", e);
+            }
+        }
+        released
+    }
+
+    fn start_shaper_timer(&self) {
+        let mut timer = self.shaper_timer.lock().unwrap_or_else(|e| e.into_inner());
+        if timer.is_some() {
+            return;
         }
+        let manager = self.clone();
+        *timer = Some(tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(Duration::from_secs(SHAPER_TICK_SECS));
+            loop {
+                ticker.tick().await;
+                manager.release_shaped().await;
+            }
+        }));
+    }
 
+    /// Add a message to the outbox and save it
+    async fn queue(&self, entry: OutboxEntry) -> Result<()> {
+        let mut outbox = self.outbox.write().await;
+        if !outbox.push(entry) {
+            tracing::warn!("Outbox full, dropped oldest queued message");
+        }
+        if let Some(dir) = self.config_dir.read().await.as_ref() {
+            outbox.save(Path::new(dir))?;
+        }
+        Ok(())
+    }
+
+    /// Save the outbox after a change; a failure is logged, since the
+    /// messages are still queued in memory
+    async fn save_outbox(&self, outbox: &Outbox) {
+        if let Some(dir) = self.config_dir.read().await.as_ref() {
+            if let Err(e) = outbox.save(Path::new(dir)) {
+                tracing::warn!("Could not save outbox: {}", e);
+            }
+        }
+    }
+
+    /// Send a message through a chain of trusted relays
+    /// Only the last relay learns the recipient's route, and the recipient
+    /// only sees the last relay
+    pub async fn send_via_relay(&self, hops: &[RelayHop], recipient: &RelayHop, message: Vec<u8>) -> Result<()> {
+        for hop in hops.iter().chain(std::iter::once(recipient)) {
+            self.check_route(&hop.route).await?;
+        }
+
+        let (first_route, blob) = wrap_onion(hops, recipient, &message)?;
+        self.send_via_private_route(&first_route, blob).await
+    }
+
+    /// Handle a relay blob received from a contact we share `key` with
+    /// Forwards it if we are an intermediate hop, or returns the payload if it is for us
+    pub async fn handle_relay(&self, key: &[u8], blob: &[u8]) -> Result<Option<Vec<u8>>> {
+        match peel_onion(key, blob)? {
+            RelayLayer::Forward { next_route, payload } => {
+                if self.is_route_blocked(&next_route).await {
+                    tracing::debug!("Dropping relay blob addressed to a blocked route");
+                    return Ok(None);
+                }
+                if !self.relay_cache.read().await.is_enabled() || !self.is_attached().await {
+                    self.send_via_private_route(&next_route, payload).await?;
+                    return Ok(None);
+                }
+
+                // Store-and-forward: hold the blob if the next hop is offline
+                let safety = SafetyProfile::from_tuning(&self.network_tuning().await);
+                if let Err(e) = self.deliver(&next_route, payload.clone(), safety).await {
+                    let now = crate::util::unix_now();
+                    if !self.relay_cache.write().await.hold(&next_route, payload, now) {
+                        return Err(e);
+                    }
+                    tracing::debug!("Next hop unreachable, holding relay blob: {}", e);
+                }
+                Ok(None)
+            }
+            RelayLayer::Deliver { payload } => Ok(Some(payload)),
+        }
+    }
+
+    /// Send over the first path and wait for an ACK, escalating through the
+    /// remaining paths on timeout. `conversation_key` is the key the message
+    /// was sealed under; only an ACK made with it counts. Returns the message id
+    pub async fn send_tracked(
+        &self,
+        contact: &str,
+        paths: Vec<DeliveryPath>,
+        message: Vec<u8>,
+        conversation_key: &[u8],
+    ) -> Result<String> {
+        let first = paths
+            .first()
+            .cloned()
+            .ok_or_else(|| UndergroundError::InvalidMessage("No delivery path".to_string()))?;
+        // Tracked before sending, so a full tracker refuses the send
+        let id = self.acks.write().await.track(
+            contact,
+            message.clone(),
+            conversation_key,
+            paths,
+            crate::util::unix_now(),
+        )?;
+        let sent = self.send_over(&first, message).await;
+        let mut acks = self.acks.write().await;
+        if let Err(e) = sent {
+            acks.forget(&id);
+            return Err(e);
+        }
+        self.save_acks(&acks).await?;
+        Ok(id)
+    }
+
+    /// Acknowledge a received message back to its sender with the key it was sealed under
+    pub async fn send_ack(&self, reply_route: &str, message: &[u8], conversation_key: &[u8]) -> Result<()> {
+        self.send_via_private_route(reply_route, encode_ack(conversation_key, message)?)
+            .await
+    }
+
+    async fn save_acks(&self, acks: &AckTracker) -> Result<()> {
+        let storage_key = self.storage_key.read().await.clone();
+        if let (Some(dir), Some(storage_key)) = (self.config_dir.read().await.as_ref(), storage_key) {
+            acks.save(Path::new(dir), &storage_key)?;
+        }
+        Ok(())
+    }
+
+    /// Retry or give up on sends whose ACK timed out, returning how many were retried
+    pub async fn escalate_unacknowledged(&self) -> usize {
+        let steps = {
+            let mut acks = self.acks.write().await;
+            let steps = acks.due(crate::util::unix_now());
+            if !steps.is_empty() {
+                if let Err(e) = self.save_acks(&acks).await {
+                    tracing::warn!("Could not save ACK tracker: {}", e);
+                }
+            }
+            steps
+        };
+        let mut retried = 0;
+        for step in steps {
+            match step {
+                Escalation::Retry { message_id, path, message } => {
+                    // A failed retry escalates again at the next timeout
+                    if let Err(e) = self.send_over(&path, message).await {
+                        tracing::debug!("Retry of {} failed: {}", message_id, e);
+                    }
+                    retried += 1;
+                }
+                Escalation::GiveUp { message_id, contact } => {
+                    self.emit(VeilidEvent::Unreachable { message_id, contact });
+                }
+            }
+        }
+        retried
+    }
+
+    async fn send_over(&self, path: &DeliveryPath, message: Vec<u8>) -> Result<()> {
+        match path {
+            DeliveryPath::Route(route) => self.send_via_private_route(route, message).await,
+            DeliveryPath::Relay { hops, recipient } => self.send_via_relay(hops, recipient, message).await,
+            DeliveryPath::Mailbox(key) => self.dht_set(key, message).await,
+            DeliveryPath::Mesh(route) => {
+                self.check_route(route).await?;
+                let safety = SafetyProfile::from_tuning(&self.network_tuning().await);
+                self.mesh.send(route, message, safety).await
+            }
+        }
+    }
+
+    /// Opt in or out of holding relay blobs for offline next hops
+    pub async fn set_relay_cache(&self, config: RelayCacheConfig) {
+        self.relay_cache.write().await.set_config(config);
+    }
+
+    /// Number of relay blobs held for offline next hops
+    pub async fn relay_cache_len(&self) -> usize {
+        self.relay_cache.read().await.len()
+    }
+
+    /// Retry held relay blobs, returning how many were forwarded
+    /// A route that still fails keeps its blobs for the next attempt
+    pub async fn retry_relay_cache(&self) -> usize {
+        if !self.is_attached().await {
+            return 0;
+        }
+
+        let safety = SafetyProfile::from_tuning(&self.network_tuning().await);
+        let mut forwarded = 0;
+        let routes = self.relay_cache.read().await.routes();
+        for route in routes {
+            let mut blobs = self.relay_cache.write().await.take(&route).into_iter();
+            if self.is_route_blocked(&route).await {
+                tracing::debug!("Dropping relay blobs held for a blocked route");
+                continue;
+            }
+            while let Some(blob) = blobs.next() {
+                if self.deliver(&route, blob.payload.clone(), safety).await.is_err() {
+                    let remaining = std::iter::once(blob).chain(blobs).collect();
+                    self.relay_cache.write().await.restore(&route, remaining);
+                    break;
+                }
+                forwarded += 1;
+            }
+        }
+        forwarded
+    }
+
+    /// Block an identity key (persisted)
+    pub async fn block_key(&self, key: [u8; 32]) -> Result<()> {
+        self.blocklist.write().await.block_key(key);
+        self.save_blocklist().await
+    }
+
+    /// Unblock an identity key
+    pub async fn unblock_key(&self, key: &[u8; 32]) -> Result<()> {
+        self.blocklist.write().await.unblock_key(key);
+        self.save_blocklist().await
+    }
+
+    /// Block a route: nothing is sent or relayed to it (persisted)
+    pub async fn block_route(&self, route: &str) -> Result<()> {
+        self.blocklist.write().await.block_route(route);
+        self.save_blocklist().await
+    }
+
+    /// Unblock a route
+    pub async fn unblock_route(&self, route: &str) -> Result<()> {
+        self.blocklist.write().await.unblock_route(route);
+        self.save_blocklist().await
+    }
+
+    pub async fn is_key_blocked(&self, key: &[u8; 32]) -> bool {
+        self.blocklist.read().await.is_key_blocked(key)
+    }
+
+    pub async fn is_route_blocked(&self, route: &str) -> bool {
+        self.blocklist.read().await.is_route_blocked(route)
+    }
+
+    /// Hold every send to these routes until the key change of the contact
+    /// each belongs to is resolved; replaces the previous holds
+    pub async fn set_pin_holds(&self, holds: HashMap<String, String>) {
+        *self.pin_holds.write().await = holds;
+    }
+
+    /// Refuse any send to a blocked route or to a contact whose keys changed
+    async fn check_route(&self, route: &str) -> Result<()> {
+        if self.is_route_blocked(route).await {
+            return Err(UndergroundError::Blocked);
+        }
+        if let Some(contact) = self.pin_holds.read().await.get(route) {
+            return Err(UndergroundError::KeyPinViolation(contact.clone()));
+        }
+        Ok(())
+    }
+
+    async fn save_blocklist(&self) -> Result<()> {
+        if let Some(dir) = self.config_dir.read().await.as_ref() {
+            self.blocklist.read().await.save(Path::new(dir))?;
+        }
+        Ok(())
+    }
+
+    /// Snapshot of network performance metrics
+    pub async fn metrics(&self) -> MetricsSnapshot {
+        self.metrics.read().await.snapshot()
+    }
+
+    /// Record a message round trip measured by the messaging layer
+    pub async fn record_round_trip(&self, elapsed: Duration) {
+        self.metrics.write().await.record_round_trip(elapsed);
+    }
+
+    /// Send all queued messages, returning how many were sent
+    /// While detached they are offered to nearby devices instead, if any are in
+    /// reach, and stay queued until one of them acknowledges the message
+    pub async fn flush_outbox(&self) -> Result<usize> {
+        if !self.is_attached().await {
+            return Ok(self.offer_outbox_to_mesh().await);
+        }
+
+        let mut sent = 0;
+        let mut held = Vec::new();
+        let mut failed = None;
+        loop {
+            let entry = match self.outbox.write().await.pop() {
+                Some(entry) => entry,
+                None => break,
+            };
+
+            match self.deliver(&entry.route, entry.message.clone(), entry.safety).await {
+                Ok(()) => sent += 1,
+                // Blocked after it was queued: it must never leave, so drop it
+                Err(UndergroundError::Blocked) => tracing::debug!("Dropping queued message for a blocked route"),
+                // Waits for the key change to be resolved without holding up the rest
+                Err(UndergroundError::KeyPinViolation(_)) => held.push(entry),
+                Err(e) => {
+                    held.push(entry);
+                    failed = Some(e);
+                    break;
+                }
+            }
+        }
+
+        self.requeue_all(held).await;
+        match failed {
+            Some(e) => Err(e),
+            None => Ok(sent),
+        }
+    }
+
+    /// Put entries back at the front of the outbox in their original order
+    /// and save what is left
+    async fn requeue_all(&self, entries: Vec<OutboxEntry>) {
+        let mut outbox = self.outbox.write().await;
+        for entry in entries.into_iter().rev() {
+            outbox.requeue(entry);
+        }
+        self.save_outbox(&outbox).await;
+    }
+
+    /// Queue outbox messages for nearby devices, returning how many were offered
+    /// Stops at the first one the mesh refuses (e.g. a full queue)
+    async fn offer_outbox_to_mesh(&self) -> usize {
+        if self.mesh.peer_count() == 0 {
+            return 0;
+        }
+
+        let entries: Vec<OutboxEntry> = self.outbox.read().await.iter().cloned().collect();
+        let mut offered = 0;
+        for entry in entries {
+            match self.check_route(&entry.route).await {
+                Ok(()) => {}
+                Err(UndergroundError::Blocked) => {
+                    tracing::debug!("Dropping queued message for a blocked route");
+                    let mut outbox = self.outbox.write().await;
+                    outbox.remove_where(|e| *e == entry);
+                    self.save_outbox(&outbox).await;
+                    continue;
+                }
+                Err(_) => continue,
+            }
+            if let Err(e) = self.mesh.send(&entry.route, entry.message, entry.safety).await {
+                tracing::debug!("Nearby devices cannot take more messages: {}", e);
+                break;
+            }
+            offered += 1;
+        }
+        offered
+    }
+
+    /// Hand queued messages to `carrier` instead of the network (e.g. a
+    /// sneakernet bundle), returning the entries it took
+    /// They stay queued until remove_carried is called once the carrier has
+    /// them safely; entries for blocked routes are dropped
+    pub async fn carry_outbox(&self, carrier: &dyn Transport) -> Vec<OutboxEntry> {
+        let entries: Vec<OutboxEntry> = self.outbox.read().await.iter().cloned().collect();
+        let mut carried = Vec::new();
+        for entry in entries {
+            match self.check_route(&entry.route).await {
+                Ok(()) => {}
+                Err(UndergroundError::Blocked) => {
+                    self.remove_carried(std::slice::from_ref(&entry)).await;
+                    continue;
+                }
+                Err(_) => continue,
+            }
+            if carrier.send(&entry.route, entry.message.clone(), entry.safety).await.is_err() {
+                break;
+            }
+            carried.push(entry);
+        }
+        carried
+    }
+
+    /// Drop outbox entries that left with a carrier
+    pub async fn remove_carried(&self, entries: &[OutboxEntry]) {
+        let mut outbox = self.outbox.write().await;
+        outbox.remove_where(|e| entries.contains(e));
+        self.save_outbox(&outbox).await;
+    }
+
+    /// Transport to nearby devices, driven by the platform's radios
+    pub fn mesh(&self) -> &MeshTransport {
+        &self.mesh
+    }
+
+    /// Onion-mailbox transport used when the primary one cannot attach
+    #[cfg(feature = "tor")]
+    pub fn tor(&self) -> &TorTransport {
+        &self.tor
+    }
+
+    /// Handle a frame heard from a nearby device
+    /// Frames for other routes are forwarded only while we are attached, so
+    /// two offline devices never pass the same frame back and forth. Messages
+    /// we delivered or forwarded are acknowledged so the sender can let go
+    pub async fn handle_mesh_frame(&self, frame: &[u8]) -> Result<MeshFrameOutcome> {
+        let (route, message) = match self.mesh.open(frame)? {
+            MeshFrame::Message { route, message } => (route, message),
+            MeshFrame::Ack(id) => {
+                self.mesh.acknowledge(&id);
+                let mut outbox = self.outbox.write().await;
+                outbox.remove_where(|e| frame_id(&e.route, &e.message) == id);
+                self.save_outbox(&outbox).await;
+                return Ok(MeshFrameOutcome::Acknowledged);
+            }
+        };
+
+        let id = frame_id(&route, &message);
+        let outcome = if self.private_routes.read().await.contains_key(&route) {
+            self.handle_app_message(message).await;
+            MeshFrameOutcome::DeliveredLocally
+        } else if self.is_attached().await {
+            self.send_via_private_route(&route, message).await?;
+            MeshFrameOutcome::Forwarded
+        } else {
+            return Ok(MeshFrameOutcome::Dropped);
+        };
+        // Without the ack the sender offers the message again later
+        if let Err(e) = self.mesh.queue_ack(id) {
+            tracing::debug!("Could not acknowledge mesh frame: {}", e);
+        }
+        Ok(outcome)
+    }
+
+    /// Number of messages waiting for the network
+    pub async fn outbox_len(&self) -> usize {
+        self.outbox.read().await.len()
+    }
+
+    /// Drop queued messages older than `max_age_secs`, or only count them on a dry run
+    pub async fn expire_outbox(&self, max_age_secs: u64, dry_run: bool) -> usize {
+        let cutoff = crate::util::unix_now().saturating_sub(max_age_secs);
+        if dry_run {
+            return self.outbox.read().await.count_before(cutoff);
+        }
+        let mut outbox = self.outbox.write().await;
+        let expired = outbox.expire_before(cutoff);
+        if expired > 0 {
+            self.save_outbox(&outbox).await;
+        }
+        expired
+    }
+
+    /// Drop received messages older than `max_age_secs`, or only count them on a dry run
+    pub async fn expire_inbox(&self, max_age_secs: u64, dry_run: bool) -> usize {
+        let cutoff = crate::util::unix_now().saturating_sub(max_age_secs);
+        if dry_run {
+            return self.inbox.read().await.count_before(cutoff);
+        }
+        let mut inbox = self.inbox.write().await;
+        let expired = inbox.expire_before(cutoff);
+        if let (true, Some(dir)) = (expired > 0, self.config_dir.read().await.as_ref()) {
+            if let Err(e) = inbox.save(Path::new(dir)) {
+                tracing::warn!("Could not save inbox: {}", e);
+            }
+        }
+        expired
+    }
+
+    /// Stop waiting for ACKs of sends older than `max_age_secs`, or only count
+    /// them on a dry run
+    pub async fn expire_acks(&self, max_age_secs: u64, dry_run: bool) -> usize {
+        let cutoff = crate::util::unix_now().saturating_sub(max_age_secs);
+        if dry_run {
+            return self.acks.read().await.count_before(cutoff);
+        }
+        let mut acks = self.acks.write().await;
+        let expired = acks.expire_before(cutoff);
+        if expired > 0 {
+            if let Err(e) = self.save_acks(&acks).await {
+                tracing::warn!("Could not save ACK tracker: {}", e);
+            }
+        }
+        expired
+    }
+
+    /// Drop relay blobs past the relay TTL, or only count them on a dry run
+    pub async fn expire_relay_cache(&self, dry_run: bool) -> usize {
+        let now = crate::util::unix_now();
+        if dry_run {
+            return self.relay_cache.read().await.count_expired(now);
+        }
+        self.relay_cache.write().await.expire(now)
+    }
+
+    /// Every send that reaches the network passes through here
+    async fn deliver(&self, route: &str, message: Vec<u8>, safety: SafetyProfile) -> Result<()> {
+        self.check_route(route).await?;
+        tracing::debug!(
+            "Sending via private route with {} hops ({:?})",
+            safety.hop_count,
+            safety.stability
+        );
+
+        let started = Instant::now();
+
+        // Our own routes loop straight back to us
+        let mut routes = self.private_routes.write().await;
+        let is_local = match routes.get_mut(route) {
+            Some(messages) => {
+                messages.extend_from_slice(&message);
+                true
+            }
+            None => false,
+        };
+        drop(routes);
+
+        let result = if is_local {
+            Ok(())
+        } else {
+            self.transport.send(route, message.clone(), safety).await
+        };
+        self.metrics
+            .write()
+            .await
+            .record_send(route, started.elapsed(), result.is_ok());
+        result?;
+
+        self.emit(VeilidEvent::MessageSent(route.to_string()));
+        if is_local {
+            self.handle_app_message(message).await;
+        }
         Ok(())
     }
 }
@@ -187,6 +1329,7 @@ impl Default for VeilidManager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use futures::future::{self, BoxFuture};
 
     #[tokio::test]
     async fn test_veilid_manager_lifecycle() {
@@ -197,4 +1340,303 @@ mod tests {
         // Note: Full initialization requires proper config
         // This is just testing the manager structure
     }
+
+    #[tokio::test]
+    async fn test_message_events() {
+        let manager = VeilidManager::new();
+        let tmp = crate::util::TempDir::new("manager");
+        manager.initialize(tmp.path_string()).await.unwrap();
+        let route = manager.create_private_route().await.unwrap();
+        let mut events = manager.subscribe();
+
+        manager.send_via_private_route(&route, vec![9]).await.unwrap();
+        assert_eq!(events.recv().await.unwrap(), VeilidEvent::MessageSent(route.clone()));
+        assert_eq!(events.recv().await.unwrap(), VeilidEvent::MessageReceived(0));
+        assert_eq!(manager.inbox_messages(None).await[0].message, vec![9]);
+
+        manager.detach().await.unwrap();
+        manager.send_via_private_route(&route, vec![10]).await.unwrap();
+        assert_eq!(
+            events.recv().await.unwrap(),
+            VeilidEvent::Attachment(AttachmentState::Detaching)
+        );
+        assert_eq!(
+            events.recv().await.unwrap(),
+            VeilidEvent::Attachment(AttachmentState::Detached)
+        );
+        assert_eq!(events.recv().await.unwrap(), VeilidEvent::MessageQueued(route));
+    }
+
+    #[tokio::test]
+    async fn test_shaped_sends_survive_restart() {
+        let tmp = crate::util::TempDir::new("shaped");
+        let shaping = ShaperConfig {
+            enabled: true,
+            min_delay_secs: 3600,
+            max_delay_secs: 3600,
+            max_batch: 16,
+        };
+        let manager = VeilidManager::new();
+        manager.initialize(tmp.path_string()).await.unwrap();
+        manager.set_shaper(shaping).await;
+        let route = manager.create_private_route().await.unwrap();
+        manager.send_shaped(&route, vec![1], Urgency::Normal).await.unwrap();
+        assert_eq!(manager.release_shaped().await, 0);
+        manager.shutdown().await.unwrap();
+
+        let restarted = VeilidManager::new();
+        restarted.initialize(tmp.path_string()).await.unwrap();
+        assert_eq!(restarted.outbox_len().await, 1);
+        // Held until released, even though we are attached
+        assert_eq!(restarted.flush_outbox().await.unwrap(), 0);
+        restarted.set_shaper(ShaperConfig::default()).await;
+        assert_eq!(restarted.release_shaped().await, 1);
+    }
+
+    /// Attaches, and fails every send while `failing` is set
+    struct FlakyTransport {
+        failing: std::sync::atomic::AtomicBool,
+    }
+
+    impl FlakyTransport {
+        fn failing() -> Arc<Self> {
+            Arc::new(Self {
+                failing: std::sync::atomic::AtomicBool::new(true),
+            })
+        }
+
+        fn recover(&self) {
+            self.failing.store(false, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    impl Transport for FlakyTransport {
+        fn name(&self) -> &'static str {
+            "flaky"
+        }
+
+        fn attach(&self) -> BoxFuture<'_, Result<bool>> {
+            Box::pin(future::ready(Ok(true)))
+        }
+
+        fn detach(&self) -> BoxFuture<'_, Result<()>> {
+            Box::pin(future::ready(Ok(())))
+        }
+
+        fn send(&self, _route: &str, _message: Vec<u8>, _safety: SafetyProfile) -> BoxFuture<'_, Result<()>> {
+            if !self.failing.load(std::sync::atomic::Ordering::SeqCst) {
+                return Box::pin(future::ready(Ok(())));
+            }
+            Box::pin(future::ready(Err(UndergroundError::Veilid("unreachable".to_string()))))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_flush_requeues_in_order() {
+        let tmp = crate::util::TempDir::new("requeue");
+        let manager = VeilidManager::with_transport(FlakyTransport::failing());
+        manager.initialize(tmp.path_string()).await.unwrap();
+        manager.detach().await.unwrap();
+        for route in ["VLD1:route:first", "VLD1:route:second"] {
+            manager.send_via_private_route(route, vec![1]).await.unwrap();
+        }
+
+        manager.attach().await.unwrap();
+        assert!(manager.flush_outbox().await.is_err());
+        assert_eq!(manager.outbox_len().await, 2);
+
+        let saved: Vec<String> = Outbox::load(tmp.path()).unwrap().iter().map(|e| e.route.clone()).collect();
+        assert_eq!(saved, ["VLD1:route:first", "VLD1:route:second"]);
+    }
+
+    #[tokio::test]
+    async fn test_held_relay_blobs_retried() {
+        let tmp = crate::util::TempDir::new("relay-retry");
+        let transport = FlakyTransport::failing();
+        let manager = VeilidManager::with_transport(transport.clone());
+        manager.initialize(tmp.path_string()).await.unwrap();
+        manager
+            .set_relay_cache(RelayCacheConfig {
+                enabled: true,
+                ..RelayCacheConfig::default()
+            })
+            .await;
+
+        let key = crate::crypto::generate_random_bytes(32);
+        let hop = RelayHop { route: "route-relay".to_string(), key: key.clone() };
+        let recipient = RelayHop { route: "VLD1:route:offline".to_string(), key: key.clone() };
+        let (_, blob) = wrap_onion(&[hop], &recipient, b"payload").unwrap();
+        assert_eq!(manager.handle_relay(&key, &blob).await.unwrap(), None);
+        assert_eq!(manager.relay_cache_len().await, 1);
+
+        // Bounded so a retry that never finishes fails the test
+        let retry = || tokio::time::timeout(Duration::from_secs(5), manager.retry_relay_cache());
+        assert_eq!(retry().await.unwrap(), 0);
+        assert_eq!(manager.relay_cache_len().await, 1);
+
+        transport.recover();
+        assert_eq!(retry().await.unwrap(), 1);
+        assert_eq!(manager.relay_cache_len().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_inbox_and_outbox_set_aside() {
+        let tmp = crate::util::TempDir::new("corrupt-stores");
+        for name in [crate::inbox::INBOX_FILE, crate::outbox::OUTBOX_FILE] {
+            std::fs::write(tmp.path().join(name), b"{not json").unwrap();
+        }
+
+        let manager = VeilidManager::with_transport(Arc::new(crate::transport::NoopTransport));
+        manager.initialize(tmp.path_string()).await.unwrap();
+        assert!(manager.inbox_messages(None).await.is_empty());
+        manager.send_via_private_route("VLD1:route:remote", vec![1]).await.unwrap();
+        assert_eq!(manager.outbox_len().await, 1);
+
+        for name in [crate::inbox::INBOX_CORRUPT_FILE, crate::outbox::OUTBOX_CORRUPT_FILE] {
+            assert_eq!(std::fs::read(tmp.path().join(name)).unwrap(), b"{not json");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_blocked_routes_not_relayed() {
+        let manager = VeilidManager::new();
+        let tmp = crate::util::TempDir::new("blocklist");
+        manager.initialize(tmp.path_string()).await.unwrap();
+
+        let key = crate::crypto::generate_random_bytes(32);
+        let hop = RelayHop { route: "route-relay".to_string(), key: key.clone() };
+        let recipient = RelayHop { route: "route-blocked".to_string(), key: key.clone() };
+        manager.block_route("route-blocked").await.unwrap();
+
+        let result = manager.send_via_relay(std::slice::from_ref(&hop), &recipient, vec![1]).await;
+        assert!(matches!(result, Err(UndergroundError::Blocked)));
+
+        let (_, blob) = wrap_onion(&[hop], &recipient, b"payload").unwrap();
+        assert_eq!(manager.handle_relay(&key, &blob).await.unwrap(), None);
+
+        manager.unblock_route("route-blocked").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_blocked_routes_refused_on_every_send() {
+        let manager = VeilidManager::new();
+        let tmp = crate::util::TempDir::new("blocklist");
+        manager.initialize(tmp.path_string()).await.unwrap();
+        let route = manager.create_private_route().await.unwrap();
+        let safety = SafetyProfile::urgent();
+        let blocked = |result: Result<_>| matches!(result, Err(UndergroundError::Blocked));
+
+        // Queued before the block, then flushed after it
+        manager.detach().await.unwrap();
+        manager.send_via_private_route(&route, vec![1]).await.unwrap();
+        manager.block_route(&route).await.unwrap();
+        manager.attach().await.unwrap();
+        assert_eq!(manager.flush_outbox().await.unwrap(), 0);
+        assert_eq!(manager.outbox_len().await, 0);
+
+        assert!(blocked(manager.send_via_private_route(&route, vec![2]).await));
+        assert!(blocked(manager.send_with_safety(&route, vec![2], safety).await));
+        assert!(blocked(manager.send_shaped(&route, vec![2], Urgency::Critical).await));
+        manager
+            .set_shaper(ShaperConfig { enabled: true, ..ShaperConfig::default() })
+            .await;
+        assert!(blocked(manager.send_shaped(&route, vec![2], Urgency::Normal).await));
+        for path in [DeliveryPath::Route(route.clone()), DeliveryPath::Mesh(route.clone())] {
+            assert!(blocked(manager.send_tracked("bob", vec![path], vec![2], &[0u8; 32]).await.map(drop)));
+        }
+
+        // A corrupt blocklist starts empty instead of failing startup
+        let corrupt = crate::util::TempDir::new("blocklist-corrupt");
+        std::fs::write(corrupt.path().join(crate::blocklist::BLOCKLIST_FILE), b"not json").unwrap();
+        let fresh = VeilidManager::new();
+        fresh.initialize(corrupt.path_string()).await.unwrap();
+        assert!(!fresh.is_route_blocked(&route).await);
+    }
+
+    #[tokio::test]
+    async fn test_mesh_messages_stay_queued_until_acknowledged() {
+        let sender = VeilidManager::new();
+        let sender_dir = crate::util::TempDir::new("mesh-sender");
+        sender.initialize(sender_dir.path_string()).await.unwrap();
+        let relay = VeilidManager::new();
+        let relay_dir = crate::util::TempDir::new("mesh-relay");
+        relay.initialize(relay_dir.path_string()).await.unwrap();
+        for manager in [&sender, &relay] {
+            manager.mesh().set_link_key(&[5u8; 32]).unwrap();
+            manager.mesh().set_peer_count(1);
+        }
+
+        sender.detach().await.unwrap();
+        sender.send_via_private_route("VLD1:route:far", vec![1]).await.unwrap();
+        assert_eq!(sender.flush_outbox().await.unwrap(), 1);
+        assert_eq!(sender.mesh().take_outgoing(10).len(), 1);
+        assert_eq!(sender.outbox_len().await, 1, "handed over but not yet acknowledged");
+
+        // Losing the radio queue does not lose the message
+        sender.mesh().detach().await.unwrap();
+        assert_eq!(sender.flush_outbox().await.unwrap(), 1);
+        let frames = sender.mesh().take_outgoing(10);
+        assert_eq!(frames.len(), 1);
+
+        assert_eq!(relay.handle_mesh_frame(&frames[0]).await.unwrap(), MeshFrameOutcome::Forwarded);
+        let acks = relay.mesh().take_outgoing(10);
+        assert_eq!(sender.handle_mesh_frame(&acks[0]).await.unwrap(), MeshFrameOutcome::Acknowledged);
+        assert_eq!(sender.outbox_len().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_bootstrap_cache_does_not_block_startup() {
+        let tmp = crate::util::TempDir::new("bootstrap-corrupt");
+        std::fs::write(tmp.path().join(crate::bootstrap_cache::CACHE_FILE), b"{").unwrap();
+
+        let manager = VeilidManager::new();
+        manager.initialize(tmp.path_string()).await.unwrap();
+        assert!(manager.is_attached().await);
+        manager.shutdown().await.unwrap();
+        assert!(!manager.is_initialized().await);
+    }
+
+    #[tokio::test]
+    async fn test_dht_metrics_record_failures() {
+        let manager = VeilidManager::new();
+        let tmp = crate::util::TempDir::new("dht-metrics");
+        manager.initialize(tmp.path_string()).await.unwrap();
+
+        manager.dht_set("VLD1:dht:aa", vec![1]).await.unwrap();
+        let oversized = vec![0u8; chunking::MAX_SUBKEY_LEN + 1];
+        assert!(manager.dht_set("VLD1:dht:aa", oversized).await.is_err());
+        assert_eq!(manager.metrics().await.dht_write_success_rate, Some(0.5));
+    }
+
+    #[tokio::test]
+    async fn test_attachment_events() {
+        let manager = VeilidManager::new();
+        let mut events = manager.subscribe();
+
+        let tmp = crate::util::TempDir::new("manager");
+        manager.initialize(tmp.path_string()).await.unwrap();
+        assert!(manager.is_attached().await);
+        assert_eq!(
+            events.recv().await.unwrap(),
+            VeilidEvent::Attachment(AttachmentState::Attaching)
+        );
+        assert_eq!(
+            events.recv().await.unwrap(),
+            VeilidEvent::Attachment(AttachmentState::AttachedGood)
+        );
+
+        let route = manager.create_private_route().await.unwrap();
+        manager.handle_route_change(vec![route.clone()]).await;
+        assert_eq!(events.recv().await.unwrap(), VeilidEvent::RouteDied(route));
+
+        manager.shutdown().await.unwrap();
+        assert_eq!(
+            events.recv().await.unwrap(),
+            VeilidEvent::Attachment(AttachmentState::Detaching)
+        );
+        assert_eq!(
+            events.recv().await.unwrap(),
+            VeilidEvent::Attachment(AttachmentState::Detached)
+        );
+    }
 }
@@ -0,0 +1,154 @@
+// Signed vouching attestations
+// "I verified X in person on date D", signed by the voucher so it can be
+// forwarded and checked against the voucher's pinned key
+
+use crate::crypto::{sign_data, signing_public_key, verify_signature};
+use crate::error::{Result, UndergroundError};
+use crate::wire::{split_signature, Reader};
+
+const MAGIC: &[u8; 3] = b"URV";
+const VERSION: u8 = 1;
+
+/// How the voucher verified the subject
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerificationMethod {
+    InPerson,
+    VideoCall,
+    SharedSecret,
+}
+
+impl VerificationMethod {
+    fn to_byte(self) -> u8 {
+        match self {
+            Self::InPerson => 0,
+            Self::VideoCall => 1,
+            Self::SharedSecret => 2,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Self::InPerson),
+            1 => Some(Self::VideoCall),
+            2 => Some(Self::SharedSecret),
+            _ => None,
+        }
+    }
+}
+
+/// A signed statement that the voucher verified the subject
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vouch {
+    pub voucher_key: [u8; 32],
+    pub subject_key: [u8; 32],
+    pub method: VerificationMethod,
+    /// When the verification happened (Unix seconds)
+    pub verified_at: u64,
+    /// When the statement was signed (Unix seconds)
+    pub issued_at: u64,
+    signature: [u8; 64],
+}
+
+impl Vouch {
+    /// Sign a vouch for a subject with our identity secret key
+    pub fn create(
+        secret_key: &[u8],
+        subject_key: [u8; 32],
+        method: VerificationMethod,
+        verified_at: u64,
+    ) -> Result<Self> {
+        let voucher_key = signing_public_key(secret_key)?;
+        if voucher_key == subject_key {
+            return Err(UndergroundError::InvalidMessage("Cannot vouch for yourself".to_string()));
+        }
+
+        let issued_at = crate::util::unix_now();
+        if verified_at > issued_at {
+            return Err(UndergroundError::InvalidMessage("Verification date is in the future".to_string()));
+        }
+
+        let mut vouch = Self {
+            voucher_key,
+            subject_key,
+            method,
+            verified_at,
+            issued_at,
+            signature: [0u8; 64],
+        };
+        vouch.signature = sign_data(secret_key, &vouch.signed_bytes())?;
+        Ok(vouch)
+    }
+
+    /// Check the vouch was signed by the key we pinned for the voucher
+    pub fn verify(&self, pinned_voucher_key: &[u8; 32]) -> Result<()> {
+        if &self.voucher_key != pinned_voucher_key {
+            return Err(UndergroundError::AuthenticationFailed);
+        }
+        verify_signature(&self.voucher_key, &self.signed_bytes(), &self.signature)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = self.signed_bytes();
+        out.extend_from_slice(&self.signature);
+        out
+    }
+
+    /// Decode a forwarded vouch and check it is self-consistently signed
+    /// Callers must still `verify` it against the voucher's pinned key
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let (body, signature) = split_signature(data, "Vouch")?;
+
+        let mut reader = Reader::new(body, "Vouch");
+        reader.header(MAGIC, VERSION)?;
+        let voucher_key = reader.array()?;
+        let subject_key = reader.array()?;
+        let method = VerificationMethod::from_byte(reader.u8()?)
+            .ok_or_else(|| reader.invalid("unknown verification method"))?;
+        let verified_at = reader.u64()?;
+        let issued_at = reader.u64()?;
+        reader.finish()?;
+
+        verify_signature(&voucher_key, body, &signature)?;
+
+        Ok(Self {
+            voucher_key,
+            subject_key,
+            method,
+            verified_at,
+            issued_at,
+            signature,
+        })
+    }
+
+    fn signed_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(85);
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.extend_from_slice(&self.voucher_key);
+        out.extend_from_slice(&self.subject_key);
+        out.push(self.method.to_byte());
+        out.extend_from_slice(&self.verified_at.to_be_bytes());
+        out.extend_from_slice(&self.issued_at.to_be_bytes());
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_signing_keypair;
+
+    #[test]
+    fn test_vouch_verifies_against_pinned_key() {
+        let (voucher_sk, voucher_pk) = generate_signing_keypair();
+        let (_, subject_pk) = generate_signing_keypair();
+        let (_, other_pk) = generate_signing_keypair();
+
+        let vouch = Vouch::create(voucher_sk.as_slice(), subject_pk, VerificationMethod::InPerson, 1_700_000_000).unwrap();
+        let forwarded = Vouch::decode(&vouch.encode()).unwrap();
+
+        assert!(forwarded.verify(&voucher_pk).is_ok());
+        assert!(forwarded.verify(&other_pk).is_err());
+        assert_eq!(forwarded.subject_key, subject_pk);
+    }
+}
@@ -0,0 +1,71 @@
+// Browser bindings for the read-only verification tool
+// Only pure parsing and signature checks are exposed; nothing here touches
+// storage or the network
+
+use crate::contact_qr;
+use crate::crypto::decode_typed_key;
+use crate::error::{Result, UndergroundError};
+use crate::revocation::BurnNotice;
+use crate::vouch::Vouch;
+use serde_json::json;
+use wasm_bindgen::prelude::*;
+
+/// Verify a scanned contact code and describe it as JSON
+#[wasm_bindgen(js_name = parseContactQr)]
+pub fn parse_contact_qr(text: &str) -> std::result::Result<String, JsValue> {
+    let card = contact_qr::decode_qr(text).map_err(to_js)?;
+    let bundle = &card.bundle;
+    Ok(json!({
+        "public_key": format!("VLD1:pub:{}", hex::encode(bundle.public_key)),
+        "fingerprint": contact_qr::fingerprint(&bundle.public_key),
+        "route": bundle.route,
+        "mailbox_key": bundle.mailbox_key,
+        "created_at": bundle.created_at,
+        "introduced_by": card
+            .chain
+            .iter()
+            .map(|v| format!("VLD1:pub:{}", hex::encode(v.voucher_key)))
+            .collect::<Vec<_>>(),
+    })
+    .to_string())
+}
+
+/// Check a vouch against the voucher's pinned key and describe it as JSON
+#[wasm_bindgen(js_name = verifyVouch)]
+pub fn verify_vouch(data: &[u8], voucher_public_key: &str) -> std::result::Result<String, JsValue> {
+    let vouch = Vouch::decode(data).map_err(to_js)?;
+    vouch.verify(&public_key_bytes(voucher_public_key).map_err(to_js)?).map_err(to_js)?;
+    Ok(json!({
+        "voucher_public_key": format!("VLD1:pub:{}", hex::encode(vouch.voucher_key)),
+        "subject_public_key": format!("VLD1:pub:{}", hex::encode(vouch.subject_key)),
+        "method": format!("{:?}", vouch.method),
+        "verified_at": vouch.verified_at,
+        "issued_at": vouch.issued_at,
+    })
+    .to_string())
+}
+
+/// Check a burn notice's signature by the issuer key it carries and describe it as JSON
+/// Whether to trust that issuer is up to the caller
+#[wasm_bindgen(js_name = verifyBurnNotice)]
+pub fn verify_burn_notice(data: &[u8]) -> std::result::Result<String, JsValue> {
+    let notice = BurnNotice::decode(data).map_err(to_js)?;
+    notice.verify(crate::util::unix_now()).map_err(to_js)?;
+    Ok(json!({
+        "issuer_public_key": format!("VLD1:pub:{}", hex::encode(notice.issuer_key)),
+        "subject_public_key": format!("VLD1:pub:{}", hex::encode(notice.subject_key)),
+        "reason": notice.reason,
+        "issued_at": notice.issued_at,
+        "max_hops": notice.max_hops,
+        "forward_until": notice.forward_until,
+    })
+    .to_string())
+}
+
+fn public_key_bytes(key: &str) -> Result<[u8; 32]> {
+    decode_typed_key(key)?.try_into().map_err(|_| UndergroundError::InvalidKey)
+}
+
+fn to_js(err: UndergroundError) -> JsValue {
+    JsValue::from_str(&err.to_string())
+}
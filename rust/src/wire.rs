@@ -0,0 +1,139 @@
+// Bounds-checked helpers for compact binary encodings of untrusted data
+
+use crate::error::{Result, UndergroundError};
+
+/// Cursor over untrusted bytes
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+    what: &'static str,
+}
+
+impl<'a> Reader<'a> {
+    /// `what` names the structure being decoded in error messages
+    pub(crate) fn new(data: &'a [u8], what: &'static str) -> Self {
+        Self { data, what }
+    }
+
+    pub(crate) fn invalid(&self, reason: &str) -> UndergroundError {
+        UndergroundError::InvalidMessage(format!("{}: {}", self.what, reason))
+    }
+
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.data.len() < len {
+            return Err(self.invalid("truncated"));
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let bytes = self.take(N)?;
+        let mut out = [0u8; N];
+        out.copy_from_slice(bytes);
+        Ok(out)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_be_bytes(self.array()?))
+    }
+
+    /// Length-prefixed (u16) bytes, at most `max_len` long
+    pub(crate) fn bytes(&mut self, max_len: usize) -> Result<&'a [u8]> {
+        let len = self.u16()? as usize;
+        if len > max_len {
+            return Err(self.invalid("field too long"));
+        }
+        self.take(len)
+    }
+
+    /// Length-prefixed (u16) UTF-8 string, at most `max_len` bytes long
+    pub(crate) fn string(&mut self, max_len: usize) -> Result<String> {
+        let bytes = self.bytes(max_len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| self.invalid("invalid text"))
+    }
+
+    /// Check a magic prefix and version byte
+    pub(crate) fn header(&mut self, magic: &[u8], version: u8) -> Result<()> {
+        if self.take(magic.len())? != magic {
+            return Err(self.invalid("wrong type"));
+        }
+        if self.u8()? != version {
+            return Err(self.invalid("unsupported version"));
+        }
+        Ok(())
+    }
+
+    /// Everything not read yet
+    pub(crate) fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.data)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Fail if bytes remain
+    pub(crate) fn finish(&self) -> Result<()> {
+        if !self.data.is_empty() {
+            return Err(self.invalid("trailing data"));
+        }
+        Ok(())
+    }
+}
+
+/// Append length-prefixed (u16) bytes
+/// Callers validate lengths against their own limits before encoding
+pub(crate) fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+    out.extend_from_slice(bytes);
+}
+
+/// Split a trailing fixed-size signature off an encoded message
+pub(crate) fn split_signature<'a>(data: &'a [u8], what: &'static str) -> Result<(&'a [u8], [u8; 64])> {
+    if data.len() < 64 {
+        return Err(UndergroundError::InvalidMessage(format!("{}: truncated", what)));
+    }
+    let (body, signature) = data.split_at(data.len() - 64);
+    let mut sig = [0u8; 64];
+    sig.copy_from_slice(signature);
+    Ok((body, sig))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reader_roundtrip_and_bounds() {
+        let mut data = b"URR".to_vec();
+        data.push(1);
+        data.extend_from_slice(&7u32.to_be_bytes());
+        put_bytes(&mut data, b"hello");
+
+        let mut reader = Reader::new(&data, "test");
+        reader.header(b"URR", 1).unwrap();
+        assert_eq!(reader.u32().unwrap(), 7);
+        assert_eq!(reader.string(5).unwrap(), "hello");
+        reader.finish().unwrap();
+
+        assert!(Reader::new(&data, "test").header(b"URX", 1).is_err());
+        assert!(Reader::new(&data, "test").header(b"URR", 2).is_err());
+        assert!(Reader::new(&data[8..], "test").string(4).is_err());
+        assert!(Reader::new(&data[..data.len() - 1], "test").take(data.len()).is_err());
+        assert!(Reader::new(&data, "test").finish().is_err());
+        assert!(split_signature(&[0u8; 63], "test").is_err());
+    }
+}